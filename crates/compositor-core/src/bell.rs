@@ -0,0 +1,248 @@
+// Bell - System bell from xdg_system_bell_v1
+//
+// A bell rings for a surface or for a client as a whole. Depending on the bell
+// configuration it flashes the window or the output under the pointer, marks
+// the window urgent until it gets keyboard focus, and runs a command such as a
+// sound player. Flashes are drawn above the scene as translucent white quads
+// fading out, see `flash_draws`.
+
+use compositor_utils::prelude::*;
+use config::BellFlash;
+use smithay::{
+    desktop::Window,
+    output::Output,
+    reexports::wayland_server::protocol::wl_surface::WlSurface,
+    utils::{IsAlive, Logical, Rectangle},
+};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use vulkan_renderer::SurfaceDrawState;
+
+use crate::wayland::WaylandServerState;
+
+/// Opacity of a flash when the bell rings
+const FLASH_OPACITY: f32 = 0.5;
+
+/// Area flashed by a bell
+#[derive(Debug, Clone, PartialEq)]
+enum FlashTarget {
+    Window(Window),
+    Output(Output),
+}
+
+/// Flash started by a bell
+#[derive(Debug, Clone)]
+struct Flash {
+    target: FlashTarget,
+    start: Duration,
+}
+
+/// Bell flash to draw over an output
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlashOverlay {
+    /// Flashed area in global logical coordinates
+    pub area: Rectangle<i32, Logical>,
+    /// Strength of the flash, fading from 1.0 when the bell rings to 0.0
+    pub intensity: f64,
+}
+
+/// Bell flashes and urgent windows
+#[derive(Debug, Default)]
+pub struct BellState {
+    flashes: Vec<Flash>,
+    /// Windows that rang and have not been focused since
+    urgent: Vec<Window>,
+    /// Set while the bell command of the previous bell still runs
+    command_running: Arc<AtomicBool>,
+}
+
+impl BellState {
+    /// Whether a window rang since it last had keyboard focus
+    pub fn is_urgent(&self, window: &Window) -> bool {
+        self.urgent.contains(window)
+    }
+}
+
+impl WaylandServerState {
+    /// Window of a surface, including its subsurfaces and popups
+    fn window_for_bell_surface(&self, surface: &WlSurface) -> Option<Window> {
+        self.windows()
+            .map(|(_, window)| window)
+            .find(|window| {
+                let mut found = false;
+                window.with_surfaces(|candidate, _| found |= candidate == surface);
+                found
+            })
+            .cloned()
+    }
+
+    /// Output under the pointer, or the first output
+    fn bell_output(&self) -> Option<Output> {
+        let pointer = self.seat.get_pointer().map(|pointer| pointer.current_location().to_i32_round());
+        self.space
+            .outputs()
+            .find(|output| {
+                let geometry = self.space.output_geometry(output);
+                matches!((geometry, pointer), (Some(geometry), Some(pointer)) if geometry.contains(pointer))
+            })
+            .or_else(|| self.space.outputs().next())
+            .cloned()
+    }
+
+    /// Respond to a bell, rung for a surface or for a client without one
+    pub fn ring_bell(&mut self, surface: Option<&WlSurface>) {
+        let window = surface.and_then(|surface| self.window_for_bell_surface(surface));
+        debug!("Bell rung, for a window: {}", window.is_some());
+        let config = self.config.bell.clone();
+
+        let target = match (config.flash, &window) {
+            (BellFlash::None, _) => None,
+            // Windows on hidden workspaces have nothing to flash
+            (BellFlash::Window, Some(window)) => self
+                .space
+                .element_location(window)
+                .is_some()
+                .then(|| FlashTarget::Window(window.clone())),
+            (BellFlash::Window, None) | (BellFlash::Output, _) => self.bell_output().map(FlashTarget::Output),
+        };
+        if let Some(target) = target {
+            let start = Duration::from(self.clock.now());
+            self.bell_state.flashes.retain(|flash| flash.target != target);
+            self.bell_state.flashes.push(Flash { target, start });
+        }
+
+        if let Some(window) = &window {
+            if config.urgency && !self.bell_state.is_urgent(window) && !self.is_window_focused(window) {
+                self.bell_state.urgent.push(window.clone());
+            }
+        }
+
+        if let Some(command) = &config.command {
+            let (title, app_id) = window.as_ref().map(crate::ipc_server::window_title_and_app_id).unwrap_or_default();
+            self.run_bell_command(command, &title, &app_id);
+        }
+    }
+
+    /// Run the bell command, unless the one of a previous bell still runs
+    fn run_bell_command(&self, command: &str, title: &str, app_id: &str) {
+        let running = self.bell_state.command_running.clone();
+        if running.swap(true, Ordering::AcqRel) {
+            debug!("Previous bell command still running, skipping");
+            return;
+        }
+
+        let spawned = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .env("COMPOSITOR_BELL_TITLE", title)
+            .env("COMPOSITOR_BELL_APP_ID", app_id)
+            .stdin(Stdio::null())
+            .spawn();
+        match spawned {
+            Ok(mut child) => {
+                // Reap the command off the event loop
+                std::thread::spawn(move || {
+                    if let Err(e) = child.wait() {
+                        warn!("Failed to wait for bell command: {}", e);
+                    }
+                    running.store(false, Ordering::Release);
+                });
+            }
+            Err(e) => {
+                warn!("Failed to run bell command: {}", e);
+                running.store(false, Ordering::Release);
+            }
+        }
+    }
+
+    /// Whether a window has keyboard focus
+    fn is_window_focused(&self, window: &Window) -> bool {
+        let focused = self.seat.get_keyboard().and_then(|keyboard| keyboard.current_focus());
+        focused.is_some() && window.toplevel().map(|toplevel| toplevel.wl_surface()) == focused.as_ref()
+    }
+
+    /// Whether a window rang since it last had keyboard focus
+    pub fn is_window_urgent(&self, window: &Window) -> bool {
+        self.bell_state.is_urgent(window)
+    }
+
+    /// Bell flashes to draw over an output
+    pub fn bell_flashes(&self, output: &Output) -> Vec<FlashOverlay> {
+        let Some(output_geometry) = self.space.output_geometry(output) else {
+            return Vec::new();
+        };
+        let duration = Duration::from_millis(self.config.bell.flash_duration);
+        let now = Duration::from(self.clock.now());
+
+        self.bell_state
+            .flashes
+            .iter()
+            .filter_map(|flash| {
+                let elapsed = now.saturating_sub(flash.start);
+                if elapsed >= duration {
+                    return None;
+                }
+                let area = match &flash.target {
+                    FlashTarget::Window(window) => {
+                        let mut geometry = window.geometry();
+                        geometry.loc = self.space.element_location(window)?;
+                        geometry
+                    }
+                    FlashTarget::Output(flashed) if flashed == output => output_geometry,
+                    FlashTarget::Output(_) => return None,
+                };
+                output_geometry.overlaps(area).then(|| FlashOverlay {
+                    area,
+                    intensity: 1.0 - elapsed.as_secs_f64() / duration.as_secs_f64(),
+                })
+            })
+            .collect()
+    }
+
+    /// Bell flashes of an output as solid quads in output pixels, drawn above its scene
+    pub fn flash_draws(&self, output: &Output) -> Vec<SurfaceDrawState> {
+        let Some(output_geometry) = self.space.output_geometry(output) else {
+            return Vec::new();
+        };
+        let scale = output.current_scale().fractional_scale();
+        self.bell_flashes(output)
+            .into_iter()
+            .map(|flash| {
+                let position = (flash.area.loc - output_geometry.loc).to_f64().to_physical(scale);
+                let size = flash.area.size.to_f64().to_physical(scale);
+                SurfaceDrawState {
+                    position: [position.x as f32, position.y as f32],
+                    destination: Some([size.w as f32, size.h as f32]),
+                    alpha: FLASH_OPACITY * flash.intensity as f32,
+                    color: Some([1.0, 1.0, 1.0, 1.0]),
+                    ..Default::default()
+                }
+            })
+            .collect()
+    }
+
+    /// Drop the urgency of a window taking keyboard focus
+    pub(crate) fn bell_focus_changed(&mut self, focused: Option<&WlSurface>) {
+        let Some(focused) = focused else {
+            return;
+        };
+        self.bell_state
+            .urgent
+            .retain(|window| window.toplevel().map(|toplevel| toplevel.wl_surface()) != Some(focused));
+    }
+
+    /// Drop finished flashes and closed windows
+    pub(crate) fn refresh_bell(&mut self) {
+        let duration = Duration::from_millis(self.config.bell.flash_duration);
+        let now = Duration::from(self.clock.now());
+        self.bell_state
+            .flashes
+            .retain(|flash| now.saturating_sub(flash.start) < duration && match &flash.target {
+                FlashTarget::Window(window) => window.alive(),
+                FlashTarget::Output(_) => true,
+            });
+        self.bell_state.urgent.retain(|window| window.alive());
+    }
+}
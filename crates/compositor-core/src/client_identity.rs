@@ -0,0 +1,169 @@
+// Client identity - Who is behind each Wayland connection
+//
+// The pid, uid and gid of a client are read from its socket with SO_PEERCRED
+// when the connection is accepted, and the executable and cgroup of the
+// process are resolved from /proc right away, while the process is known to
+// be alive. Clients connecting through a wp-security-context listener also
+// carry the sandbox engine, app id and instance id of the context.
+//
+// Identities are kept in the client state for window rules, IPC window
+// queries and logs. A pid may be reused once its process exits, so the
+// identity recorded at connection time is the one to trust, not a later
+// lookup of the pid.
+
+use compositor_utils::prelude::*;
+use ipc::protocol::ClientInfo;
+use smithay::{
+    desktop::Window,
+    reexports::{
+        rustix,
+        wayland_server::{backend::Credentials, Client, Resource},
+    },
+    wayland::security_context::SecurityContext,
+};
+use std::fmt;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::wayland::{ClientState, WaylandServerState};
+
+/// Identity of a connected client
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientIdentity {
+    pub pid: i32,
+    pub uid: u32,
+    pub gid: u32,
+    /// Executable of the process, if it could be resolved
+    pub executable: Option<PathBuf>,
+    /// cgroup of the process, the unified hierarchy path where available
+    pub cgroup: Option<String>,
+    pub connected_at: SystemTime,
+    /// Security context the client connected through, if it is sandboxed
+    pub sandbox_engine: Option<String>,
+    pub sandbox_app_id: Option<String>,
+    pub sandbox_instance_id: Option<String>,
+}
+
+impl ClientIdentity {
+    /// Resolve the identity of a process from its socket credentials
+    pub fn resolve(credentials: Credentials, security_context: Option<&SecurityContext>) -> Self {
+        let process = PathBuf::from(format!("/proc/{}", credentials.pid));
+        Self {
+            pid: credentials.pid,
+            uid: credentials.uid,
+            gid: credentials.gid,
+            executable: std::fs::read_link(process.join("exe")).ok(),
+            cgroup: std::fs::read_to_string(process.join("cgroup"))
+                .ok()
+                .and_then(|cgroups| parse_cgroup(&cgroups)),
+            connected_at: SystemTime::now(),
+            sandbox_engine: security_context.and_then(|context| context.sandbox_engine.clone()),
+            sandbox_app_id: security_context.and_then(|context| context.app_id.clone()),
+            sandbox_instance_id: security_context.and_then(|context| context.instance_id.clone()),
+        }
+    }
+
+    /// Summary sent to IPC clients
+    pub fn info(&self) -> ClientInfo {
+        ClientInfo {
+            pid: self.pid,
+            uid: self.uid,
+            gid: self.gid,
+            executable: self.executable.as_ref().map(|path| path.display().to_string()),
+            cgroup: self.cgroup.clone(),
+            connected_at: self
+                .connected_at
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_secs())
+                .unwrap_or(0),
+            sandbox_engine: self.sandbox_engine.clone(),
+            sandbox_app_id: self.sandbox_app_id.clone(),
+            sandbox_instance_id: self.sandbox_instance_id.clone(),
+        }
+    }
+}
+
+impl fmt::Display for ClientIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.executable {
+            Some(executable) => write!(f, "{}", executable.display())?,
+            None => write!(f, "unknown executable")?,
+        }
+        write!(f, " (pid {}, uid {}", self.pid, self.uid)?;
+        if let Some(app_id) = &self.sandbox_app_id {
+            write!(f, ", sandboxed {}", app_id)?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// cgroup of a process from /proc/<pid>/cgroup
+///
+/// The unified hierarchy entry `0::<path>` is preferred; with only legacy
+/// hierarchies the path of the first one is used.
+fn parse_cgroup(cgroups: &str) -> Option<String> {
+    let paths = cgroups.lines().filter_map(|line| {
+        let mut fields = line.splitn(3, ':');
+        let id = fields.next()?;
+        let _controllers = fields.next()?;
+        Some((id, fields.next()?))
+    });
+    let mut first = None;
+    for (id, path) in paths {
+        if id == "0" {
+            return Some(path.to_string());
+        }
+        first.get_or_insert(path);
+    }
+    first.map(str::to_string)
+}
+
+/// Credentials of the process on the other end of a socket
+pub(crate) fn peer_credentials(stream: &UnixStream) -> std::io::Result<Credentials> {
+    let credentials = rustix::net::sockopt::get_socket_peercred(stream)?;
+    Ok(Credentials {
+        pid: credentials.pid.as_raw_nonzero().get(),
+        uid: credentials.uid.as_raw(),
+        gid: credentials.gid.as_raw(),
+    })
+}
+
+impl WaylandServerState {
+    /// Insert a client connection, recording who is behind it
+    pub fn insert_client(&mut self, stream: UnixStream, client_state: ClientState) -> std::io::Result<Client> {
+        let credentials = peer_credentials(&stream);
+        self.insert_client_with_credentials(stream, client_state, credentials)
+    }
+
+    /// Insert a client connection for a process whose credentials were read elsewhere
+    pub(crate) fn insert_client_with_credentials(
+        &mut self,
+        stream: UnixStream,
+        client_state: ClientState,
+        credentials: std::io::Result<Credentials>,
+    ) -> std::io::Result<Client> {
+        let client_state = Arc::new(client_state);
+        let client = self.display_handle.insert_client(stream, client_state.clone())?;
+        match credentials {
+            Ok(credentials) => {
+                let identity = ClientIdentity::resolve(credentials, client_state.security_context.as_ref());
+                info!("Client connected: {}", identity);
+                let _ = client_state.identity.set(identity);
+            }
+            Err(e) => warn!("Failed to read credentials of client {:?}: {}", client.id(), e),
+        }
+        Ok(client)
+    }
+
+    /// Identity of a client, if its credentials could be read
+    pub fn client_identity(&self, client: &Client) -> Option<ClientIdentity> {
+        client.get_data::<ClientState>()?.identity.get().cloned()
+    }
+
+    /// Identity of the client owning a window, for window rules and IPC
+    pub fn window_client_identity(&self, window: &Window) -> Option<ClientIdentity> {
+        self.client_identity(&window.toplevel()?.wl_surface().client()?)
+    }
+}
@@ -0,0 +1,664 @@
+// Clipboard - Selection persistence and clipboard history
+//
+// Reads the clipboard and primary selection into memory as soon as a client
+// sets them, limited to the configured mime types and sizes. When the client
+// that owns a selection exits, the captured data is offered again from a
+// compositor-owned source, so copied content survives the application closing.
+// The size limit covers a whole selection, all of its mime types together.
+// Pasting from a compositor-owned source writes to the client pipe as the
+// client reads it, and gives up when the client stops reading. Compositor
+// drags send their data to the drop target the same way.
+//
+// Clipboard selections are also recorded in a bounded history that can be
+// listed and restored over IPC. Primary selections are persisted but not
+// recorded, since every text selection made with the mouse would end up there.
+// Both are tracked on the default seat only; the selections of other seats
+// pass between their clients without being kept or recorded.
+//
+// The owner of a selection is the client of the source object it was set with,
+// taken from the `wl_data_device` or primary selection request before smithay
+// handles it. Selections set by clipboard managers through data control have no
+// tracked owner and are left to the manager to keep alive.
+
+use compositor_utils::prelude::*;
+use config::ClipboardConfig;
+use ipc::protocol::ClipboardEntryInfo;
+use smithay::{
+    input::Seat,
+    reexports::{
+        calloop::{
+            generic::Generic,
+            timer::{TimeoutAction, Timer},
+            Interest, Mode, PostAction, RegistrationToken,
+        },
+        wayland_protocols::wp::primary_selection::zv1::server::zwp_primary_selection_device_v1::{
+            self, ZwpPrimarySelectionDeviceV1,
+        },
+        wayland_server::{
+            protocol::wl_data_device::{self, WlDataDevice},
+            Client, DataInit, Dispatch, DisplayHandle, Resource,
+        },
+    },
+    wayland::selection::{
+        data_device::{
+            request_data_device_client_selection, set_data_device_selection, DataDeviceState, DataDeviceUserData,
+        },
+        primary_selection::{
+            request_primary_client_selection, set_primary_selection, PrimaryDeviceUserData, PrimarySelectionState,
+        },
+        SelectionSource, SelectionTarget,
+    },
+};
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::{ErrorKind, PipeReader, Read, Write};
+use nix::fcntl::{fcntl, FcntlArg, OFlag};
+use std::os::fd::{AsRawFd, OwnedFd};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::wayland::WaylandServerState;
+
+/// Mime type password managers add to selections that must not be stored
+const SECRET_HINT_MIME_TYPE: &str = "x-kde-passwordManagerHint";
+
+/// How long the selection owner gets to send its data before the capture is abandoned
+const CAPTURE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long a client gets to read pasted or dropped data before the transfer is abandoned
+const SEND_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Bytes read from a selection pipe per wakeup
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Number of characters of text included in history previews
+const PREVIEW_LENGTH: usize = 100;
+
+/// Selection data read from a client, stored per mime type
+#[derive(Debug)]
+pub struct ClipboardEntry {
+    id: u64,
+    contents: Vec<(String, Arc<[u8]>)>,
+    timestamp: SystemTime,
+}
+
+impl ClipboardEntry {
+    /// Identifier used to restore the entry over IPC
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Mime types the entry holds data for, in the order the owner offered them
+    pub fn mime_types(&self) -> Vec<String> {
+        self.contents.iter().map(|(mime_type, _)| mime_type.clone()).collect()
+    }
+
+    /// Data stored for a mime type
+    pub fn data(&self, mime_type: &str) -> Option<&Arc<[u8]>> {
+        self.contents
+            .iter()
+            .find(|(stored, _)| stored == mime_type)
+            .map(|(_, data)| data)
+    }
+
+    /// Combined size of the stored data in bytes
+    pub fn size(&self) -> usize {
+        self.contents.iter().map(|(_, data)| data.len()).sum()
+    }
+
+    /// Start of the text contents, if the entry has any
+    pub fn preview(&self) -> Option<String> {
+        self.contents
+            .iter()
+            .find(|(mime_type, _)| mime_type.starts_with("text/plain") || mime_type == "UTF8_STRING")
+            .map(|(_, data)| String::from_utf8_lossy(data).chars().take(PREVIEW_LENGTH).collect())
+    }
+
+    /// Whether both entries hold the same data
+    fn same_contents(&self, other: &ClipboardEntry) -> bool {
+        self.contents == other.contents
+    }
+
+    /// Summary sent to IPC clients
+    pub fn info(&self) -> ClipboardEntryInfo {
+        ClipboardEntryInfo {
+            entry_id: self.id,
+            mime_types: self.mime_types(),
+            size: self.size() as u64,
+            preview: self.preview(),
+            timestamp: self
+                .timestamp
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_secs())
+                .unwrap_or(0),
+        }
+    }
+}
+
+/// Selection data being read from its owner
+#[derive(Default)]
+struct Capture {
+    /// Data read so far per mime type; `None` once a mime type is dropped
+    contents: Vec<(String, Option<Vec<u8>>)>,
+    /// Pipe sources still being read, indexed like `contents`
+    readers: Vec<Option<RegistrationToken>>,
+    size: usize,
+    timeout: Option<RegistrationToken>,
+}
+
+impl Capture {
+    fn is_complete(&self) -> bool {
+        self.readers.iter().all(Option::is_none)
+    }
+}
+
+/// Compositor-owned data being written to a client
+struct PendingSend {
+    data: Arc<[u8]>,
+    written: usize,
+    writer: Option<RegistrationToken>,
+    timeout: Option<RegistrationToken>,
+}
+
+/// State of the clipboard or primary selection of the seat
+#[derive(Default)]
+struct TrackedSelection {
+    /// Bumped on every selection change to invalidate in-flight captures
+    generation: u64,
+    /// Client whose source currently provides the selection
+    owner: Option<Client>,
+    capture: Option<Capture>,
+    /// Captured data of the current selection
+    entry: Option<Arc<ClipboardEntry>>,
+}
+
+/// Clipboard persistence and history state
+pub struct ClipboardState {
+    config: ClipboardConfig,
+    history: VecDeque<Arc<ClipboardEntry>>,
+    next_id: u64,
+    clipboard: TrackedSelection,
+    primary: TrackedSelection,
+    /// Transfers to pasting clients still in progress, by transfer id
+    sends: HashMap<u64, PendingSend>,
+    next_send: u64,
+    /// Client of the source in the selection request being dispatched
+    setting_owner: Option<Client>,
+}
+
+impl ClipboardState {
+    /// Create the clipboard service with the given configuration
+    pub fn new(config: ClipboardConfig) -> Self {
+        Self {
+            config,
+            history: VecDeque::new(),
+            next_id: 1,
+            clipboard: TrackedSelection::default(),
+            primary: TrackedSelection::default(),
+            sends: HashMap::new(),
+            next_send: 0,
+            setting_owner: None,
+        }
+    }
+
+    /// Past clipboard selections, newest first
+    pub fn history(&self) -> impl Iterator<Item = &Arc<ClipboardEntry>> {
+        self.history.iter()
+    }
+
+    /// Look up a history entry by id
+    pub fn entry(&self, id: u64) -> Option<Arc<ClipboardEntry>> {
+        self.history.iter().find(|entry| entry.id == id).cloned()
+    }
+
+    /// Captured data of the current selection, once it has been read
+    pub fn current(&self, ty: SelectionTarget) -> Option<&Arc<ClipboardEntry>> {
+        self.selection(ty).entry.as_ref()
+    }
+
+    /// Whether new selections are read into memory at all
+    fn is_capturing(&self) -> bool {
+        self.config.persistence || self.config.history_size > 0
+    }
+
+    fn wants_mime_type(&self, mime_type: &str) -> bool {
+        self.config
+            .mime_types
+            .iter()
+            .any(|prefix| mime_type.starts_with(prefix.as_str()))
+    }
+
+    fn selection(&self, ty: SelectionTarget) -> &TrackedSelection {
+        match ty {
+            SelectionTarget::Clipboard => &self.clipboard,
+            SelectionTarget::Primary => &self.primary,
+        }
+    }
+
+    fn selection_mut(&mut self, ty: SelectionTarget) -> &mut TrackedSelection {
+        match ty {
+            SelectionTarget::Clipboard => &mut self.clipboard,
+            SelectionTarget::Primary => &mut self.primary,
+        }
+    }
+
+    /// Add an entry to the front of the history, dropping duplicates and old entries
+    fn record(&mut self, entry: Arc<ClipboardEntry>) {
+        if self.config.history_size == 0 {
+            return;
+        }
+
+        self.history.retain(|existing| !existing.same_contents(&entry));
+        self.history.push_front(entry);
+
+        let mut total: usize = self.history.iter().map(|entry| entry.size()).sum();
+        while self.history.len() > self.config.history_size
+            || (total > self.config.max_history_bytes && self.history.len() > 1)
+        {
+            if let Some(evicted) = self.history.pop_back() {
+                total -= evicted.size();
+            }
+        }
+    }
+}
+
+impl WaylandServerState {
+    /// Make a history entry the current clipboard selection
+    pub fn restore_clipboard_entry(&mut self, id: u64) -> Result<()> {
+        let entry = self
+            .clipboard_state
+            .entry(id)
+            .ok_or_else(|| CompositorError::runtime(format!("No clipboard history entry with id {}", id)))?;
+
+        self.reset_selection(SelectionTarget::Clipboard);
+        self.clipboard_state.clipboard.entry = Some(entry.clone());
+        self.clipboard_state.record(entry.clone());
+        set_data_device_selection(&self.display_handle, &self.seat, entry.mime_types(), entry);
+
+        info!("Restored clipboard history entry {}", id);
+        Ok(())
+    }
+
+    /// Track a selection set by a client and start reading it
+    ///
+    /// Called before smithay installs the new selection, so reading is deferred
+    /// to an idle callback. Only selections of the default seat are tracked.
+    pub(crate) fn clipboard_selection_changed(
+        &mut self,
+        ty: SelectionTarget,
+        source: Option<SelectionSource>,
+        seat: &Seat<WaylandServerState>,
+    ) {
+        if *seat != self.seat {
+            return;
+        }
+        self.reset_selection(ty);
+
+        let Some(source) = source else {
+            return;
+        };
+
+        let mime_types = source.mime_types();
+        if !self.clipboard_state.is_capturing() || mime_types.iter().any(|mime| mime == SECRET_HINT_MIME_TYPE) {
+            return;
+        }
+
+        let mime_types: Vec<String> = mime_types
+            .into_iter()
+            .filter(|mime_type| self.clipboard_state.wants_mime_type(mime_type))
+            .collect();
+        if mime_types.is_empty() {
+            return;
+        }
+
+        let owner = self.clipboard_state.setting_owner.clone();
+        let tracked = self.clipboard_state.selection_mut(ty);
+        tracked.owner = owner;
+        let generation = tracked.generation;
+
+        self.loop_handle.insert_idle(move |state| {
+            state.start_capture(ty, generation, mime_types);
+        });
+    }
+
+    /// Serve a compositor-owned selection to a client
+    pub(crate) fn send_clipboard_data(&mut self, mime_type: &str, fd: OwnedFd, entry: &ClipboardEntry) {
+        let Some(data) = entry.data(mime_type).cloned() else {
+            debug!("Clipboard entry {} has no data for {}", entry.id, mime_type);
+            return;
+        };
+        self.send_data(data, fd);
+    }
+
+    /// Write compositor-owned data to a client pipe, for pastes and compositor drags
+    pub(crate) fn send_data(&mut self, data: Arc<[u8]>, fd: OwnedFd) {
+        let file = File::from(fd);
+        if let Err(e) = fcntl(file.as_raw_fd(), FcntlArg::F_SETFL(OFlag::O_NONBLOCK)) {
+            warn!("Failed to configure data pipe: {}", e);
+            return;
+        }
+
+        // Large selections can fill the pipe, so write them as the client drains it
+        let id = self.clipboard_state.next_send;
+        self.clipboard_state.next_send += 1;
+        let source = Generic::new(file, Interest::WRITE, Mode::Level);
+        let writer = match self
+            .loop_handle
+            .insert_source(source, move |_, file, state| Ok(state.write_send(id, file)))
+        {
+            Ok(token) => token,
+            Err(e) => {
+                warn!("Failed to watch data pipe: {}", e);
+                return;
+            }
+        };
+        let timeout = self
+            .loop_handle
+            .insert_source(Timer::from_duration(SEND_TIMEOUT), move |_, _, state| {
+                if let Some(send) = state.clipboard_state.sends.get_mut(&id) {
+                    send.timeout = None;
+                }
+                state.abort_send(id);
+                TimeoutAction::Drop
+            });
+        let send = PendingSend { data, written: 0, writer: Some(writer), timeout: timeout.ok() };
+        self.clipboard_state.sends.insert(id, send);
+    }
+
+    /// Write as much data as the client pipe accepts
+    fn write_send(&mut self, id: u64, mut file: &File) -> PostAction {
+        let Some(send) = self.clipboard_state.sends.get_mut(&id) else {
+            return PostAction::Remove;
+        };
+
+        while send.written < send.data.len() {
+            match file.write(&send.data[send.written..]) {
+                Ok(0) => break,
+                Ok(written) => send.written += written,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return PostAction::Continue,
+                Err(e) => {
+                    debug!("Failed to write data to client: {}", e);
+                    break;
+                }
+            }
+        }
+
+        // The source removes itself, closing the pipe
+        send.writer = None;
+        self.abort_send(id);
+        PostAction::Remove
+    }
+
+    /// Stop a transfer, closing the pipe if it is still being written
+    fn abort_send(&mut self, id: u64) {
+        let Some(send) = self.clipboard_state.sends.remove(&id) else {
+            return;
+        };
+        if send.written < send.data.len() {
+            debug!("Abandoned transfer to client after {} of {} bytes", send.written, send.data.len());
+        }
+        for token in send.writer.into_iter().chain(send.timeout) {
+            self.loop_handle.remove(token);
+        }
+    }
+
+    /// Re-offer captured selections whose owner has disconnected
+    pub(crate) fn refresh_clipboard(&mut self) {
+        for ty in [SelectionTarget::Clipboard, SelectionTarget::Primary] {
+            let tracked = self.clipboard_state.selection(ty);
+            let owner_gone = tracked.owner.as_ref().is_some_and(|owner| {
+                self.display_handle.backend_handle().get_client_data(owner.id()).is_err()
+            });
+
+            // Wait for an in-flight capture, the pipes close once the owner is gone
+            if !owner_gone || tracked.capture.is_some() {
+                continue;
+            }
+
+            let tracked = self.clipboard_state.selection_mut(ty);
+            tracked.owner = None;
+            let Some(entry) = tracked.entry.clone() else {
+                continue;
+            };
+
+            if !self.clipboard_state.config.persistence {
+                continue;
+            }
+
+            info!("Selection owner disconnected, keeping {:?} selection {} alive", ty, entry.id);
+            match ty {
+                SelectionTarget::Clipboard => {
+                    set_data_device_selection(&self.display_handle, &self.seat, entry.mime_types(), entry)
+                }
+                SelectionTarget::Primary => {
+                    set_primary_selection(&self.display_handle, &self.seat, entry.mime_types(), entry)
+                }
+            }
+        }
+    }
+
+    /// Forget the current selection and stop reading it
+    fn reset_selection(&mut self, ty: SelectionTarget) {
+        let tracked = self.clipboard_state.selection_mut(ty);
+        tracked.generation += 1;
+        tracked.owner = None;
+        tracked.entry = None;
+
+        if let Some(capture) = tracked.capture.take() {
+            for token in capture.readers.into_iter().flatten().chain(capture.timeout) {
+                self.loop_handle.remove(token);
+            }
+        }
+    }
+
+    /// Request every wanted mime type from the selection owner
+    fn start_capture(&mut self, ty: SelectionTarget, generation: u64, mime_types: Vec<String>) {
+        if self.clipboard_state.selection(ty).generation != generation {
+            return;
+        }
+
+        let mut capture = Capture::default();
+        for mime_type in mime_types {
+            let (reader, writer) = match std::io::pipe() {
+                Ok(pipe) => pipe,
+                Err(e) => {
+                    warn!("Failed to create clipboard pipe: {}", e);
+                    break;
+                }
+            };
+
+            let requested = match ty {
+                SelectionTarget::Clipboard => {
+                    request_data_device_client_selection(&self.seat, mime_type.clone(), OwnedFd::from(writer))
+                        .map_err(|e| e.to_string())
+                }
+                SelectionTarget::Primary => {
+                    request_primary_client_selection(&self.seat, mime_type.clone(), OwnedFd::from(writer))
+                        .map_err(|e| e.to_string())
+                }
+            };
+            if let Err(e) = requested {
+                debug!("Not capturing {} from {:?} selection: {}", mime_type, ty, e);
+                continue;
+            }
+
+            if let Err(e) = fcntl(reader.as_raw_fd(), FcntlArg::F_SETFL(OFlag::O_NONBLOCK)) {
+                warn!("Failed to configure clipboard pipe: {}", e);
+                continue;
+            }
+
+            let index = capture.contents.len();
+            let source = Generic::new(reader, Interest::READ, Mode::Level);
+            let token = self
+                .loop_handle
+                .insert_source(source, move |_, reader, state| {
+                    Ok(state.read_capture(ty, generation, index, reader))
+                });
+
+            match token {
+                Ok(token) => {
+                    capture.contents.push((mime_type, Some(Vec::new())));
+                    capture.readers.push(Some(token));
+                }
+                Err(e) => warn!("Failed to watch clipboard pipe: {}", e),
+            }
+        }
+
+        if capture.is_complete() {
+            return;
+        }
+
+        let timeout = self
+            .loop_handle
+            .insert_source(Timer::from_duration(CAPTURE_TIMEOUT), move |_, _, state| {
+                state.abort_capture(ty, generation);
+                TimeoutAction::Drop
+            });
+        capture.timeout = timeout.ok();
+        self.clipboard_state.selection_mut(ty).capture = Some(capture);
+    }
+
+    /// Read available selection data from one pipe
+    fn read_capture(&mut self, ty: SelectionTarget, generation: u64, index: usize, mut reader: &PipeReader) -> PostAction {
+        let max_entry_size = self.clipboard_state.config.max_entry_size;
+        let tracked = self.clipboard_state.selection_mut(ty);
+        let Some(capture) = tracked.capture.as_mut() else {
+            return PostAction::Remove;
+        };
+        if tracked.generation != generation {
+            return PostAction::Remove;
+        }
+
+        // Drain the pipe until the owner has to write more or closes it
+        let mut chunk = vec![0; READ_CHUNK_SIZE];
+        loop {
+            match reader.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(read) if capture.size + read > max_entry_size => {
+                    debug!("Dropping {} from {:?} selection, the selection exceeds {} bytes", capture.contents[index].0, ty, max_entry_size);
+                    if let Some(data) = capture.contents[index].1.take() {
+                        capture.size -= data.len();
+                    }
+                    break;
+                }
+                Ok(read) => {
+                    if let Some(data) = capture.contents[index].1.as_mut() {
+                        data.extend_from_slice(&chunk[..read]);
+                        capture.size += read;
+                    }
+                }
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return PostAction::Continue,
+                Err(e) => {
+                    debug!("Failed to read {:?} selection: {}", ty, e);
+                    capture.contents[index].1 = None;
+                    break;
+                }
+            }
+        }
+
+        capture.readers[index] = None;
+        if capture.is_complete() {
+            self.finish_capture(ty);
+        }
+        PostAction::Remove
+    }
+
+    /// Give up on mime types the owner has not finished sending
+    fn abort_capture(&mut self, ty: SelectionTarget, generation: u64) {
+        let tracked = self.clipboard_state.selection_mut(ty);
+        if tracked.generation != generation {
+            return;
+        }
+        let Some(capture) = tracked.capture.as_mut() else {
+            return;
+        };
+
+        capture.timeout = None;
+        for (index, reader) in capture.readers.iter_mut().enumerate() {
+            if let Some(token) = reader.take() {
+                debug!("Timed out reading {} from {:?} selection", capture.contents[index].0, ty);
+                capture.contents[index].1 = None;
+                self.loop_handle.remove(token);
+            }
+        }
+
+        self.finish_capture(ty);
+    }
+
+    /// Turn a completed capture into the entry for the current selection
+    fn finish_capture(&mut self, ty: SelectionTarget) {
+        let Some(capture) = self.clipboard_state.selection_mut(ty).capture.take() else {
+            return;
+        };
+        if let Some(token) = capture.timeout {
+            self.loop_handle.remove(token);
+        }
+
+        let contents: Vec<(String, Arc<[u8]>)> = capture
+            .contents
+            .into_iter()
+            .filter_map(|(mime_type, data)| Some((mime_type, Arc::from(data.filter(|data| !data.is_empty())?))))
+            .collect();
+        if contents.is_empty() {
+            return;
+        }
+
+        let entry = Arc::new(ClipboardEntry {
+            id: self.clipboard_state.next_id,
+            contents,
+            timestamp: SystemTime::now(),
+        });
+        self.clipboard_state.next_id += 1;
+        debug!("Captured {:?} selection {} ({} bytes)", ty, entry.id, entry.size());
+
+        if ty == SelectionTarget::Clipboard {
+            self.clipboard_state.record(entry.clone());
+        }
+        self.clipboard_state.selection_mut(ty).entry = Some(entry);
+
+        // The owner may have exited while its data was being read
+        self.refresh_clipboard();
+    }
+}
+
+impl Dispatch<WlDataDevice, DataDeviceUserData> for WaylandServerState {
+    fn request(
+        state: &mut Self,
+        client: &Client,
+        device: &WlDataDevice,
+        request: wl_data_device::Request,
+        data: &DataDeviceUserData,
+        dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let wl_data_device::Request::SetSelection { source, .. } = &request {
+            state.clipboard_state.setting_owner = source.as_ref().and_then(|source| source.client());
+        }
+        <DataDeviceState as Dispatch<WlDataDevice, DataDeviceUserData, Self>>::request(
+            state, client, device, request, data, dh, data_init,
+        );
+        state.clipboard_state.setting_owner = None;
+    }
+}
+
+impl Dispatch<ZwpPrimarySelectionDeviceV1, PrimaryDeviceUserData> for WaylandServerState {
+    fn request(
+        state: &mut Self,
+        client: &Client,
+        device: &ZwpPrimarySelectionDeviceV1,
+        request: zwp_primary_selection_device_v1::Request,
+        data: &PrimaryDeviceUserData,
+        dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let zwp_primary_selection_device_v1::Request::SetSelection { source, .. } = &request {
+            state.clipboard_state.setting_owner = source.as_ref().and_then(|source| source.client());
+        }
+        <PrimarySelectionState as Dispatch<ZwpPrimarySelectionDeviceV1, PrimaryDeviceUserData, Self>>::request(
+            state, client, device, request, data, dh, data_init,
+        );
+        state.clipboard_state.setting_owner = None;
+    }
+}
@@ -0,0 +1,186 @@
+// Commit Queue - Commit-timing and FIFO constraints on surface commits
+//
+// Commits carrying a wp_commit_timer_v1 target time, or waiting on a wp_fifo_v1
+// barrier, are held back as blocked transactions that smithay queues per
+// surface in commit order. This module decides when they apply: timed commits
+// once the next frame of their output is expected on screen at or after the
+// target time, FIFO commits once the content that set their barrier has been
+// presented. Synchronized subsurfaces apply with their parent: FIFO waits are
+// ignored for them and their timed commits are released with the parent's
+// frame.
+//
+// Surfaces no output shows, including those hidden behind opaque content, would
+// never be presented, so their constraints are released at the pace of
+// presented frames instead, keeping hidden windows from stalling their clients.
+
+use compositor_utils::prelude::*;
+use smithay::{
+    output::Output,
+    reexports::wayland_server::{protocol::wl_surface::WlSurface, Client, Resource},
+    utils::{IsAlive, Monotonic, Time},
+    wayland::{
+        commit_timing::{CommitTimerBarrierStateUserData, CommitTimerStateUserData},
+        compositor::with_states,
+        fifo::{FifoBarrierCachedState, FifoCachedState},
+    },
+};
+use std::time::Duration;
+
+use crate::frame::root_surface;
+use crate::occlusion::Culling;
+use crate::wayland::{ClientState, WaylandServerState};
+
+/// Surfaces with commits held back by timing constraints
+#[derive(Debug, Default)]
+pub struct CommitQueueState {
+    /// Surfaces with commit timers or FIFO barriers to release
+    surfaces: Vec<WlSurface>,
+}
+
+/// Release the commit timers of a surface up to a deadline
+fn signal_commit_timers(surface: &WlSurface, deadline: Duration) -> bool {
+    with_states(surface, |states| {
+        states
+            .data_map
+            .get::<CommitTimerBarrierStateUserData>()
+            .is_some_and(|barriers| barriers.lock().unwrap().signal_until(Time::<Monotonic>::from(deadline)))
+    })
+}
+
+/// Release the FIFO barrier set by the current content of a surface
+fn signal_fifo_barrier(surface: &WlSurface) -> bool {
+    with_states(surface, |states| {
+        let barrier = states.cached_state.get::<FifoBarrierCachedState>().current().barrier.take();
+        match barrier {
+            Some(barrier) if !barrier.is_signaled() => {
+                barrier.signal();
+                true
+            }
+            _ => false,
+        }
+    })
+}
+
+/// Whether a surface still has constraints to release
+fn has_constraints(surface: &WlSurface) -> bool {
+    with_states(surface, |states| {
+        let timers = states
+            .data_map
+            .get::<CommitTimerBarrierStateUserData>()
+            .is_some_and(|barriers| barriers.lock().unwrap().next_deadline().is_some());
+        let barrier = states.cached_state.get::<FifoBarrierCachedState>().current().barrier.is_some();
+        timers || barrier
+    })
+}
+
+impl WaylandServerState {
+    /// Track a surface about to commit with a target time or a FIFO barrier
+    ///
+    /// Runs as a pre-commit hook, ahead of the hooks placing the blockers.
+    pub(crate) fn commit_queued(&mut self, surface: &WlSurface) {
+        let constrained = with_states(surface, |states| {
+            let timed = states
+                .data_map
+                .get::<CommitTimerStateUserData>()
+                .is_some_and(|timer| timer.borrow().timestamp.is_some());
+            let fifo = *states.cached_state.get::<FifoCachedState>().pending();
+            timed || fifo.set_barrier || fifo.wait_barrier
+        });
+        if constrained && !self.commit_queue_state.surfaces.contains(surface) {
+            self.commit_queue_state.surfaces.push(surface.clone());
+        }
+    }
+
+    /// Root surfaces shown on outputs, not hidden behind opaque content
+    fn shown_surfaces<'a>(&self, cullings: impl IntoIterator<Item = &'a (Output, Culling)>) -> Vec<WlSurface> {
+        cullings
+            .into_iter()
+            .flat_map(|(output, culling)| culling.visible_elements(self.render_elements(output)))
+            .map(|element| root_surface(&element.surface))
+            .collect()
+    }
+
+    /// Release timed commits due by the next frame of an output
+    ///
+    /// `cullings` holds the scenes of all outputs. Returns whether commits
+    /// were released, which changes the scenes.
+    pub(crate) fn release_commit_timers(&mut self, output: &Output, cullings: &[(Output, Culling)]) -> bool {
+        if self.commit_queue_state.surfaces.is_empty() {
+            return false;
+        }
+        let target = self.next_presentation_time(output);
+        let now = Duration::from(self.clock.now());
+        let on_output = self.shown_surfaces(cullings.iter().filter(|(other, _)| other == output));
+        let shown = self.shown_surfaces(cullings);
+
+        let mut released = Vec::new();
+        for surface in &self.commit_queue_state.surfaces {
+            let root = root_surface(surface);
+            let deadline = if on_output.contains(&root) {
+                target
+            } else if !shown.contains(&root) {
+                now
+            } else {
+                // Released by the frames of the output showing it
+                continue;
+            };
+            if signal_commit_timers(surface, deadline) {
+                released.push(surface.clone());
+            }
+        }
+        self.commits_released(&released);
+        !released.is_empty()
+    }
+
+    /// Release FIFO barriers of content presented in a frame
+    ///
+    /// `presented` holds the root surfaces drawn in the frame. Barriers of
+    /// surfaces no output shows are released along with it.
+    pub(crate) fn release_fifo_barriers(&mut self, presented: &[WlSurface]) {
+        if self.commit_queue_state.surfaces.is_empty() {
+            return;
+        }
+        let shown = self.shown_surfaces(&self.cull_outputs());
+        let now = Duration::from(self.clock.now());
+
+        let mut released = Vec::new();
+        for surface in &self.commit_queue_state.surfaces {
+            let root = root_surface(surface);
+            if presented.contains(&root) || !shown.contains(&root) {
+                if signal_fifo_barrier(surface) {
+                    released.push(surface.clone());
+                }
+                // Hidden surfaces also have their timed commits released as time passes
+                if !shown.contains(&root) && signal_commit_timers(surface, now) {
+                    released.push(surface.clone());
+                }
+            }
+        }
+        self.commits_released(&released);
+    }
+
+    /// Apply the commits unblocked by released constraints
+    fn commits_released(&mut self, released: &[WlSurface]) {
+        let mut clients: Vec<Client> = Vec::new();
+        for surface in released {
+            if let Ok(client) = self.display_handle.get_client(surface.id()) {
+                if !clients.contains(&client) {
+                    clients.push(client);
+                }
+            }
+        }
+        if !clients.is_empty() {
+            trace!("Releasing held commits of {} surfaces", released.len());
+        }
+
+        let dh = self.display_handle.clone();
+        for client in clients {
+            if let Some(client_state) = client.get_data::<ClientState>() {
+                client_state.compositor_state.blocker_cleared(self, &dh);
+            }
+        }
+        self.commit_queue_state
+            .surfaces
+            .retain(|surface| surface.alive() && has_constraints(surface));
+    }
+}
@@ -0,0 +1,253 @@
+// Cursor - XCursor themes and client cursor surfaces
+//
+// Named cursors, whether chosen by the compositor or requested by clients
+// through wp_cursor_shape_v1, are loaded from the configured XCursor theme.
+// Each theme file holds images at several nominal sizes; every output gets the
+// size closest to the cursor size at its scale, so cursors stay sharp on
+// HiDPI outputs. Cursors with several frames at a size are animated with the
+// compositor clock.
+//
+// Client cursor surfaces are drawn by the scene at the pointer, offset by
+// their hotspot, which follows the buffer offsets of their commits. Theme
+// cursors, named ones and cursor shapes alike, are drawn above the scene from
+// the frame current for the output scale, placed the same way.
+
+use compositor_utils::prelude::*;
+use smithay::{
+    input::{
+        pointer::{CursorIcon, CursorImageStatus, CursorImageSurfaceData},
+        Seat,
+    },
+    output::Output,
+    reexports::wayland_server::protocol::wl_surface::WlSurface,
+    utils::{Buffer, Logical, Physical, Point, Rectangle, Size},
+    wayland::compositor::{with_states, SurfaceAttributes},
+};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use vulkan_renderer::SurfaceDrawState;
+use xcursor::{parser::parse_xcursor, CursorTheme};
+
+use crate::render::draw_geometry;
+use crate::seats::seat_cursor_status;
+use crate::wayland::WaylandServerState;
+
+/// One frame of a theme cursor at one nominal size
+#[derive(Debug, Clone)]
+pub struct CursorImage {
+    /// Nominal size the image was drawn for
+    pub nominal_size: u32,
+    pub size: Size<i32, Buffer>,
+    pub hotspot: Point<i32, Buffer>,
+    /// Time the frame stays on screen, zero for still cursors
+    pub delay: Duration,
+    /// Pixels in RGBA order, row by row
+    pub pixels_rgba: Arc<[u8]>,
+}
+
+/// Theme cursor frame placed on an output
+#[derive(Debug, Clone)]
+pub struct CursorFrame {
+    pub image: CursorImage,
+    /// Buffer pixels per logical pixel
+    pub scale: f64,
+    /// Position of the image's top-left corner in global compositor coordinates
+    pub location: Point<f64, Logical>,
+}
+
+/// Theme cursor with the state the renderer draws it with
+#[derive(Debug, Clone)]
+pub struct CursorDraw {
+    /// Name of the seat whose pointer carries the cursor
+    pub seat: String,
+    pub image: CursorImage,
+    /// Placement of the image in output pixels, drawn whole
+    pub state: SurfaceDrawState,
+}
+
+impl CursorDraw {
+    /// Area covered by the cursor in output pixels
+    pub fn geometry(&self) -> Rectangle<i32, Physical> {
+        draw_geometry(&self.state)
+    }
+}
+
+/// XCursor theme loaded for the compositor
+#[derive(Debug)]
+pub struct CursorThemeState {
+    name: String,
+    size: u32,
+    theme: CursorTheme,
+    /// Images of each cursor loaded so far, `None` when the theme lacks it
+    cursors: Mutex<HashMap<CursorIcon, Option<Arc<[CursorImage]>>>>,
+}
+
+impl CursorThemeState {
+    /// Look up a theme in the XCursor search path, loading cursors on first use
+    pub fn new(name: &str, size: u32) -> Self {
+        info!("Using cursor theme {} at size {}", name, size);
+        Self {
+            name: name.to_string(),
+            size,
+            theme: CursorTheme::load(name),
+            cursors: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Name of the theme
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Cursor size in logical pixels
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// Images of a cursor, falling back to the default arrow when the theme lacks it
+    pub fn cursor(&self, icon: CursorIcon) -> Option<Arc<[CursorImage]>> {
+        self.load(icon).or_else(|| self.load(CursorIcon::Default))
+    }
+
+    /// Frame of a cursor to show at an output scale and a point in time
+    pub fn frame(&self, icon: CursorIcon, scale: f64, time: Duration) -> Option<CursorImage> {
+        let images = self.cursor(icon)?;
+        let target = (self.size as f64 * scale).round() as i64;
+        let nominal_size = images
+            .iter()
+            .map(|image| image.nominal_size)
+            .min_by_key(|&size| (size as i64 - target).abs())?;
+        let frames: Vec<_> = images.iter().filter(|image| image.nominal_size == nominal_size).collect();
+
+        let cycle: Duration = frames.iter().map(|frame| frame.delay).sum();
+        if cycle.is_zero() {
+            return frames.first().map(|&frame| frame.clone());
+        }
+        let mut elapsed = Duration::from_nanos((time.as_nanos() % cycle.as_nanos()) as u64);
+        for frame in &frames {
+            if elapsed < frame.delay {
+                return Some((*frame).clone());
+            }
+            elapsed -= frame.delay;
+        }
+        frames.last().map(|&frame| frame.clone())
+    }
+
+    fn load(&self, icon: CursorIcon) -> Option<Arc<[CursorImage]>> {
+        let mut cursors = self.cursors.lock().unwrap();
+        cursors
+            .entry(icon)
+            .or_insert_with(|| {
+                // Themes name cursors after the CSS names or older X11 ones
+                let images = std::iter::once(icon.name())
+                    .chain(icon.alt_names().iter().copied())
+                    .find_map(|name| self.load_file(name));
+                if images.is_none() {
+                    debug!("Cursor theme {} has no {} cursor", self.name, icon.name());
+                }
+                images
+            })
+            .clone()
+    }
+
+    fn load_file(&self, name: &str) -> Option<Arc<[CursorImage]>> {
+        let path = self.theme.load_icon(name)?;
+        let content = match std::fs::read(&path) {
+            Ok(content) => content,
+            Err(e) => {
+                warn!("Failed to read cursor {}: {}", path.display(), e);
+                return None;
+            }
+        };
+        let Some(images) = parse_xcursor(&content).filter(|images| !images.is_empty()) else {
+            warn!("Invalid cursor file {}", path.display());
+            return None;
+        };
+        Some(
+            images
+                .into_iter()
+                .map(|image| CursorImage {
+                    nominal_size: image.size,
+                    size: (image.width as i32, image.height as i32).into(),
+                    hotspot: (image.xhot as i32, image.yhot as i32).into(),
+                    delay: Duration::from_millis(image.delay as u64),
+                    pixels_rgba: image.pixels_rgba.into(),
+                })
+                .collect(),
+        )
+    }
+}
+
+impl WaylandServerState {
+    /// Theme cursor to draw on an output, when the cursor is a named one over it
+    ///
+    /// Client cursor surfaces are part of the output's render elements instead.
+    pub fn cursor_frame(&self, output: &Output) -> Option<CursorFrame> {
+        self.seat_cursor_frame(&self.seat, output)
+    }
+
+    /// Theme cursor to draw on an output for the pointer of a seat
+    pub fn seat_cursor_frame(&self, seat: &Seat<Self>, output: &Output) -> Option<CursorFrame> {
+        let CursorImageStatus::Named(icon) = self.seat_cursor_image(seat) else {
+            return None;
+        };
+        let pointer_location = seat.get_pointer()?.current_location();
+        let output_geometry = self.space.output_geometry(output)?;
+        if !output_geometry.contains(pointer_location.to_i32_round()) {
+            return None;
+        }
+
+        let output_scale = output.current_scale().fractional_scale();
+        let image = self
+            .cursor_theme_state
+            .frame(icon, output_scale, Duration::from(self.clock.now()))?;
+        let scale = image.nominal_size as f64 / self.cursor_theme_state.size() as f64;
+        let hotspot = Point::<f64, Logical>::from((image.hotspot.x as f64 / scale, image.hotspot.y as f64 / scale));
+        Some(CursorFrame {
+            image,
+            scale,
+            location: pointer_location - hotspot,
+        })
+    }
+
+    /// Theme cursors of the seats whose pointer is over an output, drawn above its scene
+    pub fn cursor_draws(&self, output: &Output) -> Vec<CursorDraw> {
+        let Some(output_geometry) = self.space.output_geometry(output) else {
+            return Vec::new();
+        };
+        let output_scale = output.current_scale().fractional_scale();
+        self.seats()
+            .filter_map(|seat| {
+                let frame = self.seat_cursor_frame(seat, output)?;
+                let position = (frame.location - output_geometry.loc.to_f64()).to_physical(output_scale);
+                let size = frame.image.size.to_f64();
+                let destination = Size::<f64, Physical>::from((
+                    size.w / frame.scale * output_scale,
+                    size.h / frame.scale * output_scale,
+                ));
+                let state = SurfaceDrawState {
+                    position: [position.x as f32, position.y as f32],
+                    source: Some([0.0, 0.0, size.w as f32, size.h as f32]),
+                    destination: Some([destination.w as f32, destination.h as f32]),
+                    ..Default::default()
+                };
+                Some(CursorDraw { seat: seat.name().to_string(), image: frame.image, state })
+            })
+            .collect()
+    }
+
+    /// Move the hotspot of the client cursor surface by the buffer offset of a commit
+    pub(crate) fn cursor_surface_committed(&mut self, surface: &WlSurface) {
+        let is_cursor = |seat| matches!(seat_cursor_status(seat), CursorImageStatus::Surface(cursor) if &cursor == surface);
+        if !self.seats().any(is_cursor) {
+            return;
+        }
+        with_states(surface, |states| {
+            let delta = states.cached_state.get::<SurfaceAttributes>().current().buffer_delta.take();
+            if let (Some(delta), Some(data)) = (delta, states.data_map.get::<CursorImageSurfaceData>()) {
+                data.lock().unwrap().hotspot -= delta;
+            }
+        });
+    }
+}
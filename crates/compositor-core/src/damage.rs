@@ -0,0 +1,266 @@
+// Damage - Output damage tracking for partial redraws
+//
+// Each frame of an output is compared with the one before: surfaces that
+// appeared, disappeared, moved, were restacked or changed how they are drawn
+// damage both their old and new area, the others the buffer damage committed
+// since the last frame. Buffer damage is carried through the buffer scale and
+// transform, the viewport crop and destination size, the surface position and
+// the output scale into output pixels, and dropped where opaque content above
+// hides it. Bell flashes damage their area while they fade, and theme cursors
+// their old and new area when they move or show another image.
+//
+// The damage of the last frames is kept per output, so that backends can
+// repaint a swapchain image by its buffer age: an image last drawn N frames
+// ago needs the damage of those N frames. A frame without damage needs no
+// repaint at all, so the renderer draws nothing and ages no image: only
+// frames with damage enter the history, keeping buffer ages and history
+// positions on the same count.
+
+use smithay::{
+    backend::renderer::utils::{with_renderer_surface_state, CommitCounter},
+    output::Output,
+    reexports::wayland_server::protocol::wl_surface::WlSurface,
+    utils::{Physical, Point, Rectangle, Size},
+};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use vulkan_renderer::SurfaceDrawState;
+
+use crate::occlusion::Culling;
+use crate::render::draw_geometry;
+use crate::wayland::WaylandServerState;
+
+/// Frames of damage kept per output, the oldest buffer age repainted partially
+const MAX_BUFFER_AGE: usize = 4;
+
+/// Damage split into more rectangles than this is merged into its bounding box
+const MAX_DAMAGE_RECTS: usize = 32;
+
+/// A surface as drawn in the last frame of an output
+#[derive(Debug)]
+struct DrawnSurface {
+    surface: WlSurface,
+    geometry: Rectangle<i32, Physical>,
+    commit: CommitCounter,
+    state: SurfaceDrawState,
+    visible: Vec<Rectangle<i32, Physical>>,
+}
+
+/// A theme cursor as drawn in the last frame of an output
+#[derive(Debug)]
+struct DrawnCursor {
+    geometry: Rectangle<i32, Physical>,
+    pixels: Arc<[u8]>,
+}
+
+impl PartialEq for DrawnCursor {
+    fn eq(&self, other: &Self) -> bool {
+        self.geometry == other.geometry && Arc::ptr_eq(&self.pixels, &other.pixels)
+    }
+}
+
+/// Last frame and damage history of an output
+#[derive(Debug)]
+struct OutputDamage {
+    output: Output,
+    /// Output size in pixels and scale the history was recorded with
+    mode: (Size<i32, Physical>, f64),
+    surfaces: Vec<DrawnSurface>,
+    flashes: Vec<Rectangle<i32, Physical>>,
+    cursors: Vec<DrawnCursor>,
+    /// Damage of the last frames that were drawn, newest first
+    history: VecDeque<Vec<Rectangle<i32, Physical>>>,
+    /// Whether the last frame built had no damage and was not drawn
+    idle: bool,
+}
+
+/// Damage of the frames built for each output
+#[derive(Debug, Default)]
+pub struct DamageState {
+    outputs: Vec<OutputDamage>,
+}
+
+/// Buffer damage committed to a surface since a commit, in output pixels
+fn surface_damage(
+    surface: &WlSurface,
+    since: CommitCounter,
+    geometry: Rectangle<i32, Physical>,
+) -> Vec<Rectangle<i32, Physical>> {
+    with_renderer_surface_state(surface, |state| {
+        if state.current_commit() == since {
+            return Vec::new();
+        }
+        let (Some(view), Some(size)) = (state.view(), state.buffer_size()) else {
+            return vec![geometry];
+        };
+        let (scale, transform) = (state.buffer_scale(), state.buffer_transform());
+        let buffer_size = size.to_buffer(scale, transform);
+        // Surface coordinates of the viewport source to output pixels
+        let factor_x = geometry.size.w as f64 / view.src.size.w;
+        let factor_y = geometry.size.h as f64 / view.src.size.h;
+
+        state
+            .damage_since(Some(since))
+            .iter()
+            .filter_map(|rect| {
+                let rect = rect.to_logical(scale, transform, &buffer_size).to_f64().intersection(view.src)?;
+                let damage = Rectangle::<f64, Physical>::new(
+                    Point::from((
+                        geometry.loc.x as f64 + (rect.loc.x - view.src.loc.x) * factor_x,
+                        geometry.loc.y as f64 + (rect.loc.y - view.src.loc.y) * factor_y,
+                    )),
+                    Size::from((rect.size.w * factor_x, rect.size.h * factor_y)),
+                );
+                damage.to_i32_up().intersection(geometry)
+            })
+            .collect()
+    })
+    .unwrap_or_default()
+}
+
+/// Damage clipped to an output, merged once it splits into too many rectangles
+fn simplify_damage(damage: Vec<Rectangle<i32, Physical>>, output: Rectangle<i32, Physical>) -> Vec<Rectangle<i32, Physical>> {
+    let mut clipped: Vec<Rectangle<i32, Physical>> = Vec::new();
+    for rect in damage.into_iter().filter_map(|rect| rect.intersection(output)) {
+        if rect.is_empty() || clipped.iter().any(|other| other.contains_rect(rect)) {
+            continue;
+        }
+        clipped.retain(|other| !rect.contains_rect(*other));
+        clipped.push(rect);
+    }
+    if clipped.len() > MAX_DAMAGE_RECTS {
+        let bounds = clipped.iter().copied().reduce(|bounds, rect| bounds.merge(rect));
+        clipped = bounds.into_iter().collect();
+    }
+    clipped
+}
+
+impl WaylandServerState {
+    /// Compute the damage of the frame being built for an output
+    ///
+    /// Called once per frame with the culled scene of the output, it compares
+    /// the scene with the previous frame and adds the result to the damage
+    /// history, unless there is none.
+    pub(crate) fn damage_frame(&mut self, output: &Output, culling: &Culling) {
+        self.damage_state.outputs.retain(|entry| self.space.outputs().any(|other| *other == entry.output));
+        let Some(output_geometry) = self.space.output_geometry(output) else {
+            return;
+        };
+        let scale = output.current_scale().fractional_scale();
+        let output_area = Rectangle::from_size(output_geometry.size.to_f64().to_physical(scale).to_i32_round());
+        let mode = (output_area.size, scale);
+
+        let surfaces: Vec<DrawnSurface> = culling
+            .draws
+            .iter()
+            .map(|draw| DrawnSurface {
+                commit: with_renderer_surface_state(&draw.surface, |state| state.current_commit()).unwrap_or_default(),
+                geometry: draw.geometry(),
+                surface: draw.surface.clone(),
+                state: draw.state,
+                visible: draw.visible.clone(),
+            })
+            .collect();
+        let flashes: Vec<Rectangle<i32, Physical>> = self.flash_draws(output).iter().map(draw_geometry).collect();
+        let cursors: Vec<DrawnCursor> = self
+            .cursor_draws(output)
+            .into_iter()
+            .map(|draw| DrawnCursor { geometry: draw.geometry(), pixels: draw.image.pixels_rgba })
+            .collect();
+
+        let Some(entry) = self.damage_state.outputs.iter_mut().find(|entry| &entry.output == output) else {
+            self.damage_state.outputs.push(OutputDamage {
+                output: output.clone(),
+                mode,
+                surfaces,
+                flashes,
+                cursors,
+                history: VecDeque::from([vec![output_area]]),
+                idle: false,
+            });
+            return;
+        };
+        // A new mode or scale invalidates everything drawn before
+        if entry.mode != mode {
+            entry.mode = mode;
+            entry.surfaces = surfaces;
+            entry.flashes = flashes;
+            entry.cursors = cursors;
+            entry.history = VecDeque::from([vec![output_area]]);
+            entry.idle = false;
+            return;
+        }
+
+        let mut damage = Vec::new();
+        let kept_before: Vec<&WlSurface> = entry
+            .surfaces
+            .iter()
+            .map(|drawn| &drawn.surface)
+            .filter(|surface| surfaces.iter().any(|drawn| &drawn.surface == *surface))
+            .collect();
+        let kept_now: Vec<&WlSurface> = surfaces
+            .iter()
+            .map(|drawn| &drawn.surface)
+            .filter(|surface| entry.surfaces.iter().any(|drawn| &drawn.surface == *surface))
+            .collect();
+        for previous in &entry.surfaces {
+            if !surfaces.iter().any(|drawn| drawn.surface == previous.surface) {
+                damage.push(previous.geometry);
+            }
+        }
+        for drawn in &surfaces {
+            let Some(previous) = entry.surfaces.iter().find(|previous| previous.surface == drawn.surface) else {
+                damage.push(drawn.geometry);
+                continue;
+            };
+            let restacked = kept_before.iter().position(|surface| *surface == &drawn.surface)
+                != kept_now.iter().position(|surface| *surface == &drawn.surface);
+            if restacked || previous.state != drawn.state {
+                damage.push(previous.geometry);
+                damage.push(drawn.geometry);
+            } else {
+                // Damage behind opaque content above needs no repaint
+                let damage_rects = surface_damage(&drawn.surface, previous.commit, drawn.geometry);
+                damage.extend(damage_rects.iter().flat_map(|rect| {
+                    drawn.visible.iter().filter_map(|visible| visible.intersection(*rect))
+                }));
+            }
+        }
+        // Flashes fade every frame until they are gone
+        if !flashes.is_empty() || entry.flashes != flashes {
+            damage.extend(entry.flashes.iter().chain(&flashes));
+        }
+        if entry.cursors != cursors {
+            damage.extend(entry.cursors.iter().chain(&cursors).map(|cursor| cursor.geometry));
+        }
+
+        entry.surfaces = surfaces;
+        entry.flashes = flashes;
+        entry.cursors = cursors;
+        let damage = simplify_damage(damage, output_area);
+        entry.idle = damage.is_empty();
+        if !entry.idle {
+            entry.history.push_front(damage);
+            entry.history.truncate(MAX_BUFFER_AGE);
+        }
+    }
+
+    /// Damage to repaint an image of an output last drawn `buffer_age` frames ago
+    ///
+    /// Covers the changes of the last built frame and the drawn frames before
+    /// it, in output pixels, the buffer age counting drawn frames only. `None`
+    /// asks for a full repaint: the buffer age is 0 for images of undefined
+    /// contents, or older than the kept history. An empty list means the last
+    /// frame had no damage and nothing needs drawing, whatever the image.
+    pub fn output_damage(&self, output: &Output, buffer_age: usize) -> Option<Vec<Rectangle<i32, Physical>>> {
+        let entry = self.damage_state.outputs.iter().find(|entry| &entry.output == output)?;
+        if entry.idle {
+            return Some(Vec::new());
+        }
+        if buffer_age == 0 || buffer_age > entry.history.len() {
+            return None;
+        }
+        let damage = entry.history.iter().take(buffer_age).flatten().copied().collect();
+        Some(simplify_damage(damage, Rectangle::from_size(entry.mode.0)))
+    }
+}
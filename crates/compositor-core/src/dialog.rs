@@ -0,0 +1,240 @@
+// Dialog - Modal dialogs from xdg_dialog_v1
+//
+// Toplevels marked modal over a parent are centered on it and kept above it,
+// so they cannot get lost behind it. While a window has modal dialogs it takes
+// no input: pointer and tablet input over it is dropped, and focusing it
+// focuses its topmost dialog instead. Moving a window carries its dialogs
+// along.
+//
+// xdg-activation requests focus that way too, but only with a valid token: one
+// created less than ten seconds ago and, when created for an input event,
+// whose serial is no older than the last keyboard enter of its seat. Other
+// requests are ignored.
+
+use compositor_utils::prelude::*;
+use smithay::{
+    desktop::Window,
+    input::Seat,
+    reexports::wayland_server::protocol::wl_surface::WlSurface,
+    utils::{Logical, Point, Size, SERIAL_COUNTER},
+    wayland::{compositor::with_states, shell::xdg::XdgToplevelSurfaceData, xdg_activation::XdgActivationTokenData},
+};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::wayland::WaylandServerState;
+
+/// Age after which activation tokens no longer activate
+const ACTIVATION_TOKEN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Size a dialog had when it was last centered on its parent
+#[derive(Debug, Default)]
+struct DialogPlacement {
+    size: Option<Size<i32, Logical>>,
+}
+
+/// Whether a window is a toplevel marked modal by its client
+fn is_modal(window: &Window) -> bool {
+    let Some(toplevel) = window.toplevel() else {
+        return false;
+    };
+    with_states(toplevel.wl_surface(), |states| {
+        states
+            .data_map
+            .get::<XdgToplevelSurfaceData>()
+            .map(|data| data.lock().unwrap().modal)
+            .unwrap_or(false)
+    })
+}
+
+impl WaylandServerState {
+    /// Window of a toplevel surface, on any workspace
+    pub fn window_for_surface(&self, surface: &WlSurface) -> Option<Window> {
+        self.windows()
+            .find(|(_, window)| window.toplevel().map(|toplevel| toplevel.wl_surface()) == Some(surface))
+            .map(|(_, window)| window.clone())
+    }
+
+    /// Modal dialogs over a window, bottom to top
+    pub fn modal_dialogs(&self, parent: &Window) -> Vec<Window> {
+        let Some(parent) = parent.toplevel().map(|toplevel| toplevel.wl_surface().clone()) else {
+            return Vec::new();
+        };
+        self.windows()
+            .map(|(_, window)| window)
+            .filter(|window| window.toplevel().and_then(|toplevel| toplevel.parent()).as_ref() == Some(&parent))
+            .filter(|window| is_modal(window))
+            .cloned()
+            .collect()
+    }
+
+    /// Whether a window has modal dialogs, which take its input
+    pub fn is_blocked_by_dialog(&self, window: &Window) -> bool {
+        !self.modal_dialogs(window).is_empty()
+    }
+
+    /// Window taking focus in place of a window, following its topmost modal dialogs
+    pub fn focus_target(&self, window: &Window) -> Window {
+        let mut target = window.clone();
+        // Bounded in case clients chain their dialogs in a loop
+        for _ in 0..self.windows().count() {
+            match self.modal_dialogs(&target).pop() {
+                Some(dialog) => target = dialog,
+                None => break,
+            }
+        }
+        target
+    }
+
+    /// Raise a window above the others, along with its modal dialogs
+    pub fn raise_window(&mut self, window: &Window) {
+        let mut windows = vec![window.clone()];
+        let mut index = 0;
+        while index < windows.len() && index <= self.windows().count() {
+            let window = windows[index].clone();
+            self.space.raise_element(&window, false);
+            windows.extend(self.modal_dialogs(&window));
+            index += 1;
+        }
+    }
+
+    /// Raise and give keyboard focus to a window, or to its topmost modal dialog
+    pub fn focus_window(&mut self, window: &Window) -> Window {
+        let seat = self.seat.clone();
+        self.seat_focus_window(&seat, window)
+    }
+
+    /// Raise a window and give it the keyboard focus of a seat
+    pub fn seat_focus_window(&mut self, seat: &Seat<Self>, window: &Window) -> Window {
+        let target = self.focus_target(window);
+        if &target != window {
+            debug!("Focus redirected to a modal dialog");
+        }
+        self.raise_window(window);
+
+        if let (Some(keyboard), Some(toplevel)) = (seat.get_keyboard(), target.toplevel()) {
+            let surface = toplevel.wl_surface().clone();
+            self.save_window_layout(seat);
+            keyboard.set_focus(self, Some(surface), SERIAL_COUNTER.next_serial());
+            self.restore_window_layout(seat);
+        }
+        target
+    }
+
+    /// Focus the window of a surface for an xdg-activation token, when the token is valid
+    ///
+    /// Tokens created for an input event focus the window on the seat of the
+    /// event, others on the default seat. Returns the window focused, which is
+    /// the topmost modal dialog of a window that has some.
+    pub fn activate_surface(&mut self, token_data: &XdgActivationTokenData, surface: &WlSurface) -> Option<Window> {
+        if token_data.timestamp.elapsed() >= ACTIVATION_TOKEN_TIMEOUT {
+            debug!("Ignoring activation with an expired token");
+            return None;
+        }
+        let seat = match &token_data.serial {
+            Some((serial, wl_seat)) => {
+                let Some(seat) = Seat::<Self>::from_resource(wl_seat) else {
+                    debug!("Ignoring activation for an input event of a destroyed seat");
+                    return None;
+                };
+                let last_enter = seat.get_keyboard().and_then(|keyboard| keyboard.last_enter());
+                if !last_enter.is_some_and(|last_enter| serial.is_no_older_than(&last_enter)) {
+                    debug!("Ignoring activation for a stale input event");
+                    return None;
+                }
+                seat
+            }
+            None => self.seat.clone(),
+        };
+        let window = self.window_for_surface(surface)?;
+        Some(self.seat_focus_window(&seat, &window))
+    }
+
+    /// Move a window of the active workspace, carrying its modal dialogs along
+    pub fn move_window(&mut self, window: &Window, location: Point<i32, Logical>) -> Result<()> {
+        let current = self
+            .space
+            .element_location(window)
+            .ok_or_else(|| CompositorError::runtime("Window is not on the active workspace"))?;
+        let delta = location - current;
+
+        let mut windows = vec![window.clone()];
+        let mut index = 0;
+        while index < windows.len() && index <= self.windows().count() {
+            let window = windows[index].clone();
+            if let Some(location) = self.space.element_location(&window) {
+                self.space.map_element(window.clone(), location + delta, false);
+            }
+            windows.extend(self.modal_dialogs(&window));
+            index += 1;
+        }
+        // Mapping raises each window, so restore the dialogs above their parent
+        self.raise_window(window);
+        Ok(())
+    }
+
+    /// Center a modal dialog on its parent and raise it above
+    fn center_dialog(&mut self, dialog: &Window) {
+        let Some(parent) = dialog
+            .toplevel()
+            .and_then(|toplevel| toplevel.parent())
+            .and_then(|parent| self.window_for_surface(&parent))
+        else {
+            return;
+        };
+        let (Some(parent_location), Some(_)) = (self.space.element_location(&parent), self.space.element_location(dialog))
+        else {
+            return;
+        };
+
+        let parent_size = parent.geometry().size;
+        let size = dialog.geometry().size;
+        let location = parent_location + Point::from(((parent_size.w - size.w) / 2, (parent_size.h - size.h) / 2));
+        debug!("Centering modal dialog at {:?}", location);
+        self.space.map_element(dialog.clone(), location, false);
+
+        dialog.user_data().insert_if_missing_threadsafe(|| Mutex::new(DialogPlacement::default()));
+        if let Some(placement) = dialog.user_data().get::<Mutex<DialogPlacement>>() {
+            placement.lock().unwrap().size = Some(size);
+        }
+    }
+
+    /// Place a toplevel whose modal hint or parent changed
+    pub(crate) fn dialog_changed(&mut self, surface: &WlSurface) {
+        let Some(dialog) = self.window_for_surface(surface) else {
+            return;
+        };
+        if !is_modal(&dialog) || dialog.toplevel().and_then(|toplevel| toplevel.parent()).is_none() {
+            return;
+        }
+        self.center_dialog(&dialog);
+
+        // A dialog over the focused window takes its focus
+        let Some(parent) = dialog.toplevel().and_then(|toplevel| toplevel.parent()) else {
+            return;
+        };
+        let focused = self.seat.get_keyboard().and_then(|keyboard| keyboard.current_focus());
+        if focused.as_ref() == Some(&parent) {
+            if let Some(parent) = self.window_for_surface(&parent) {
+                self.focus_window(&parent);
+            }
+        }
+    }
+
+    /// Keep a modal dialog centered while its size changes
+    pub(crate) fn dialog_committed(&mut self, surface: &WlSurface) {
+        let Some(dialog) = self.window_for_surface(surface) else {
+            return;
+        };
+        if !is_modal(&dialog) {
+            return;
+        }
+        let placed_size = dialog
+            .user_data()
+            .get::<Mutex<DialogPlacement>>()
+            .and_then(|placement| placement.lock().unwrap().size);
+        if placed_size != Some(dialog.geometry().size) {
+            self.center_dialog(&dialog);
+        }
+    }
+}
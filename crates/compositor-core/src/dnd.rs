@@ -0,0 +1,238 @@
+// Drag and Drop - Drag icons, cursor feedback and compositor-originated drags
+//
+// Tracks the drag in progress on the default seat, so the scene can draw the
+// drag icon at the pointer and the cursor can show the action negotiated with
+// the drop target.
+//
+// Client drags are driven by smithay's DnD grab and only reported here.
+// Drags started by compositor UI such as the app bar use a server-side grab;
+// their data lives in memory until the drop target has finished reading it or
+// the drag is cancelled.
+
+use compositor_utils::prelude::*;
+use smithay::{
+    input::pointer::CursorIcon,
+    reexports::wayland_server::{
+        protocol::{wl_data_device_manager::DndAction, wl_surface::WlSurface},
+        Resource,
+    },
+    utils::{Logical, Point, SERIAL_COUNTER},
+    wayland::{
+        compositor::{with_states, SurfaceAttributes},
+        selection::data_device::{start_dnd, SourceMetadata},
+    },
+};
+use std::os::fd::OwnedFd;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::wayland::WaylandServerState;
+
+/// Mime type of file drops
+pub const URI_LIST_MIME_TYPE: &str = "text/uri-list";
+
+/// Surface drawn under the pointer while a client drags
+#[derive(Debug, Clone)]
+pub struct DragIcon {
+    pub surface: WlSurface,
+    /// Position of the icon relative to the pointer
+    pub offset: Point<i32, Logical>,
+}
+
+/// Drag state of the default seat
+#[derive(Debug)]
+pub struct DndState {
+    active: bool,
+    icon: Option<DragIcon>,
+    action: DndAction,
+    accepted: bool,
+    /// Data of a compositor-originated drag, per mime type
+    server_data: Option<Vec<(String, Arc<[u8]>)>>,
+}
+
+impl Default for DndState {
+    fn default() -> Self {
+        Self {
+            active: false,
+            icon: None,
+            action: DndAction::empty(),
+            accepted: false,
+            server_data: None,
+        }
+    }
+}
+
+impl DndState {
+    /// Whether a drag is in progress
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Icon of the drag in progress
+    pub fn icon(&self) -> Option<&DragIcon> {
+        self.icon.as_ref().filter(|icon| icon.surface.is_alive())
+    }
+
+    /// Whether data of a compositor drag is held for a drop target
+    pub fn has_pending_data(&self) -> bool {
+        self.server_data.is_some()
+    }
+
+    /// Action last negotiated with the drop target
+    pub fn action(&self) -> DndAction {
+        self.action
+    }
+
+    /// Cursor shown while dragging, reflecting whether and how the target takes the drop
+    pub fn cursor_icon(&self) -> Option<CursorIcon> {
+        if !self.active {
+            return None;
+        }
+        let icon = match self.action {
+            _ if !self.accepted => CursorIcon::NoDrop,
+            DndAction::Copy => CursorIcon::Copy,
+            DndAction::Move => CursorIcon::Move,
+            DndAction::Ask => CursorIcon::ContextMenu,
+            _ => CursorIcon::NoDrop,
+        };
+        Some(icon)
+    }
+
+    fn start(&mut self, icon: Option<WlSurface>) {
+        self.active = true;
+        self.icon = icon.map(|surface| DragIcon {
+            surface,
+            offset: Point::default(),
+        });
+        self.action = DndAction::empty();
+        self.accepted = true;
+    }
+
+    fn end(&mut self) {
+        self.active = false;
+        self.icon = None;
+        self.action = DndAction::empty();
+    }
+}
+
+/// Build a `text/uri-list` entry for a local file
+pub fn file_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for &byte in path.as_os_str().as_bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~/".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    uri
+}
+
+impl WaylandServerState {
+    /// Start a compositor-originated drag from the held pointer button
+    ///
+    /// `contents` pairs each offered mime type with its data. The drag follows
+    /// the pointer until the button is released over a client surface.
+    pub fn start_server_drag(&mut self, contents: Vec<(String, Vec<u8>)>, actions: DndAction) -> Result<()> {
+        if self.dnd_state.is_active() {
+            return Err(CompositorError::runtime("A drag is already in progress"));
+        }
+        let pointer = self
+            .seat
+            .get_pointer()
+            .ok_or_else(|| CompositorError::runtime("Seat has no pointer to drag with"))?;
+        let start_data = pointer
+            .grab_start_data()
+            .ok_or_else(|| CompositorError::runtime("Drags must start from a pressed pointer button"))?;
+
+        let metadata = SourceMetadata {
+            mime_types: contents.iter().map(|(mime_type, _)| mime_type.clone()).collect(),
+            dnd_action: actions,
+        };
+        let contents = contents
+            .into_iter()
+            .map(|(mime_type, data)| (mime_type, Arc::from(data)))
+            .collect();
+
+        info!("Starting compositor drag offering {:?}", metadata.mime_types);
+        self.dnd_state.start(None);
+        self.dnd_state.server_data = Some(contents);
+
+        let seat = self.seat.clone();
+        let dh = self.display_handle.clone();
+        start_dnd(&dh, &seat, self, SERIAL_COUNTER.next_serial(), Some(start_data), None, metadata);
+        Ok(())
+    }
+
+    /// Start a compositor-originated drag of local files, copied on drop
+    pub fn start_file_drag(&mut self, paths: &[impl AsRef<Path>]) -> Result<()> {
+        if paths.is_empty() {
+            return Err(CompositorError::runtime("No files to drag"));
+        }
+        let uri_list: String = paths.iter().map(|path| file_uri(path.as_ref()) + "\r\n").collect();
+        self.start_server_drag(vec![(URI_LIST_MIME_TYPE.to_string(), uri_list.into_bytes())], DndAction::Copy)
+    }
+
+    /// Record a client drag starting
+    pub(crate) fn drag_started(&mut self, icon: Option<WlSurface>) {
+        // A client drag replaces any compositor drag still waiting for its target
+        self.dnd_state.server_data = None;
+        self.dnd_state.start(icon);
+    }
+
+    /// Record the pointer being released, successfully or not
+    pub(crate) fn drag_dropped(&mut self) {
+        self.dnd_state.end();
+    }
+
+    /// Record the negotiated action, called for client and compositor drags
+    pub(crate) fn drag_action_chosen(&mut self, action: DndAction) {
+        if self.dnd_state.active {
+            self.dnd_state.action = action;
+        }
+    }
+
+    /// Record whether the target of a compositor drag accepts one of the offered mime types
+    pub(crate) fn drag_target_accepts(&mut self, accepted: bool) {
+        if self.dnd_state.active {
+            self.dnd_state.accepted = accepted;
+        }
+    }
+
+    /// Release the data of a compositor drag once the target is done with it
+    pub(crate) fn drag_finished(&mut self) {
+        self.dnd_state.end();
+        self.dnd_state.server_data = None;
+    }
+
+    /// Write compositor drag data for the drop target
+    pub(crate) fn send_drag_data(&mut self, mime_type: &str, fd: OwnedFd) {
+        let data = self.dnd_state.server_data.as_ref().and_then(|contents| {
+            contents
+                .iter()
+                .find(|(offered, _)| offered == mime_type)
+                .map(|(_, data)| data.clone())
+        });
+        let Some(data) = data else {
+            debug!("Compositor drag has no data for {}", mime_type);
+            return;
+        };
+
+        // Written as the target reads it, the same way as pasted clipboard data
+        self.send_data(data, fd);
+    }
+
+    /// Apply the offset a drag icon commits relative to its previous position
+    pub(crate) fn drag_icon_committed(&mut self, surface: &WlSurface) {
+        let Some(icon) = self.dnd_state.icon.as_mut().filter(|icon| &icon.surface == surface) else {
+            return;
+        };
+        let delta = with_states(surface, |states| {
+            states.cached_state.get::<SurfaceAttributes>().current().buffer_delta.take()
+        });
+        if let Some(delta) = delta {
+            icon.offset += delta;
+        }
+    }
+}
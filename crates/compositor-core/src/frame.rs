@@ -0,0 +1,348 @@
+// Frame - Frame scheduling and presentation-time feedback
+//
+// A frame of an output is built from its render elements. Building it sends
+// the frame callbacks of the drawn surfaces and takes the wp_presentation
+// feedback they committed; once the backend reports the frame on screen, the
+// feedback is answered with the presentation time, the refresh interval of the
+// output, the frame sequence and how the frame was shown. Feedback of surfaces
+// not shown on any output is discarded with the next frame instead. Surfaces
+// hidden behind opaque content are not shown, see occlusion.rs.
+//
+// Commits held back by commit-timing or FIFO constraints are released around
+// these frames, see commit_queue.rs, and each frame records the damage to
+// repaint, see damage.rs.
+//
+// Without a display to scan out, a timer per output stands in for vblank and
+// presents frames at the refresh rate of the output's mode. Once the clocks
+// run, outputs mapped later, newly added or enabled again, get a clock of
+// their own; an output never has two.
+
+use compositor_utils::prelude::*;
+use smithay::{
+    output::Output,
+    reexports::{
+        calloop::timer::{TimeoutAction, Timer},
+        wayland_protocols::wp::presentation_time::server::wp_presentation_feedback,
+        wayland_server::protocol::wl_surface::WlSurface,
+    },
+    utils::IsAlive,
+    wayland::{
+        compositor::{get_parent, with_states, with_surface_tree_downward, SurfaceAttributes, TraversalAction},
+        presentation::{PresentationFeedbackCachedState, PresentationFeedbackCallback, Refresh},
+    },
+};
+use std::time::Duration;
+use vulkan_renderer::ContentType;
+
+use crate::occlusion::Culling;
+use crate::render::{surface_content_type, RenderElement};
+use crate::wayland::WaylandServerState;
+
+/// Refresh interval used for outputs whose mode has no refresh rate
+const DEFAULT_REFRESH_INTERVAL: Duration = Duration::from_nanos(16_666_667);
+
+/// Frame built for an output and waiting to be presented
+#[derive(Debug)]
+struct PendingFrame {
+    output: Output,
+    /// Root surfaces drawn in the frame
+    surfaces: Vec<WlSurface>,
+    feedback: Vec<PresentationFeedbackCallback>,
+}
+
+/// Last frame presented on an output
+#[derive(Debug)]
+struct PresentedFrame {
+    output: Output,
+    sequence: u64,
+    time: Duration,
+}
+
+/// Frames waiting for presentation and surfaces waiting for feedback
+#[derive(Debug, Default)]
+pub struct FrameState {
+    pending: Vec<PendingFrame>,
+    /// Root surfaces with committed presentation feedback
+    feedback_surfaces: Vec<WlSurface>,
+    /// Last presented frame per output, counting frames for backends without a hardware counter
+    presented: Vec<PresentedFrame>,
+    /// Whether frames are paced by the frame clocks
+    clocked: bool,
+    /// Outputs with a running frame clock
+    clocks: Vec<Output>,
+}
+
+/// Refresh interval of the current mode of an output
+pub fn refresh_interval(output: &Output) -> Option<Duration> {
+    let refresh = output.current_mode()?.refresh;
+    (refresh > 0).then(|| Duration::from_nanos(1_000_000_000_000 / refresh as u64))
+}
+
+/// Root of a surface tree
+pub(crate) fn root_surface(surface: &WlSurface) -> WlSurface {
+    let mut root = surface.clone();
+    while let Some(parent) = get_parent(&root) {
+        root = parent;
+    }
+    root
+}
+
+/// Answer the frame callbacks committed to a single surface of a tree
+///
+/// Subsurfaces are drawn as elements of their own, so each one is answered
+/// only when it is drawn and not hidden behind opaque content.
+fn send_frame_callbacks(surface: &WlSurface, time: Duration) {
+    let callbacks = with_states(surface, |states| {
+        std::mem::take(&mut states.cached_state.get::<SurfaceAttributes>().current().frame_callbacks)
+    });
+    for callback in callbacks {
+        callback.done(time.as_millis() as u32);
+    }
+}
+
+/// Take the presentation feedback committed in a surface tree
+fn take_feedback(surface: &WlSurface) -> Vec<PresentationFeedbackCallback> {
+    let mut feedback = Vec::new();
+    with_surface_tree_downward(
+        surface,
+        (),
+        |_, _, _| TraversalAction::DoChildren(()),
+        |_, states, _| {
+            let mut cached = states.cached_state.get::<PresentationFeedbackCachedState>();
+            feedback.append(&mut cached.current().callbacks);
+        },
+        |_, _, _| true,
+    );
+    feedback
+}
+
+/// Whether a surface tree has committed presentation feedback
+fn has_feedback(surface: &WlSurface) -> bool {
+    let mut found = false;
+    with_surface_tree_downward(
+        surface,
+        (),
+        |_, _, _| TraversalAction::DoChildren(()),
+        |_, states, _| {
+            found |= !states
+                .cached_state
+                .get::<PresentationFeedbackCachedState>()
+                .current()
+                .callbacks
+                .is_empty();
+        },
+        |_, _, _| true,
+    );
+    found
+}
+
+impl WaylandServerState {
+    /// Track a surface tree whose commit asked for presentation feedback
+    pub(crate) fn presentation_committed(&mut self, surface: &WlSurface) {
+        let root = root_surface(surface);
+        if !self.frame_state.feedback_surfaces.contains(&root) && has_feedback(&root) {
+            self.frame_state.feedback_surfaces.push(root);
+        }
+    }
+
+    /// Build the next frame of an output
+    ///
+    /// Sends the frame callbacks of the drawn surfaces and holds their
+    /// presentation feedback until `frame_presented` reports the frame.
+    /// Occluded surfaces are left out until they show again.
+    pub fn render_frame(&mut self, output: &Output) -> Vec<RenderElement> {
+        self.record_render_frame(output);
+        // The frame works from one culled scene per output, culled again only
+        // when timed commits due by the time the frame is shown change them
+        let mut cullings = self.cull_outputs();
+        if self.release_commit_timers(output, &cullings) {
+            cullings = self.cull_outputs();
+        }
+        let unmapped = Culling::default();
+        let culling = cullings.iter().find(|(other, _)| other == output).map_or(&unmapped, |(_, culling)| culling);
+        self.damage_frame(output, culling);
+        if self.renderer.is_some() {
+            if let Err(e) = self.surface_manager.update_frame(culling) {
+                warn!("Failed to pass frame to renderer: {}", e);
+            }
+        }
+
+        let stats = culling.stats;
+        trace!(
+            "{} of {} surfaces occluded on {}, {} partially, {} pixels culled",
+            stats.occluded,
+            stats.surfaces,
+            output.name(),
+            stats.partially_occluded,
+            stats.culled_pixels
+        );
+        let elements = culling.visible_elements(self.render_elements(output));
+        let time = Duration::from(self.clock.now());
+        let mut surfaces: Vec<WlSurface> = Vec::new();
+        for element in &elements {
+            let root = root_surface(&element.surface);
+            if !surfaces.contains(&root) {
+                surfaces.push(root);
+            }
+        }
+
+        // Feedback covers whole trees, which are presented together
+        let mut feedback = Vec::new();
+        for surface in &surfaces {
+            feedback.append(&mut take_feedback(surface));
+        }
+        for element in &elements {
+            send_frame_callbacks(&element.surface, time);
+        }
+        match self.frame_state.pending.iter_mut().find(|frame| &frame.output == output) {
+            Some(frame) => {
+                frame.surfaces.extend(surfaces);
+                frame.feedback.append(&mut feedback);
+            }
+            None => self.frame_state.pending.push(PendingFrame { output: output.clone(), surfaces, feedback }),
+        }
+
+        // Content that no output shows will not be presented
+        let shown_elsewhere: Vec<WlSurface> = cullings
+            .iter()
+            .filter(|(other, _)| other != output)
+            .flat_map(|(other, culling)| culling.visible_elements(self.render_elements(other)))
+            .map(|element| element.surface)
+            .collect();
+        self.frame_state.feedback_surfaces.retain(|surface| {
+            if !surface.alive() || elements.iter().any(|element| &element.surface == surface) {
+                return false;
+            }
+            if shown_elsewhere.contains(surface) {
+                return true;
+            }
+            for callback in take_feedback(surface) {
+                callback.discarded();
+            }
+            false
+        });
+        self.log_frame_decisions(output, &elements);
+        elements
+    }
+
+    /// Report the last frame built for an output as shown on screen
+    ///
+    /// `time` is the presentation time on the monotonic clock and `sequence`
+    /// the vblank counter of the display, counted per output when the backend
+    /// has none.
+    pub fn frame_presented(
+        &mut self,
+        output: &Output,
+        time: Duration,
+        sequence: Option<u64>,
+        flags: wp_presentation_feedback::Kind,
+    ) {
+        self.record_frame_presented(output, sequence, flags);
+        let sequence = match self.frame_state.presented.iter_mut().find(|frame| &frame.output == output) {
+            Some(frame) => {
+                frame.sequence = sequence.unwrap_or(frame.sequence + 1);
+                frame.time = time;
+                frame.sequence
+            }
+            None => {
+                let sequence = sequence.unwrap_or(1);
+                self.frame_state.presented.push(PresentedFrame { output: output.clone(), sequence, time });
+                sequence
+            }
+        };
+        let refresh = refresh_interval(output).map_or(Refresh::Unknown, Refresh::fixed);
+
+        let frame = match self.frame_state.pending.iter().position(|frame| &frame.output == output) {
+            Some(index) => self.frame_state.pending.remove(index),
+            None => PendingFrame { output: output.clone(), surfaces: Vec::new(), feedback: Vec::new() },
+        };
+        trace!("Frame {} presented with {} feedback", sequence, frame.feedback.len());
+        for callback in frame.feedback {
+            callback.presented(output, time, refresh, sequence, flags);
+        }
+        self.release_fifo_barriers(&frame.surfaces);
+    }
+
+    /// Expected presentation time of the next frame of an output
+    ///
+    /// One refresh interval after the last presented frame, or now when the
+    /// output has been idle for longer.
+    pub fn next_presentation_time(&self, output: &Output) -> Duration {
+        let now = Duration::from(self.clock.now());
+        let last = self.frame_state.presented.iter().find(|frame| &frame.output == output);
+        match (last, refresh_interval(output)) {
+            (Some(frame), Some(refresh)) => (frame.time + refresh).max(now),
+            _ => now,
+        }
+    }
+
+    /// Most demanding content type shown on an output
+    ///
+    /// Input for frame scheduling policy: game content favours presenting as
+    /// early as possible, video a steady cadence matching its frame rate.
+    pub fn frame_content_type(&self, output: &Output) -> ContentType {
+        self.visible_elements(output)
+            .iter()
+            .map(|element| surface_content_type(&element.surface))
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// Pace frames of all mapped outputs and of outputs mapped later by frame clocks
+    pub fn start_frame_clocks(&mut self) -> Result<()> {
+        self.frame_state.clocked = true;
+        let outputs: Vec<Output> = self.space.outputs().cloned().collect();
+        for output in &outputs {
+            self.start_frame_clock(output)?;
+        }
+        Ok(())
+    }
+
+    /// Whether a frame clock presents frames of an output
+    pub fn has_frame_clock(&self, output: &Output) -> bool {
+        self.frame_state.clocks.contains(output)
+    }
+
+    /// Start the frame clock of an output just mapped, when frames are paced by clocks
+    pub(crate) fn resume_frame_clock(&mut self, output: &Output) {
+        if !self.frame_state.clocked {
+            return;
+        }
+        if let Err(e) = self.start_frame_clock(output) {
+            warn!("Failed to resume frame clock of {}: {}", output.name(), e);
+        }
+    }
+
+    /// Present frames of an output from a timer at its refresh rate
+    ///
+    /// Stands in for vblank on outputs without a display. The timer stops once
+    /// the output leaves the space, and skips frames while a recording is
+    /// replayed, which brings its own frames. Nothing is started when the
+    /// output already has a clock, one that has not noticed yet that the
+    /// output left and came back included.
+    pub fn start_frame_clock(&mut self, output: &Output) -> Result<()> {
+        if self.has_frame_clock(output) {
+            return Ok(());
+        }
+        let interval = refresh_interval(output).unwrap_or(DEFAULT_REFRESH_INTERVAL);
+        let clock_output = output.clone();
+        self.loop_handle
+            .insert_source(Timer::from_duration(interval), move |_, _, state| {
+                let output = &clock_output;
+                if !state.space.outputs().any(|other| other == output) {
+                    debug!("Stopping frame clock of removed output {}", output.name());
+                    state.frame_state.clocks.retain(|other| other != output);
+                    return TimeoutAction::Drop;
+                }
+                if !state.replay_state.is_replaying() {
+                    state.render_frame(output);
+                    let time = Duration::from(state.clock.now());
+                    state.frame_presented(output, time, None, wp_presentation_feedback::Kind::Vsync);
+                }
+                TimeoutAction::ToDuration(refresh_interval(output).unwrap_or(DEFAULT_REFRESH_INTERVAL))
+            })
+            .map_err(|e| CompositorError::runtime(format!("Failed to start frame clock: {}", e)))?;
+        self.frame_state.clocks.push(output.clone());
+        Ok(())
+    }
+}
@@ -0,0 +1,191 @@
+// Gestures - Touchpad gesture bindings and forwarding
+//
+// Swipes with a finger count bound in the configuration drive compositor
+// actions, switching workspaces or opening the overview while following the
+// fingers. A bound swipe is consumed as a whole, from begin to end, so clients
+// never see part of it. All other swipes, pinches and holds are forwarded to
+// the client under the pointer of the seat the touchpad belongs to, through
+// zwp_pointer_gestures_v1.
+//
+// Touchscreen gestures are out of scope: the compositor handles no touch
+// input yet, so there is nothing to recognize them from.
+
+use compositor_utils::prelude::*;
+use config::GestureAction;
+use smithay::{
+    backend::input::{
+        Event, GestureBeginEvent as _, GestureEndEvent as _, GesturePinchUpdateEvent as _,
+        GestureSwipeUpdateEvent as _, InputBackend, InputEvent,
+    },
+    input::{
+        pointer::{
+            GestureHoldBeginEvent, GestureHoldEndEvent, GesturePinchBeginEvent, GesturePinchEndEvent,
+            GesturePinchUpdateEvent, GestureSwipeBeginEvent, GestureSwipeEndEvent, GestureSwipeUpdateEvent,
+        },
+        Seat,
+    },
+    utils::{Logical, Point, SERIAL_COUNTER},
+};
+
+use crate::wayland::WaylandServerState;
+
+/// Swipe consumed by the compositor
+#[derive(Debug, Clone, Copy)]
+struct BoundSwipe {
+    action: GestureAction,
+    /// Distance travelled by the fingers since the swipe began
+    delta: Point<f64, Logical>,
+    overview_was_open: bool,
+}
+
+/// Bound gesture in progress, on any seat
+#[derive(Debug, Default)]
+pub struct GestureState {
+    swipe: Option<BoundSwipe>,
+}
+
+impl GestureState {
+    /// Action of the swipe the compositor is consuming
+    pub fn bound_swipe(&self) -> Option<GestureAction> {
+        self.swipe.map(|swipe| swipe.action)
+    }
+}
+
+impl WaylandServerState {
+    /// Route gesture events from an input backend to the default seat, ignoring other events
+    pub fn process_gesture_event<B: InputBackend>(&mut self, event: InputEvent<B>) {
+        let seat = self.seat.clone();
+        match event {
+            InputEvent::GestureSwipeBegin { event } => self.gesture_swipe_begin(&seat, GestureSwipeBeginEvent {
+                serial: SERIAL_COUNTER.next_serial(),
+                time: event.time_msec(),
+                fingers: event.fingers(),
+            }),
+            InputEvent::GestureSwipeUpdate { event } => self.gesture_swipe_update(&seat, GestureSwipeUpdateEvent {
+                time: event.time_msec(),
+                delta: event.delta(),
+            }),
+            InputEvent::GestureSwipeEnd { event } => self.gesture_swipe_end(&seat, GestureSwipeEndEvent {
+                serial: SERIAL_COUNTER.next_serial(),
+                time: event.time_msec(),
+                cancelled: event.cancelled(),
+            }),
+            InputEvent::GesturePinchBegin { event } => self.gesture_pinch_begin(&seat, GesturePinchBeginEvent {
+                serial: SERIAL_COUNTER.next_serial(),
+                time: event.time_msec(),
+                fingers: event.fingers(),
+            }),
+            InputEvent::GesturePinchUpdate { event } => self.gesture_pinch_update(&seat, GesturePinchUpdateEvent {
+                time: event.time_msec(),
+                delta: event.delta(),
+                scale: event.scale(),
+                rotation: event.rotation(),
+            }),
+            InputEvent::GesturePinchEnd { event } => self.gesture_pinch_end(&seat, GesturePinchEndEvent {
+                serial: SERIAL_COUNTER.next_serial(),
+                time: event.time_msec(),
+                cancelled: event.cancelled(),
+            }),
+            InputEvent::GestureHoldBegin { event } => self.gesture_hold_begin(&seat, GestureHoldBeginEvent {
+                serial: SERIAL_COUNTER.next_serial(),
+                time: event.time_msec(),
+                fingers: event.fingers(),
+            }),
+            InputEvent::GestureHoldEnd { event } => self.gesture_hold_end(&seat, GestureHoldEndEvent {
+                serial: SERIAL_COUNTER.next_serial(),
+                time: event.time_msec(),
+                cancelled: event.cancelled(),
+            }),
+            _ => {}
+        }
+    }
+
+    /// Start a swipe, consuming it if its finger count is bound
+    pub fn gesture_swipe_begin(&mut self, seat: &Seat<Self>, event: GestureSwipeBeginEvent) {
+        self.gesture_state.swipe = None;
+        if let Some(action) = self.config.gestures.swipe_action(event.fingers) {
+            debug!("{} finger swipe bound to {:?}", event.fingers, action);
+            // A new swipe takes over from animations still running
+            self.settle_transitions(true);
+            self.gesture_state.swipe = Some(BoundSwipe {
+                action,
+                delta: Point::default(),
+                overview_was_open: self.workspace_state.is_overview_open(),
+            });
+            return;
+        }
+
+        if let Some(pointer) = seat.get_pointer() {
+            pointer.gesture_swipe_begin(self, &event);
+        }
+    }
+
+    /// Move the fingers of a swipe
+    pub fn gesture_swipe_update(&mut self, seat: &Seat<Self>, event: GestureSwipeUpdateEvent) {
+        let Some(swipe) = self.gesture_state.swipe.as_mut() else {
+            if let Some(pointer) = seat.get_pointer() {
+                pointer.gesture_swipe_update(self, &event);
+            }
+            return;
+        };
+
+        swipe.delta += event.delta;
+        let swipe = *swipe;
+        let distance = self.config.gestures.swipe_distance;
+        match swipe.action {
+            GestureAction::SwitchWorkspace => self.track_workspace_switch(swipe.delta.x / distance),
+            GestureAction::Overview => {
+                // Swiping up opens the overview, swiping down closes it
+                let base = if swipe.overview_was_open { 1.0 } else { 0.0 };
+                self.track_overview(base - swipe.delta.y / distance);
+            }
+        }
+    }
+
+    /// End a swipe, completing or reverting the action of a bound swipe
+    pub fn gesture_swipe_end(&mut self, seat: &Seat<Self>, event: GestureSwipeEndEvent) {
+        if self.gesture_state.swipe.take().is_some() {
+            self.release_transitions(event.cancelled);
+            return;
+        }
+
+        if let Some(pointer) = seat.get_pointer() {
+            pointer.gesture_swipe_end(self, &event);
+        }
+    }
+
+    /// Start a pinch
+    pub fn gesture_pinch_begin(&mut self, seat: &Seat<Self>, event: GesturePinchBeginEvent) {
+        if let Some(pointer) = seat.get_pointer() {
+            pointer.gesture_pinch_begin(self, &event);
+        }
+    }
+
+    /// Move the fingers of a pinch
+    pub fn gesture_pinch_update(&mut self, seat: &Seat<Self>, event: GesturePinchUpdateEvent) {
+        if let Some(pointer) = seat.get_pointer() {
+            pointer.gesture_pinch_update(self, &event);
+        }
+    }
+
+    /// End a pinch
+    pub fn gesture_pinch_end(&mut self, seat: &Seat<Self>, event: GesturePinchEndEvent) {
+        if let Some(pointer) = seat.get_pointer() {
+            pointer.gesture_pinch_end(self, &event);
+        }
+    }
+
+    /// Start holding fingers still on the touchpad
+    pub fn gesture_hold_begin(&mut self, seat: &Seat<Self>, event: GestureHoldBeginEvent) {
+        if let Some(pointer) = seat.get_pointer() {
+            pointer.gesture_hold_begin(self, &event);
+        }
+    }
+
+    /// End a hold
+    pub fn gesture_hold_end(&mut self, seat: &Seat<Self>, event: GestureHoldEndEvent) {
+        if let Some(pointer) = seat.get_pointer() {
+            pointer.gesture_hold_end(self, &event);
+        }
+    }
+}
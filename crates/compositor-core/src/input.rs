@@ -1,2 +1,380 @@
-// Input handling placeholder
+// Input - Keyboard, pointer and gesture events from input backends
+//
+// Backend events are first turned into `BackendInput`, which carries what the
+// compositor acts on without the backend's types, and then applied to the
+// seat of the device they come from, see seats.rs. Keys go to the surface
+// focused on the seat, pointer motion moves its pointer over the outputs and
+// focuses the surface under it, and pressing a button on a window gives it the
+// keyboard focus of the seat and raises it. Gestures go
+// through gestures.rs and tablet events through tablet.rs. The layout switch
+// binding is taken from keys before clients see them, see keyboard.rs.
+//
+// Going through `BackendInput` lets recordings capture input as it is applied
+// and replays apply it again the same way, see replay.rs. Event times come
+// from the compositor clock rather than the backend, so replays hand clients
+// the same timestamps as the virtual clock they run on.
+
+use compositor_utils::prelude::*;
+use ipc::protocol::KeyboardLayoutSwitch;
+use serde::{Deserialize, Serialize};
+use smithay::{
+    backend::input::{
+        AbsolutePositionEvent, Axis, AxisRelativeDirection, AxisSource, ButtonState, Event, GestureBeginEvent as _,
+        GestureEndEvent as _, GesturePinchUpdateEvent as _, GestureSwipeUpdateEvent as _, InputBackend, InputEvent,
+        KeyState, KeyboardKeyEvent, PointerAxisEvent, PointerButtonEvent, PointerMotionEvent,
+    },
+    input::{
+        keyboard::{FilterResult, Keycode},
+        pointer::{
+            AxisFrame, ButtonEvent, GestureHoldBeginEvent, GestureHoldEndEvent, GesturePinchBeginEvent,
+            GesturePinchEndEvent, GesturePinchUpdateEvent, GestureSwipeBeginEvent, GestureSwipeEndEvent,
+            GestureSwipeUpdateEvent, MotionEvent, RelativeMotionEvent,
+        },
+        Seat,
+    },
+    utils::{Logical, Point, Rectangle, SERIAL_COUNTER},
+};
+use std::time::Duration;
+
+use crate::frame::root_surface;
+use crate::wayland::WaylandServerState;
+
 pub use crate::window::input::*;
+
+/// Source of scroll events
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ScrollSource {
+    Wheel,
+    Finger,
+    Continuous,
+    WheelTilt,
+}
+
+impl From<AxisSource> for ScrollSource {
+    fn from(source: AxisSource) -> Self {
+        match source {
+            AxisSource::Finger => Self::Finger,
+            AxisSource::Continuous => Self::Continuous,
+            AxisSource::WheelTilt => Self::WheelTilt,
+            _ => Self::Wheel,
+        }
+    }
+}
+
+impl From<ScrollSource> for AxisSource {
+    fn from(source: ScrollSource) -> Self {
+        match source {
+            ScrollSource::Wheel => Self::Wheel,
+            ScrollSource::Finger => Self::Finger,
+            ScrollSource::Continuous => Self::Continuous,
+            ScrollSource::WheelTilt => Self::WheelTilt,
+        }
+    }
+}
+
+/// Input event of a backend, independent of the backend
+///
+/// Pairs are horizontal and vertical components. Absolute pointer positions
+/// are in global logical coordinates.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum BackendInput {
+    Key { keycode: u32, pressed: bool },
+    PointerMotion { delta: (f64, f64), delta_unaccel: (f64, f64) },
+    PointerMotionAbsolute { location: (f64, f64) },
+    PointerButton { button: u32, pressed: bool },
+    PointerAxis {
+        source: ScrollSource,
+        amount: (f64, f64),
+        /// Wheel clicks in 120ths of a click, when the source has clicks
+        v120: Option<(i32, i32)>,
+        /// Scrolling stopped on an axis, for finger scrolling
+        stop: (bool, bool),
+        /// Scrolling is inverted on an axis, as with natural scrolling
+        inverted: (bool, bool),
+    },
+    GestureSwipeBegin { fingers: u32 },
+    GestureSwipeUpdate { delta: (f64, f64) },
+    GestureSwipeEnd { cancelled: bool },
+    GesturePinchBegin { fingers: u32 },
+    GesturePinchUpdate { delta: (f64, f64), scale: f64, rotation: f64 },
+    GesturePinchEnd { cancelled: bool },
+    GestureHoldBegin { fingers: u32 },
+    GestureHoldEnd { cancelled: bool },
+}
+
+fn point((x, y): (f64, f64)) -> Point<f64, Logical> {
+    Point::from((x, y))
+}
+
+fn pair(point: Point<f64, Logical>) -> (f64, f64) {
+    (point.x, point.y)
+}
+
+fn relative_direction(inverted: bool) -> AxisRelativeDirection {
+    if inverted {
+        AxisRelativeDirection::Inverted
+    } else {
+        AxisRelativeDirection::Identical
+    }
+}
+
+impl WaylandServerState {
+    /// Route an event from an input backend to the seat of its device
+    pub fn process_input_event<B: InputBackend>(&mut self, event: InputEvent<B>) {
+        let (device, input) = match event {
+            InputEvent::DeviceAdded { device } => {
+                self.seat_for_device(&device);
+                self.process_tablet_event(InputEvent::<B>::DeviceAdded { device });
+                return;
+            }
+            InputEvent::DeviceRemoved { device } => {
+                self.forget_device(&device);
+                self.process_tablet_event(InputEvent::<B>::DeviceRemoved { device });
+                return;
+            }
+            InputEvent::Keyboard { event } => (
+                event.device(),
+                BackendInput::Key { keycode: event.key_code().raw(), pressed: event.state() == KeyState::Pressed },
+            ),
+            InputEvent::PointerMotion { event } => (
+                event.device(),
+                BackendInput::PointerMotion { delta: pair(event.delta()), delta_unaccel: pair(event.delta_unaccel()) },
+            ),
+            InputEvent::PointerMotionAbsolute { event } => {
+                let Some(bounds) = self.output_bounds() else {
+                    return;
+                };
+                let location = pair(bounds.loc.to_f64() + event.position_transformed(bounds.size));
+                (event.device(), BackendInput::PointerMotionAbsolute { location })
+            }
+            InputEvent::PointerButton { event } => (
+                event.device(),
+                BackendInput::PointerButton {
+                    button: event.button_code(),
+                    pressed: event.state() == ButtonState::Pressed,
+                },
+            ),
+            InputEvent::PointerAxis { event } => {
+                // Wheels without continuous amounts scroll 15 units per click
+                let amount = |axis| {
+                    event
+                        .amount(axis)
+                        .unwrap_or_else(|| event.amount_v120(axis).unwrap_or(0.0) * 15.0 / 120.0)
+                };
+                let v120 = |axis| event.amount_v120(axis).map(|v120| v120 as i32);
+                let finger = event.source() == AxisSource::Finger;
+                let stop = |axis| finger && event.amount(axis) == Some(0.0);
+                let inverted = |axis| event.relative_direction(axis) == AxisRelativeDirection::Inverted;
+                let input = BackendInput::PointerAxis {
+                    source: event.source().into(),
+                    amount: (amount(Axis::Horizontal), amount(Axis::Vertical)),
+                    v120: match (v120(Axis::Horizontal), v120(Axis::Vertical)) {
+                        (None, None) => None,
+                        (horizontal, vertical) => Some((horizontal.unwrap_or(0), vertical.unwrap_or(0))),
+                    },
+                    stop: (stop(Axis::Horizontal), stop(Axis::Vertical)),
+                    inverted: (inverted(Axis::Horizontal), inverted(Axis::Vertical)),
+                };
+                (event.device(), input)
+            }
+            InputEvent::GestureSwipeBegin { event } => {
+                (event.device(), BackendInput::GestureSwipeBegin { fingers: event.fingers() })
+            }
+            InputEvent::GestureSwipeUpdate { event } => {
+                (event.device(), BackendInput::GestureSwipeUpdate { delta: pair(event.delta()) })
+            }
+            InputEvent::GestureSwipeEnd { event } => {
+                (event.device(), BackendInput::GestureSwipeEnd { cancelled: event.cancelled() })
+            }
+            InputEvent::GesturePinchBegin { event } => {
+                (event.device(), BackendInput::GesturePinchBegin { fingers: event.fingers() })
+            }
+            InputEvent::GesturePinchUpdate { event } => (
+                event.device(),
+                BackendInput::GesturePinchUpdate {
+                    delta: pair(event.delta()),
+                    scale: event.scale(),
+                    rotation: event.rotation(),
+                },
+            ),
+            InputEvent::GesturePinchEnd { event } => {
+                (event.device(), BackendInput::GesturePinchEnd { cancelled: event.cancelled() })
+            }
+            InputEvent::GestureHoldBegin { event } => {
+                (event.device(), BackendInput::GestureHoldBegin { fingers: event.fingers() })
+            }
+            InputEvent::GestureHoldEnd { event } => {
+                (event.device(), BackendInput::GestureHoldEnd { cancelled: event.cancelled() })
+            }
+            event => {
+                // Tablets are not recorded, their tools are described by the backend
+                self.process_tablet_event(event);
+                return;
+            }
+        };
+        let seat = self.seat_for_device(&device);
+        self.apply_seat_input(&seat, input);
+    }
+
+    /// Apply an input event to the default seat
+    pub fn apply_input(&mut self, input: BackendInput) {
+        let seat = self.seat.clone();
+        self.apply_seat_input(&seat, input);
+    }
+
+    /// Apply an input event to a seat
+    ///
+    /// Input is recorded here, as it is applied.
+    pub fn apply_seat_input(&mut self, seat: &Seat<Self>, input: BackendInput) {
+        self.record_input(seat, &input);
+        let time = self.clock.now().as_millis();
+        match input {
+            BackendInput::Key { keycode, pressed } => {
+                let Some(keyboard) = seat.get_keyboard() else {
+                    return;
+                };
+                let state = if pressed { KeyState::Pressed } else { KeyState::Released };
+                let switch_layout = keyboard.input::<bool, _>(
+                    self,
+                    Keycode::new(keycode),
+                    state,
+                    SERIAL_COUNTER.next_serial(),
+                    time,
+                    |state, modifiers, keysym| {
+                        if state.keyboard_state.intercepts_key(seat.name(), pressed, modifiers, &keysym) {
+                            FilterResult::Intercept(pressed)
+                        } else {
+                            FilterResult::Forward
+                        }
+                    },
+                );
+                if switch_layout == Some(true) {
+                    if let Err(e) = self.switch_keyboard_layout(seat, KeyboardLayoutSwitch::Next) {
+                        warn!("Failed to switch keyboard layout: {}", e);
+                    }
+                }
+            }
+            BackendInput::PointerMotion { delta, delta_unaccel } => {
+                let Some(pointer) = seat.get_pointer() else {
+                    return;
+                };
+                let location = pointer.current_location() + point(delta);
+                self.pointer_motion(seat, location, Some((point(delta), point(delta_unaccel))), time);
+            }
+            BackendInput::PointerMotionAbsolute { location } => self.pointer_motion(seat, point(location), None, time),
+            BackendInput::PointerButton { button, pressed } => {
+                let Some(pointer) = seat.get_pointer() else {
+                    return;
+                };
+                // Clicking a window focuses it, unless a grab such as a drag holds the pointer
+                if pressed && !pointer.is_grabbed() {
+                    let location = pointer.current_location();
+                    let window = match self.surface_under(location) {
+                        Some((surface, _)) => self.window_for_surface(&root_surface(&surface)),
+                        // Nothing takes input over a window blocked by a dialog, clicking it focuses the dialog
+                        None => self.space.element_under(location).map(|(window, _)| window.clone()),
+                    };
+                    if let Some(window) = window {
+                        self.seat_focus_window(seat, &window);
+                    }
+                }
+                let state = if pressed { ButtonState::Pressed } else { ButtonState::Released };
+                let serial = SERIAL_COUNTER.next_serial();
+                pointer.button(self, &ButtonEvent { serial, time, button, state });
+                pointer.frame(self);
+            }
+            BackendInput::PointerAxis { source, amount, v120, stop, inverted } => {
+                let Some(pointer) = seat.get_pointer() else {
+                    return;
+                };
+                let mut frame = AxisFrame::new(time).source(source.into());
+                for (axis, amount, v120, stop, inverted) in [
+                    (Axis::Horizontal, amount.0, v120.map(|v120| v120.0), stop.0, inverted.0),
+                    (Axis::Vertical, amount.1, v120.map(|v120| v120.1), stop.1, inverted.1),
+                ] {
+                    if amount != 0.0 {
+                        frame = frame.relative_direction(axis, relative_direction(inverted)).value(axis, amount);
+                        if let Some(v120) = v120.filter(|v120| *v120 != 0) {
+                            frame = frame.v120(axis, v120);
+                        }
+                    }
+                    if stop {
+                        frame = frame.stop(axis);
+                    }
+                }
+                pointer.axis(self, frame);
+                pointer.frame(self);
+            }
+            BackendInput::GestureSwipeBegin { fingers } => self.gesture_swipe_begin(seat, GestureSwipeBeginEvent {
+                serial: SERIAL_COUNTER.next_serial(),
+                time,
+                fingers,
+            }),
+            BackendInput::GestureSwipeUpdate { delta } => {
+                self.gesture_swipe_update(seat, GestureSwipeUpdateEvent { time, delta: point(delta) })
+            }
+            BackendInput::GestureSwipeEnd { cancelled } => self.gesture_swipe_end(seat, GestureSwipeEndEvent {
+                serial: SERIAL_COUNTER.next_serial(),
+                time,
+                cancelled,
+            }),
+            BackendInput::GesturePinchBegin { fingers } => self.gesture_pinch_begin(seat, GesturePinchBeginEvent {
+                serial: SERIAL_COUNTER.next_serial(),
+                time,
+                fingers,
+            }),
+            BackendInput::GesturePinchUpdate { delta, scale, rotation } => {
+                self.gesture_pinch_update(seat, GesturePinchUpdateEvent { time, delta: point(delta), scale, rotation })
+            }
+            BackendInput::GesturePinchEnd { cancelled } => self.gesture_pinch_end(seat, GesturePinchEndEvent {
+                serial: SERIAL_COUNTER.next_serial(),
+                time,
+                cancelled,
+            }),
+            BackendInput::GestureHoldBegin { fingers } => self.gesture_hold_begin(seat, GestureHoldBeginEvent {
+                serial: SERIAL_COUNTER.next_serial(),
+                time,
+                fingers,
+            }),
+            BackendInput::GestureHoldEnd { cancelled } => self.gesture_hold_end(seat, GestureHoldEndEvent {
+                serial: SERIAL_COUNTER.next_serial(),
+                time,
+                cancelled,
+            }),
+        }
+    }
+
+    /// Area covered by all outputs
+    fn output_bounds(&self) -> Option<Rectangle<i32, Logical>> {
+        self.space
+            .outputs()
+            .filter_map(|output| self.space.output_geometry(output))
+            .reduce(|bounds, geometry| bounds.merge(geometry))
+    }
+
+    /// Move the pointer of a seat, kept within the outputs, and focus the surface under it
+    fn pointer_motion(
+        &mut self,
+        seat: &Seat<Self>,
+        location: Point<f64, Logical>,
+        relative: Option<(Point<f64, Logical>, Point<f64, Logical>)>,
+        time: u32,
+    ) {
+        let (Some(pointer), Some(bounds)) = (seat.get_pointer(), self.output_bounds()) else {
+            return;
+        };
+        let bounds = bounds.to_f64();
+        let location = Point::from((
+            location.x.clamp(bounds.loc.x, bounds.loc.x + bounds.size.w - 1.0),
+            location.y.clamp(bounds.loc.y, bounds.loc.y + bounds.size.h - 1.0),
+        ));
+
+        let focus = self.surface_under(location);
+        pointer.motion(self, focus.clone(), &MotionEvent { location, serial: SERIAL_COUNTER.next_serial(), time });
+        if let Some((delta, delta_unaccel)) = relative {
+            let utime = Duration::from(self.clock.now()).as_micros() as u64;
+            pointer.relative_motion(self, focus, &RelativeMotionEvent { delta, delta_unaccel, utime });
+        }
+        pointer.frame(self);
+    }
+}
@@ -0,0 +1,95 @@
+// Input Method - IME popup placement
+//
+// Input methods such as fcitx5 and ibus show their candidate windows through
+// `zwp_input_popup_surface_v2`. smithay attaches each popup to the surface
+// with text input focus; the popup is placed just below the text cursor
+// rectangle reported over `zwp_text_input_v3`, flipped above or to the left
+// of the cursor when it would leave the output, and drawn above all other
+// surfaces.
+//
+// Positions are computed whenever the scene is built, so popups follow their
+// parent when it moves and the candidate list when it grows.
+
+use smithay::{
+    desktop::utils::bbox_from_surface_tree,
+    utils::{Logical, Point, Rectangle, Size},
+    wayland::input_method::PopupSurface,
+};
+
+use crate::wayland::WaylandServerState;
+
+/// Place a popup of `size` next to the text cursor, keeping it inside `bounds`
+///
+/// The popup opens below the cursor and left-aligned with it. It flips above
+/// the cursor when there is no room below, and right-aligns with the cursor
+/// when there is no room to the right. If it fits neither way it is pushed
+/// back inside `bounds`.
+pub fn place_popup(
+    cursor: Rectangle<i32, Logical>,
+    size: Size<i32, Logical>,
+    bounds: Rectangle<i32, Logical>,
+) -> Point<i32, Logical> {
+    let right = bounds.loc.x + bounds.size.w;
+    let bottom = bounds.loc.y + bounds.size.h;
+
+    let mut x = cursor.loc.x;
+    if x + size.w > right {
+        x = cursor.loc.x + cursor.size.w - size.w;
+    }
+
+    let mut y = cursor.loc.y + cursor.size.h;
+    if y + size.h > bottom && cursor.loc.y - size.h >= bounds.loc.y {
+        y = cursor.loc.y - size.h;
+    }
+
+    Point::from((
+        x.min(right - size.w).max(bounds.loc.x),
+        y.min(bottom - size.h).max(bounds.loc.y),
+    ))
+}
+
+impl WaylandServerState {
+    /// Global geometry of an input method popup, if its parent is on screen
+    pub fn input_method_popup_geometry(&self, popup: &PopupSurface) -> Option<Rectangle<i32, Logical>> {
+        let parent = popup.get_parent()?;
+        let parent_geometry = self.surface_geometry(&parent.surface)?;
+
+        let mut cursor = popup.text_input_rectangle();
+        cursor.loc += parent_geometry.loc;
+        let size = bbox_from_surface_tree(popup.wl_surface(), (0, 0)).size;
+
+        // Keep the popup on the output showing the text cursor
+        let bounds = self
+            .space
+            .outputs()
+            .filter_map(|output| self.space.output_geometry(output))
+            .find(|geometry| geometry.contains(cursor.loc))
+            .or_else(|| self.space.outputs().next().and_then(|output| self.space.output_geometry(output)))?;
+
+        Some(Rectangle::new(place_popup(cursor, size, bounds), size))
+    }
+
+    /// Track a popup that gained a parent surface
+    pub(crate) fn input_method_popup_mapped(&mut self, popup: PopupSurface) {
+        self.input_method_popups.retain(|tracked| tracked != &popup);
+        self.input_method_popups.push(popup);
+    }
+
+    /// Stop drawing a popup whose input method was deactivated
+    pub(crate) fn input_method_popup_dismissed(&mut self, popup: &PopupSurface) {
+        self.input_method_popups.retain(|tracked| tracked != popup);
+    }
+
+    /// Take the latest text cursor rectangle of a tracked popup
+    pub(crate) fn input_method_popup_repositioned(&mut self, popup: PopupSurface) {
+        if let Some(tracked) = self.input_method_popups.iter_mut().find(|tracked| **tracked == popup) {
+            *tracked = popup;
+        }
+    }
+
+    /// Forget popups destroyed by their input method
+    pub(crate) fn refresh_input_method_popups(&mut self) {
+        self.input_method_popups.retain(PopupSurface::alive);
+    }
+}
+
@@ -0,0 +1,319 @@
+// IPC Server - Compositor control socket
+//
+// Serves `ipc::protocol::IPCMessage` requests from desktop components over a
+// Unix socket driven by the compositor event loop, so requests are answered
+// with direct access to the compositor state.
+//
+// Messages are bincode encoded and framed with a 4-byte big-endian length
+// prefix, matching the default `LengthDelimitedCodec` framing used by the ipc
+// crate. Every request receives exactly one response, in order.
+
+use compositor_utils::prelude::*;
+use ipc::protocol::{IPCMessage, IconImageData, OutputOcclusionInfo, ProtocolHandler, WindowGeometry, WindowSummary};
+use smithay::{
+    desktop::Window,
+    reexports::calloop::{generic::Generic, Interest, LoopHandle, Mode, PostAction},
+    wayland::{compositor::with_states, shell::xdg::XdgToplevelSurfaceData},
+};
+use std::io::{ErrorKind, Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::protocol_trace::TraceFilter;
+use crate::wayland::{WaylandServer, WaylandServerState};
+use crate::workspace::{window_id, WindowId};
+
+/// Environment variable advertising the control socket to child processes
+pub const IPC_SOCKET_ENV: &str = "COMPOSITOR_IPC_SOCKET";
+
+/// Largest accepted request frame
+const MAX_FRAME_SIZE: usize = 8 * 1024 * 1024;
+
+/// How long a response write may block on a client that stopped reading
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Default control socket path for a Wayland socket name
+pub fn default_socket_path(wayland_socket: &str) -> PathBuf {
+    std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir)
+        .join(format!("{}-ipc.sock", wayland_socket))
+}
+
+/// Title and app_id of a window, empty when unset
+pub(crate) fn window_title_and_app_id(window: &Window) -> (String, String) {
+    let Some(toplevel) = window.toplevel() else {
+        return Default::default();
+    };
+    with_states(toplevel.wl_surface(), |states| {
+        states
+            .data_map
+            .get::<XdgToplevelSurfaceData>()
+            .map(|data| {
+                let data = data.lock().unwrap();
+                (data.title.clone().unwrap_or_default(), data.app_id.clone().unwrap_or_default())
+            })
+            .unwrap_or_default()
+    })
+}
+
+/// Encode a message as a length-prefixed frame
+pub fn encode_frame(message: &IPCMessage) -> Result<Vec<u8>> {
+    let payload = ProtocolHandler::new().serialize_message(message)?;
+    let mut frame = (payload.len() as u32).to_be_bytes().to_vec();
+    frame.extend_from_slice(&payload);
+    Ok(frame)
+}
+
+impl WaylandServerState {
+    /// Answer a single IPC request
+    pub fn handle_ipc_message(&mut self, message: IPCMessage) -> IPCMessage {
+        match message {
+            IPCMessage::GetStatus => IPCMessage::Status {
+                version: env!("CARGO_PKG_VERSION").to_string(),
+                active_windows: self.space.elements().count() as u32,
+                memory_usage: compositor_utils::memory::get_memory_stats().current_bytes as u64,
+            },
+            IPCMessage::GetClipboardHistory => IPCMessage::ClipboardHistory {
+                entries: self.clipboard_state.history().map(|entry| entry.info()).collect(),
+            },
+            IPCMessage::RestoreClipboardEntry { entry_id } => match self.restore_clipboard_entry(entry_id) {
+                Ok(()) => IPCMessage::ClipboardEntryRestored { entry_id },
+                Err(e) => IPCMessage::Error { message: e.to_string() },
+            },
+            IPCMessage::GetWindowInfo { window_id } => {
+                let Some((_, window)) = self.window_by_id(WindowId(window_id)) else {
+                    return IPCMessage::Error { message: format!("No window {}", window_id) };
+                };
+                let (title, app_id) = window_title_and_app_id(window);
+                let location = self.window_location(window).unwrap_or_default();
+                let size = window.geometry().size;
+                IPCMessage::WindowInfo {
+                    window_id,
+                    title,
+                    app_id,
+                    geometry: WindowGeometry {
+                        x: location.x,
+                        y: location.y,
+                        width: size.w.max(0) as u32,
+                        height: size.h.max(0) as u32,
+                    },
+                    client: self.window_client_identity(window).map(|identity| identity.info()),
+                }
+            }
+            IPCMessage::FocusWindow { window_id } => {
+                let Some((_, window)) = self.window_by_id(WindowId(window_id)) else {
+                    return IPCMessage::Error { message: format!("No window {}", window_id) };
+                };
+                let window = window.clone();
+                if self.space.element_location(&window).is_none() {
+                    return IPCMessage::Error { message: format!("Window {} is on an inactive workspace", window_id) };
+                }
+                // Report the window that took focus, a modal dialog of the requested one
+                let focused = self.focus_window(&window);
+                self.handle_ipc_message(IPCMessage::GetWindowInfo {
+                    window_id: crate::workspace::window_id(&focused).map_or(window_id, |id| id.0),
+                })
+            }
+            IPCMessage::ListWindows => IPCMessage::WindowList {
+                windows: self
+                    .windows()
+                    .filter_map(|(workspace, window)| {
+                        let (title, app_id) = window_title_and_app_id(window);
+                        let icon = self.window_icon(window).unwrap_or_default();
+                        let mut icon_sizes: Vec<u32> = icon.images.iter().map(|image| image.size as u32).collect();
+                        icon_sizes.sort_unstable();
+                        icon_sizes.dedup();
+                        Some(WindowSummary {
+                            window_id: window_id(window)?.0,
+                            title,
+                            app_id,
+                            workspace: workspace as u32,
+                            icon_name: icon.name,
+                            icon_sizes,
+                            urgent: self.is_window_urgent(window),
+                            client: self.window_client_identity(window).map(|identity| identity.info()),
+                        })
+                    })
+                    .collect(),
+            },
+            IPCMessage::GetWindowIcon { window_id, size, scale } => {
+                let Some((_, window)) = self.window_by_id(WindowId(window_id)) else {
+                    return IPCMessage::Error { message: format!("No window {}", window_id) };
+                };
+                let icon = self.window_icon(window).unwrap_or_default();
+                let image = icon.image(size.min(4096) as i32, scale.clamp(1, 16) as i32).map(|image| IconImageData {
+                    size: image.size as u32,
+                    scale: image.scale as u32,
+                    pixels: image.pixels.to_vec(),
+                });
+                IPCMessage::WindowIcon { window_id, icon_name: icon.name, image }
+            }
+            IPCMessage::GetOcclusionStats => IPCMessage::OcclusionStats {
+                outputs: self
+                    .space
+                    .outputs()
+                    .map(|output| {
+                        let stats = self.occlusion_stats(output);
+                        OutputOcclusionInfo {
+                            output: output.name(),
+                            surfaces: stats.surfaces as u32,
+                            occluded: stats.occluded as u32,
+                            partially_occluded: stats.partially_occluded as u32,
+                            drawn_pixels: stats.drawn_pixels,
+                            culled_pixels: stats.culled_pixels,
+                        }
+                    })
+                    .collect(),
+            },
+            IPCMessage::SetProtocolTrace { enabled, client_pid, interfaces } => {
+                let filter = enabled.then(|| TraceFilter {
+                    client_pid: client_pid.map(|pid| pid as i32),
+                    interfaces,
+                });
+                match self.set_protocol_trace(filter) {
+                    Ok(()) => IPCMessage::ProtocolTrace {
+                        enabled,
+                        path: self.protocol_trace_path().display().to_string(),
+                    },
+                    Err(e) => IPCMessage::Error { message: e.to_string() },
+                }
+            }
+            IPCMessage::SetRecording { enabled } => match self.set_recording(enabled) {
+                Ok(()) => IPCMessage::Recording {
+                    enabled,
+                    path: self.recording_path().display().to_string(),
+                },
+                Err(e) => IPCMessage::Error { message: e.to_string() },
+            },
+            IPCMessage::GetKeyboardLayout => self.keyboard_layout_response(),
+            IPCMessage::SwitchKeyboardLayout { layout } => {
+                let seat = self.seat.clone();
+                match self.switch_keyboard_layout(&seat, layout) {
+                    Ok(()) => self.keyboard_layout_response(),
+                    Err(e) => IPCMessage::Error { message: e.to_string() },
+                }
+            }
+            IPCMessage::StartFileDrag { paths } => match self.start_file_drag(&paths) {
+                Ok(()) => IPCMessage::DragStarted,
+                Err(e) => IPCMessage::Error { message: e.to_string() },
+            },
+            _ => IPCMessage::Error {
+                message: "Unsupported message type".to_string(),
+            },
+        }
+    }
+
+    /// Keyboard layouts of the default seat
+    fn keyboard_layout_response(&mut self) -> IPCMessage {
+        let seat = self.seat.clone();
+        match self.keyboard_layouts(&seat) {
+            Some((layouts, active)) => IPCMessage::KeyboardLayout { layouts, active },
+            None => IPCMessage::Error { message: "The default seat has no keyboard".to_string() },
+        }
+    }
+}
+
+impl WaylandServer {
+    /// Listen for IPC connections on the given socket path
+    pub fn start_ipc(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+
+        // Remove a socket left behind by a previous instance
+        if path.exists() {
+            std::fs::remove_file(path)?;
+        }
+
+        let listener = UnixListener::bind(path)
+            .map_err(|e| CompositorError::ipc(format!("Failed to bind {}: {}", path.display(), e)))?;
+        listener.set_nonblocking(true)?;
+
+        let handle = self.event_loop.handle();
+        self.event_loop
+            .handle()
+            .insert_source(Generic::new(listener, Interest::READ, Mode::Level), move |_, listener, _| {
+                loop {
+                    match listener.accept() {
+                        Ok((stream, _)) => insert_connection(&handle, stream),
+                        Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                        Err(e) => {
+                            warn!("Failed to accept IPC connection: {}", e);
+                            break;
+                        }
+                    }
+                }
+                Ok(PostAction::Continue)
+            })
+            .map_err(|e| CompositorError::ipc(format!("Failed to insert IPC socket source: {}", e)))?;
+
+        info!("IPC server listening on: {}", path.display());
+        std::env::set_var(IPC_SOCKET_ENV, path);
+
+        Ok(())
+    }
+}
+
+fn insert_connection(handle: &LoopHandle<'static, WaylandServerState>, stream: UnixStream) {
+    // Reads only happen once the socket is readable; writes may block briefly
+    if let Err(e) = stream.set_write_timeout(Some(WRITE_TIMEOUT)) {
+        warn!("Failed to configure IPC connection: {}", e);
+        return;
+    }
+
+    let mut connection = IpcConnection::default();
+    let inserted = handle.insert_source(Generic::new(stream, Interest::READ, Mode::Level), move |_, stream, state| {
+        Ok(connection.process(stream, state))
+    });
+    if let Err(e) = inserted {
+        warn!("Failed to insert IPC connection source: {}", e);
+    }
+}
+
+/// Buffered request data of one IPC client
+#[derive(Default)]
+struct IpcConnection {
+    buffer: Vec<u8>,
+}
+
+impl IpcConnection {
+    /// Read available data and answer every complete request
+    fn process(&mut self, mut stream: &UnixStream, state: &mut WaylandServerState) -> PostAction {
+        let mut chunk = [0; 4096];
+        match stream.read(&mut chunk) {
+            Ok(0) => return PostAction::Remove,
+            Ok(read) => self.buffer.extend_from_slice(&chunk[..read]),
+            Err(e) if matches!(e.kind(), ErrorKind::Interrupted | ErrorKind::WouldBlock) => return PostAction::Continue,
+            Err(e) => {
+                debug!("IPC connection failed: {}", e);
+                return PostAction::Remove;
+            }
+        }
+
+        let handler = ProtocolHandler::new();
+        while self.buffer.len() >= 4 {
+            let length = u32::from_be_bytes([self.buffer[0], self.buffer[1], self.buffer[2], self.buffer[3]]) as usize;
+            if length > MAX_FRAME_SIZE {
+                warn!("Closing IPC connection after oversized frame of {} bytes", length);
+                return PostAction::Remove;
+            }
+            if self.buffer.len() < 4 + length {
+                break;
+            }
+
+            let response = match handler.deserialize_message(&self.buffer[4..4 + length]) {
+                Ok(message) => state.handle_ipc_message(message),
+                Err(e) => IPCMessage::Error { message: e.to_string() },
+            };
+            self.buffer.drain(..4 + length);
+
+            let written = encode_frame(&response).and_then(|frame| stream.write_all(&frame).map_err(Into::into));
+            if let Err(e) = written {
+                debug!("Failed to send IPC response: {}", e);
+                return PostAction::Remove;
+            }
+        }
+
+        PostAction::Continue
+    }
+}
@@ -0,0 +1,205 @@
+// Keyboard - Keymaps, key repeat and keyboard layout switching
+//
+// The keymap of every seat is compiled from the xkb names of the keyboard
+// configuration, falling back to the xkb defaults when they do not compile.
+// Num lock is turned on at startup when configured, by pressing the key
+// without any client seeing it.
+//
+// Layouts switch with the configured key binding, through IPC, or with xkb
+// group switching options, which xkb handles by itself. The binding matches
+// keys by their symbol in the first layout, so it keeps working whichever
+// layout is active. With per-window layouts, the layout a window had when it
+// lost the focus of a seat is restored when the seat focuses it again, and
+// windows focused for the first time start on the first layout.
+
+use compositor_utils::prelude::*;
+use config::{KeyBinding, KeyboardConfig};
+use ipc::protocol::KeyboardLayoutSwitch;
+use smithay::{
+    backend::input::KeyState,
+    desktop::Window,
+    input::{
+        keyboard::{xkb, FilterResult, Keycode, KeysymHandle, Keysym, Layout, ModifiersState, XkbConfig},
+        Seat,
+    },
+    utils::SERIAL_COUNTER,
+};
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+use crate::wayland::WaylandServerState;
+
+/// Num lock in the evdev keymap
+const NUMLOCK_KEYCODE: u32 = 77;
+
+/// Layout a window had when it last lost the focus of each seat, by seat name
+#[derive(Debug, Default)]
+struct WindowKeyboardLayouts(Mutex<HashMap<String, u32>>);
+
+/// Keyboard layout switching state
+#[derive(Debug, Default)]
+pub struct KeyboardState {
+    /// Binding switching to the next layout, with the keysym of its key
+    switch_binding: Option<(KeyBinding, Keysym)>,
+    /// Keys taken by the binding while pressed, by seat name, so their
+    /// release is taken as well
+    intercepted: HashSet<(String, Keycode)>,
+}
+
+impl KeyboardState {
+    pub(crate) fn new(config: &KeyboardConfig) -> Self {
+        let switch_binding = config.switch_layout.as_deref().and_then(|binding| {
+            let binding: KeyBinding = binding
+                .parse()
+                .map_err(|e| warn!("Ignoring layout switch binding: {}", e))
+                .ok()?;
+            let keysym = xkb::keysym_from_name(&binding.key, xkb::KEYSYM_NO_FLAGS);
+            if keysym == Keysym::NoSymbol {
+                warn!("Ignoring layout switch binding with unknown key {}", binding.key);
+                return None;
+            }
+            Some((binding, keysym))
+        });
+        Self { switch_binding, intercepted: HashSet::new() }
+    }
+
+    /// Whether a key of a seat is kept from clients, being the layout switch
+    /// binding or the release of its key
+    pub(crate) fn intercepts_key(
+        &mut self,
+        seat: &str,
+        pressed: bool,
+        modifiers: &ModifiersState,
+        keysym: &KeysymHandle<'_>,
+    ) -> bool {
+        let key = (seat.to_string(), keysym.raw_code());
+        if !pressed {
+            return self.intercepted.remove(&key);
+        }
+        let intercepted = self.is_switch_binding(modifiers, keysym);
+        if intercepted {
+            self.intercepted.insert(key);
+        }
+        intercepted
+    }
+
+    /// Whether a key pressed with the given modifiers is the layout switch binding
+    ///
+    /// Modifier keys add their own modifier when pressed, so a binding such as
+    /// `Alt+Shift_L` matches when at least its modifiers are held.
+    fn is_switch_binding(&self, modifiers: &ModifiersState, keysym: &KeysymHandle<'_>) -> bool {
+        let Some((binding, bound_keysym)) = &self.switch_binding else {
+            return false;
+        };
+        if keysym.raw_latin_sym_or_raw_current_sym() != Some(*bound_keysym) {
+            return false;
+        }
+        let bound = [binding.ctrl, binding.alt, binding.shift, binding.logo];
+        let held = [modifiers.ctrl, modifiers.alt, modifiers.shift, modifiers.logo];
+        if bound_keysym.is_modifier_key() {
+            bound.iter().zip(held).all(|(&bound, held)| !bound || held)
+        } else {
+            bound == held
+        }
+    }
+}
+
+/// Keymap names of the keyboard configuration
+pub(crate) fn xkb_config(config: &KeyboardConfig) -> XkbConfig<'_> {
+    XkbConfig {
+        rules: &config.rules,
+        model: &config.model,
+        layout: &config.layout,
+        variant: &config.variant,
+        options: config.options.clone(),
+    }
+}
+
+impl WaylandServerState {
+    /// Names of the layouts in the keymap of a seat and the index of the active one
+    pub fn keyboard_layouts(&mut self, seat: &Seat<Self>) -> Option<(Vec<String>, u32)> {
+        let keyboard = seat.get_keyboard()?;
+        Some(keyboard.with_xkb_state(self, |context| {
+            let xkb = context.xkb().lock().unwrap();
+            let layouts = xkb.layouts().map(|layout| xkb.layout_name(layout).to_string()).collect();
+            (layouts, xkb.active_layout().0)
+        }))
+    }
+
+    /// Switch the keyboard layout of a seat
+    pub fn switch_keyboard_layout(&mut self, seat: &Seat<Self>, switch: KeyboardLayoutSwitch) -> Result<()> {
+        let keyboard = seat
+            .get_keyboard()
+            .ok_or_else(|| CompositorError::runtime(format!("Seat {} has no keyboard", seat.name())))?;
+        keyboard.with_xkb_state(self, |mut context| {
+            let count = context.xkb().lock().unwrap().layouts().count() as u32;
+            match switch {
+                KeyboardLayoutSwitch::Next => context.cycle_next_layout(),
+                KeyboardLayoutSwitch::Previous => context.cycle_prev_layout(),
+                KeyboardLayoutSwitch::Index(index) if index < count => context.set_layout(Layout(index)),
+                KeyboardLayoutSwitch::Index(index) => {
+                    return Err(CompositorError::runtime(format!(
+                        "No keyboard layout {}, the keymap has {}",
+                        index, count
+                    )));
+                }
+            }
+            Ok(())
+        })?;
+        debug!("Switched keyboard layout of {} with {:?}", seat.name(), switch);
+        Ok(())
+    }
+
+    /// Turn num lock on for a seat, without clients seeing the key
+    pub(crate) fn enable_numlock(&mut self, seat: &Seat<Self>) {
+        let Some(keyboard) = seat.get_keyboard() else {
+            return;
+        };
+        if keyboard.modifier_state().num_lock {
+            return;
+        }
+        let time = self.clock.now().as_millis();
+        for state in [KeyState::Pressed, KeyState::Released] {
+            let serial = SERIAL_COUNTER.next_serial();
+            keyboard.input::<(), _>(self, Keycode::new(NUMLOCK_KEYCODE), state, serial, time, |_, _, _| {
+                FilterResult::Intercept(())
+            });
+        }
+    }
+
+    /// Window holding the keyboard focus of a seat
+    fn keyboard_focus_window(&self, seat: &Seat<Self>) -> Option<Window> {
+        let surface = seat.get_keyboard()?.current_focus()?;
+        self.window_for_surface(&surface)
+    }
+
+    /// Remember the layout of the window about to lose the keyboard focus of a seat
+    pub(crate) fn save_window_layout(&mut self, seat: &Seat<Self>) {
+        if !self.config.keyboard.per_window_layout {
+            return;
+        }
+        let (Some(window), Some((_, active))) = (self.keyboard_focus_window(seat), self.keyboard_layouts(seat)) else {
+            return;
+        };
+        let layouts = window.user_data().get_or_insert_threadsafe(WindowKeyboardLayouts::default);
+        layouts.0.lock().unwrap().insert(seat.name().to_string(), active);
+    }
+
+    /// Restore the layout of the window that took the keyboard focus of a seat
+    pub(crate) fn restore_window_layout(&mut self, seat: &Seat<Self>) {
+        if !self.config.keyboard.per_window_layout {
+            return;
+        }
+        let Some(window) = self.keyboard_focus_window(seat) else {
+            return;
+        };
+        let layout = window
+            .user_data()
+            .get::<WindowKeyboardLayouts>()
+            .and_then(|layouts| layouts.0.lock().unwrap().get(seat.name()).copied())
+            .unwrap_or(0);
+        if let Err(e) = self.switch_keyboard_layout(seat, KeyboardLayoutSwitch::Index(layout)) {
+            debug!("Failed to restore keyboard layout: {}", e);
+        }
+    }
+}
@@ -8,12 +8,36 @@
 
 use compositor_utils::prelude::*;
 use vulkan_renderer::VulkanRenderer;
-use std::sync::{atomic::AtomicBool, Arc};
+use std::sync::{atomic::AtomicBool, Arc, Mutex};
 
 pub mod wayland;
+pub mod clipboard;
+pub mod dnd;
+pub mod cursor;
+pub mod frame;
+pub mod commit_queue;
+pub mod damage;
+pub mod occlusion;
+pub mod popup;
+pub mod resource_limits;
+pub mod client_identity;
+pub mod protocol_trace;
+pub mod replay;
+pub mod seats;
+pub mod keyboard;
+pub mod ipc_server;
+pub mod render;
 pub mod window;
 pub mod input;
+pub mod input_method;
+pub mod tablet;
+pub mod gestures;
+pub mod workspace;
+pub mod toplevel_icon;
+pub mod dialog;
+pub mod bell;
 pub mod output;
+pub mod output_management;
 pub mod surface;
 pub mod surface_manager;
 pub mod backend;
@@ -31,7 +55,7 @@ pub use backend::Backend;
 /// Main compositor instance
 pub struct Compositor {
     wayland_server: WaylandServer,
-    renderer: VulkanRenderer,
+    renderer: Arc<Mutex<VulkanRenderer>>,
     backend: Backend,
     running: Arc<AtomicBool>,
 }
@@ -46,6 +70,7 @@ impl Compositor {
             .map_err(|e| CompositorError::init(format!("Failed to initialize renderer: {}", e)))?;
         
         info!("Renderer info: {:?}", renderer.get_info());
+        let renderer = Arc::new(Mutex::new(renderer));
         
         // Initialize backend (DRM/libinput)
         let backend = Backend::new()
@@ -56,6 +81,9 @@ impl Compositor {
         let mut wayland_server = WaylandServer::new()
             .map_err(|e| CompositorError::init(format!("Failed to initialize Wayland server: {}", e)))?;
         
+        // Surfaces are uploaded to the renderer as clients commit them
+        wayland_server.set_renderer(renderer.clone());
+        
         // Initialize wl_drm protocol support via EGL backend
         wayland_server.initialize_wl_drm()
             .map_err(|e| CompositorError::init(format!("Failed to initialize wl_drm protocol: {}", e)))?;
@@ -64,6 +92,13 @@ impl Compositor {
         wayland_server.start_listening()
             .map_err(|e| CompositorError::init(format!("Failed to start Wayland server: {}", e)))?;
         
+        // Start the IPC control socket next to the Wayland socket
+        if let Some(socket_name) = wayland_server.socket_name().map(str::to_owned) {
+            if let Err(e) = wayland_server.start_ipc(ipc_server::default_socket_path(&socket_name)) {
+                warn!("IPC server unavailable: {}", e);
+            }
+        }
+        
         info!("Compositor initialized successfully");
         
         Ok(Self {
@@ -159,8 +194,10 @@ impl Compositor {
     /// Render a frame
     #[allow(dead_code)]
     async fn render_frame(&mut self) -> Result<()> {
+        let mut renderer = self.renderer.lock().unwrap();
+        
         // Begin frame
-        self.renderer.begin_frame()?;
+        renderer.begin_frame()?;
         
         // TODO: Render compositor content
         // - Render windows
@@ -168,7 +205,7 @@ impl Compositor {
         // - Apply effects (glassmorphism, etc.)
         
         // End frame and present
-        self.renderer.end_frame()?;
+        renderer.end_frame()?;
         
         Ok(())
     }
@@ -0,0 +1,156 @@
+// Occlusion - Culling of surfaces hidden behind opaque content
+//
+// The scene of an output is walked front to back, accumulating the area
+// covered by opaque content: the opaque regions clients set through
+// wl_surface.set_opaque_region and single-pixel buffers of opaque colors, for
+// surfaces drawn without an alpha multiplier. Each surface is left with the
+// part of it outside that area. Surfaces left with nothing are culled: they
+// are not drawn, get no frame callbacks and their buffers are not uploaded
+// until they show again. Partially covered surfaces are drawn only in their
+// visible parts.
+//
+// Opaque regions are rounded inwards to output pixels and the visible parts
+// outwards, so that culling never hides a pixel that could show.
+
+use compositor_utils::math::Region;
+use smithay::{
+    output::Output,
+    reexports::wayland_server::protocol::wl_surface::WlSurface,
+    utils::{Physical, Point, Rectangle},
+    wayland::compositor::{with_states, RectangleKind, SurfaceAttributes},
+};
+
+use crate::render::{RenderElement, SurfaceDraw};
+use crate::wayland::WaylandServerState;
+
+/// How much of an output's scene is hidden behind opaque content
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OcclusionStats {
+    /// Surfaces with content on the output
+    pub surfaces: usize,
+    /// Surfaces hidden entirely and culled
+    pub occluded: usize,
+    /// Surfaces drawn only in part
+    pub partially_occluded: usize,
+    /// Surface pixels drawn, where surfaces overlap each one counts
+    pub drawn_pixels: u64,
+    /// Surface pixels on the output not drawn because opaque content covers them
+    pub culled_pixels: u64,
+}
+
+/// Scene of an output after culling
+#[derive(Debug, Default)]
+pub(crate) struct Culling {
+    /// Draws of surfaces with a visible part, back to front
+    pub draws: Vec<SurfaceDraw>,
+    /// Surfaces hidden entirely
+    pub occluded: Vec<WlSurface>,
+    pub stats: OcclusionStats,
+}
+
+impl Culling {
+    /// Elements of the scene that are not hidden entirely
+    pub(crate) fn visible_elements(&self, elements: Vec<RenderElement>) -> Vec<RenderElement> {
+        elements.into_iter().filter(|element| !self.occluded.contains(&element.surface)).collect()
+    }
+}
+
+fn pixel_count(region: &Region) -> u64 {
+    region.rects().map(|rect| rect.width as u64 * rect.height as u64).sum()
+}
+
+/// Area of a drawn surface that hides everything below it, in output pixels
+fn opaque_region(draw: &SurfaceDraw) -> Region {
+    let state = &draw.state;
+    let geometry = Region::from(draw.geometry());
+    if state.alpha < 1.0 {
+        return Region::new();
+    }
+    if let Some([.., alpha]) = state.color {
+        return if alpha >= 1.0 { geometry } else { Region::new() };
+    }
+
+    let Some(opaque) = with_states(&draw.surface, |states| {
+        states.cached_state.get::<SurfaceAttributes>().current().opaque_region.clone()
+    }) else {
+        return Region::new();
+    };
+    // Opaque regions are in surface coordinates, the viewport destination once scaled
+    let origin = Point::<f64, Physical>::from((state.position[0] as f64, state.position[1] as f64));
+    let mut region = Region::new();
+    for (kind, rect) in opaque.rects {
+        let mut rect = rect.to_f64().to_physical(draw.scale);
+        rect.loc += origin;
+        match kind {
+            RectangleKind::Add => region.add_rect(rect.to_i32_down().into()),
+            RectangleKind::Subtract => region.subtract_rect(rect.to_i32_up().into()),
+        }
+    }
+    region.intersection(&geometry)
+}
+
+/// Cull draws hidden behind opaque content above them
+///
+/// `draws` are back to front, `output` is the area of the output in pixels.
+pub(crate) fn cull_draws(draws: Vec<SurfaceDraw>, output: Rectangle<i32, Physical>) -> Culling {
+    let output = Region::from(output);
+    let mut culling = Culling::default();
+    let mut opaque = Region::new();
+    for mut draw in draws.into_iter().rev() {
+        let shown = Region::from(draw.geometry()).intersection(&output);
+        if shown.is_empty() {
+            continue;
+        }
+        let visible = shown.subtract(&opaque);
+        opaque = opaque.union(&opaque_region(&draw));
+
+        let stats = &mut culling.stats;
+        stats.surfaces += 1;
+        stats.culled_pixels += pixel_count(&shown) - pixel_count(&visible);
+        if visible.is_empty() {
+            stats.occluded += 1;
+            culling.occluded.push(draw.surface);
+            continue;
+        }
+        if visible != shown {
+            stats.partially_occluded += 1;
+        }
+        stats.drawn_pixels += pixel_count(&visible);
+        draw.visible = visible.to_rectangles();
+        culling.draws.push(draw);
+    }
+    culling.draws.reverse();
+    culling
+}
+
+impl WaylandServerState {
+    /// Scene of an output with the surfaces hidden behind opaque content culled
+    pub(crate) fn cull_occluded(&self, output: &Output) -> Culling {
+        let Some(output_geometry) = self.space.output_geometry(output) else {
+            return Culling::default();
+        };
+        let scale = output.current_scale().fractional_scale();
+        let area = Rectangle::from_size(output_geometry.size.to_f64().to_physical(scale).to_i32_round());
+        cull_draws(self.all_surface_draws(output), area)
+    }
+
+    /// Scenes of all outputs after culling, computed once for everything a frame needs
+    pub(crate) fn cull_outputs(&self) -> Vec<(Output, Culling)> {
+        self.space.outputs().map(|output| (output.clone(), self.cull_occluded(output))).collect()
+    }
+
+    /// Surfaces of an output hidden entirely behind opaque content
+    pub fn occluded_surfaces(&self, output: &Output) -> Vec<WlSurface> {
+        self.cull_occluded(output).occluded
+    }
+
+    /// Surfaces of an output not hidden behind opaque content, back to front
+    pub fn visible_elements(&self, output: &Output) -> Vec<RenderElement> {
+        self.cull_occluded(output).visible_elements(self.render_elements(output))
+    }
+
+    /// How much of the scene of an output is culled
+    pub fn occlusion_stats(&self, output: &Output) -> OcclusionStats {
+        self.cull_occluded(output).stats
+    }
+}
@@ -0,0 +1,957 @@
+// Output Management - Runtime output configuration via wlr-output-management
+//
+// Implements `zwlr_output_manager_v1` so configuration tools such as wlr-randr
+// and kanshi can enumerate heads and modes and change the mode, position,
+// scale, transform and enabled state of outputs at runtime.
+//
+// Every output known to the compositor is advertised as a head, including
+// outputs that are currently disabled. A configuration submitted by a client
+// must cover all heads; it is validated as a whole before any output is
+// touched, so a rejected configuration leaves the current layout untouched.
+
+use compositor_utils::prelude::*;
+use smithay::{
+    desktop::{layer_map_for_output, LayerSurface, Window},
+    output::{Mode, Output, Scale, WeakOutput},
+    reexports::wayland_server::{
+        backend::{ClientId, GlobalId},
+        protocol::wl_surface::WlSurface,
+        Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource, WEnum,
+    },
+    utils::{Logical, Point, Rectangle, Transform},
+    wayland::{
+        compositor::{send_surface_state, with_surface_tree_downward, TraversalAction},
+        fractional_scale::with_fractional_scale,
+    },
+};
+use wayland_protocols_wlr::output_management::v1::server::{
+    zwlr_output_configuration_head_v1::{self, ZwlrOutputConfigurationHeadV1},
+    zwlr_output_configuration_v1::{self, ZwlrOutputConfigurationV1},
+    zwlr_output_head_v1::{self, ZwlrOutputHeadV1},
+    zwlr_output_manager_v1::{self, ZwlrOutputManagerV1},
+    zwlr_output_mode_v1::{self, ZwlrOutputModeV1},
+};
+use std::sync::{Arc, Mutex};
+
+use crate::wayland::WaylandServerState;
+
+/// Highest protocol version advertised for the output manager global
+const OUTPUT_MANAGER_VERSION: u32 = 4;
+
+/// `zwlr_output_mode_v1` tops out one version below the other interfaces
+const OUTPUT_MODE_VERSION: u32 = 3;
+
+/// Largest output scale accepted from a configuration
+pub const MAX_OUTPUT_SCALE: f64 = 8.0;
+
+/// Refresh rate used for custom modes that leave the rate unspecified (mHz)
+const DEFAULT_REFRESH: i32 = 60_000;
+
+/// Requested state for a single output within an output configuration
+///
+/// Fields left as `None` keep the output's current value.
+#[derive(Debug, Clone)]
+pub struct OutputHeadConfiguration {
+    pub output: Output,
+    pub enabled: bool,
+    pub mode: Option<Mode>,
+    pub position: Option<Point<i32, Logical>>,
+    pub transform: Option<Transform>,
+    pub scale: Option<f64>,
+    pub adaptive_sync: Option<bool>,
+}
+
+impl OutputHeadConfiguration {
+    /// Configuration that enables the output, keeping all current properties
+    pub fn enabled(output: &Output) -> Self {
+        Self {
+            output: output.clone(),
+            enabled: true,
+            mode: None,
+            position: None,
+            transform: None,
+            scale: None,
+            adaptive_sync: None,
+        }
+    }
+
+    /// Configuration that disables the output
+    pub fn disabled(output: &Output) -> Self {
+        Self {
+            enabled: false,
+            ..Self::enabled(output)
+        }
+    }
+}
+
+/// Output tracked by the manager together with its `wl_output` global
+struct ManagedOutput {
+    output: Output,
+    /// Present while the output is enabled
+    global: Option<GlobalId>,
+}
+
+/// A head advertised to one manager instance
+struct HeadInstance {
+    head: ZwlrOutputHeadV1,
+    output: Output,
+    modes: Vec<ZwlrOutputModeV1>,
+}
+
+/// A bound `zwlr_output_manager_v1` and the heads it has been sent
+struct ManagerInstance {
+    manager: ZwlrOutputManagerV1,
+    heads: Vec<HeadInstance>,
+}
+
+/// State of the `zwlr_output_manager_v1` global
+pub struct OutputManagementState {
+    display: DisplayHandle,
+    global: GlobalId,
+    serial: u32,
+    outputs: Vec<ManagedOutput>,
+    managers: Vec<ManagerInstance>,
+}
+
+impl OutputManagementState {
+    /// Create the output manager global
+    pub fn new(display: &DisplayHandle) -> Self {
+        let global = display.create_global::<WaylandServerState, ZwlrOutputManagerV1, _>(OUTPUT_MANAGER_VERSION, ());
+
+        Self {
+            display: display.clone(),
+            global,
+            serial: 0,
+            outputs: Vec::new(),
+            managers: Vec::new(),
+        }
+    }
+
+    /// Id of the output manager global
+    pub fn global(&self) -> GlobalId {
+        self.global.clone()
+    }
+
+    /// Serial of the current output configuration
+    ///
+    /// Configurations created against an older serial are cancelled.
+    pub fn serial(&self) -> u32 {
+        self.serial
+    }
+
+    /// All outputs known to the compositor, enabled or not
+    pub fn outputs(&self) -> impl Iterator<Item = &Output> {
+        self.outputs.iter().map(|managed| &managed.output)
+    }
+
+    /// Whether the output is currently enabled
+    pub fn is_enabled(&self, output: &Output) -> bool {
+        self.outputs
+            .iter()
+            .any(|managed| &managed.output == output && managed.global.is_some())
+    }
+
+    /// Whether a mode object was advertised for a head of the output
+    ///
+    /// Includes the custom mode an output was sent as its current mode.
+    fn is_mode_of(&self, output: &Output, mode: &ZwlrOutputModeV1) -> bool {
+        self.managers
+            .iter()
+            .flat_map(|manager| &manager.heads)
+            .any(|head| &head.output == output && head.modes.contains(mode))
+    }
+
+    /// Start managing an output and advertise it to every bound manager
+    ///
+    /// The output is created enabled, with its `wl_output` global in place.
+    pub(crate) fn add_output(&mut self, output: &Output) {
+        if self.outputs.iter().any(|managed| &managed.output == output) {
+            return;
+        }
+
+        let global = output.create_global::<WaylandServerState>(&self.display);
+        self.outputs.push(ManagedOutput {
+            output: output.clone(),
+            global: Some(global),
+        });
+
+        for instance in &mut self.managers {
+            if let Some(head) = create_head(&self.display, &instance.manager, output, true) {
+                instance.heads.push(head);
+            }
+        }
+
+        self.notify_changes();
+        info!("Output {} added to output management", output.name());
+    }
+
+    /// Stop managing an output, retracting its head and `wl_output` global
+    pub(crate) fn remove_output(&mut self, output: &Output) {
+        let Some(index) = self.outputs.iter().position(|managed| &managed.output == output) else {
+            return;
+        };
+
+        let managed = self.outputs.remove(index);
+        if let Some(global) = managed.global {
+            self.display.remove_global::<WaylandServerState>(global);
+        }
+
+        for instance in &mut self.managers {
+            instance.heads.retain(|head| {
+                if &head.output != output {
+                    return true;
+                }
+                for mode in &head.modes {
+                    mode.finished();
+                }
+                head.head.finished();
+                false
+            });
+        }
+
+        self.notify_changes();
+        info!("Output {} removed from output management", output.name());
+    }
+
+    /// Create or retract the `wl_output` global of an output
+    fn set_enabled(&mut self, output: &Output, enabled: bool) {
+        let Some(managed) = self.outputs.iter_mut().find(|managed| &managed.output == output) else {
+            return;
+        };
+
+        match (enabled, managed.global.take()) {
+            (true, None) => {
+                managed.global = Some(output.create_global::<WaylandServerState>(&self.display));
+            }
+            (false, Some(global)) => {
+                self.display.remove_global::<WaylandServerState>(global);
+            }
+            (_, global) => managed.global = global,
+        }
+    }
+
+    /// Bump the serial and resend the state of every head to every manager
+    fn notify_changes(&mut self) {
+        self.serial = self.serial.wrapping_add(1);
+
+        for instance in &mut self.managers {
+            for head in &mut instance.heads {
+                let enabled = self
+                    .outputs
+                    .iter()
+                    .any(|managed| managed.output == head.output && managed.global.is_some());
+                send_head_state(&self.display, head, enabled);
+            }
+            instance.manager.done(self.serial);
+        }
+    }
+}
+
+/// Advertise an output as a new head of the given manager
+fn create_head(
+    dh: &DisplayHandle,
+    manager: &ZwlrOutputManagerV1,
+    output: &Output,
+    enabled: bool,
+) -> Option<HeadInstance> {
+    let client = manager.client()?;
+    let head = client
+        .create_resource::<ZwlrOutputHeadV1, _, WaylandServerState>(dh, manager.version(), output.downgrade())
+        .ok()?;
+    manager.head(&head);
+
+    head.name(output.name());
+    head.description(output.description());
+
+    let physical = output.physical_properties();
+    if physical.size.w > 0 && physical.size.h > 0 {
+        head.physical_size(physical.size.w, physical.size.h);
+    }
+    if head.version() >= 2 {
+        head.make(physical.make);
+        head.model(physical.model);
+    }
+
+    let mut instance = HeadInstance {
+        head,
+        output: output.clone(),
+        modes: Vec::new(),
+    };
+
+    let preferred = output.preferred_mode();
+    for mode in output.modes() {
+        if let Some(mode_object) = create_mode(dh, &client, &instance.head, mode, preferred == Some(mode)) {
+            instance.modes.push(mode_object);
+        }
+    }
+
+    send_head_state(dh, &mut instance, enabled);
+    Some(instance)
+}
+
+/// Advertise a mode of a head
+fn create_mode(
+    dh: &DisplayHandle,
+    client: &Client,
+    head: &ZwlrOutputHeadV1,
+    mode: Mode,
+    preferred: bool,
+) -> Option<ZwlrOutputModeV1> {
+    let version = head.version().min(OUTPUT_MODE_VERSION);
+    let mode_object = client
+        .create_resource::<ZwlrOutputModeV1, _, WaylandServerState>(dh, version, mode)
+        .ok()?;
+    head.mode(&mode_object);
+
+    mode_object.size(mode.size.w, mode.size.h);
+    if mode.refresh > 0 {
+        mode_object.refresh(mode.refresh);
+    }
+    if preferred {
+        mode_object.preferred();
+    }
+
+    Some(mode_object)
+}
+
+/// Send the mutable properties of a head
+fn send_head_state(dh: &DisplayHandle, instance: &mut HeadInstance, enabled: bool) {
+    let output = &instance.output;
+    instance.head.enabled(enabled as i32);
+
+    if enabled {
+        if let Some(current) = output.current_mode() {
+            let known = instance
+                .modes
+                .iter()
+                .find(|mode_object| mode_object.data::<Mode>() == Some(&current))
+                .cloned();
+
+            // Custom modes are not part of the advertised list until first used
+            let mode_object = known.or_else(|| {
+                let client = instance.head.client()?;
+                let created = create_mode(dh, &client, &instance.head, current, false)?;
+                instance.modes.push(created.clone());
+                Some(created)
+            });
+
+            if let Some(mode_object) = mode_object {
+                instance.head.current_mode(&mode_object);
+            }
+        }
+
+        let location = output.current_location();
+        instance.head.position(location.x, location.y);
+        instance.head.transform(output.current_transform().into());
+        instance.head.scale(output.current_scale().fractional_scale());
+    }
+
+    if instance.head.version() >= 4 {
+        instance.head.adaptive_sync(zwlr_output_head_v1::AdaptiveSyncState::Disabled);
+    }
+}
+
+/// Send the scale and transform of an output to a surface tree
+///
+/// Updates the preferred fractional scale for clients using wp-fractional-scale
+/// and the preferred buffer scale/transform for wl_surface version 6 clients.
+pub(crate) fn send_output_preferences(surface: &WlSurface, output: &Output) {
+    let scale = output.current_scale();
+    let transform = output.current_transform();
+
+    with_surface_tree_downward(
+        surface,
+        (),
+        |_, _, _| TraversalAction::DoChildren(()),
+        |surface, states, _| {
+            with_fractional_scale(states, |fractional| {
+                fractional.set_preferred_scale(scale.fractional_scale());
+            });
+            send_surface_state(surface, states, scale.integer_scale(), transform);
+        },
+        |_, _, _| true,
+    );
+}
+
+// ============================================================================
+// Configuration Application
+// ============================================================================
+
+impl WaylandServerState {
+    /// Register a new output, enable it and place it at the given location
+    pub fn add_output(&mut self, output: &Output, location: Point<i32, Logical>) {
+        output.change_current_state(None, None, None, Some(location));
+        self.space.map_output(output, location);
+        self.output_management_state.add_output(output);
+        layer_map_for_output(output).arrange();
+        self.resume_frame_clock(output);
+    }
+
+    /// Remove an output, moving its windows and layer surfaces to the remaining outputs
+    pub fn remove_output(&mut self, output: &Output) {
+        let previous = self.output_geometries();
+        self.space.unmap_output(output);
+        self.output_management_state.remove_output(output);
+        self.migrate_layer_surfaces(output);
+        self.relayout_windows(&previous);
+        self.space.refresh();
+        self.send_output_preferences();
+    }
+
+    /// Validate and optionally apply an output configuration
+    ///
+    /// The configuration is checked as a whole first; nothing is changed when
+    /// any head is rejected or when `test_only` is set.
+    pub fn apply_output_configuration(
+        &mut self,
+        heads: &[OutputHeadConfiguration],
+        test_only: bool,
+    ) -> Result<()> {
+        self.validate_output_configuration(heads)?;
+
+        if test_only {
+            debug!("Output configuration with {} heads passed testing", heads.len());
+            return Ok(());
+        }
+
+        let previous = self.output_geometries();
+
+        for head in heads.iter().filter(|head| head.enabled) {
+            let output = &head.output;
+            let position = head.position.unwrap_or_else(|| output.current_location());
+            let scale = head.scale.map(|scale| {
+                if scale.fract() == 0.0 {
+                    Scale::Integer(scale as i32)
+                } else {
+                    Scale::Fractional(scale)
+                }
+            });
+
+            output.change_current_state(head.mode, head.transform, scale, Some(position));
+            self.space.map_output(output, position);
+            self.output_management_state.set_enabled(output, true);
+            layer_map_for_output(output).arrange();
+            self.resume_frame_clock(output);
+        }
+
+        for head in heads.iter().filter(|head| !head.enabled) {
+            self.space.unmap_output(&head.output);
+            self.output_management_state.set_enabled(&head.output, false);
+            self.migrate_layer_surfaces(&head.output);
+        }
+
+        self.relayout_windows(&previous);
+        self.space.refresh();
+        self.send_output_preferences();
+        self.output_management_state.notify_changes();
+
+        info!("Applied output configuration with {} heads", heads.len());
+        Ok(())
+    }
+
+    /// Check a configuration without side effects
+    fn validate_output_configuration(&self, heads: &[OutputHeadConfiguration]) -> Result<()> {
+        if !heads.iter().any(|head| head.enabled) {
+            return Err(CompositorError::wayland("Output configuration must keep at least one output enabled"));
+        }
+
+        for (index, head) in heads.iter().enumerate() {
+            let name = head.output.name();
+
+            if heads[..index].iter().any(|other| other.output == head.output) {
+                return Err(CompositorError::wayland(format!("Output {} configured more than once", name)));
+            }
+
+            if !self.output_management_state.outputs().any(|output| output == &head.output) {
+                return Err(CompositorError::wayland(format!("Output {} is not managed by the compositor", name)));
+            }
+
+            if !head.enabled {
+                continue;
+            }
+
+            let mode = head.mode.or_else(|| head.output.current_mode()).ok_or_else(|| {
+                CompositorError::wayland(format!("Output {} has no mode to enable", name))
+            })?;
+            if mode.size.w <= 0 || mode.size.h <= 0 || mode.refresh < 0 {
+                return Err(CompositorError::wayland(format!(
+                    "Invalid mode {}x{}@{} for output {}",
+                    mode.size.w, mode.size.h, mode.refresh, name
+                )));
+            }
+
+            if let Some(scale) = head.scale {
+                if !scale.is_finite() || scale <= 0.0 || scale > MAX_OUTPUT_SCALE {
+                    return Err(CompositorError::wayland(format!("Invalid scale {} for output {}", scale, name)));
+                }
+            }
+
+            if head.adaptive_sync == Some(true) {
+                return Err(CompositorError::wayland(format!("Adaptive sync is not supported on output {}", name)));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Current geometry of every enabled output
+    fn output_geometries(&self) -> Vec<(Output, Rectangle<i32, Logical>)> {
+        self.space
+            .outputs()
+            .filter_map(|output| self.space.output_geometry(output).map(|geo| (output.clone(), geo)))
+            .collect()
+    }
+
+    /// Keep windows attached to the output they were on
+    ///
+    /// Windows follow their output when it moves and are moved to the primary
+    /// output when theirs is disabled. Windows are kept inside the output
+    /// bounds where they fit.
+    fn relayout_windows(&mut self, previous: &[(Output, Rectangle<i32, Logical>)]) {
+        let Some(primary) = self.space.outputs().next().cloned() else {
+            return;
+        };
+
+        let windows: Vec<Window> = self.space.elements().cloned().collect();
+        for window in windows {
+            let Some(location) = self.space.element_location(&window) else {
+                continue;
+            };
+
+            let (target, offset) = match previous.iter().find(|(_, geo)| geo.contains(location)) {
+                Some((output, geo)) if self.space.outputs().any(|o| o == output) => {
+                    (output.clone(), location - geo.loc)
+                }
+                Some((_, geo)) => (primary.clone(), location - geo.loc),
+                None => (primary.clone(), Point::from((0, 0))),
+            };
+
+            let Some(target_geo) = self.space.output_geometry(&target) else {
+                continue;
+            };
+
+            let size = window.geometry().size;
+            let offset = Point::<i32, Logical>::from((
+                offset.x.clamp(0, (target_geo.size.w - size.w).max(0)),
+                offset.y.clamp(0, (target_geo.size.h - size.h).max(0)),
+            ));
+
+            let new_location = target_geo.loc + offset;
+            if new_location != location {
+                debug!("Moving window from {:?} to {:?} on {}", location, new_location, target.name());
+                self.space.map_element(window, new_location, false);
+            }
+        }
+    }
+
+    /// Move the layer surfaces of a disabled output to the primary output
+    fn migrate_layer_surfaces(&mut self, from: &Output) {
+        let Some(target) = self.space.outputs().find(|output| *output != from).cloned() else {
+            return;
+        };
+
+        let layers: Vec<LayerSurface> = {
+            let mut map = layer_map_for_output(from);
+            let layers: Vec<LayerSurface> = map.layers().cloned().collect();
+            for layer in &layers {
+                map.unmap_layer(layer);
+            }
+            layers
+        };
+
+        if layers.is_empty() {
+            return;
+        }
+
+        let mut map = layer_map_for_output(&target);
+        for layer in &layers {
+            if let Err(e) = map.map_layer(layer) {
+                warn!("Failed to move layer surface {} to {}: {}", layer.namespace(), target.name(), e);
+            }
+        }
+        info!("Moved {} layer surfaces from {} to {}", layers.len(), from.name(), target.name());
+    }
+
+    /// Resend output scale and transform to every mapped surface
+    fn send_output_preferences(&self) {
+        for window in self.space.elements() {
+            let Some(output) = self.space.outputs_for_element(window).into_iter().next() else {
+                continue;
+            };
+            if let Some(toplevel) = window.toplevel() {
+                send_output_preferences(toplevel.wl_surface(), &output);
+            }
+        }
+
+        for output in self.space.outputs() {
+            let map = layer_map_for_output(output);
+            for layer in map.layers() {
+                send_output_preferences(layer.wl_surface(), output);
+            }
+        }
+    }
+}
+
+// ============================================================================
+// Protocol Dispatch
+// ============================================================================
+
+/// User data of a `zwlr_output_configuration_v1`
+pub struct OutputConfigurationData {
+    serial: u32,
+    inner: Mutex<PendingConfiguration>,
+}
+
+#[derive(Default)]
+struct PendingConfiguration {
+    heads: Vec<Arc<Mutex<OutputHeadConfiguration>>>,
+    /// A head of this configuration refers to an output that no longer exists
+    stale: bool,
+    /// Set once apply or test has been requested
+    used: bool,
+}
+
+/// User data of a `zwlr_output_configuration_head_v1`
+///
+/// `None` when the head refers to an output that has already been removed.
+pub struct ConfigurationHeadData {
+    pending: Option<Arc<Mutex<OutputHeadConfiguration>>>,
+}
+
+impl GlobalDispatch<ZwlrOutputManagerV1, ()> for WaylandServerState {
+    fn bind(
+        state: &mut Self,
+        dh: &DisplayHandle,
+        _client: &Client,
+        resource: New<ZwlrOutputManagerV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        let manager = data_init.init(resource, ());
+        let management = &mut state.output_management_state;
+
+        let mut instance = ManagerInstance {
+            manager: manager.clone(),
+            heads: Vec::new(),
+        };
+        for managed in &management.outputs {
+            if let Some(head) = create_head(dh, &manager, &managed.output, managed.global.is_some()) {
+                instance.heads.push(head);
+            }
+        }
+        manager.done(management.serial);
+        management.managers.push(instance);
+
+        debug!("Output manager bound with {} heads", management.outputs.len());
+    }
+}
+
+impl Dispatch<ZwlrOutputManagerV1, ()> for WaylandServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        manager: &ZwlrOutputManagerV1,
+        request: zwlr_output_manager_v1::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            zwlr_output_manager_v1::Request::CreateConfiguration { id, serial } => {
+                data_init.init(
+                    id,
+                    OutputConfigurationData {
+                        serial,
+                        inner: Mutex::new(PendingConfiguration::default()),
+                    },
+                );
+            }
+            zwlr_output_manager_v1::Request::Stop => {
+                state
+                    .output_management_state
+                    .managers
+                    .retain(|instance| &instance.manager != manager);
+                manager.finished();
+            }
+            _ => {}
+        }
+    }
+
+    fn destroyed(state: &mut Self, _client: ClientId, manager: &ZwlrOutputManagerV1, _data: &()) {
+        state
+            .output_management_state
+            .managers
+            .retain(|instance| &instance.manager != manager);
+    }
+}
+
+impl Dispatch<ZwlrOutputHeadV1, WeakOutput> for WaylandServerState {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _head: &ZwlrOutputHeadV1,
+        _request: zwlr_output_head_v1::Request,
+        _data: &WeakOutput,
+        _dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        // The only request is the release destructor
+    }
+
+    fn destroyed(state: &mut Self, _client: ClientId, head: &ZwlrOutputHeadV1, _data: &WeakOutput) {
+        for instance in &mut state.output_management_state.managers {
+            instance.heads.retain(|instance_head| &instance_head.head != head);
+        }
+    }
+}
+
+impl Dispatch<ZwlrOutputModeV1, Mode> for WaylandServerState {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _mode: &ZwlrOutputModeV1,
+        _request: zwlr_output_mode_v1::Request,
+        _data: &Mode,
+        _dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        // The only request is the release destructor
+    }
+
+    fn destroyed(state: &mut Self, _client: ClientId, mode: &ZwlrOutputModeV1, _data: &Mode) {
+        for instance in &mut state.output_management_state.managers {
+            for head in &mut instance.heads {
+                head.modes.retain(|mode_object| mode_object != mode);
+            }
+        }
+    }
+}
+
+impl Dispatch<ZwlrOutputConfigurationV1, OutputConfigurationData> for WaylandServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        configuration: &ZwlrOutputConfigurationV1,
+        request: zwlr_output_configuration_v1::Request,
+        data: &OutputConfigurationData,
+        _dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            zwlr_output_configuration_v1::Request::EnableHead { id, head } => {
+                let pending = add_configured_head(configuration, data, &head, true);
+                data_init.init(id, ConfigurationHeadData { pending });
+            }
+            zwlr_output_configuration_v1::Request::DisableHead { head } => {
+                add_configured_head(configuration, data, &head, false);
+            }
+            zwlr_output_configuration_v1::Request::Apply => {
+                finish_configuration(state, configuration, data, false);
+            }
+            zwlr_output_configuration_v1::Request::Test => {
+                finish_configuration(state, configuration, data, true);
+            }
+            zwlr_output_configuration_v1::Request::Destroy => {}
+            _ => {}
+        }
+    }
+}
+
+/// Record an enable_head or disable_head request
+fn add_configured_head(
+    configuration: &ZwlrOutputConfigurationV1,
+    data: &OutputConfigurationData,
+    head: &ZwlrOutputHeadV1,
+    enabled: bool,
+) -> Option<Arc<Mutex<OutputHeadConfiguration>>> {
+    let mut inner = data.inner.lock().unwrap();
+
+    if inner.used {
+        configuration.post_error(
+            zwlr_output_configuration_v1::Error::AlreadyUsed,
+            "configuration has already been applied or tested",
+        );
+        return None;
+    }
+
+    let Some(output) = head.data::<WeakOutput>().and_then(WeakOutput::upgrade) else {
+        inner.stale = true;
+        return None;
+    };
+
+    if inner.heads.iter().any(|pending| pending.lock().unwrap().output == output) {
+        configuration.post_error(
+            zwlr_output_configuration_v1::Error::AlreadyConfiguredHead,
+            format!("head {} has already been configured", output.name()),
+        );
+        return None;
+    }
+
+    let pending = Arc::new(Mutex::new(if enabled {
+        OutputHeadConfiguration::enabled(&output)
+    } else {
+        OutputHeadConfiguration::disabled(&output)
+    }));
+    inner.heads.push(pending.clone());
+
+    Some(pending)
+}
+
+/// Handle an apply or test request and report the outcome
+fn finish_configuration(
+    state: &mut WaylandServerState,
+    configuration: &ZwlrOutputConfigurationV1,
+    data: &OutputConfigurationData,
+    test_only: bool,
+) {
+    let heads = {
+        let mut inner = data.inner.lock().unwrap();
+        if inner.used {
+            configuration.post_error(
+                zwlr_output_configuration_v1::Error::AlreadyUsed,
+                "configuration has already been applied or tested",
+            );
+            return;
+        }
+        inner.used = true;
+
+        if inner.stale || data.serial != state.output_management_state.serial() {
+            debug!("Cancelling outdated output configuration (serial {})", data.serial);
+            configuration.cancelled();
+            return;
+        }
+
+        inner
+            .heads
+            .iter()
+            .map(|pending| pending.lock().unwrap().clone())
+            .collect::<Vec<_>>()
+    };
+
+    let unconfigured = state
+        .output_management_state
+        .outputs()
+        .find(|output| !heads.iter().any(|head| &head.output == *output))
+        .map(Output::name);
+    if let Some(name) = unconfigured {
+        configuration.post_error(
+            zwlr_output_configuration_v1::Error::UnconfiguredHead,
+            format!("head {} has not been configured", name),
+        );
+        return;
+    }
+
+    match state.apply_output_configuration(&heads, test_only) {
+        Ok(()) => configuration.succeeded(),
+        Err(e) => {
+            warn!("Rejected output configuration: {}", e);
+            configuration.failed();
+        }
+    }
+}
+
+impl Dispatch<ZwlrOutputConfigurationHeadV1, ConfigurationHeadData> for WaylandServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        resource: &ZwlrOutputConfigurationHeadV1,
+        request: zwlr_output_configuration_head_v1::Request,
+        data: &ConfigurationHeadData,
+        _dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        use zwlr_output_configuration_head_v1::Error;
+
+        let Some(pending) = &data.pending else {
+            return;
+        };
+        let mut pending = pending.lock().unwrap();
+
+        match request {
+            zwlr_output_configuration_head_v1::Request::SetMode { mode } => {
+                if pending.mode.is_some() {
+                    resource.post_error(Error::AlreadySet, "mode has already been set");
+                    return;
+                }
+                match mode.data::<Mode>() {
+                    Some(data) if state.output_management_state.is_mode_of(&pending.output, &mode) => {
+                        pending.mode = Some(*data)
+                    }
+                    _ => resource.post_error(Error::InvalidMode, "mode does not belong to this head"),
+                }
+            }
+            zwlr_output_configuration_head_v1::Request::SetCustomMode { width, height, refresh } => {
+                if pending.mode.is_some() {
+                    resource.post_error(Error::AlreadySet, "mode has already been set");
+                    return;
+                }
+                if width <= 0 || height <= 0 || refresh < 0 {
+                    resource.post_error(
+                        Error::InvalidCustomMode,
+                        format!("invalid custom mode {}x{}@{}", width, height, refresh),
+                    );
+                    return;
+                }
+                let refresh = if refresh == 0 {
+                    pending
+                        .output
+                        .current_mode()
+                        .map(|mode| mode.refresh)
+                        .unwrap_or(DEFAULT_REFRESH)
+                } else {
+                    refresh
+                };
+                pending.mode = Some(Mode {
+                    size: (width, height).into(),
+                    refresh,
+                });
+            }
+            zwlr_output_configuration_head_v1::Request::SetPosition { x, y } => {
+                if pending.position.is_some() {
+                    resource.post_error(Error::AlreadySet, "position has already been set");
+                    return;
+                }
+                pending.position = Some((x, y).into());
+            }
+            zwlr_output_configuration_head_v1::Request::SetTransform { transform } => {
+                if pending.transform.is_some() {
+                    resource.post_error(Error::AlreadySet, "transform has already been set");
+                    return;
+                }
+                match transform {
+                    WEnum::Value(transform) => pending.transform = Some(transform.into()),
+                    WEnum::Unknown(value) => {
+                        resource.post_error(Error::InvalidTransform, format!("invalid transform {}", value))
+                    }
+                }
+            }
+            zwlr_output_configuration_head_v1::Request::SetScale { scale } => {
+                if pending.scale.is_some() {
+                    resource.post_error(Error::AlreadySet, "scale has already been set");
+                    return;
+                }
+                if scale <= 0.0 {
+                    resource.post_error(Error::InvalidScale, format!("invalid scale {}", scale));
+                    return;
+                }
+                pending.scale = Some(scale);
+            }
+            zwlr_output_configuration_head_v1::Request::SetAdaptiveSync { state } => {
+                if pending.adaptive_sync.is_some() {
+                    resource.post_error(Error::AlreadySet, "adaptive sync has already been set");
+                    return;
+                }
+                match state {
+                    WEnum::Value(state) => {
+                        pending.adaptive_sync = Some(state == zwlr_output_head_v1::AdaptiveSyncState::Enabled)
+                    }
+                    WEnum::Unknown(value) => resource.post_error(
+                        Error::InvalidAdaptiveSyncState,
+                        format!("invalid adaptive sync state {}", value),
+                    ),
+                }
+            }
+            _ => {}
+        }
+    }
+}
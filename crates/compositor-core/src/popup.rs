@@ -0,0 +1,81 @@
+// Popup - xdg_popup placement and tracking
+//
+// Popups are placed by their positioner relative to their parent, then
+// constrained to the outputs showing their window: the positioner's
+// constraint adjustments slide, flip or resize them so that menus opened at
+// the edge of a screen stay on it. The popup manager tracks the popup trees
+// of each window for drawing and input; popups are drawn above the surface
+// tree of their window, see render.rs.
+
+use compositor_utils::prelude::*;
+use smithay::{
+    desktop::{find_popup_root_surface, get_popup_toplevel_coords, PopupKind},
+    reexports::wayland_server::protocol::wl_surface::WlSurface,
+    wayland::{seat::WaylandFocus, shell::xdg::{PopupSurface, PositionerState}},
+};
+
+use crate::wayland::WaylandServerState;
+
+impl WaylandServerState {
+    /// Place a new popup and track it until it is destroyed
+    pub(crate) fn popup_created(&mut self, surface: PopupSurface, positioner: PositionerState) {
+        surface.with_pending_state(|state| state.geometry = positioner.get_geometry());
+        self.constrain_popup(&surface);
+        if let Err(e) = self.popup_manager.track_popup(PopupKind::Xdg(surface)) {
+            warn!("Failed to track popup: {:?}", e);
+        }
+    }
+
+    /// Move a popup to a new positioner, answering with `repositioned`
+    pub(crate) fn popup_repositioned(&mut self, surface: PopupSurface, positioner: PositionerState, token: u32) {
+        surface.with_pending_state(|state| {
+            state.geometry = positioner.get_geometry();
+            state.positioner = positioner;
+        });
+        self.constrain_popup(&surface);
+        surface.send_repositioned(token);
+    }
+
+    /// Map popups on their first commit and configure them
+    pub(crate) fn popup_committed(&mut self, surface: &WlSurface) {
+        self.popup_manager.commit(surface);
+        if let Some(PopupKind::Xdg(popup)) = self.popup_manager.find_popup(surface) {
+            if !popup.is_initial_configure_sent() {
+                if let Err(e) = popup.send_configure() {
+                    warn!("Failed to configure popup: {:?}", e);
+                }
+            }
+        }
+    }
+
+    /// Keep a popup within the outputs showing its window
+    fn constrain_popup(&self, surface: &PopupSurface) {
+        let popup = PopupKind::Xdg(surface.clone());
+        let Ok(root) = find_popup_root_surface(&popup) else {
+            return;
+        };
+        let Some(window) = self.space.elements().find(|window| window.wl_surface().as_deref() == Some(&root)) else {
+            return;
+        };
+        let Some(outputs) = self
+            .space
+            .outputs_for_element(window)
+            .iter()
+            .filter_map(|output| self.space.output_geometry(output))
+            .reduce(|bounds, geometry| bounds.merge(geometry))
+        else {
+            return;
+        };
+        let Some(window_geometry) = self.space.element_geometry(window) else {
+            return;
+        };
+
+        // The target area is relative to the popup's parent
+        let mut target = outputs;
+        target.loc -= get_popup_toplevel_coords(&popup);
+        target.loc -= window_geometry.loc;
+        surface.with_pending_state(|state| {
+            state.geometry = state.positioner.get_unconstrained_geometry(target);
+        });
+    }
+}
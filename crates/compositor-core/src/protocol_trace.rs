@@ -0,0 +1,633 @@
+// Protocol trace - Compositor-side WAYLAND_DEBUG for any client
+//
+// Clients accepted on the Wayland sockets while tracing is enabled are
+// connected through a relay: the compositor keeps the socket of the client and
+// hands the display one end of a socket pair, forwarding bytes and file
+// descriptors between the two. The relay follows objects being created and
+// destroyed, so tracing can be switched on and off at any time, for all
+// clients or the clients of one process, without restarting them with
+// WAYLAND_DEBUG=1.
+//
+// Other clients are inserted directly and cost nothing while tracing is off,
+// but cannot be traced later. Relaying every client, so that tracing can start
+// for clients already running, is opt-in.
+//
+// Traced requests and events are written to a file as JSON lines with a
+// timestamp, the connection and pid of the client, the object and the decoded
+// arguments. While a client is not traced its relay only forwards data and
+// scans the messages creating objects; nothing is decoded or written.
+//
+// Objects bound from the registry are decoded with the interfaces of the
+// globals this compositor advertises. All other objects follow from the
+// protocol descriptions of the messages creating them.
+
+use compositor_utils::prelude::*;
+use smithay::reexports::{
+    calloop::{generic::Generic, Interest, Mode, PostAction, Readiness},
+    rustix::{
+        self,
+        io::retry_on_intr,
+        net::{recvmsg, sendmsg, RecvAncillaryBuffer, RecvAncillaryMessage, RecvFlags, SendAncillaryBuffer, SendAncillaryMessage, SendFlags},
+    },
+    wayland_server::{
+        backend::{
+            protocol::{ArgumentType, Interface},
+            DisconnectReason,
+        },
+        protocol::__interfaces::WL_DISPLAY_INTERFACE,
+        Client, Resource,
+    },
+};
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{BufWriter, ErrorKind, IoSlice, IoSliceMut, Write};
+use std::net::Shutdown;
+use std::os::fd::{AsFd, BorrowedFd, OwnedFd};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::client_identity::peer_credentials;
+use crate::wayland::{ClientState, WaylandServerState};
+
+/// File descriptors a single socket message may carry, as in libwayland
+const MAX_FDS: usize = 28;
+
+/// Data a relay holds for a side that stopped reading before dropping the connection
+const MAX_PENDING_BYTES: usize = 4 * 1024 * 1024;
+
+/// Clients and interfaces to trace
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TraceFilter {
+    /// Process of the clients to trace, all clients when unset
+    pub client_pid: Option<i32>,
+    /// Interfaces to trace, all when empty
+    pub interfaces: Vec<String>,
+}
+
+/// Protocol trace state
+#[derive(Debug, Default)]
+pub struct ProtocolTracer {
+    filter: Option<TraceFilter>,
+    output: Option<BufWriter<File>>,
+    /// Connections relayed so far, numbering them in traces
+    connections: u64,
+}
+
+impl ProtocolTracer {
+    /// What is being traced, if tracing is enabled
+    pub fn filter(&self) -> Option<&TraceFilter> {
+        self.filter.as_ref()
+    }
+
+    /// Number of client connections relayed so far
+    pub fn relayed_connections(&self) -> u64 {
+        self.connections
+    }
+
+    /// Whether messages of clients of a process are traced
+    fn traces_client(&self, pid: Option<i32>) -> bool {
+        self.filter
+            .as_ref()
+            .is_some_and(|filter| filter.client_pid.is_none() || filter.client_pid == pid)
+    }
+
+    fn traces_interface(&self, interface: &str) -> bool {
+        self.filter
+            .as_ref()
+            .is_some_and(|filter| filter.interfaces.is_empty() || filter.interfaces.iter().any(|name| name == interface))
+    }
+
+    fn write(&mut self, record: &str) {
+        let Some(output) = &mut self.output else {
+            return;
+        };
+        if let Err(e) = writeln!(output, "{}", record) {
+            warn!("Failed to write protocol trace, stopping: {}", e);
+            self.filter = None;
+            self.output = None;
+        }
+    }
+
+    fn flush(&mut self) {
+        if let Some(output) = &mut self.output {
+            if let Err(e) = output.flush() {
+                warn!("Failed to write protocol trace: {}", e);
+            }
+        }
+    }
+}
+
+impl WaylandServerState {
+    /// File protocol traces are written to
+    pub fn protocol_trace_path(&self) -> PathBuf {
+        self.config.protocol_trace.path.clone().unwrap_or_else(|| {
+            std::env::var_os("XDG_RUNTIME_DIR")
+                .map(PathBuf::from)
+                .unwrap_or_else(std::env::temp_dir)
+                .join(format!("{}-protocol-trace.jsonl", self.socket_name.as_deref().unwrap_or("wayland")))
+        })
+    }
+
+    /// Start tracing with a filter, or stop tracing
+    ///
+    /// Traces are appended to the trace file, which is opened when tracing
+    /// starts and flushed and closed when it stops.
+    pub fn set_protocol_trace(&mut self, filter: Option<TraceFilter>) -> Result<()> {
+        let Some(filter) = filter else {
+            if self.protocol_tracer.filter.take().is_some() {
+                info!("Stopped tracing the Wayland protocol");
+            }
+            self.protocol_tracer.flush();
+            self.protocol_tracer.output = None;
+            return Ok(());
+        };
+
+        if self.protocol_tracer.output.is_none() {
+            let path = self.protocol_trace_path();
+            let file = File::options()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .map_err(|e| CompositorError::runtime(format!("Failed to open protocol trace {}: {}", path.display(), e)))?;
+            self.protocol_tracer.output = Some(BufWriter::new(file));
+            info!("Tracing the Wayland protocol to {}", path.display());
+        }
+        self.protocol_tracer.filter = Some(filter);
+        Ok(())
+    }
+
+    /// Insert a client accepted on a listening socket
+    ///
+    /// Clients are connected through a trace relay while tracing is enabled,
+    /// or always when relaying all clients is configured.
+    pub fn accept_client(&mut self, stream: UnixStream, client_state: ClientState) -> std::io::Result<Client> {
+        if self.config.protocol_trace.relay_clients || self.protocol_tracer.filter.is_some() {
+            self.insert_traced_client(stream, client_state)
+        } else {
+            self.insert_client(stream, client_state)
+        }
+    }
+
+    /// Insert a client connection through a trace relay
+    pub fn insert_traced_client(&mut self, stream: UnixStream, client_state: ClientState) -> std::io::Result<Client> {
+        // The display only sees the relay, the client is identified by its own socket
+        let credentials = peer_credentials(&stream);
+        let (relay_stream, display_stream) = UnixStream::pair()?;
+        stream.set_nonblocking(true)?;
+        relay_stream.set_nonblocking(true)?;
+        let client = self.insert_client_with_credentials(display_stream, client_state, credentials)?;
+
+        self.protocol_tracer.connections += 1;
+        let pid = self.client_identity(&client).map(|identity| identity.pid);
+        let relay = Rc::new(RefCell::new(Relay::new(self.protocol_tracer.connections, pid, stream, relay_stream)));
+        for direction in [Direction::Request, Direction::Event] {
+            let socket = relay.borrow().source(direction).clone();
+            let source_relay = relay.clone();
+            let inserted = self.loop_handle.insert_source(Generic::new(socket, Interest::BOTH, Mode::Edge), move |readiness, _, state| {
+                Ok(source_relay.borrow_mut().ready(direction, readiness, &mut state.protocol_tracer))
+            });
+            if let Err(e) = inserted {
+                relay.borrow_mut().close();
+                self.display_handle
+                    .backend_handle()
+                    .kill_client(client.id(), DisconnectReason::ConnectionClosed);
+                return Err(std::io::Error::other(format!("Failed to insert trace relay source: {}", e)));
+            }
+        }
+        Ok(client)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    /// Client to compositor
+    Request,
+    /// Compositor to client
+    Event,
+}
+
+impl Direction {
+    fn reverse(self) -> Self {
+        match self {
+            Self::Request => Self::Event,
+            Self::Event => Self::Request,
+        }
+    }
+}
+
+/// Data read from one side of a relay, waiting to be written to the other
+#[derive(Default)]
+struct Outgoing {
+    chunks: VecDeque<(Vec<u8>, Vec<OwnedFd>)>,
+    bytes: usize,
+    /// Start of a message not yet received entirely, kept to decode it
+    partial: Vec<u8>,
+}
+
+/// Relay between a client and the display
+struct Relay {
+    connection: u64,
+    pid: Option<i32>,
+    client_socket: Rc<UnixStream>,
+    display_socket: Rc<UnixStream>,
+    /// Interfaces of live objects by id
+    objects: HashMap<u32, &'static Interface>,
+    requests: Outgoing,
+    events: Outgoing,
+    closed: bool,
+}
+
+impl Relay {
+    fn new(connection: u64, pid: Option<i32>, client_socket: UnixStream, display_socket: UnixStream) -> Self {
+        Self {
+            connection,
+            pid,
+            client_socket: Rc::new(client_socket),
+            display_socket: Rc::new(display_socket),
+            objects: HashMap::from([(1, &WL_DISPLAY_INTERFACE)]),
+            requests: Outgoing::default(),
+            events: Outgoing::default(),
+            closed: false,
+        }
+    }
+
+    /// Socket messages in a direction are read from
+    fn source(&self, direction: Direction) -> &Rc<UnixStream> {
+        match direction {
+            Direction::Request => &self.client_socket,
+            Direction::Event => &self.display_socket,
+        }
+    }
+
+    fn outgoing(&mut self, direction: Direction) -> &mut Outgoing {
+        match direction {
+            Direction::Request => &mut self.requests,
+            Direction::Event => &mut self.events,
+        }
+    }
+
+    /// Handle readiness of the socket messages in `direction` are read from
+    fn ready(&mut self, direction: Direction, readiness: Readiness, tracer: &mut ProtocolTracer) -> PostAction {
+        // The socket is also where messages of the other direction go
+        if !self.closed && readiness.writable && !self.flush(direction.reverse()) {
+            self.close();
+        }
+        if !self.closed && (readiness.readable || readiness.error) {
+            self.receive(direction, tracer);
+        }
+        if self.closed {
+            PostAction::Remove
+        } else {
+            PostAction::Continue
+        }
+    }
+
+    /// Read everything available in a direction and forward it
+    fn receive(&mut self, direction: Direction, tracer: &mut ProtocolTracer) {
+        let source = self.source(direction).clone();
+        let tracing = tracer.traces_client(self.pid);
+        let mut buffer = [0; 4096];
+        loop {
+            let mut fds = Vec::new();
+            match receive(&source, &mut buffer, &mut fds) {
+                Ok(0) => {
+                    // Deliver what the other side has not received yet, such as a protocol error
+                    self.flush(direction);
+                    self.close();
+                    break;
+                }
+                Ok(read) => {
+                    self.observe(direction, &buffer[..read], tracing, tracer);
+                    let outgoing = self.outgoing(direction);
+                    outgoing.bytes += read;
+                    outgoing.chunks.push_back((buffer[..read].to_vec(), fds));
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    debug!("Trace relay {} failed to read: {}", self.connection, e);
+                    self.close();
+                    break;
+                }
+            }
+        }
+        if tracing {
+            tracer.flush();
+        }
+
+        if !self.closed && (!self.flush(direction) || self.outgoing(direction).bytes > MAX_PENDING_BYTES) {
+            warn!("Dropping trace relay {}, its {:?} side stopped reading", self.connection, direction.reverse());
+            self.close();
+        }
+    }
+
+    /// Write pending data in a direction, returning false when the connection failed
+    fn flush(&mut self, direction: Direction) -> bool {
+        let destination = self.source(direction.reverse()).clone();
+        let outgoing = self.outgoing(direction);
+        while let Some((bytes, fds)) = outgoing.chunks.front_mut() {
+            match send(&destination, bytes, fds) {
+                Ok(sent) => {
+                    // File descriptors go out with the first part of their data
+                    fds.clear();
+                    outgoing.bytes -= sent;
+                    if sent == bytes.len() {
+                        outgoing.chunks.pop_front();
+                    } else {
+                        bytes.drain(..sent);
+                    }
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    debug!("Trace relay failed to write: {}", e);
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    /// Stop relaying; both ends see the connection closed
+    fn close(&mut self) {
+        self.closed = true;
+        let _ = self.client_socket.shutdown(Shutdown::Both);
+        let _ = self.display_socket.shutdown(Shutdown::Both);
+    }
+
+    /// Follow the messages in data read in a direction
+    fn observe(&mut self, direction: Direction, data: &[u8], tracing: bool, tracer: &mut ProtocolTracer) {
+        let mut partial = std::mem::take(&mut self.outgoing(direction).partial);
+        partial.extend_from_slice(data);
+
+        let mut offset = 0;
+        while let Some(header) = partial.get(offset..offset + 8) {
+            let id = u32::from_ne_bytes([header[0], header[1], header[2], header[3]]);
+            let word = u32::from_ne_bytes([header[4], header[5], header[6], header[7]]);
+            let size = (word >> 16) as usize;
+            if size < 8 {
+                // The display disconnects clients sending malformed messages
+                partial.clear();
+                offset = 0;
+                break;
+            }
+            let Some(body) = partial.get(offset + 8..offset + size) else {
+                break;
+            };
+            self.message(direction, id, (word & 0xffff) as usize, body, tracing, tracer);
+            offset += size;
+        }
+        partial.drain(..offset);
+        self.outgoing(direction).partial = partial;
+    }
+
+    fn message(&mut self, direction: Direction, id: u32, opcode: usize, body: &[u8], tracing: bool, tracer: &mut ProtocolTracer) {
+        let Some(&interface) = self.objects.get(&id) else {
+            return;
+        };
+        let messages = match direction {
+            Direction::Request => interface.requests,
+            Direction::Event => interface.events,
+        };
+        let Some(message) = messages.get(opcode) else {
+            return;
+        };
+
+        let traced = tracing && tracer.traces_interface(interface.name);
+        if traced || message.signature.contains(&ArgumentType::NewId) {
+            let Some(arguments) = decode_arguments(message.signature, body) else {
+                return;
+            };
+            for (index, argument) in arguments.iter().enumerate() {
+                let Argument::NewId(new_id) = argument else {
+                    continue;
+                };
+                // Registry binds name the interface in a string argument before the id
+                let child = message.child_interface.or_else(|| {
+                    arguments[..index].iter().rev().find_map(|argument| match argument {
+                        Argument::Str(Some(name)) => global_interface(name),
+                        _ => None,
+                    })
+                });
+                match child {
+                    Some(child) => self.objects.insert(*new_id, child),
+                    None => self.objects.remove(new_id),
+                };
+            }
+            if traced {
+                tracer.write(&self.record(direction, interface, id, message.name, &arguments));
+            }
+        }
+        if message.is_destructor {
+            self.objects.remove(&id);
+        }
+    }
+
+    /// JSON line describing a message
+    fn record(&self, direction: Direction, interface: &Interface, id: u32, message: &str, arguments: &[Argument]) -> String {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_micros())
+            .unwrap_or(0);
+        let mut record = format!(
+            "{{\"time_us\":{},\"connection\":{},\"pid\":{},\"direction\":\"{}\",\"interface\":\"{}\",\"object\":{},\"message\":\"{}\",\"args\":[",
+            time,
+            self.connection,
+            self.pid.map_or_else(|| "null".to_string(), |pid| pid.to_string()),
+            if direction == Direction::Request { "request" } else { "event" },
+            interface.name,
+            id,
+            message,
+        );
+        for (index, argument) in arguments.iter().enumerate() {
+            if index > 0 {
+                record.push(',');
+            }
+            self.write_argument(&mut record, argument);
+        }
+        record.push_str("]}");
+        record
+    }
+
+    fn write_argument(&self, record: &mut String, argument: &Argument) {
+        let interface = |id: &u32| {
+            self.objects
+                .get(id)
+                .map_or_else(|| "null".to_string(), |interface| format!("\"{}\"", interface.name))
+        };
+        let _ = match argument {
+            Argument::Int(value) => write!(record, "{{\"type\":\"int\",\"value\":{}}}", value),
+            Argument::Uint(value) => write!(record, "{{\"type\":\"uint\",\"value\":{}}}", value),
+            Argument::Fixed(value) => write!(record, "{{\"type\":\"fixed\",\"value\":{}}}", *value as f64 / 256.0),
+            Argument::Str(None) => write!(record, "{{\"type\":\"string\",\"value\":null}}"),
+            Argument::Str(Some(value)) => write!(record, "{{\"type\":\"string\",\"value\":{}}}", json_string(value)),
+            Argument::Object(0) => write!(record, "{{\"type\":\"object\",\"value\":null}}"),
+            Argument::Object(id) => write!(record, "{{\"type\":\"object\",\"interface\":{},\"value\":{}}}", interface(id), id),
+            Argument::NewId(id) => write!(record, "{{\"type\":\"new_id\",\"interface\":{},\"value\":{}}}", interface(id), id),
+            Argument::Array(bytes) => {
+                let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+                write!(record, "{{\"type\":\"array\",\"value\":\"{}\"}}", hex)
+            }
+            Argument::Fd => write!(record, "{{\"type\":\"fd\"}}"),
+        };
+    }
+}
+
+/// Decoded message argument
+#[derive(Debug)]
+enum Argument {
+    Int(i32),
+    Uint(u32),
+    /// Signed 24.8 fixed point
+    Fixed(i32),
+    Str(Option<String>),
+    Object(u32),
+    NewId(u32),
+    Array(Vec<u8>),
+    /// File descriptors travel next to the data and are not decoded
+    Fd,
+}
+
+/// Decode the arguments of a message body, `None` when it is truncated
+fn decode_arguments(signature: &[ArgumentType], mut body: &[u8]) -> Option<Vec<Argument>> {
+    let mut arguments = Vec::with_capacity(signature.len());
+    for argument in signature {
+        arguments.push(match argument {
+            ArgumentType::Int => Argument::Int(take_word(&mut body)? as i32),
+            ArgumentType::Uint => Argument::Uint(take_word(&mut body)?),
+            ArgumentType::Fixed => Argument::Fixed(take_word(&mut body)? as i32),
+            ArgumentType::Str(_) => {
+                let bytes = take_array(&mut body)?;
+                Argument::Str((!bytes.is_empty()).then(|| {
+                    String::from_utf8_lossy(bytes.strip_suffix(&[0]).unwrap_or(bytes)).into_owned()
+                }))
+            }
+            ArgumentType::Object(_) => Argument::Object(take_word(&mut body)?),
+            ArgumentType::NewId => Argument::NewId(take_word(&mut body)?),
+            ArgumentType::Array => Argument::Array(take_array(&mut body)?.to_vec()),
+            ArgumentType::Fd => Argument::Fd,
+        });
+    }
+    Some(arguments)
+}
+
+fn take_word(body: &mut &[u8]) -> Option<u32> {
+    let (word, rest) = body.split_first_chunk::<4>()?;
+    *body = rest;
+    Some(u32::from_ne_bytes(*word))
+}
+
+/// Take a length-prefixed array, padded to 32 bits
+fn take_array<'a>(body: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let length = take_word(body)? as usize;
+    let bytes = body.get(..length)?;
+    *body = body.get(length.next_multiple_of(4)..)?;
+    Some(bytes)
+}
+
+fn json_string(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    escaped.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(escaped, "\\u{:04x}", c as u32);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+fn receive(socket: &UnixStream, buffer: &mut [u8], fds: &mut Vec<OwnedFd>) -> std::io::Result<usize> {
+    let mut space = [0; rustix::cmsg_space!(ScmRights(MAX_FDS))];
+    let mut control = RecvAncillaryBuffer::new(&mut space);
+    let mut iov = [IoSliceMut::new(buffer)];
+    let message = retry_on_intr(|| recvmsg(socket, &mut iov, &mut control, RecvFlags::DONTWAIT | RecvFlags::CMSG_CLOEXEC))?;
+    fds.extend(
+        control
+            .drain()
+            .filter_map(|message| match message {
+                RecvAncillaryMessage::ScmRights(fds) => Some(fds),
+                _ => None,
+            })
+            .flatten(),
+    );
+    Ok(message.bytes)
+}
+
+fn send(socket: &UnixStream, bytes: &[u8], fds: &[OwnedFd]) -> std::io::Result<usize> {
+    let fds: Vec<BorrowedFd<'_>> = fds.iter().map(|fd| fd.as_fd()).collect();
+    let mut space = vec![0; rustix::cmsg_space!(ScmRights(fds.len()))];
+    let mut control = SendAncillaryBuffer::new(&mut space);
+    if !fds.is_empty() {
+        control.push(SendAncillaryMessage::ScmRights(&fds));
+    }
+    let iov = [IoSlice::new(bytes)];
+    Ok(retry_on_intr(|| sendmsg(socket, &iov, &mut control, SendFlags::DONTWAIT | SendFlags::NOSIGNAL))?)
+}
+
+/// Interface of a global this compositor advertises, by name
+fn global_interface(name: &str) -> Option<&'static Interface> {
+    use smithay::reexports::{
+        wayland_protocols::{ext, wp, xdg},
+        wayland_protocols_misc::{zwp_input_method_v2, zwp_virtual_keyboard_v1},
+        wayland_protocols_wlr::{data_control, layer_shell, output_management},
+        wayland_server::protocol::{
+            wl_compositor::WlCompositor, wl_data_device_manager::WlDataDeviceManager, wl_output::WlOutput,
+            wl_seat::WlSeat, wl_shm::WlShm, wl_subcompositor::WlSubcompositor,
+        },
+    };
+
+    let globals: &[fn() -> &'static Interface] = &[
+        WlCompositor::interface,
+        WlSubcompositor::interface,
+        WlShm::interface,
+        WlSeat::interface,
+        WlOutput::interface,
+        WlDataDeviceManager::interface,
+        xdg::shell::server::xdg_wm_base::XdgWmBase::interface,
+        xdg::xdg_output::zv1::server::zxdg_output_manager_v1::ZxdgOutputManagerV1::interface,
+        xdg::decoration::zv1::server::zxdg_decoration_manager_v1::ZxdgDecorationManagerV1::interface,
+        xdg::foreign::zv2::server::zxdg_exporter_v2::ZxdgExporterV2::interface,
+        xdg::foreign::zv2::server::zxdg_importer_v2::ZxdgImporterV2::interface,
+        xdg::toplevel_icon::v1::server::xdg_toplevel_icon_manager_v1::XdgToplevelIconManagerV1::interface,
+        xdg::dialog::v1::server::xdg_wm_dialog_v1::XdgWmDialogV1::interface,
+        xdg::activation::v1::server::xdg_activation_v1::XdgActivationV1::interface,
+        xdg::system_bell::v1::server::xdg_system_bell_v1::XdgSystemBellV1::interface,
+        wp::linux_dmabuf::zv1::server::zwp_linux_dmabuf_v1::ZwpLinuxDmabufV1::interface,
+        wp::relative_pointer::zv1::server::zwp_relative_pointer_manager_v1::ZwpRelativePointerManagerV1::interface,
+        wp::pointer_constraints::zv1::server::zwp_pointer_constraints_v1::ZwpPointerConstraintsV1::interface,
+        wp::presentation_time::server::wp_presentation::WpPresentation::interface,
+        wp::primary_selection::zv1::server::zwp_primary_selection_device_manager_v1::ZwpPrimarySelectionDeviceManagerV1::interface,
+        wp::viewporter::server::wp_viewporter::WpViewporter::interface,
+        wp::fractional_scale::v1::server::wp_fractional_scale_manager_v1::WpFractionalScaleManagerV1::interface,
+        wp::tablet::zv2::server::zwp_tablet_manager_v2::ZwpTabletManagerV2::interface,
+        wp::content_type::v1::server::wp_content_type_manager_v1::WpContentTypeManagerV1::interface,
+        wp::alpha_modifier::v1::server::wp_alpha_modifier_v1::WpAlphaModifierV1::interface,
+        wp::single_pixel_buffer::v1::server::wp_single_pixel_buffer_manager_v1::WpSinglePixelBufferManagerV1::interface,
+        wp::cursor_shape::v1::server::wp_cursor_shape_manager_v1::WpCursorShapeManagerV1::interface,
+        wp::commit_timing::v1::server::wp_commit_timing_manager_v1::WpCommitTimingManagerV1::interface,
+        wp::fifo::v1::server::wp_fifo_manager_v1::WpFifoManagerV1::interface,
+        wp::idle_inhibit::zv1::server::zwp_idle_inhibit_manager_v1::ZwpIdleInhibitManagerV1::interface,
+        wp::keyboard_shortcuts_inhibit::zv1::server::zwp_keyboard_shortcuts_inhibit_manager_v1::ZwpKeyboardShortcutsInhibitManagerV1::interface,
+        wp::pointer_gestures::zv1::server::zwp_pointer_gestures_v1::ZwpPointerGesturesV1::interface,
+        wp::text_input::zv3::server::zwp_text_input_manager_v3::ZwpTextInputManagerV3::interface,
+        wp::security_context::v1::server::wp_security_context_manager_v1::WpSecurityContextManagerV1::interface,
+        wp::linux_drm_syncobj::v1::server::wp_linux_drm_syncobj_manager_v1::WpLinuxDrmSyncobjManagerV1::interface,
+        ext::data_control::v1::server::ext_data_control_manager_v1::ExtDataControlManagerV1::interface,
+        ext::session_lock::v1::server::ext_session_lock_manager_v1::ExtSessionLockManagerV1::interface,
+        ext::foreign_toplevel_list::v1::server::ext_foreign_toplevel_list_v1::ExtForeignToplevelListV1::interface,
+        layer_shell::v1::server::zwlr_layer_shell_v1::ZwlrLayerShellV1::interface,
+        data_control::v1::server::zwlr_data_control_manager_v1::ZwlrDataControlManagerV1::interface,
+        output_management::v1::server::zwlr_output_manager_v1::ZwlrOutputManagerV1::interface,
+        zwp_virtual_keyboard_v1::server::zwp_virtual_keyboard_manager_v1::ZwpVirtualKeyboardManagerV1::interface,
+        zwp_input_method_v2::server::zwp_input_method_manager_v2::ZwpInputMethodManagerV2::interface,
+    ];
+    globals.iter().map(|interface| interface()).find(|interface| interface.name == name)
+}
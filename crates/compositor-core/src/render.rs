@@ -0,0 +1,361 @@
+// Render - Scene composition for outputs
+//
+// Collects the surfaces visible on an output in stacking order, with their
+// positions in global compositor coordinates, for the renderer to draw:
+// background and bottom layers, windows, top and overlay layers, input method
+// popups, the drag icon and finally client cursor surfaces. Bell flashes and
+// then theme cursors are drawn above all of them, see bell.rs and cursor.rs.
+// During a workspace switch the windows of both workspaces are included, slid
+// side by side. While the overview opens, windows of the active workspace
+// move and shrink towards their overview cell, see workspace.rs.
+//
+// Each of them brings its whole surface tree: subsurfaces placed below their
+// parent come before it and those above after it, at their position relative
+// to the parent. Windows and layer surfaces are followed by their xdg popups.
+// Subsurfaces show the state of their last applied commit, which for
+// synchronized ones is that of their parent's last commit.
+//
+// Each drawn surface also carries per-draw state for the renderer: the source
+// crop and destination size of its wp_viewport, the multiplier set through
+// wp_alpha_modifier_v1 and the content type hinted through wp_content_type_v1.
+// Surfaces hidden behind opaque content are culled, see occlusion.rs.
+
+use smithay::{
+    backend::renderer::utils::{with_renderer_surface_state, RendererSurfaceStateUserData, SurfaceView},
+    desktop::{layer_map_for_output, PopupManager, WindowSurfaceType},
+    input::{
+        pointer::{CursorImageStatus, CursorImageSurfaceData},
+        Seat,
+    },
+    output::Output,
+    reexports::{
+        wayland_protocols::wp::content_type::v1::server::wp_content_type_v1,
+        wayland_server::{protocol::wl_surface::WlSurface, Resource},
+    },
+    utils::{Logical, Physical, Point, Rectangle},
+    wayland::{
+        alpha_modifier::AlphaModifierSurfaceCachedState,
+        compositor::{with_states, with_surface_tree_upward, SurfaceData, TraversalAction},
+        content_type::ContentTypeSurfaceCachedState, seat::WaylandFocus, shell::wlr_layer::Layer,
+        single_pixel_buffer::get_single_pixel_buffer,
+    },
+};
+use vulkan_renderer::{single_pixel_color, ContentType, SurfaceDrawState};
+
+use crate::seats::seat_cursor_status;
+use crate::wayland::WaylandServerState;
+
+/// A surface placed in global compositor coordinates
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderElement {
+    pub surface: WlSurface,
+    /// Position of the surface's top-left corner
+    pub location: Point<i32, Logical>,
+    /// Factor the surface is drawn at, below 1.0 for windows shrunk into the overview
+    pub scale: f64,
+}
+
+/// A surface with the state the renderer draws it with
+#[derive(Debug, Clone, PartialEq)]
+pub struct SurfaceDraw {
+    pub surface: WlSurface,
+    pub state: SurfaceDrawState,
+    /// Output pixels per surface coordinate
+    pub scale: f64,
+    /// Parts of the surface not covered by opaque content above it, in output pixels
+    pub visible: Vec<Rectangle<i32, Physical>>,
+}
+
+impl SurfaceDraw {
+    /// Area covered by the surface in output pixels
+    pub fn geometry(&self) -> Rectangle<i32, Physical> {
+        draw_geometry(&self.state)
+    }
+}
+
+/// Area covered by a draw in output pixels
+pub(crate) fn draw_geometry(state: &SurfaceDrawState) -> Rectangle<i32, Physical> {
+    let [x, y] = state.position;
+    let [w, h] = state.destination.unwrap_or_default();
+    Rectangle::<f64, Physical>::new((x as f64, y as f64).into(), (w as f64, h as f64).into()).to_i32_up()
+}
+
+/// View of a surface's current buffer, `None` while it has none
+fn surface_view(states: &SurfaceData) -> Option<SurfaceView> {
+    states.data_map.get::<RendererSurfaceStateUserData>()?.lock().unwrap().view()
+}
+
+/// Push a surface tree back to front, with the root surface's origin at `location`
+///
+/// Subsurfaces of surfaces without a buffer are unmapped and left out.
+fn push_surface_tree(elements: &mut Vec<RenderElement>, root: &WlSurface, location: Point<i32, Logical>) {
+    with_surface_tree_upward(
+        root,
+        location,
+        |_, states, location| match surface_view(states) {
+            Some(view) => TraversalAction::DoChildren(*location + view.offset),
+            None => TraversalAction::SkipChildren,
+        },
+        |surface, states, location| {
+            let location = match surface_view(states) {
+                Some(view) => *location + view.offset,
+                None if surface == root => *location,
+                None => return,
+            };
+            elements.push(RenderElement { surface: surface.clone(), location, scale: 1.0 });
+        },
+        |_, _, _| true,
+    );
+}
+
+/// Push the popups of a window or layer surface, `geometry_origin` being the origin of its geometry
+fn push_popups(elements: &mut Vec<RenderElement>, surface: &WlSurface, geometry_origin: Point<i32, Logical>) {
+    for (popup, offset) in PopupManager::popups_for_surface(surface) {
+        push_surface_tree(elements, popup.wl_surface(), geometry_origin + offset - popup.geometry().loc);
+    }
+}
+
+/// Content type hinted by a surface's client
+pub fn surface_content_type(surface: &WlSurface) -> ContentType {
+    let content_type = with_states(surface, |states| {
+        *states.cached_state.get::<ContentTypeSurfaceCachedState>().current().content_type()
+    });
+    match content_type {
+        wp_content_type_v1::Type::Photo => ContentType::Photo,
+        wp_content_type_v1::Type::Video => ContentType::Video,
+        wp_content_type_v1::Type::Game => ContentType::Game,
+        _ => ContentType::None,
+    }
+}
+
+/// Draw state of a surface with its top-left corner at `position` in output pixels
+///
+/// The viewport source is converted to buffer pixels and the destination
+/// scaled by the output scale. Single-pixel buffers are drawn as solid quads
+/// of their color. Surfaces without a buffer have nothing to draw.
+pub fn surface_draw_state(surface: &WlSurface, position: Point<f64, Physical>, scale: f64) -> Option<SurfaceDrawState> {
+    let (view, buffer_scale, transform, size, color) = with_renderer_surface_state(surface, |state| {
+        let color = state
+            .buffer()
+            .and_then(|buffer| get_single_pixel_buffer(buffer).ok())
+            .map(|pixel| single_pixel_color(pixel.rgba32f()));
+        Some((state.view()?, state.buffer_scale(), state.buffer_transform(), state.buffer_size()?, color))
+    })??;
+    let source = view.src.to_buffer(buffer_scale as f64, transform, &size.to_f64());
+    let destination = view.dst.to_f64().to_physical(scale);
+    let alpha = with_states(surface, |states| {
+        states.cached_state.get::<AlphaModifierSurfaceCachedState>().current().multiplier_f32()
+    });
+
+    Some(SurfaceDrawState {
+        position: [position.x as f32, position.y as f32],
+        source: Some([source.loc.x as f32, source.loc.y as f32, source.size.w as f32, source.size.h as f32]),
+        destination: Some([destination.w as f32, destination.h as f32]),
+        alpha: alpha.unwrap_or(1.0),
+        content_type: surface_content_type(surface),
+        color,
+    })
+}
+
+impl WaylandServerState {
+    /// Global geometry of a window or layer surface
+    ///
+    /// The location is that of the surface's top-left corner, which for windows
+    /// may lie outside their visible geometry.
+    pub fn surface_geometry(&self, surface: &WlSurface) -> Option<Rectangle<i32, Logical>> {
+        let window = self
+            .space
+            .elements()
+            .find(|window| window.wl_surface().as_deref() == Some(surface));
+        if let Some(window) = window {
+            let geometry = window.geometry();
+            let location = self.space.element_location(window)? - geometry.loc;
+            return Some(Rectangle::new(location, geometry.size));
+        }
+
+        self.space.outputs().find_map(|output| {
+            let output_location = self.space.output_geometry(output)?.loc;
+            let layer_map = layer_map_for_output(output);
+            let layer = layer_map.layer_for_surface(surface, WindowSurfaceType::TOPLEVEL)?;
+            let mut geometry = layer_map.layer_geometry(layer)?;
+            geometry.loc += output_location;
+            Some(geometry)
+        })
+    }
+
+    /// Topmost surface accepting input at a global location, with its global origin
+    pub fn surface_under(&self, location: Point<f64, Logical>) -> Option<(WlSurface, Point<f64, Logical>)> {
+        let output = self.space.output_under(location).next()?;
+        let output_location = self.space.output_geometry(output)?.loc.to_f64();
+        let layer_map = layer_map_for_output(output);
+        let layer_surface_under = |layer: Layer| {
+            let layer_surface = layer_map.layer_under(layer, location - output_location)?;
+            let layer_location = output_location + layer_map.layer_geometry(layer_surface)?.loc.to_f64();
+            layer_surface
+                .surface_under(location - layer_location, WindowSurfaceType::ALL)
+                .map(|(surface, offset)| (surface, layer_location + offset.to_f64()))
+        };
+
+        if let Some(found) = layer_surface_under(Layer::Overlay).or_else(|| layer_surface_under(Layer::Top)) {
+            return Some(found);
+        }
+        if let Some((window, window_location)) = self.space.element_under(location) {
+            // Windows with modal dialogs take no input, nor do the layers they cover
+            if self.is_blocked_by_dialog(window) {
+                return None;
+            }
+            let window_location = window_location.to_f64();
+            let found = window
+                .surface_under(location - window_location, WindowSurfaceType::ALL)
+                .map(|(surface, offset)| (surface, window_location + offset.to_f64()));
+            if found.is_some() {
+                return found;
+            }
+        }
+        layer_surface_under(Layer::Bottom).or_else(|| layer_surface_under(Layer::Background))
+    }
+
+    /// Cursor to draw for the default seat, with compositor feedback taking precedence over the client's choice
+    pub fn cursor_image(&self) -> CursorImageStatus {
+        self.seat_cursor_image(&self.seat)
+    }
+
+    /// Cursor to draw for a seat
+    ///
+    /// Drag and drop feedback only shows on the default seat.
+    pub fn seat_cursor_image(&self, seat: &Seat<Self>) -> CursorImageStatus {
+        match self.dnd_state.cursor_icon() {
+            Some(icon) if seat == &self.seat => CursorImageStatus::Named(icon),
+            _ => seat_cursor_status(seat),
+        }
+    }
+
+    /// Surfaces visible on an output with their subsurfaces and popups, back to front
+    pub fn render_elements(&self, output: &Output) -> Vec<RenderElement> {
+        let Some(output_geometry) = self.space.output_geometry(output) else {
+            return Vec::new();
+        };
+
+        let mut elements = Vec::new();
+        let layer_map = layer_map_for_output(output);
+        let push_layers = |elements: &mut Vec<RenderElement>, layer: Layer| {
+            for layer_surface in layer_map.layers_on(layer) {
+                if let Some(geometry) = layer_map.layer_geometry(layer_surface) {
+                    let location = output_geometry.loc + geometry.loc;
+                    push_surface_tree(elements, layer_surface.wl_surface(), location);
+                    push_popups(elements, layer_surface.wl_surface(), location);
+                }
+            }
+        };
+
+        push_layers(&mut elements, Layer::Background);
+        push_layers(&mut elements, Layer::Bottom);
+
+        // While switching, workspaces slide side by side by the output's width
+        let switch = self.workspace_switch();
+        let slide = |offset: f64| Point::from(((offset * output_geometry.size.w as f64).round() as i32, 0));
+        let active_slide = slide(switch.map_or(0.0, |(_, offset)| offset));
+        let overview = self.overview_progress();
+        let tiles = if overview > 0.0 { self.overview_tiles(output) } else { Vec::new() };
+        for window in self.space.elements_for_output(output) {
+            let (Some(surface), Some(location)) = (window.wl_surface(), self.space.element_location(window)) else {
+                continue;
+            };
+            let first = elements.len();
+            // Space locations refer to the window geometry, which may exclude client-side shadows
+            push_surface_tree(&mut elements, &surface, location - window.geometry().loc + active_slide);
+            push_popups(&mut elements, &surface, location + active_slide);
+
+            // Shrink the whole window around its geometry origin on the way to its cell
+            if let Some((_, tile)) = tiles.iter().find(|(tiled, _)| tiled == window) {
+                let origin = (location + active_slide).to_f64();
+                let scale = 1.0 + (tile.scale - 1.0) * overview;
+                let placed = origin + (tile.location - origin).upscale(overview);
+                for element in &mut elements[first..] {
+                    element.location = (placed + (element.location.to_f64() - origin).upscale(scale)).to_i32_round();
+                    element.scale = scale;
+                }
+            }
+        }
+        if let Some((target, offset)) = switch {
+            let target_slide = slide(offset - offset.signum());
+            for (window, location) in self.hidden_windows(target) {
+                let geometry = window.geometry();
+                let Some(surface) = window.wl_surface() else {
+                    continue;
+                };
+                if Rectangle::new(*location, geometry.size).overlaps(output_geometry) {
+                    push_surface_tree(&mut elements, &surface, *location - geometry.loc + target_slide);
+                    push_popups(&mut elements, &surface, *location + target_slide);
+                }
+            }
+        }
+        push_layers(&mut elements, Layer::Top);
+        push_layers(&mut elements, Layer::Overlay);
+
+        // Candidate windows must never be covered by the surfaces they annotate
+        for popup in &self.input_method_popups {
+            if let Some(geometry) = self.input_method_popup_geometry(popup) {
+                if output_geometry.contains(geometry.loc) {
+                    push_surface_tree(&mut elements, popup.wl_surface(), geometry.loc);
+                }
+            }
+        }
+
+        // Each seat's pointer carries its own cursor
+        for seat in self.seats() {
+            let Some(pointer) = seat.get_pointer() else {
+                continue;
+            };
+            let pointer_location = pointer.current_location().to_i32_round();
+            if !output_geometry.contains(pointer_location) {
+                continue;
+            }
+
+            if let Some(icon) = self.dnd_state.icon().filter(|_| seat == &self.seat) {
+                push_surface_tree(&mut elements, &icon.surface, pointer_location + icon.offset);
+            }
+
+            if let CursorImageStatus::Surface(surface) = self.seat_cursor_image(seat) {
+                if surface.is_alive() {
+                    let hotspot = with_states(&surface, |states| {
+                        states
+                            .data_map
+                            .get::<CursorImageSurfaceData>()
+                            .map(|data| data.lock().unwrap().hotspot)
+                            .unwrap_or_default()
+                    });
+                    push_surface_tree(&mut elements, &surface, pointer_location - hotspot);
+                }
+            }
+        }
+
+        elements
+    }
+
+    /// Surfaces visible on an output with their draw state, back to front
+    ///
+    /// Surfaces hidden entirely behind opaque content are left out.
+    pub fn surface_draws(&self, output: &Output) -> Vec<SurfaceDraw> {
+        self.cull_occluded(output).draws
+    }
+
+    /// Surfaces on an output with their draw state, back to front, hidden or not
+    pub(crate) fn all_surface_draws(&self, output: &Output) -> Vec<SurfaceDraw> {
+        let Some(output_geometry) = self.space.output_geometry(output) else {
+            return Vec::new();
+        };
+        let scale = output.current_scale().fractional_scale();
+        self.render_elements(output)
+            .into_iter()
+            .filter_map(|element| {
+                let position = (element.location - output_geometry.loc).to_f64().to_physical(scale);
+                let scale = scale * element.scale;
+                let state = surface_draw_state(&element.surface, position, scale)?;
+                let mut draw = SurfaceDraw { surface: element.surface, state, scale, visible: Vec::new() };
+                draw.visible = vec![draw.geometry()];
+                Some(draw)
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,564 @@
+// Replay - Recording input and output timing to reproduce bugs
+//
+// A recording captures what the backend hands the compositor: input events as
+// they are applied, see input.rs, and when the frames of each output are built
+// and presented. Along with them it captures the decisions the compositor took
+// in response: keyboard focus, where windows are laid out and which surfaces
+// each frame draws.
+//
+// Replaying a recording drives the outputs without a display. The frame clock
+// timers stand down and frames are built and presented when the recording
+// says, on a virtual clock that jumps from one recorded event to the next, so
+// animations, commit timers and presentation times all see the recorded
+// timing. The decisions taken during a replay are compared to the recorded
+// ones and the first difference is reported: a replay that went the same way
+// as the recorded session reproduces its bug, and a regression test replays
+// it and asserts on the state it ends in.
+//
+// Clients are not part of a recording. A regression test connects the same
+// clients and makes the same requests between the replayed events. A replay
+// set in the configuration starts with the compositor and follows real time,
+// so that clients started alongside make their requests in between as well.
+//
+// Input is recorded with the seat it was applied to, and replayed on the seat
+// with the same name.
+//
+// Recordings are a header followed by timed records, encoded with bincode like
+// IPC messages. Times are relative to the start of the recording.
+
+use compositor_utils::prelude::*;
+use serde::{Deserialize, Serialize};
+use smithay::{
+    input::Seat,
+    output::Output,
+    reexports::{
+        wayland_protocols::wp::presentation_time::server::wp_presentation_feedback,
+        wayland_server::protocol::wl_surface::WlSurface,
+    },
+    utils::{Clock, Monotonic, Time},
+};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::frame::root_surface;
+use crate::input::BackendInput;
+use crate::render::RenderElement;
+use crate::wayland::WaylandServerState;
+use crate::workspace::window_id;
+
+/// Version of the recording format
+const RECORDING_VERSION: u32 = 2;
+
+/// Clock of the compositor, on real or virtual time
+#[derive(Debug)]
+pub struct CompositorClock {
+    clock: Clock<Monotonic>,
+    /// Current time while running on virtual time
+    virtual_time: Option<Duration>,
+}
+
+impl Default for CompositorClock {
+    fn default() -> Self {
+        Self { clock: Clock::new(), virtual_time: None }
+    }
+}
+
+impl CompositorClock {
+    /// Current time on the monotonic clock, or the virtual time
+    pub fn now(&self) -> Time<Monotonic> {
+        match self.virtual_time {
+            Some(time) => Time::from(time),
+            None => self.clock.now(),
+        }
+    }
+
+    /// Whether the clock runs on virtual time
+    pub fn is_virtual(&self) -> bool {
+        self.virtual_time.is_some()
+    }
+
+    /// Switch to virtual time, moving it forward to `time`
+    ///
+    /// Virtual time never goes backwards.
+    fn advance_to(&mut self, time: Duration) {
+        self.virtual_time = Some(self.virtual_time.map_or(time, |now| time.max(now)));
+    }
+}
+
+/// Window laid out on the active workspace
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WindowLayout {
+    pub window: u32,
+    pub location: (i32, i32),
+    pub size: (i32, i32),
+}
+
+/// Decision taken by the compositor in response to input or a frame
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Decision {
+    /// Keyboard focus of a seat moved to a window, or to no window
+    Focus { seat: String, window: Option<u32> },
+    /// Windows of the active workspace changed place, bottom to top
+    Layout { windows: Vec<WindowLayout> },
+    /// Surfaces drawn in a frame of an output, back to front, by the window
+    /// they belong to and their location
+    Render { output: String, elements: Vec<(Option<u32>, (i32, i32))> },
+}
+
+/// Output present when a recording was made
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedOutput {
+    pub name: String,
+    pub location: (i32, i32),
+    pub size: (i32, i32),
+}
+
+/// Start of a recording
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordingHeader {
+    pub version: u32,
+    pub outputs: Vec<RecordedOutput>,
+    /// Names of the seats
+    pub seats: Vec<String>,
+}
+
+/// Event of a recording
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum RecordedEvent {
+    /// Input applied to the seat with the given name
+    Input { seat: String, input: BackendInput },
+    /// A frame of an output was built
+    RenderFrame { output: String },
+    /// The last frame built for an output was shown
+    FramePresented { output: String, sequence: Option<u64>, flags: u32 },
+    Decision(Decision),
+}
+
+/// Event of a recording with the time since the recording started
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+    pub time: Duration,
+    pub event: RecordedEvent,
+}
+
+/// First decision of a replay that differs from the recording
+///
+/// A missing decision means one side took fewer decisions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    pub index: usize,
+    pub recorded: Option<Decision>,
+    pub replayed: Option<Decision>,
+}
+
+/// Outcome of a replay
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplayReport {
+    /// Input and frame events applied
+    pub events: usize,
+    /// Decisions taken during the replay
+    pub decisions: Vec<Decision>,
+    pub divergence: Option<Divergence>,
+}
+
+#[derive(Debug)]
+struct Recorder {
+    output: BufWriter<File>,
+    /// Time the recording started at
+    start: Duration,
+}
+
+#[derive(Debug)]
+struct Replay {
+    /// Input and frame events left to apply
+    events: VecDeque<Record>,
+    /// Decisions of the recording
+    recorded: Vec<Decision>,
+    /// Decisions taken so far
+    decisions: Vec<Decision>,
+    divergence: Option<Divergence>,
+    /// Virtual time the replay started at
+    start: Duration,
+    applied: usize,
+}
+
+/// Recording or replay in progress
+#[derive(Debug, Default)]
+pub struct ReplayState {
+    recorder: Option<Recorder>,
+    replay: Option<Replay>,
+    /// Last layout decided, layouts are only logged when they change
+    layout: Option<Vec<WindowLayout>>,
+}
+
+impl ReplayState {
+    /// Whether input and output timing is being recorded
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    /// Whether a recording is being replayed
+    pub fn is_replaying(&self) -> bool {
+        self.replay.is_some()
+    }
+}
+
+/// Read a recording
+fn read_recording(path: &Path) -> Result<(RecordingHeader, Vec<Record>)> {
+    let file = File::open(path)
+        .map_err(|e| CompositorError::runtime(format!("Failed to open recording {}: {}", path.display(), e)))?;
+    let mut reader = BufReader::new(file);
+    let header: RecordingHeader = bincode::deserialize_from(&mut reader)
+        .map_err(|e| CompositorError::runtime(format!("Invalid recording {}: {}", path.display(), e)))?;
+    if header.version != RECORDING_VERSION {
+        return Err(CompositorError::runtime(format!(
+            "Recording {} has version {}, expected {}",
+            path.display(),
+            header.version,
+            RECORDING_VERSION
+        )));
+    }
+
+    let mut records = Vec::new();
+    loop {
+        match bincode::deserialize_from(&mut reader) {
+            Ok(record) => records.push(record),
+            // A recording cut short by a crash ends with a partial record
+            Err(e) if matches!(&*e, bincode::ErrorKind::Io(e) if e.kind() == ErrorKind::UnexpectedEof) => break,
+            Err(e) => {
+                return Err(CompositorError::runtime(format!("Invalid record in {}: {}", path.display(), e)));
+            }
+        }
+    }
+    Ok((header, records))
+}
+
+impl WaylandServerState {
+    /// File recordings are written to
+    pub fn recording_path(&self) -> PathBuf {
+        self.config.recording.path.clone().unwrap_or_else(|| {
+            std::env::var_os("XDG_RUNTIME_DIR")
+                .map(PathBuf::from)
+                .unwrap_or_else(std::env::temp_dir)
+                .join(format!("{}-recording.bin", self.socket_name.as_deref().unwrap_or("wayland")))
+        })
+    }
+
+    /// Start or stop recording input and output timing
+    ///
+    /// A new recording replaces the recording file.
+    pub fn set_recording(&mut self, enabled: bool) -> Result<()> {
+        if !enabled {
+            if let Some(mut recorder) = self.replay_state.recorder.take() {
+                if let Err(e) = recorder.output.flush() {
+                    warn!("Failed to write recording: {}", e);
+                }
+                info!("Stopped recording");
+            }
+            return Ok(());
+        }
+        if self.replay_state.recorder.is_some() {
+            return Ok(());
+        }
+        if self.replay_state.replay.is_some() {
+            return Err(CompositorError::runtime("Cannot record while replaying"));
+        }
+
+        let header = RecordingHeader {
+            version: RECORDING_VERSION,
+            outputs: self
+                .space
+                .outputs()
+                .filter_map(|output| {
+                    let geometry = self.space.output_geometry(output)?;
+                    Some(RecordedOutput {
+                        name: output.name(),
+                        location: geometry.loc.into(),
+                        size: geometry.size.into(),
+                    })
+                })
+                .collect(),
+            seats: self.seats().map(|seat| seat.name().to_string()).collect(),
+        };
+        let path = self.recording_path();
+        let file = File::create(&path)
+            .map_err(|e| CompositorError::runtime(format!("Failed to create recording {}: {}", path.display(), e)))?;
+        let mut output = BufWriter::new(file);
+        bincode::serialize_into(&mut output, &header)
+            .map_err(|e| CompositorError::runtime(format!("Failed to write recording {}: {}", path.display(), e)))?;
+        self.replay_state.recorder = Some(Recorder { output, start: Duration::from(self.clock.now()) });
+        self.replay_state.layout = None;
+        info!("Recording input and output timing to {}", path.display());
+        Ok(())
+    }
+
+    /// Add an event to the recording
+    fn record(&mut self, event: RecordedEvent) {
+        let now = Duration::from(self.clock.now());
+        let Some(recorder) = self.replay_state.recorder.as_mut() else {
+            return;
+        };
+        // Frames are flushed as they are shown, to keep as much as possible of a crashing session
+        let flush = matches!(event, RecordedEvent::FramePresented { .. });
+        let record = Record { time: now.saturating_sub(recorder.start), event };
+        let written = bincode::serialize_into(&mut recorder.output, &record)
+            .map_err(|e| e.to_string())
+            .and_then(|()| if flush { recorder.output.flush().map_err(|e| e.to_string()) } else { Ok(()) });
+        if let Err(e) = written {
+            warn!("Stopped recording after failing to write it: {}", e);
+            self.replay_state.recorder = None;
+        }
+    }
+
+    /// Record an input event about to be applied to a seat
+    pub(crate) fn record_input(&mut self, seat: &Seat<Self>, input: &BackendInput) {
+        if self.replay_state.recorder.is_some() {
+            self.record(RecordedEvent::Input { seat: seat.name().to_string(), input: input.clone() });
+        }
+    }
+
+    /// Record a frame of an output being built
+    pub(crate) fn record_render_frame(&mut self, output: &Output) {
+        if self.replay_state.recorder.is_some() {
+            self.record(RecordedEvent::RenderFrame { output: output.name() });
+        }
+    }
+
+    /// Record a frame of an output being shown
+    pub(crate) fn record_frame_presented(
+        &mut self,
+        output: &Output,
+        sequence: Option<u64>,
+        flags: wp_presentation_feedback::Kind,
+    ) {
+        if self.replay_state.recorder.is_some() {
+            self.record(RecordedEvent::FramePresented { output: output.name(), sequence, flags: flags.bits() });
+        }
+    }
+
+    /// Log a decision, to the recording or to compare with the one replayed
+    fn log_decision(&mut self, decision: Decision) {
+        if self.replay_state.recorder.is_some() {
+            self.record(RecordedEvent::Decision(decision));
+            return;
+        }
+        let Some(replay) = self.replay_state.replay.as_mut() else {
+            return;
+        };
+        let index = replay.decisions.len();
+        let recorded = replay.recorded.get(index);
+        if replay.divergence.is_none() && recorded != Some(&decision) {
+            warn!("Replay diverged at decision {}: recorded {:?}, replayed {:?}", index, recorded, decision);
+            replay.divergence = Some(Divergence { index, recorded: recorded.cloned(), replayed: Some(decision.clone()) });
+        }
+        replay.decisions.push(decision);
+    }
+
+    fn logs_decisions(&self) -> bool {
+        self.replay_state.recorder.is_some() || self.replay_state.replay.is_some()
+    }
+
+    /// Log the keyboard focus of a seat moving to a surface
+    pub(crate) fn log_focus_decision(&mut self, seat: &Seat<Self>, focused: Option<&WlSurface>) {
+        if !self.logs_decisions() {
+            return;
+        }
+        let window = focused
+            .and_then(|surface| self.window_for_surface(surface))
+            .and_then(|window| window_id(&window))
+            .map(|id| id.0);
+        self.log_decision(Decision::Focus { seat: seat.name().to_string(), window });
+    }
+
+    /// Log the layout and contents of a frame being built
+    pub(crate) fn log_frame_decisions(&mut self, output: &Output, elements: &[RenderElement]) {
+        if !self.logs_decisions() {
+            return;
+        }
+        let windows: Vec<WindowLayout> = self
+            .space
+            .elements()
+            .filter_map(|window| {
+                Some(WindowLayout {
+                    window: window_id(window)?.0,
+                    location: self.space.element_location(window)?.into(),
+                    size: window.geometry().size.into(),
+                })
+            })
+            .collect();
+        if self.replay_state.layout.as_ref() != Some(&windows) {
+            self.replay_state.layout = Some(windows.clone());
+            self.log_decision(Decision::Layout { windows });
+        }
+
+        let elements = elements
+            .iter()
+            .map(|element| {
+                let window = self
+                    .window_for_surface(&root_surface(&element.surface))
+                    .and_then(|window| window_id(&window))
+                    .map(|id| id.0);
+                (window, element.location.into())
+            })
+            .collect();
+        self.log_decision(Decision::Render { output: output.name(), elements });
+    }
+
+    /// Start replaying a recording
+    ///
+    /// The outputs and seats of the recording must be in place. From now on the clock
+    /// runs on virtual time, which stays after the replay finishes.
+    pub fn start_replay(&mut self, path: &Path) -> Result<()> {
+        if self.replay_state.recorder.is_some() {
+            return Err(CompositorError::runtime("Cannot replay while recording"));
+        }
+        let (header, records) = read_recording(path)?;
+        for recorded in &header.outputs {
+            let geometry = self
+                .space
+                .outputs()
+                .find(|output| output.name() == recorded.name)
+                .and_then(|output| self.space.output_geometry(output));
+            match geometry {
+                Some(geometry) if geometry.loc == recorded.location.into() && geometry.size == recorded.size.into() => {}
+                _ => {
+                    return Err(CompositorError::runtime(format!(
+                        "Recording needs output {} at {:?} with size {:?}",
+                        recorded.name, recorded.location, recorded.size
+                    )));
+                }
+            }
+        }
+        if let Some(seat) = header.seats.iter().find(|seat| self.seat_by_name(seat).is_none()) {
+            return Err(CompositorError::runtime(format!("Recording needs seat {}", seat)));
+        }
+
+        let (decisions, events): (Vec<Record>, Vec<Record>) =
+            records.into_iter().partition(|record| matches!(record.event, RecordedEvent::Decision(_)));
+        let recorded = decisions
+            .into_iter()
+            .filter_map(|record| match record.event {
+                RecordedEvent::Decision(decision) => Some(decision),
+                _ => None,
+            })
+            .collect();
+        let start = Duration::from(self.clock.now());
+        self.clock.advance_to(start);
+        info!("Replaying {} events from {}", events.len(), path.display());
+        self.replay_state.replay = Some(Replay {
+            events: events.into(),
+            recorded,
+            decisions: Vec::new(),
+            divergence: None,
+            start,
+            applied: 0,
+        });
+        self.replay_state.layout = None;
+        Ok(())
+    }
+
+    /// Start replaying the recording set in the configuration, if any
+    ///
+    /// Returns whether a replay started, which `advance_replay` then drives
+    /// in place of the frame clocks.
+    pub fn start_configured_replay(&mut self) -> Result<bool> {
+        let Some(path) = self.config.recording.replay.clone() else {
+            return Ok(false);
+        };
+        self.start_replay(&path)?;
+        Ok(true)
+    }
+
+    /// Replay the events recorded up to a time since the start of the
+    /// replay, and finish the replay once no events are left
+    ///
+    /// Returns the report of the finished replay.
+    pub fn advance_replay(&mut self, time: Duration) -> Option<ReplayReport> {
+        self.replay_until(time);
+        if self.next_replay_time().is_some() {
+            return None;
+        }
+        let report = self.finish_replay()?;
+        match &report.divergence {
+            None => info!("Replay took the {} recorded decisions", report.decisions.len()),
+            Some(divergence) => warn!(
+                "Replay diverged at decision {}: recorded {:?}, replayed {:?}",
+                divergence.index, divergence.recorded, divergence.replayed
+            ),
+        }
+        Some(report)
+    }
+
+    /// Time since the start of the replay of its next event
+    pub fn next_replay_time(&self) -> Option<Duration> {
+        Some(self.replay_state.replay.as_ref()?.events.front()?.time)
+    }
+
+    /// Replay the events recorded up to a time since the start of the replay
+    ///
+    /// The virtual clock moves to each event in turn and ends at `time`.
+    /// Returns the number of events applied.
+    pub fn replay_until(&mut self, time: Duration) -> usize {
+        let Some(start) = self.replay_state.replay.as_ref().map(|replay| replay.start) else {
+            return 0;
+        };
+        let mut applied = 0;
+        while let Some(record) = self.next_replay_event(time) {
+            self.clock.advance_to(start + record.time);
+            self.apply_recorded(record.event);
+            applied += 1;
+        }
+        self.clock.advance_to(start + time);
+        if let Some(replay) = self.replay_state.replay.as_mut() {
+            replay.applied += applied;
+        }
+        applied
+    }
+
+    fn next_replay_event(&mut self, time: Duration) -> Option<Record> {
+        let events = &mut self.replay_state.replay.as_mut()?.events;
+        if events.front()?.time > time {
+            return None;
+        }
+        events.pop_front()
+    }
+
+    fn apply_recorded(&mut self, event: RecordedEvent) {
+        let output_named = |state: &Self, name: &str| state.space.outputs().find(|output| output.name() == name).cloned();
+        match event {
+            RecordedEvent::Input { seat, input } => match self.seat_by_name(&seat) {
+                Some(seat) => self.apply_seat_input(&seat, input),
+                None => warn!("Replayed input of missing seat {}", seat),
+            },
+            RecordedEvent::RenderFrame { output } => match output_named(self, &output) {
+                Some(output) => {
+                    self.render_frame(&output);
+                }
+                None => warn!("Replayed frame of missing output {}", output),
+            },
+            RecordedEvent::FramePresented { output, sequence, flags } => match output_named(self, &output) {
+                Some(output) => {
+                    let time = Duration::from(self.clock.now());
+                    let flags = wp_presentation_feedback::Kind::from_bits_truncate(flags);
+                    self.frame_presented(&output, time, sequence, flags);
+                }
+                None => warn!("Replayed frame of missing output {}", output),
+            },
+            RecordedEvent::Decision(_) => {}
+        }
+    }
+
+    /// Stop replaying and report how the replay compared to the recording
+    pub fn finish_replay(&mut self) -> Option<ReplayReport> {
+        let mut replay = self.replay_state.replay.take()?;
+        if replay.divergence.is_none() && replay.recorded.len() > replay.decisions.len() {
+            let index = replay.decisions.len();
+            replay.divergence = Some(Divergence { index, recorded: replay.recorded.get(index).cloned(), replayed: None });
+        }
+        info!("Replayed {} events, {} left", replay.applied, replay.events.len());
+        Some(ReplayReport { events: replay.applied, decisions: replay.decisions, divergence: replay.divergence })
+    }
+}
@@ -0,0 +1,332 @@
+// Resource limits - Per-client resource accounting and enforcement
+//
+// Every client is charged for what it holds in the compositor: its surfaces,
+// SHM buffers and SHM pool memory, the frame callbacks waiting for a frame and
+// the texture memory its committed buffers take once uploaded. Commits and
+// object creations are also counted over one-second windows. A client going
+// over one of the `[limits]` of the configuration gets a wl_display.no_memory
+// protocol error on the offending object and is disconnected.
+//
+// Disconnecting destroys all objects of the client, which releases their
+// accounting with the client state. The surfaces destroyed release their
+// textures in the renderer, so one broken client cannot exhaust memory for
+// the others.
+//
+// wl_shm objects are dispatched here for accounting before smithay's
+// ShmState handles them. smithay keeps the size of SHM pools private, so the
+// size declared when a pool is created is charged before smithay maps it, and
+// moved to the pool once smithay has created its object.
+
+use compositor_utils::prelude::*;
+use config::LimitsConfig;
+use smithay::{
+    backend::renderer::{buffer_dimensions, utils::with_renderer_surface_state},
+    reexports::wayland_server::{
+        backend::{ClientId, ObjectId},
+        protocol::{
+            wl_buffer::{self, WlBuffer},
+            wl_shm::{self, WlShm},
+            wl_shm_pool::{self, WlShmPool},
+            wl_surface::WlSurface,
+        },
+        Client, DataInit, Dispatch, DisplayHandle, Resource,
+    },
+    utils::IsAlive,
+    wayland::{
+        compositor::{with_states, SurfaceAttributes},
+        shm::{ShmBufferUserData, ShmPoolUserData, ShmState},
+    },
+};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::wayland::{ClientState, WaylandServerState};
+use crate::surface_manager::surface_id;
+
+/// wl_display.error.no_memory, a global error any object may be posted
+const NO_MEMORY: u32 = 2;
+
+/// Bytes per pixel of uploaded textures
+const TEXTURE_BYTES_PER_PIXEL: u64 = 4;
+
+/// Resources a client holds
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ResourceUsage {
+    pub surfaces: usize,
+    pub buffers: usize,
+    pub shm_pool_bytes: u64,
+    pub pending_callbacks: usize,
+    pub texture_bytes: u64,
+}
+
+impl ResourceUsage {
+    /// First limit the usage goes over
+    fn exceeded(&self, limits: &LimitsConfig) -> Option<&'static str> {
+        if self.surfaces > limits.max_surfaces {
+            Some("surface limit")
+        } else if self.buffers > limits.max_buffers {
+            Some("buffer limit")
+        } else if self.shm_pool_bytes > limits.max_shm_pool_bytes {
+            Some("SHM pool memory limit")
+        } else if self.pending_callbacks > limits.max_pending_callbacks {
+            Some("pending frame callback limit")
+        } else if self.texture_bytes > limits.max_texture_bytes {
+            Some("texture memory limit")
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Accounts {
+    /// Surfaces created, pruned as they are destroyed
+    surfaces: Vec<WlSurface>,
+    buffers: usize,
+    /// Size of each SHM pool
+    pools: HashMap<ObjectId, u64>,
+    /// Size of the SHM pool being created, until its object exists
+    new_pool: u64,
+    /// Start of the current request window
+    window_start: Option<Duration>,
+    requests: u32,
+}
+
+impl Accounts {
+    /// Size charged for a pool
+    fn pool(&mut self, pool: &WlShmPool) -> &mut u64 {
+        self.pools.entry(pool.id()).or_default()
+    }
+
+    fn usage(&mut self) -> ResourceUsage {
+        self.surfaces.retain(|surface| surface.alive());
+        let mut usage = ResourceUsage {
+            surfaces: self.surfaces.len(),
+            buffers: self.buffers,
+            shm_pool_bytes: self.pools.values().sum::<u64>() + self.new_pool,
+            ..ResourceUsage::default()
+        };
+        for surface in &self.surfaces {
+            usage.pending_callbacks += with_states(surface, |states| {
+                states.cached_state.get::<SurfaceAttributes>().current().frame_callbacks.len()
+            });
+            let size = with_renderer_surface_state(surface, |state| state.buffer().and_then(|buffer| buffer_dimensions(buffer)))
+                .flatten();
+            usage.texture_bytes += size.map_or(0, |size| size.w as u64 * size.h as u64 * TEXTURE_BYTES_PER_PIXEL);
+        }
+        usage
+    }
+}
+
+/// Resource accounting of a client, kept in its `ClientState`
+#[derive(Debug, Default)]
+pub struct ClientResources {
+    accounts: Mutex<Accounts>,
+}
+
+impl ClientResources {
+    /// Resources the client holds
+    pub fn usage(&self) -> ResourceUsage {
+        self.accounts.lock().unwrap().usage()
+    }
+
+    /// Count a request and apply its change, returning the limit it goes over
+    fn charge(&self, now: Duration, limits: &LimitsConfig, change: impl FnOnce(&mut Accounts)) -> Option<&'static str> {
+        let mut accounts = self.accounts.lock().unwrap();
+        change(&mut accounts);
+
+        let window_start = *accounts.window_start.get_or_insert(now);
+        if now.saturating_sub(window_start) >= Duration::from_secs(1) {
+            accounts.window_start = Some(now);
+            accounts.requests = 0;
+        }
+        accounts.requests += 1;
+        if accounts.requests > limits.max_requests_per_second {
+            return Some("request rate limit");
+        }
+        accounts.usage().exceeded(limits)
+    }
+
+    fn release(&self, change: impl FnOnce(&mut Accounts)) {
+        change(&mut self.accounts.lock().unwrap());
+    }
+}
+
+impl WaylandServerState {
+    /// Charge a client for a request on `object`, disconnecting it when it goes over a limit
+    ///
+    /// Returns whether the request may proceed.
+    fn charge<I: Resource>(&self, client: &Client, object: &I, change: impl FnOnce(&mut Accounts)) -> bool {
+        let Some(client_state) = client.get_data::<ClientState>() else {
+            return true;
+        };
+        let now = Duration::from(self.clock.now());
+        let Some(limit) = client_state.resources.charge(now, &self.config.limits, change) else {
+            return true;
+        };
+        match client_state.identity.get() {
+            Some(identity) => warn!("Disconnecting client {}, over its {}", identity, limit),
+            None => warn!("Disconnecting client {:?}, over its {}", client.id(), limit),
+        }
+        object.post_error(NO_MEMORY, format!("client went over its {}", limit));
+        false
+    }
+
+    /// Release resources of a client, if it is still connected
+    fn release(&self, client: ClientId, change: impl FnOnce(&mut Accounts)) {
+        let Ok(data) = self.display_handle.backend_handle().get_client_data(client) else {
+            return;
+        };
+        if let Some(client_state) = data.downcast_ref::<ClientState>() {
+            client_state.resources.release(change);
+        }
+    }
+
+    /// Resources a client holds
+    pub fn client_resource_usage(&self, client: &Client) -> ResourceUsage {
+        client
+            .get_data::<ClientState>()
+            .map(|client_state| client_state.resources.usage())
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn surface_created(&mut self, surface: &WlSurface) {
+        if let Some(client) = surface.client() {
+            self.charge(&client, surface, |accounts| accounts.surfaces.push(surface.clone()));
+        }
+    }
+
+    /// Charge a commit, which may add frame callbacks and texture memory
+    pub(crate) fn surface_resources_committed(&mut self, surface: &WlSurface) -> bool {
+        match surface.client() {
+            Some(client) => self.charge(&client, surface, |_| {}),
+            None => true,
+        }
+    }
+
+    /// Release the texture of a destroyed surface in the renderer
+    ///
+    /// Accounting drops dead surfaces by itself.
+    pub(crate) fn surface_destroyed(&mut self, surface: &WlSurface) {
+        if let Err(e) = self.surface_manager.remove_surface(surface_id(surface)) {
+            warn!("Failed to release surface texture: {}", e);
+        }
+    }
+
+    /// Charge a new SHM pool before it is mapped
+    fn shm_pool_requested(&mut self, client: &Client, shm: &WlShm, size: i32) -> bool {
+        self.charge(client, shm, |accounts| accounts.new_pool = size.max(0) as u64)
+    }
+
+    /// Move the charge of a new SHM pool to its object, the pool of the client not charged yet
+    fn shm_pool_created(&mut self, client: &Client) {
+        let mut pools = Vec::new();
+        let _ = self
+            .display_handle
+            .backend_handle()
+            .with_all_objects_for(client.id(), |object| {
+                if object.interface().name == WlShmPool::interface().name {
+                    pools.push(object);
+                }
+            });
+        self.release(client.id(), |accounts| {
+            let size = std::mem::take(&mut accounts.new_pool);
+            if let Some(pool) = pools.into_iter().find(|pool| !accounts.pools.contains_key(pool)) {
+                accounts.pools.insert(pool, size);
+            }
+        });
+    }
+
+    /// Charge buffers created from an SHM pool and pool growth
+    fn shm_pool_request(&mut self, client: &Client, pool: &WlShmPool, request: &wl_shm_pool::Request) -> bool {
+        match request {
+            wl_shm_pool::Request::CreateBuffer { .. } => self.charge(client, pool, |accounts| {
+                accounts.pool(pool);
+                accounts.buffers += 1;
+            }),
+            wl_shm_pool::Request::Resize { size } => self.charge(client, pool, |accounts| {
+                let charged = accounts.pool(pool);
+                *charged = (*charged).max((*size).max(0) as u64);
+            }),
+            _ => true,
+        }
+    }
+
+    fn shm_pool_destroyed(&mut self, client: ClientId, pool: &WlShmPool) {
+        self.release(client, |accounts| {
+            accounts.pool(pool);
+            accounts.pools.remove(&pool.id());
+        });
+    }
+
+    fn shm_buffer_destroyed(&mut self, client: ClientId) {
+        self.release(client, |accounts| accounts.buffers = accounts.buffers.saturating_sub(1));
+    }
+}
+
+impl Dispatch<WlShm, ()> for WaylandServerState {
+    fn request(
+        state: &mut Self,
+        client: &Client,
+        shm: &WlShm,
+        request: wl_shm::Request,
+        data: &(),
+        dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        let creates_pool = match &request {
+            wl_shm::Request::CreatePool { size, .. } => {
+                if !state.shm_pool_requested(client, shm, *size) {
+                    return;
+                }
+                true
+            }
+            _ => false,
+        };
+        <ShmState as Dispatch<WlShm, (), Self>>::request(state, client, shm, request, data, dh, data_init);
+        if creates_pool {
+            state.shm_pool_created(client);
+        }
+    }
+}
+
+impl Dispatch<WlShmPool, ShmPoolUserData> for WaylandServerState {
+    fn request(
+        state: &mut Self,
+        client: &Client,
+        pool: &WlShmPool,
+        request: wl_shm_pool::Request,
+        data: &ShmPoolUserData,
+        dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        if state.shm_pool_request(client, pool, &request) {
+            <ShmState as Dispatch<WlShmPool, ShmPoolUserData, Self>>::request(state, client, pool, request, data, dh, data_init);
+        }
+    }
+
+    fn destroyed(state: &mut Self, client: ClientId, pool: &WlShmPool, data: &ShmPoolUserData) {
+        state.shm_pool_destroyed(client.clone(), pool);
+        <ShmState as Dispatch<WlShmPool, ShmPoolUserData, Self>>::destroyed(state, client, pool, data);
+    }
+}
+
+impl Dispatch<WlBuffer, ShmBufferUserData> for WaylandServerState {
+    fn request(
+        state: &mut Self,
+        client: &Client,
+        buffer: &WlBuffer,
+        request: wl_buffer::Request,
+        data: &ShmBufferUserData,
+        dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        <ShmState as Dispatch<WlBuffer, ShmBufferUserData, Self>>::request(state, client, buffer, request, data, dh, data_init);
+    }
+
+    fn destroyed(state: &mut Self, client: ClientId, buffer: &WlBuffer, data: &ShmBufferUserData) {
+        state.shm_buffer_destroyed(client.clone());
+        <ShmState as Dispatch<WlBuffer, ShmBufferUserData, Self>>::destroyed(state, client, buffer, data);
+    }
+}
@@ -0,0 +1,141 @@
+// Seats - Named seats and the input devices assigned to them
+//
+// Besides the default seat, every seat in the configuration gets a wl_seat
+// global of its own with a pointer and a keyboard, so clients see separate
+// seats. Keyboard focus, pointer position, cursor and selections all belong to
+// a seat: two people sharing a workstation each type into the window they
+// clicked, with their own cursor and clipboard.
+//
+// Input devices are assigned to a seat when they are first seen, by device
+// name or by the ID_SEAT property udev gives them, following the rules of the
+// configuration; devices matching no rule go to the default seat. Drag and
+// drop feedback, clipboard persistence and history, and tablets stay with the
+// default seat: selections set on other seats are offered to clients of that
+// seat, but go away with the client that set them and are not recorded.
+
+use compositor_utils::prelude::*;
+use config::{KeyboardConfig, DEFAULT_SEAT};
+use smithay::{
+    backend::input::Device,
+    input::{keyboard::XkbConfig, pointer::CursorImageStatus, Seat, SeatState},
+    reexports::wayland_server::DisplayHandle,
+};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::keyboard::xkb_config;
+use crate::wayland::WaylandServerState;
+
+/// Cursor requested by the client under the pointer of a seat
+struct SeatCursor(Mutex<CursorImageStatus>);
+
+/// Seats besides the default one and the devices assigned to seats
+#[derive(Debug, Default)]
+pub struct SeatsState {
+    /// Configured seats, in configuration order
+    seats: Vec<Seat<WaylandServerState>>,
+    /// Seat of each input device seen, by device id
+    devices: HashMap<String, Seat<WaylandServerState>>,
+}
+
+/// Create a seat with a pointer and a keyboard, advertised to clients
+pub(crate) fn new_seat(
+    seat_state: &mut SeatState<WaylandServerState>,
+    display_handle: &DisplayHandle,
+    name: &str,
+    keyboard: &KeyboardConfig,
+) -> Seat<WaylandServerState> {
+    let mut seat = seat_state.new_wl_seat(display_handle, name);
+    seat.add_pointer();
+    let (delay, rate) = (keyboard.repeat_delay, keyboard.repeat_rate);
+    if let Err(e) = seat.add_keyboard(xkb_config(keyboard), delay, rate) {
+        warn!("Failed to compile configured keymap for {}, using the default keymap: {}", name, e);
+        if let Err(e) = seat.add_keyboard(XkbConfig::default(), delay, rate) {
+            warn!("Failed to initialize keyboard of {} with default keymap: {}", name, e);
+        }
+    }
+    seat.user_data()
+        .insert_if_missing_threadsafe(|| SeatCursor(Mutex::new(CursorImageStatus::default_named())));
+    seat
+}
+
+impl SeatsState {
+    /// Create the seats of the configuration
+    pub(crate) fn new(
+        seat_state: &mut SeatState<WaylandServerState>,
+        display_handle: &DisplayHandle,
+        config: &[config::SeatConfig],
+        keyboard: &KeyboardConfig,
+    ) -> Self {
+        let seats = config
+            .iter()
+            .map(|seat| {
+                info!("Creating seat {}", seat.name);
+                new_seat(seat_state, display_handle, &seat.name, keyboard)
+            })
+            .collect();
+        Self { seats, devices: HashMap::new() }
+    }
+}
+
+/// Cursor a client requested for the pointer of a seat
+pub fn seat_cursor_status(seat: &Seat<WaylandServerState>) -> CursorImageStatus {
+    seat.user_data()
+        .get::<SeatCursor>()
+        .map(|cursor| cursor.0.lock().unwrap().clone())
+        .unwrap_or_else(CursorImageStatus::default_named)
+}
+
+pub(crate) fn set_seat_cursor_status(seat: &Seat<WaylandServerState>, status: CursorImageStatus) {
+    if let Some(cursor) = seat.user_data().get::<SeatCursor>() {
+        *cursor.0.lock().unwrap() = status;
+    }
+}
+
+/// Seat udev assigns a device to, from the ID_SEAT property in the udev database
+fn udev_seat(syspath: &Path) -> Option<String> {
+    let device_number = std::fs::read_to_string(syspath.join("dev")).ok()?;
+    let properties = std::fs::read_to_string(format!("/run/udev/data/c{}", device_number.trim())).ok()?;
+    properties
+        .lines()
+        .find_map(|line| line.strip_prefix("E:ID_SEAT="))
+        .map(str::to_string)
+}
+
+impl WaylandServerState {
+    /// All seats, the default seat first
+    pub fn seats(&self) -> impl Iterator<Item = &Seat<Self>> {
+        std::iter::once(&self.seat).chain(&self.seats.seats)
+    }
+
+    /// Seat with the given name
+    pub fn seat_by_name(&self, name: &str) -> Option<Seat<Self>> {
+        self.seats().find(|seat| seat.name() == name).cloned()
+    }
+
+    /// Seat of the device with the given name and udev seat, by the configured rules
+    pub fn seat_for_device_properties(&self, device_name: &str, udev_seat: Option<&str>) -> Seat<Self> {
+        let name = self.config.device_seat(device_name, udev_seat);
+        self.seat_by_name(name).unwrap_or_else(|| self.seat.clone())
+    }
+
+    /// Seat an input device belongs to, assigning it when first seen
+    pub fn seat_for_device<D: Device>(&mut self, device: &D) -> Seat<Self> {
+        if let Some(seat) = self.seats.devices.get(&device.id()) {
+            return seat.clone();
+        }
+        let udev_seat = device.syspath().and_then(|syspath| udev_seat(&syspath));
+        let seat = self.seat_for_device_properties(&device.name(), udev_seat.as_deref());
+        if seat.name() != DEFAULT_SEAT {
+            info!("Input device {} assigned to {}", device.name(), seat.name());
+        }
+        self.seats.devices.insert(device.id(), seat.clone());
+        seat
+    }
+
+    /// Forget the seat of a removed input device
+    pub(crate) fn forget_device<D: Device>(&mut self, device: &D) {
+        self.seats.devices.remove(&device.id());
+    }
+}
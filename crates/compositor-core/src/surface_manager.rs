@@ -2,16 +2,35 @@
 //
 // This module provides the interface between the Wayland server (which receives
 // client surface data) and the Vulkan renderer (which renders textures to screen).
+// The Wayland server owns one: commits upload the buffers of surfaces, every
+// frame passes how, where and in which order surfaces are drawn, and destroyed
+// surfaces release their textures. Nothing reaches the renderer until one is
+// set with `WaylandServer::set_renderer`.
 
 use compositor_utils::prelude::*;
-use vulkan_renderer::{VulkanRenderer, SurfaceBuffer};
-use wayland_server::protocol::wl_buffer::WlBuffer as WaylandBuffer;
+use vulkan_renderer::{single_pixel_color, VulkanRenderer, SurfaceBuffer, SurfaceDrawState};
+use wayland_server::{protocol::{wl_buffer::WlBuffer as WaylandBuffer, wl_surface::WlSurface}, Resource};
 use smithay::wayland::shm;
 use smithay::wayland::dmabuf;
+use smithay::wayland::single_pixel_buffer;
 use smithay::backend::allocator::Buffer;
+use smithay::utils::{Physical, Rectangle};
 use drm_fourcc::DrmFourcc;
 use std::sync::{Arc, Mutex};
-use std::collections::HashMap;
+use std::collections::{hash_map::DefaultHasher, HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+
+use crate::occlusion::Culling;
+
+/// Identifier of a Wayland surface in the surface manager
+///
+/// Protocol ids are reused across clients, so surfaces are told apart by
+/// their whole object id.
+pub fn surface_id(surface: &WlSurface) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    surface.id().hash(&mut hasher);
+    hasher.finish()
+}
 
 /// Surface manager that coordinates between Wayland and Vulkan
 pub struct SurfaceManager {
@@ -19,6 +38,10 @@ pub struct SurfaceManager {
     /// Map of Wayland surface ID to our internal surface ID
     surface_mapping: HashMap<u64, u32>,
     next_surface_id: u32,
+    /// Wayland surfaces hidden behind opaque content
+    occluded: HashSet<u64>,
+    /// Last buffer committed to each occluded surface, uploaded once it shows
+    deferred_buffers: HashMap<u64, WaylandBuffer>,
 }
 
 impl SurfaceManager {
@@ -30,6 +53,8 @@ impl SurfaceManager {
             renderer: None,
             surface_mapping: HashMap::new(),
             next_surface_id: 1,
+            occluded: HashSet::new(),
+            deferred_buffers: HashMap::new(),
         }
     }
     
@@ -60,6 +85,26 @@ impl SurfaceManager {
             }
         };
         
+        // Single-pixel buffers become solid quads, skipping the texture upload
+        if let Ok(pixel) = single_pixel_buffer::get_single_pixel_buffer(buffer) {
+            if let Some(ref renderer) = self.renderer {
+                if let Ok(mut renderer) = renderer.lock() {
+                    renderer.set_surface_solid_color(surface_id, single_pixel_color(pixel.rgba32f()))?;
+                    debug!("Updated surface {} with single-pixel buffer", surface_id);
+                } else {
+                    warn!("Failed to lock renderer for surface update");
+                }
+            }
+            return Ok(());
+        }
+        
+        // Hidden surfaces upload their last buffer once they show again
+        if self.occluded.contains(&wayland_surface_id) {
+            self.deferred_buffers.insert(wayland_surface_id, buffer.clone());
+            debug!("Deferred buffer upload of occluded surface {}", surface_id);
+            return Ok(());
+        }
+        
         // Convert Wayland buffer to our surface buffer format
         let surface_buffer = self.convert_wayland_buffer(buffer)?;
         
@@ -91,8 +136,100 @@ impl SurfaceManager {
         Ok(())
     }
     
+    /// Update how a surface is drawn: position, viewport, alpha and content type
+    pub fn update_draw_state(&mut self, wayland_surface_id: u64, state: SurfaceDrawState) {
+        let Some(&surface_id) = self.surface_mapping.get(&wayland_surface_id) else {
+            return;
+        };
+        if let Some(ref renderer) = self.renderer {
+            if let Ok(mut renderer) = renderer.lock() {
+                renderer.set_surface_draw_state(surface_id, state);
+            } else {
+                warn!("Failed to lock renderer for draw state update");
+            }
+        }
+    }
+    
+    /// Update the parts of a surface not hidden behind opaque content
+    ///
+    /// `visible` is in output pixels, empty for occluded surfaces. Occluded
+    /// surfaces are not drawn and their commits skip the texture upload; the
+    /// last buffer committed meanwhile is uploaded when they show again.
+    pub fn update_visibility(&mut self, wayland_surface_id: u64, visible: &[Rectangle<i32, Physical>]) -> Result<()> {
+        let Some(&surface_id) = self.surface_mapping.get(&wayland_surface_id) else {
+            return Ok(());
+        };
+        if visible.is_empty() {
+            self.occluded.insert(wayland_surface_id);
+        } else if self.occluded.remove(&wayland_surface_id) {
+            if let Some(buffer) = self.deferred_buffers.remove(&wayland_surface_id) {
+                if buffer.is_alive() {
+                    self.handle_surface_commit(wayland_surface_id, &buffer)?;
+                }
+            }
+        }
+        
+        if let Some(ref renderer) = self.renderer {
+            if let Ok(mut renderer) = renderer.lock() {
+                let region = visible
+                    .iter()
+                    .map(|rect| ash::vk::Rect2D {
+                        offset: ash::vk::Offset2D { x: rect.loc.x, y: rect.loc.y },
+                        extent: ash::vk::Extent2D { width: rect.size.w.max(0) as u32, height: rect.size.h.max(0) as u32 },
+                    })
+                    .collect();
+                renderer.set_surface_visible_region(surface_id, Some(region));
+            } else {
+                warn!("Failed to lock renderer for visibility update");
+            }
+        }
+        Ok(())
+    }
+    
+    /// Update the stacking order of surfaces, back to front
+    ///
+    /// Subsurfaces and popups are surfaces of their own, placed among their
+    /// parents as the scene of the output orders them.
+    pub fn update_draw_order(&mut self, wayland_surface_ids: &[u64]) {
+        let order = wayland_surface_ids
+            .iter()
+            .filter_map(|id| self.surface_mapping.get(id).copied())
+            .collect();
+        if let Some(ref renderer) = self.renderer {
+            if let Ok(mut renderer) = renderer.lock() {
+                renderer.set_draw_order(order);
+            } else {
+                warn!("Failed to lock renderer for draw order update");
+            }
+        }
+    }
+    
+    /// Pass the scene of a frame: draw state and visible part of each surface,
+    /// hidden surfaces and stacking order
+    pub(crate) fn update_frame(&mut self, culling: &Culling) -> Result<()> {
+        for surface in &culling.occluded {
+            self.update_visibility(surface_id(surface), &[])?;
+        }
+        let mut order = Vec::with_capacity(culling.draws.len());
+        for draw in &culling.draws {
+            let id = surface_id(&draw.surface);
+            self.update_draw_state(id, draw.state);
+            self.update_visibility(id, &draw.visible)?;
+            order.push(id);
+        }
+        self.update_draw_order(&order);
+        Ok(())
+    }
+    
+    /// Whether a surface has a buffer waiting to be uploaded until it shows
+    pub fn has_deferred_upload(&self, wayland_surface_id: u64) -> bool {
+        self.deferred_buffers.contains_key(&wayland_surface_id)
+    }
+    
     /// Remove a surface
     pub fn remove_surface(&mut self, wayland_surface_id: u64) -> Result<()> {
+        self.occluded.remove(&wayland_surface_id);
+        self.deferred_buffers.remove(&wayland_surface_id);
         if let Some(surface_id) = self.surface_mapping.remove(&wayland_surface_id) {
             if let Some(ref renderer) = self.renderer {
                 if let Ok(mut renderer) = renderer.lock() {
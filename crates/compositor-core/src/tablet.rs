@@ -0,0 +1,320 @@
+// Tablet - Graphics tablet input routed to zwp_tablet_v2
+//
+// Tablet tools such as pens, erasers and airbrushes report absolute positions
+// on the tablet along with pressure, tilt, rotation and buttons. Positions are
+// mapped to the configured output or region of the desktop and sent to the
+// surface under the tool; pressure goes through the tool's configured curve
+// first, so each pen can be given the feel its user is used to.
+//
+// Events come from an input backend through `process_tablet_event`, or are
+// injected directly through the `tablet_*` methods, which take positions
+// normalised to the tablet area.
+
+use compositor_utils::prelude::*;
+use smithay::{
+    backend::input::{
+        ButtonState, Device, DeviceCapability, Event, InputBackend, InputEvent, ProximityState,
+        TabletToolButtonEvent, TabletToolDescriptor, TabletToolEvent, TabletToolProximityEvent,
+        TabletToolTipEvent, TabletToolTipState, TabletToolType,
+    },
+    utils::{Logical, Point, Rectangle, SERIAL_COUNTER},
+    wayland::tablet_manager::{TabletDescriptor, TabletHandle, TabletSeatTrait, TabletToolHandle},
+};
+use std::collections::HashMap;
+
+use crate::wayland::WaylandServerState;
+
+/// Tablets connected to the default seat
+#[derive(Debug, Default)]
+pub struct TabletState {
+    /// Physical size of each tablet in millimetres, when known
+    sizes: HashMap<TabletDescriptor, Option<(f64, f64)>>,
+}
+
+impl TabletState {
+    /// Connected tablets
+    pub fn tablets(&self) -> impl Iterator<Item = &TabletDescriptor> {
+        self.sizes.keys()
+    }
+
+    /// Physical size of a tablet in millimetres
+    pub fn size(&self, tablet: &TabletDescriptor) -> Option<(f64, f64)> {
+        self.sizes.get(tablet).copied().flatten()
+    }
+}
+
+/// Tool state carried by a tablet event
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TabletToolAxes {
+    /// Position on the tablet, from (0, 0) at the top-left corner to (1, 1)
+    pub position: (f64, f64),
+    /// Pressure in 0.0-1.0 before the tool's pressure curve, if changed
+    pub pressure: Option<f64>,
+    /// Distance from the tablet in 0.0-1.0, if changed
+    pub distance: Option<f64>,
+    /// Tilt from the vertical in degrees on the x and y axes, if changed
+    pub tilt: Option<(f64, f64)>,
+    /// Rotation in degrees, if changed
+    pub rotation: Option<f64>,
+    /// Slider position in -1.0-1.0, if changed
+    pub slider: Option<f64>,
+    /// Wheel rotation in degrees and clicks, if changed
+    pub wheel: Option<(f64, i32)>,
+}
+
+/// Map a normalised tablet position into `area`
+///
+/// With `keep_aspect_ratio` and a known tablet size, only the part of the
+/// tablet from its top-left corner that matches the area's aspect ratio is
+/// used, and positions beyond it stay on the area's edge.
+pub fn map_to_area(
+    position: (f64, f64),
+    tablet_size: Option<(f64, f64)>,
+    area: Rectangle<i32, Logical>,
+    keep_aspect_ratio: bool,
+) -> Point<f64, Logical> {
+    let (mut used_width, mut used_height) = (1.0, 1.0);
+    if let Some((width, height)) = tablet_size.filter(|_| keep_aspect_ratio) {
+        if width > 0.0 && height > 0.0 && !area.is_empty() {
+            let tablet_ratio = width / height;
+            let area_ratio = area.size.w as f64 / area.size.h as f64;
+            if tablet_ratio > area_ratio {
+                used_width = area_ratio / tablet_ratio;
+            } else {
+                used_height = tablet_ratio / area_ratio;
+            }
+        }
+    }
+
+    let x = (position.0 / used_width).clamp(0.0, 1.0);
+    let y = (position.1 / used_height).clamp(0.0, 1.0);
+    Point::from((
+        area.loc.x as f64 + x * area.size.w as f64,
+        area.loc.y as f64 + y * area.size.h as f64,
+    ))
+}
+
+/// Name of a tool type in the pressure curve configuration
+fn tool_type_name(tool_type: TabletToolType) -> &'static str {
+    match tool_type {
+        TabletToolType::Pen => "pen",
+        TabletToolType::Eraser => "eraser",
+        TabletToolType::Brush => "brush",
+        TabletToolType::Pencil => "pencil",
+        TabletToolType::Airbrush => "airbrush",
+        TabletToolType::Mouse => "mouse",
+        TabletToolType::Lens => "lens",
+        TabletToolType::Totem => "totem",
+        TabletToolType::Unknown => "unknown",
+    }
+}
+
+/// Axes of a backend tablet event
+fn tool_axes<B: InputBackend>(event: &impl TabletToolEvent<B>) -> TabletToolAxes {
+    TabletToolAxes {
+        position: (event.x_transformed(1), event.y_transformed(1)),
+        pressure: event.pressure_has_changed().then(|| event.pressure()),
+        distance: event.distance_has_changed().then(|| event.distance()),
+        tilt: event.tilt_has_changed().then(|| event.tilt()),
+        rotation: event.rotation_has_changed().then(|| event.rotation()),
+        slider: event.slider_has_changed().then(|| event.slider_position()),
+        wheel: event
+            .wheel_has_changed()
+            .then(|| (event.wheel_delta(), event.wheel_delta_discrete())),
+    }
+}
+
+impl WaylandServerState {
+    /// Route tablet events from an input backend, ignoring other events
+    ///
+    /// Generic backends do not know the physical size of a tablet; register
+    /// it with `add_tablet` beforehand to keep the tablet's aspect ratio.
+    pub fn process_tablet_event<B: InputBackend>(&mut self, event: InputEvent<B>) {
+        match event {
+            InputEvent::DeviceAdded { device } if device.has_capability(DeviceCapability::TabletTool) => {
+                let tablet = TabletDescriptor::from(&device);
+                if !self.tablet_state.sizes.contains_key(&tablet) {
+                    self.add_tablet(tablet, None);
+                }
+            }
+            InputEvent::DeviceRemoved { device } if device.has_capability(DeviceCapability::TabletTool) => {
+                self.remove_tablet(&TabletDescriptor::from(&device));
+            }
+            InputEvent::TabletToolProximity { event } => {
+                let tablet = TabletDescriptor::from(&event.device());
+                let axes = tool_axes(&event);
+                let in_proximity = event.state() == ProximityState::In;
+                self.tablet_tool_proximity(&tablet, &event.tool(), in_proximity, axes, event.time_msec());
+            }
+            InputEvent::TabletToolAxis { event } => {
+                let tablet = TabletDescriptor::from(&event.device());
+                self.tablet_tool_axis(&tablet, &event.tool(), tool_axes(&event), event.time_msec());
+            }
+            InputEvent::TabletToolTip { event } => {
+                self.tablet_tool_tip(&event.tool(), event.tip_state(), event.time_msec());
+            }
+            InputEvent::TabletToolButton { event } => {
+                self.tablet_tool_button(&event.tool(), event.button(), event.button_state(), event.time_msec());
+            }
+            _ => {}
+        }
+    }
+
+    /// Announce a tablet to clients, with its physical size in millimetres if known
+    pub fn add_tablet(&mut self, tablet: TabletDescriptor, size: Option<(f64, f64)>) {
+        info!("Tablet added: {}", tablet.name);
+        self.seat.tablet_seat().add_tablet::<Self>(&self.display_handle, &tablet);
+        self.tablet_state.sizes.insert(tablet, size);
+    }
+
+    /// Remove a tablet, and its tools once no tablet is left
+    pub fn remove_tablet(&mut self, tablet: &TabletDescriptor) {
+        info!("Tablet removed: {}", tablet.name);
+        let tablet_seat = self.seat.tablet_seat();
+        tablet_seat.remove_tablet(tablet);
+        self.tablet_state.sizes.remove(tablet);
+        if self.tablet_state.sizes.is_empty() {
+            tablet_seat.clear_tools();
+        }
+    }
+
+    /// Area of the desktop tablets are mapped to
+    pub fn tablet_mapping_area(&self) -> Option<Rectangle<i32, Logical>> {
+        let config = &self.config.tablet;
+        let output = config
+            .output
+            .as_ref()
+            .and_then(|name| self.space.outputs().find(|output| output.name() == *name))
+            .and_then(|output| self.space.output_geometry(output));
+        let base = match output {
+            Some(geometry) => geometry,
+            None => self
+                .space
+                .outputs()
+                .filter_map(|output| self.space.output_geometry(output))
+                .reduce(|desktop, geometry| desktop.merge(geometry))?,
+        };
+
+        Some(match config.region {
+            Some([x, y, width, height]) => Rectangle::new(base.loc + Point::from((x, y)), (width, height).into()),
+            None => base,
+        })
+    }
+
+    /// A tool entering or leaving the proximity of a tablet
+    pub fn tablet_tool_proximity(
+        &mut self,
+        tablet: &TabletDescriptor,
+        tool: &TabletToolDescriptor,
+        in_proximity: bool,
+        axes: TabletToolAxes,
+        time: u32,
+    ) {
+        if !in_proximity {
+            if let Some(tool) = self.seat.tablet_seat().get_tool(tool) {
+                tool.proximity_out(time);
+            }
+            return;
+        }
+
+        let tool_descriptor = tool;
+        let dh = self.display_handle.clone();
+        let tool = self.seat.tablet_seat().add_tool::<Self>(self, &dh, tool_descriptor);
+        let tablet_handle = self.tablet_handle(tablet);
+        let Some(location) = self.tablet_location(tablet, axes.position) else {
+            return;
+        };
+
+        if let Some((surface, origin)) = self.surface_under(location) {
+            tool.proximity_in(location, (surface.clone(), origin), &tablet_handle, SERIAL_COUNTER.next_serial(), time);
+            // Axes are only sent along with motion
+            self.queue_tool_axes(&tool, tool_descriptor, &axes);
+            tool.motion(location, Some((surface, origin)), &tablet_handle, SERIAL_COUNTER.next_serial(), time);
+        }
+    }
+
+    /// A tool moving or changing any of its axes
+    pub fn tablet_tool_axis(
+        &mut self,
+        tablet: &TabletDescriptor,
+        tool: &TabletToolDescriptor,
+        axes: TabletToolAxes,
+        time: u32,
+    ) {
+        let Some(tool_handle) = self.seat.tablet_seat().get_tool(tool) else {
+            debug!("Ignoring axis event of tool out of proximity");
+            return;
+        };
+        let tablet_handle = self.tablet_handle(tablet);
+        let Some(location) = self.tablet_location(tablet, axes.position) else {
+            return;
+        };
+
+        self.queue_tool_axes(&tool_handle, tool, &axes);
+        let focus = self.surface_under(location);
+        tool_handle.motion(location, focus, &tablet_handle, SERIAL_COUNTER.next_serial(), time);
+    }
+
+    /// A tool touching or leaving the tablet surface
+    pub fn tablet_tool_tip(&mut self, tool: &TabletToolDescriptor, tip_state: TabletToolTipState, time: u32) {
+        let Some(tool) = self.seat.tablet_seat().get_tool(tool) else {
+            return;
+        };
+        match tip_state {
+            TabletToolTipState::Down => tool.tip_down(SERIAL_COUNTER.next_serial(), time),
+            TabletToolTipState::Up => tool.tip_up(time),
+        }
+    }
+
+    /// A button on a tool being pressed or released
+    pub fn tablet_tool_button(&mut self, tool: &TabletToolDescriptor, button: u32, state: ButtonState, time: u32) {
+        if let Some(tool) = self.seat.tablet_seat().get_tool(tool) {
+            tool.button(button, state, SERIAL_COUNTER.next_serial(), time);
+        }
+    }
+
+    /// Handle of a tablet, announcing tablets the backend did not report
+    fn tablet_handle(&mut self, tablet: &TabletDescriptor) -> TabletHandle {
+        if let Some(handle) = self.seat.tablet_seat().get_tablet(tablet) {
+            return handle;
+        }
+        self.add_tablet(tablet.clone(), None);
+        self.seat
+            .tablet_seat()
+            .get_tablet(tablet)
+            .unwrap_or_default()
+    }
+
+    /// Global location of a position on a tablet
+    fn tablet_location(&self, tablet: &TabletDescriptor, position: (f64, f64)) -> Option<Point<f64, Logical>> {
+        let area = self.tablet_mapping_area()?;
+        let size = self.tablet_state.size(tablet);
+        Some(map_to_area(position, size, area, self.config.tablet.keep_aspect_ratio))
+    }
+
+    /// Queue changed axes for the next motion, applying the tool's pressure curve
+    fn queue_tool_axes(&self, tool: &TabletToolHandle, descriptor: &TabletToolDescriptor, axes: &TabletToolAxes) {
+        if let Some(pressure) = axes.pressure {
+            let curve = self
+                .config
+                .tablet
+                .pressure_curve(tool_type_name(descriptor.tool_type), descriptor.hardware_serial);
+            tool.pressure(curve.map_or(pressure, |curve| curve.apply(pressure)));
+        }
+        if let Some(distance) = axes.distance {
+            tool.distance(distance);
+        }
+        if let Some(tilt) = axes.tilt {
+            tool.tilt(tilt);
+        }
+        if let Some(rotation) = axes.rotation {
+            tool.rotation(rotation);
+        }
+        if let Some(slider) = axes.slider {
+            tool.slider_position(slider);
+        }
+        if let Some((degrees, clicks)) = axes.wheel {
+            tool.wheel(degrees, clicks);
+        }
+    }
+}
@@ -174,4 +174,5 @@ async fn test_graphics_processing_pipeline() {
             // but the capability is validated by successful initialization
         }
     }
-}
\ No newline at end of file
+}
+mod output_management;
@@ -0,0 +1,191 @@
+// Output management validation
+//
+// Exercises wlr-output-management configuration handling directly on the
+// server state, without a connected client.
+
+use crate::output_management::OutputHeadConfiguration;
+use crate::wayland::WaylandServer;
+use smithay::output::{Mode, Output, PhysicalProperties, Subpixel};
+use smithay::utils::{Rectangle, Transform};
+use std::time::{Duration, Instant};
+
+fn primary_output(server: &WaylandServer) -> Output {
+    server.state.space.outputs().next().cloned()
+        .expect("Default output should be mapped")
+}
+
+fn virtual_output(name: &str) -> Output {
+    let output = Output::new(
+        name.to_string(),
+        PhysicalProperties {
+            size: (600, 340).into(),
+            subpixel: Subpixel::Unknown,
+            make: "Custom Compositor".into(),
+            model: "Test Output".into(),
+        },
+    );
+    let mode = Mode { size: (1920, 1080).into(), refresh: 60_000 };
+    output.add_mode(mode);
+    output.set_preferred(mode);
+    output.change_current_state(Some(mode), None, None, None);
+    output
+}
+
+/// Test that the default output is registered as an enabled head
+#[test]
+fn default_output_is_managed() {
+    let server = WaylandServer::new().expect("Failed to create Wayland server");
+    let output = primary_output(&server);
+
+    assert!(server.state.output_management_state.is_enabled(&output));
+    assert_eq!(server.state.output_management_state.outputs().count(), 1);
+    println!("[PASS] Default output advertised through output management");
+}
+
+/// Test applying mode, position, scale and transform atomically
+#[test]
+fn apply_output_configuration() {
+    let mut server = WaylandServer::new().expect("Failed to create Wayland server");
+    let output = primary_output(&server);
+    let serial = server.state.output_management_state.serial();
+
+    let mut head = OutputHeadConfiguration::enabled(&output);
+    head.mode = Some(Mode { size: (1920, 1080).into(), refresh: 144_000 });
+    head.position = Some((100, 50).into());
+    head.scale = Some(1.5);
+    head.transform = Some(Transform::_90);
+
+    server.state.apply_output_configuration(&[head], false)
+        .expect("Valid configuration should apply");
+
+    assert_eq!(output.current_mode().map(|mode| mode.refresh), Some(144_000));
+    assert_eq!(output.current_scale().fractional_scale(), 1.5);
+    assert_eq!(output.current_transform(), Transform::_90);
+    // 1080x1920 after rotation, divided by 1.5
+    assert_eq!(
+        server.state.space.output_geometry(&output),
+        Some(Rectangle::new((100, 50).into(), (720, 1280).into()))
+    );
+    assert_ne!(server.state.output_management_state.serial(), serial);
+    println!("[PASS] Output configuration applied");
+}
+
+/// Test that test-only configurations leave outputs untouched
+#[test]
+fn test_only_configuration() {
+    let mut server = WaylandServer::new().expect("Failed to create Wayland server");
+    let output = primary_output(&server);
+    let geometry = server.state.space.output_geometry(&output);
+
+    let mut head = OutputHeadConfiguration::enabled(&output);
+    head.scale = Some(2.0);
+    head.position = Some((500, 0).into());
+
+    server.state.apply_output_configuration(&[head], true)
+        .expect("Valid configuration should pass testing");
+
+    assert_eq!(server.state.space.output_geometry(&output), geometry);
+    assert_eq!(output.current_scale().fractional_scale(), 1.0);
+    println!("[PASS] Test-only configuration has no side effects");
+}
+
+/// Test that invalid configurations are rejected as a whole
+#[test]
+fn reject_invalid_configuration() {
+    let mut server = WaylandServer::new().expect("Failed to create Wayland server");
+    let output = primary_output(&server);
+    let second = virtual_output("virtual-2");
+    server.state.add_output(&second, (3840, 0).into());
+
+    // Disabling every output is not allowed
+    let heads = [OutputHeadConfiguration::disabled(&output), OutputHeadConfiguration::disabled(&second)];
+    assert!(server.state.apply_output_configuration(&heads, false).is_err());
+
+    // A bad scale on one head rejects the changes to the other head too
+    let mut moved = OutputHeadConfiguration::enabled(&output);
+    moved.position = Some((0, 2160).into());
+    let mut bad_scale = OutputHeadConfiguration::enabled(&second);
+    bad_scale.scale = Some(0.0);
+    assert!(server.state.apply_output_configuration(&[moved, bad_scale], false).is_err());
+    assert_eq!(server.state.space.output_geometry(&output).map(|geo| geo.loc), Some((0, 0).into()));
+
+    // Adaptive sync is not available on virtual outputs
+    let mut vrr = OutputHeadConfiguration::enabled(&output);
+    vrr.adaptive_sync = Some(true);
+    assert!(server.state.apply_output_configuration(&[vrr], true).is_err());
+    println!("[PASS] Invalid configurations rejected atomically");
+}
+
+/// Test disabling and re-enabling an output
+#[test]
+fn disable_and_enable_output() {
+    let mut server = WaylandServer::new().expect("Failed to create Wayland server");
+    let output = primary_output(&server);
+    let second = virtual_output("virtual-2");
+    server.state.add_output(&second, (3840, 0).into());
+
+    let heads = [OutputHeadConfiguration::disabled(&output), OutputHeadConfiguration::enabled(&second)];
+    server.state.apply_output_configuration(&heads, false)
+        .expect("Disabling one of two outputs should succeed");
+
+    assert!(!server.state.output_management_state.is_enabled(&output));
+    assert_eq!(server.state.space.outputs().count(), 1);
+    assert_eq!(server.state.output_management_state.outputs().count(), 2);
+
+    let mut enable = OutputHeadConfiguration::enabled(&output);
+    enable.position = Some((0, 0).into());
+    server.state.apply_output_configuration(&[enable, OutputHeadConfiguration::enabled(&second)], false)
+        .expect("Re-enabling the output should succeed");
+
+    assert!(server.state.output_management_state.is_enabled(&output));
+    assert_eq!(server.state.space.outputs().count(), 2);
+
+    server.state.remove_output(&second);
+    assert_eq!(server.state.output_management_state.outputs().count(), 1);
+    println!("[PASS] Outputs disabled, re-enabled and removed");
+}
+
+/// Test that frame clocks follow outputs leaving and joining the space
+#[test]
+fn frame_clocks_follow_outputs() {
+    let mut server = WaylandServer::new().expect("Failed to create Wayland server");
+    let output = primary_output(&server);
+    let second = virtual_output("virtual-2");
+
+    // Outputs get no clock before frames are paced by clocks
+    server.state.add_output(&second, (3840, 0).into());
+    assert!(!server.state.has_frame_clock(&second));
+    server.state.start_frame_clocks().expect("Failed to start frame clocks");
+    assert!(server.state.has_frame_clock(&output) && server.state.has_frame_clock(&second));
+
+    // The clock of a disabled output stops with its next tick
+    let heads = [OutputHeadConfiguration::enabled(&output), OutputHeadConfiguration::disabled(&second)];
+    server.state.apply_output_configuration(&heads, false)
+        .expect("Disabling an output should succeed");
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while server.state.has_frame_clock(&second) {
+        assert!(Instant::now() < deadline, "Frame clock of the disabled output did not stop");
+        server.event_loop.dispatch(Some(Duration::from_millis(5)), &mut server.state)
+            .expect("Failed to dispatch event loop");
+    }
+    assert!(server.state.has_frame_clock(&output));
+
+    // Enabled and added outputs get a clock again
+    server.state.apply_output_configuration(&[OutputHeadConfiguration::enabled(&second)], false)
+        .expect("Re-enabling an output should succeed");
+    assert!(server.state.has_frame_clock(&second));
+    let third = virtual_output("virtual-3");
+    server.state.add_output(&third, (5760, 0).into());
+    assert!(server.state.has_frame_clock(&third));
+
+    // Enabled again before its clock noticed, an output keeps its one clock
+    let heads = [OutputHeadConfiguration::enabled(&output), OutputHeadConfiguration::disabled(&third)];
+    server.state.apply_output_configuration(&heads, false)
+        .expect("Disabling an output should succeed");
+    server.state.apply_output_configuration(&[OutputHeadConfiguration::enabled(&third)], false)
+        .expect("Re-enabling an output should succeed");
+    server.event_loop.dispatch(Some(Duration::from_millis(40)), &mut server.state)
+        .expect("Failed to dispatch event loop");
+    assert!(server.state.has_frame_clock(&third));
+    println!("[PASS] Frame clocks follow outputs");
+}
@@ -0,0 +1,167 @@
+// Toplevel Icon - Window icons from xdg_toplevel_icon_v1
+//
+// Clients name an icon of the icon theme, provide pixel data at several sizes
+// and scales, or both. An icon applies with the next commit of its toplevel;
+// its pixels are copied then, so they stay available once the client reuses
+// its buffers or destroys the icon. The app bar and window switcher read icons
+// over IPC instead of guessing them from the app_id.
+
+use compositor_utils::prelude::*;
+use smithay::{
+    desktop::Window,
+    reexports::wayland_server::{
+        backend::ObjectId,
+        protocol::{wl_buffer::WlBuffer, wl_shm, wl_surface::WlSurface},
+        Resource,
+    },
+    wayland::{compositor::with_states, shm::with_buffer_contents, xdg_toplevel_icon::ToplevelIconCachedState},
+};
+use std::sync::{Arc, Mutex};
+
+use crate::wayland::WaylandServerState;
+
+/// Icon sizes in logical pixels advertised to clients, covering the app bar and switcher
+pub const PREFERRED_ICON_SIZES: [i32; 6] = [16, 24, 32, 48, 64, 128];
+
+/// Icon image copied from a client buffer
+#[derive(Debug, Clone, PartialEq)]
+pub struct IconImage {
+    /// Size in logical pixels
+    pub size: i32,
+    pub scale: i32,
+    /// Square ARGB8888 pixels in little-endian byte order, `size * scale` per row
+    pub pixels: Arc<[u8]>,
+}
+
+impl IconImage {
+    /// Width and height in buffer pixels
+    pub fn pixel_size(&self) -> i32 {
+        self.size * self.scale
+    }
+}
+
+/// Icon set by a client for its window
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WindowIcon {
+    /// Name of the icon in the icon theme
+    pub name: Option<String>,
+    pub images: Vec<IconImage>,
+}
+
+impl WindowIcon {
+    /// Image best suited to draw the icon at a logical size and output scale
+    ///
+    /// The smallest image at least as large as needed is preferred, so it only
+    /// has to be scaled down; otherwise the largest image is used.
+    pub fn image(&self, size: i32, scale: i32) -> Option<&IconImage> {
+        let wanted = size * scale;
+        self.images
+            .iter()
+            .filter(|image| image.pixel_size() >= wanted)
+            .min_by_key(|image| image.pixel_size())
+            .or_else(|| self.images.iter().max_by_key(|image| image.pixel_size()))
+    }
+}
+
+/// Name and buffers an icon was copied from
+#[derive(Debug, PartialEq)]
+struct IconSource {
+    name: Option<String>,
+    buffers: Vec<(ObjectId, i32)>,
+}
+
+/// Icon applied to a toplevel, kept in its surface data
+#[derive(Debug, Default)]
+struct AppliedIcon {
+    /// Source of the icon, to skip commits that keep it
+    source: Option<IconSource>,
+    icon: Option<WindowIcon>,
+}
+
+/// Copy the pixels of an icon buffer
+fn copy_icon_image(buffer: &WlBuffer, scale: i32) -> Option<IconImage> {
+    let copied = with_buffer_contents(buffer, |ptr, len, data| {
+        if !matches!(data.format, wl_shm::Format::Argb8888 | wl_shm::Format::Xrgb8888) {
+            return None;
+        }
+        let row = data.width as usize * 4;
+        let end = data.offset as usize + data.stride as usize * (data.height as usize - 1) + row;
+        if data.stride < 0 || (data.stride as usize) < row || end > len {
+            return None;
+        }
+        // SAFETY: the pool mapping is valid for `len` bytes during this closure
+        let pool = unsafe { std::slice::from_raw_parts(ptr, len) };
+        let mut pixels = Vec::with_capacity(row * data.height as usize);
+        for y in 0..data.height as usize {
+            let start = data.offset as usize + y * data.stride as usize;
+            pixels.extend_from_slice(&pool[start..start + row]);
+        }
+        Some((data.width, pixels))
+    });
+
+    match copied {
+        Ok(Some((width, pixels))) => Some(IconImage {
+            size: width / scale.max(1),
+            scale: scale.max(1),
+            pixels: pixels.into(),
+        }),
+        Ok(None) => {
+            warn!("Ignoring icon buffer with an unsupported layout");
+            None
+        }
+        Err(e) => {
+            warn!("Failed to read icon buffer: {:?}", e);
+            None
+        }
+    }
+}
+
+impl WaylandServerState {
+    /// Icon set by the client of a window
+    pub fn window_icon(&self, window: &Window) -> Option<WindowIcon> {
+        let surface = window.toplevel()?.wl_surface().clone();
+        with_states(&surface, |states| {
+            states
+                .data_map
+                .get::<Mutex<AppliedIcon>>()
+                .and_then(|applied| applied.lock().unwrap().icon.clone())
+        })
+    }
+
+    /// Apply the icon committed with a toplevel surface
+    pub(crate) fn toplevel_icon_committed(&mut self, surface: &WlSurface) {
+        let is_toplevel = self
+            .windows()
+            .any(|(_, window)| window.toplevel().map(|toplevel| toplevel.wl_surface()) == Some(surface));
+        if !is_toplevel {
+            return;
+        }
+
+        with_states(surface, |states| {
+            let mut cached = states.cached_state.get::<ToplevelIconCachedState>();
+            let current = cached.current();
+            let source = IconSource {
+                name: current.icon_name().map(str::to_string),
+                buffers: current.buffers().iter().map(|(buffer, scale)| (buffer.id(), *scale)).collect(),
+            };
+
+            states.data_map.insert_if_missing_threadsafe(|| Mutex::new(AppliedIcon::default()));
+            let mut applied = states.data_map.get::<Mutex<AppliedIcon>>().unwrap().lock().unwrap();
+            let unset = source.name.is_none() && source.buffers.is_empty();
+            if applied.source.as_ref() == Some(&source) || (unset && applied.source.is_none()) {
+                return;
+            }
+
+            applied.icon = (!unset).then(|| WindowIcon {
+                name: source.name.clone(),
+                images: current
+                    .buffers()
+                    .iter()
+                    .filter_map(|(buffer, scale)| copy_icon_image(buffer, *scale))
+                    .collect(),
+            });
+            debug!("Toplevel icon changed: {:?}", source.name);
+            applied.source = (!unset).then_some(source);
+        });
+    }
+}
@@ -9,42 +9,46 @@ use smithay::{
         allocator::{dmabuf::Dmabuf, Buffer, Format, gbm::GbmDevice},
         drm::{DrmNode, DrmDeviceFd},
         egl::{EGLContext, EGLDisplay},
+        renderer::utils::{on_commit_buffer_handler, with_renderer_surface_state},
     },
     utils::DeviceFd,
-    desktop::{Space, Window},
-    input::{Seat, SeatHandler, SeatState, pointer::PointerHandle},
+    desktop::{layer_map_for_output, LayerSurface as DesktopLayerSurface, PopupManager, Space, Window},
+    input::{Seat, SeatHandler, SeatState, pointer::{CursorImageStatus, PointerHandle}},
     output::{Output, PhysicalProperties, Subpixel},
     wayland::output::{OutputHandler, OutputManagerState},
     reexports::{
-        calloop::{EventLoop, LoopSignal},
+        calloop::{EventLoop, LoopHandle, LoopSignal},
         wayland_server::{
             backend::{ClientData, ClientId, DisconnectReason},
             protocol::wl_surface::WlSurface,
-            protocol::wl_seat::WlSeat,
-            Display,
+            protocol::{wl_data_device_manager::DndAction, wl_seat::WlSeat},
+            Client, Display, DisplayHandle,
         },
     },
-    utils::{Clock, Monotonic, Serial, Point, Logical},
+    utils::{Serial, Point, Logical},
     wayland::{
         buffer::BufferHandler,
-        compositor::{CompositorClientState, CompositorHandler, CompositorState, with_states},
+        compositor::{add_pre_commit_hook, CompositorClientState, CompositorHandler, CompositorState, with_states},
         dmabuf::{DmabufHandler, DmabufState, DmabufGlobal, ImportNotifier},
         drm_syncobj::{DrmSyncobjHandler, DrmSyncobjState, supports_syncobj_eventfd},
         pointer_constraints::{PointerConstraintsHandler, PointerConstraintsState},
         presentation::PresentationState,
         relative_pointer::RelativePointerManagerState,
         selection::{
-            SelectionHandler,
-            primary_selection::{PrimarySelectionHandler, PrimarySelectionState},
-            data_device::{DataDeviceHandler, DataDeviceState, ClientDndGrabHandler, ServerDndGrabHandler},
+            SelectionHandler, SelectionSource, SelectionTarget,
+            primary_selection::{PrimarySelectionHandler, PrimarySelectionState, set_primary_focus},
+            data_device::{DataDeviceHandler, DataDeviceState, ClientDndGrabHandler, ServerDndGrabHandler, default_action_chooser, set_data_device_focus},
+            ext_data_control::{DataControlHandler as ExtDataControlHandler, DataControlState as ExtDataControlState},
+            wlr_data_control::{DataControlHandler as WlrDataControlHandler, DataControlState as WlrDataControlState},
         },
         tablet_manager::{TabletManagerState, TabletSeatHandler},
         shell::{
             xdg::{
                 PopupSurface, PositionerState, ToplevelSurface, XdgShellHandler, XdgShellState,
                 decoration::{XdgDecorationHandler, XdgDecorationState},
+                dialog::{XdgDialogHandler, XdgDialogState},
             },
-            wlr_layer::{WlrLayerShellHandler, WlrLayerShellState, LayerSurface, Layer},
+            wlr_layer::{WlrLayerShellHandler, WlrLayerShellState, LayerSurface, LayerSurfaceData, Layer},
         },
         shm::{ShmHandler, ShmState},
         viewporter::ViewporterState,
@@ -53,7 +57,7 @@ use smithay::{
         alpha_modifier::AlphaModifierState,
         single_pixel_buffer::SinglePixelBufferState,
         cursor_shape::CursorShapeManagerState,
-        commit_timing::CommitTimerState,
+        commit_timing::CommitTimingManagerState,
         fifo::FifoManagerState,
         // drm_lease::{DrmLeaseHandler, DrmLeaseState},  // Requires DrmNode and handler implementation
         xdg_foreign::{XdgForeignHandler, XdgForeignState},
@@ -62,69 +66,173 @@ use smithay::{
         pointer_gestures::PointerGesturesState,
         virtual_keyboard::VirtualKeyboardManagerState,
         text_input::TextInputManagerState,
-        input_method::{InputMethodHandler, InputMethodManagerState},
+        input_method::{InputMethodHandler, InputMethodManagerState, PopupSurface as InputMethodPopup},
         session_lock::{SessionLockHandler, SessionLockManagerState},
-        security_context::{SecurityContextHandler, SecurityContextState},
+        security_context::{SecurityContext, SecurityContextHandler, SecurityContextState},
         xdg_activation::{XdgActivationHandler, XdgActivationState},
         foreign_toplevel_list::{ForeignToplevelListHandler, ForeignToplevelListState},
+        xdg_toplevel_icon::{XdgToplevelIconHandler, XdgToplevelIconManager},
+        xdg_system_bell::{XdgSystemBellHandler, XdgSystemBellState},
+        seat::WaylandFocus,
         socket::ListeningSocketSource,
     },
 };
 
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use config::{CompositorConfig, DEFAULT_SEAT};
+
+use crate::bell::BellState;
+use crate::clipboard::{ClipboardEntry, ClipboardState};
+use crate::commit_queue::CommitQueueState;
+use crate::cursor::CursorThemeState;
+use crate::damage::DamageState;
+use crate::dnd::DndState;
+use crate::frame::{root_surface, FrameState};
+use crate::tablet::TabletState;
+use crate::toplevel_icon::PREFERRED_ICON_SIZES;
+use crate::gestures::GestureState;
+use crate::workspace::WorkspaceState;
+use crate::output_management::{send_output_preferences, OutputManagementState};
+use crate::resource_limits::ClientResources;
+use crate::client_identity::ClientIdentity;
+use crate::protocol_trace::{ProtocolTracer, TraceFilter};
+use crate::replay::{CompositorClock, ReplayState};
+use crate::keyboard::KeyboardState;
+use crate::seats::{new_seat, set_seat_cursor_status, SeatsState};
+use crate::surface_manager::{surface_id, SurfaceManager};
 
 /// Client state data
 #[derive(Default)]
 pub struct ClientState {
     pub compositor_state: CompositorClientState,
+    /// Security context the client connected through, if it is sandboxed
+    pub security_context: Option<SecurityContext>,
+    /// Resources the client holds, checked against the configured limits
+    pub resources: ClientResources,
+    /// Who is behind the connection, recorded when it is inserted
+    pub identity: OnceLock<ClientIdentity>,
+}
+
+impl ClientState {
+    /// Whether the client may use privileged protocols such as data control
+    ///
+    /// Clients connecting through a wp-security-context listener are sandboxed
+    /// and never privileged.
+    pub fn is_privileged(&self) -> bool {
+        self.security_context.is_none()
+    }
+}
+
+/// Global filter for protocols restricted to privileged clients
+pub fn client_is_privileged(client: &Client) -> bool {
+    client
+        .get_data::<ClientState>()
+        .map(ClientState::is_privileged)
+        .unwrap_or(false)
 }
 
 impl ClientData for ClientState {
-    fn initialized(&self, _client_id: ClientId) {}
-    fn disconnected(&self, _client_id: ClientId, _reason: DisconnectReason) {}
+    fn initialized(&self, client_id: ClientId) {
+        debug!("Client {:?} initialized", client_id);
+    }
+
+    fn disconnected(&self, client_id: ClientId, reason: DisconnectReason) {
+        let identity = match self.identity.get() {
+            Some(identity) => identity.to_string(),
+            None => format!("{:?}", client_id),
+        };
+        match reason {
+            DisconnectReason::ConnectionClosed => info!("Client disconnected: {}", identity),
+            DisconnectReason::ProtocolError(error) => warn!(
+                "Client disconnected after a protocol error: {}: {}@{}: error {}: {}",
+                identity, error.object_interface, error.object_id, error.code, error.message
+            ),
+        }
+    }
 }
 
 /// Main Wayland server state
 pub struct WaylandServerState {
     pub compositor_state: CompositorState,
     pub xdg_shell_state: XdgShellState,
+    pub xdg_dialog_state: XdgDialogState,
     pub wlr_layer_shell_state: WlrLayerShellState,
     pub shm_state: ShmState,
     pub dmabuf_state: DmabufState,
     pub dmabuf_global: DmabufGlobal,
     pub output_manager_state: OutputManagerState,
+    pub output_management_state: OutputManagementState,
     pub relative_pointer_manager_state: RelativePointerManagerState,
     pub pointer_constraints_state: PointerConstraintsState,
     pub presentation_state: PresentationState,
+    /// Frames waiting for presentation and their feedback
+    pub frame_state: FrameState,
+    /// Damage history of the frames built for each output
+    pub damage_state: DamageState,
     pub primary_selection_state: PrimarySelectionState,
     pub data_device_state: DataDeviceState,
+    pub ext_data_control_state: ExtDataControlState,
+    pub wlr_data_control_state: WlrDataControlState,
+    pub clipboard_state: ClipboardState,
+    pub dnd_state: DndState,
     pub xdg_decoration_state: XdgDecorationState,
     pub xdg_foreign_state: XdgForeignState,
     pub tablet_manager_state: TabletManagerState,
+    /// Connected tablets and their physical size
+    pub tablet_state: TabletState,
     pub viewporter_state: ViewporterState,
     pub fractional_scale_manager_state: FractionalScaleManagerState,
     pub content_type_state: ContentTypeState,
     pub alpha_modifier_state: AlphaModifierState,
     pub single_pixel_buffer_state: SinglePixelBufferState,
     pub cursor_shape_manager_state: CursorShapeManagerState,
-    pub commit_timer_state: CommitTimerState,
+    pub commit_timing_manager_state: CommitTimingManagerState,
     pub fifo_manager_state: FifoManagerState,
+    /// Surfaces with commits held back by commit timers or FIFO barriers
+    pub commit_queue_state: CommitQueueState,
     // pub drm_lease_state: DrmLeaseState,  // Requires DrmNode and handler implementation
     pub idle_inhibit_manager_state: IdleInhibitManagerState,
     pub keyboard_shortcuts_inhibit_state: KeyboardShortcutsInhibitState,
     pub pointer_gestures_state: PointerGesturesState,
+    /// Swipe consumed by a compositor gesture binding
+    pub gesture_state: GestureState,
     pub virtual_keyboard_manager_state: VirtualKeyboardManagerState,
     pub text_input_manager_state: TextInputManagerState,
     pub input_method_manager_state: InputMethodManagerState,
+    /// Input method popups with a parent surface, drawn above everything else
+    pub input_method_popups: Vec<InputMethodPopup>,
     pub session_lock_manager_state: SessionLockManagerState,
     pub security_context_state: SecurityContextState,
     pub xdg_activation_state: XdgActivationState,
     pub foreign_toplevel_list_state: ForeignToplevelListState,
+    pub xdg_toplevel_icon_manager: XdgToplevelIconManager,
+    pub xdg_system_bell_state: XdgSystemBellState,
+    /// Bell flashes and urgent windows
+    pub bell_state: BellState,
     pub drm_syncobj_state: Option<DrmSyncobjState>,
     pub seat_state: SeatState<Self>,
+    /// Default seat carrying pointer, keyboard and selections
+    pub seat: Seat<Self>,
+    /// Configured seats besides the default one and their input devices
+    pub seats: SeatsState,
+    /// Keyboard layout switching state
+    pub keyboard_state: KeyboardState,
+    /// XCursor theme for named cursors
+    pub cursor_theme_state: CursorThemeState,
     pub space: Space<Window>,
-    pub clock: Clock<Monotonic>,
+    /// Popup trees of windows
+    pub popup_manager: PopupManager,
+    /// Workspaces, with windows of the inactive ones unmapped from the space
+    pub workspace_state: WorkspaceState,
+    pub config: CompositorConfig,
+    /// Monotonic clock, on virtual time while replaying a recording
+    pub clock: CompositorClock,
     pub socket_name: Option<String>,
+    /// Handle to the display for protocol operations outside of dispatch
+    pub display_handle: DisplayHandle,
+    /// Handle to the event loop for registering additional sources
+    pub loop_handle: LoopHandle<'static, WaylandServerState>,
     /// EGL context for hardware acceleration and wl_drm protocol support
     pub egl_context: Option<EGLContext>,
     /// EGL display for wl_drm protocol integration 
@@ -135,6 +243,12 @@ pub struct WaylandServerState {
     pub drm_device_fd: Option<DrmDeviceFd>,
     /// Vulkan renderer for surface compositing
     pub renderer: Option<Arc<Mutex<VulkanRenderer>>>,
+    /// Textures of surfaces in the Vulkan renderer
+    pub surface_manager: SurfaceManager,
+    /// Wayland protocol tracing of relayed clients
+    pub protocol_tracer: ProtocolTracer,
+    /// Recording or replay of input and output timing
+    pub replay_state: ReplayState,
 }
 
 /// Wayland server implementation using smithay and calloop
@@ -148,13 +262,18 @@ pub struct WaylandServer {
 impl WaylandServer {
     /// Create a new Wayland server with event loop
     pub fn new() -> Result<Self> {
+        Self::with_config(CompositorConfig::default())
+    }
+    
+    /// Create a new Wayland server using the given compositor configuration
+    pub fn with_config(config: CompositorConfig) -> Result<Self> {
         info!("Initializing Wayland server with smithay and calloop");
         
         // Create event loop first
         let event_loop = EventLoop::try_new()
             .map_err(|e| CompositorError::wayland(format!("Failed to create event loop: {}", e)))?;
         
-        let _loop_handle = event_loop.handle();
+        let loop_handle = event_loop.handle();
         let loop_signal = event_loop.get_signal();
         
         // Create display with the loop handle
@@ -186,7 +305,11 @@ impl WaylandServer {
         
         let dmabuf_global = dmabuf_state.create_global::<WaylandServerState>(&dh, formats);
         
-        let seat_state = SeatState::new();
+        let mut seat_state = SeatState::new();
+        
+        // Create the default seat so clients can receive input and selections
+        let seat = new_seat(&mut seat_state, &dh, DEFAULT_SEAT, &config.keyboard);
+        let seats = SeatsState::new(&mut seat_state, &dh, &config.seats, &config.keyboard);
         
         // Initialize output manager with xdg-output support for multi-monitor configuration
         let output_manager_state = OutputManagerState::new_with_xdg_output::<WaylandServerState>(&dh);
@@ -206,6 +329,13 @@ impl WaylandServer {
         // Initialize data device manager for drag-and-drop operations and clipboard management
         let data_device_state = DataDeviceState::new::<WaylandServerState>(&dh);
         
+        // Initialize data control for clipboard managers (ext and wlr variants), restricted to privileged clients
+        let ext_data_control_state = ExtDataControlState::new::<WaylandServerState, _>(&dh, Some(&primary_selection_state), client_is_privileged);
+        let wlr_data_control_state = WlrDataControlState::new::<WaylandServerState, _>(&dh, Some(&primary_selection_state), client_is_privileged);
+        
+        // Initialize clipboard persistence and history
+        let clipboard_state = ClipboardState::new(config.clipboard.clone());
+        
         // Initialize XDG decoration manager for client-side/server-side decoration control
         let xdg_decoration_state = XdgDecorationState::new::<WaylandServerState>(&dh);
         
@@ -221,6 +351,15 @@ impl WaylandServer {
         // Initialize tablet manager for professional graphics tablet integration
         let tablet_manager_state = TabletManagerState::new::<WaylandServerState>(&dh);
         
+        // Initialize toplevel icons, preferring the sizes drawn by the app bar and switcher
+        let mut xdg_toplevel_icon_manager = XdgToplevelIconManager::new::<WaylandServerState>(&dh);
+        for size in PREFERRED_ICON_SIZES {
+            xdg_toplevel_icon_manager.add_icon_size(size);
+        }
+        
+        // Initialize wlr-output-management for runtime output configuration
+        let output_management_state = OutputManagementState::new(&dh);
+        
         // Create default output (4K setup)
         let output = Output::new(
             "custom-compositor-output".to_string(),
@@ -233,68 +372,118 @@ impl WaylandServer {
         );
         
         // Add modes to output
-        output.add_mode(smithay::output::Mode {
+        let mode = smithay::output::Mode {
             size: (3840, 2160).into(),
             refresh: 60_000, // 60Hz in mHz
-        });
-        output.set_preferred(smithay::output::Mode {
-            size: (3840, 2160).into(),
-            refresh: 60_000,
-        });
+        };
+        output.add_mode(mode);
+        output.set_preferred(mode);
+        output.change_current_state(Some(mode), None, None, None);
         
-        // Create space and map output
-        let mut space = Space::default();
-        space.map_output(&output, (0, 0));
+        let space = Space::default();
         
-        let clock = Clock::new();
+        let clock = CompositorClock::default();
         
-        let state = WaylandServerState {
+        let mut state = WaylandServerState {
             compositor_state,
             xdg_shell_state,
+            xdg_dialog_state: XdgDialogState::new::<WaylandServerState>(&dh),
             wlr_layer_shell_state,
             shm_state,
             dmabuf_state,
             dmabuf_global,
             output_manager_state,
+            output_management_state,
             relative_pointer_manager_state,
             pointer_constraints_state,
             presentation_state,
+            frame_state: FrameState::default(),
+            damage_state: DamageState::default(),
             primary_selection_state,
             data_device_state,
+            ext_data_control_state,
+            wlr_data_control_state,
+            clipboard_state,
+            dnd_state: DndState::default(),
             xdg_decoration_state,
             xdg_foreign_state,
             tablet_manager_state,
+            tablet_state: TabletState::default(),
             viewporter_state,
             fractional_scale_manager_state,
             content_type_state: ContentTypeState::new::<WaylandServerState>(&dh),
             alpha_modifier_state: AlphaModifierState::new::<WaylandServerState>(&dh),
             single_pixel_buffer_state: SinglePixelBufferState::new::<WaylandServerState>(&dh),
             cursor_shape_manager_state: CursorShapeManagerState::new::<WaylandServerState>(&dh),
-            commit_timer_state: CommitTimerState::default(),
+            commit_timing_manager_state: CommitTimingManagerState::new::<WaylandServerState>(&dh),
             fifo_manager_state: FifoManagerState::new::<WaylandServerState>(&dh),
+            commit_queue_state: CommitQueueState::default(),
             // drm_lease_state: DrmLeaseState::new::<WaylandServerState>(&dh), // Requires DrmNode and handler
             idle_inhibit_manager_state: IdleInhibitManagerState::new::<WaylandServerState>(&dh),
             keyboard_shortcuts_inhibit_state: KeyboardShortcutsInhibitState::new::<WaylandServerState>(&dh),
             pointer_gestures_state: PointerGesturesState::new::<WaylandServerState>(&dh),
+            gesture_state: GestureState::default(),
             virtual_keyboard_manager_state: VirtualKeyboardManagerState::new::<WaylandServerState, _>(&dh, |_client| true),
             text_input_manager_state: TextInputManagerState::new::<WaylandServerState>(&dh),
             input_method_manager_state: InputMethodManagerState::new::<WaylandServerState, _>(&dh, |_client| true),
+            input_method_popups: Vec::new(),
             session_lock_manager_state: SessionLockManagerState::new::<WaylandServerState, _>(&dh, |_client| true),
-            security_context_state: SecurityContextState::new::<WaylandServerState, _>(&dh, |_client| true),
+            security_context_state: SecurityContextState::new::<WaylandServerState, _>(&dh, client_is_privileged),
             xdg_activation_state: XdgActivationState::new::<WaylandServerState>(&dh),
             foreign_toplevel_list_state: ForeignToplevelListState::new::<WaylandServerState>(&dh),
+            xdg_toplevel_icon_manager,
+            xdg_system_bell_state: XdgSystemBellState::new::<WaylandServerState>(&dh),
+            bell_state: BellState::default(),
             drm_syncobj_state: None, // Will be initialized when DRM device is configured
             seat_state,
+            seat,
+            seats,
+            keyboard_state: KeyboardState::new(&config.keyboard),
+            cursor_theme_state: CursorThemeState::new(&config.cursor.theme_name(), config.cursor.size()),
             space,
+            popup_manager: PopupManager::default(),
+            workspace_state: WorkspaceState::new(config.workspaces.count),
+            config,
             clock,
             socket_name: None,
+            display_handle: dh.clone(),
+            loop_handle,
             egl_context: None, // Will be initialized when backend is configured
             egl_display: None, // Will be initialized for wl_drm protocol support
             drm_node: None,    // Will be set when DRM device is detected
             drm_device_fd: None, // Will be set for explicit sync support
             renderer: None,    // Initialize with no renderer
+            surface_manager: SurfaceManager::new(),
+            protocol_tracer: ProtocolTracer::default(),
+            replay_state: ReplayState::default(),
         };
         
+        if state.config.protocol_trace.enabled {
+            let filter = TraceFilter {
+                client_pid: None,
+                interfaces: state.config.protocol_trace.interfaces.clone(),
+            };
+            if let Err(e) = state.set_protocol_trace(Some(filter)) {
+                warn!("Failed to start protocol tracing: {}", e);
+            }
+        }
+        
+        // Register the default output; it can be reconfigured at runtime through wlr-output-management
+        state.add_output(&output, (0, 0).into());
+        
+        if state.config.keyboard.numlock {
+            let seats: Vec<_> = state.seats().cloned().collect();
+            for seat in seats {
+                state.enable_numlock(&seat);
+            }
+        }
+        
+        if state.config.recording.enabled {
+            if let Err(e) = state.set_recording(true) {
+                warn!("Failed to start recording: {}", e);
+            }
+        }
+        
         info!("Wayland server state initialized with calloop");
         
         Ok(Self {
@@ -621,12 +810,11 @@ impl WaylandServer {
         self.state.socket_name = Some(socket_name.clone());
         
         // Insert socket into event loop
-        let mut display_handle = self.display.handle();
         self.event_loop
             .handle()
-            .insert_source(socket_source, move |client_stream, _, _state| {
+            .insert_source(socket_source, move |client_stream, _, state| {
                 // Handle new client connections
-                if let Err(err) = display_handle.insert_client(client_stream, Arc::new(ClientState::default())) {
+                if let Err(err) = state.accept_client(client_stream, ClientState::default()) {
                     error!("Failed to insert client: {}", err);
                 }
             })
@@ -653,6 +841,8 @@ impl WaylandServer {
                 break;
             }
             
+            self.state.refresh();
+            
             // Flush pending events  
             if let Err(e) = self.display.flush_clients() {
                 error!("Error flushing clients: {}", e);
@@ -674,6 +864,14 @@ impl WaylandServer {
     pub async fn run_async(mut self) -> Result<()> {
         info!("Starting Wayland server async event loop");
         
+        // No display is driven yet, so frames are paced by timers, or by the
+        // recording being replayed
+        let replaying = self.state.start_configured_replay()?;
+        if !replaying {
+            self.state.start_frame_clocks()?;
+        }
+        let started = std::time::Instant::now();
+        
         // Async event loop using smithay's standard pattern
         loop {
             // Dispatch wayland events
@@ -682,6 +880,8 @@ impl WaylandServer {
                 break;
             }
             
+            self.state.refresh();
+            
             // Flush pending events  
             if let Err(e) = self.display.flush_clients() {
                 error!("Error flushing clients: {}", e);
@@ -694,6 +894,10 @@ impl WaylandServer {
                 break;
             }
             
+            if replaying && self.state.advance_replay(started.elapsed()).is_some() {
+                break;
+            }
+            
             // Yield to other async tasks
             tokio::task::yield_now().await;
         }
@@ -705,6 +909,7 @@ impl WaylandServer {
     /// Set the Vulkan renderer for surface rendering
     pub fn set_renderer(&mut self, renderer: Arc<Mutex<VulkanRenderer>>) {
         info!("Setting Vulkan renderer for Wayland server");
+        self.state.surface_manager.set_renderer(renderer.clone());
         self.state.renderer = Some(renderer);
     }
     
@@ -731,6 +936,17 @@ impl WaylandServer {
     }
 }
 
+impl WaylandServerState {
+    /// Per-iteration housekeeping after client requests have been dispatched
+    pub fn refresh(&mut self) {
+        self.space.refresh();
+        self.refresh_clipboard();
+        self.refresh_input_method_popups();
+        self.refresh_bell();
+        self.settle_transitions(false);
+    }
+}
+
 // Implement required smithay handlers
 impl DmabufHandler for WaylandServerState {
     fn dmabuf_state(&mut self) -> &mut DmabufState {
@@ -769,24 +985,76 @@ impl CompositorHandler for WaylandServerState {
     
     fn new_surface(&mut self, surface: &WlSurface) {
         debug!("New surface created: {:?}", surface.id());
+        
+        // Track commits held back by commit timers or FIFO barriers
+        add_pre_commit_hook::<Self, _>(surface, |state, _, surface| state.commit_queued(surface));
+        self.surface_created(surface);
+    }
+    
+    fn destroyed(&mut self, surface: &WlSurface) {
+        self.surface_destroyed(surface);
     }
     
     fn commit(&mut self, surface: &WlSurface) {
         debug!("Surface committed: {:?}", surface.id());
         
-        // Handle surface commits for rendering
-        with_states(surface, |_surface_data| {
-            // TODO: Implement proper buffer handling with current Smithay API
-            // For now, just log the commit
-            debug!("Surface committed with data, will handle buffer access in future implementation");
-            
-            // TODO: Access buffer through proper surface state API
-            // TODO: Handle frame callbacks through proper API
-            // TODO: Send buffer data to renderer
-        });
+        // Drag icons move by the offset they attach with
+        self.drag_icon_committed(surface);
+        self.cursor_surface_committed(surface);
+        self.toplevel_icon_committed(surface);
+        self.presentation_committed(surface);
+        
+        // Track buffer size and damage, which give surfaces their geometry in the scene;
+        // synchronized subsurfaces keep their state until their parent commits
+        on_commit_buffer_handler::<Self>(surface);
+
+        // Clients going over their resource limits are disconnected
+        if !self.surface_resources_committed(surface) {
+            return;
+        }
+        self.popup_committed(surface);
+        let root = root_surface(surface);
+        if let Some(window) = self.space.elements().find(|window| window.wl_surface().as_deref() == Some(&root)) {
+            window.on_commit();
+        }
+        self.dialog_committed(surface);
+        
+        // Upload the new buffer to the renderer
+        if self.renderer.is_some() {
+            let buffer = with_renderer_surface_state(surface, |state| state.buffer().map(|buffer| (**buffer).clone())).flatten();
+            if let Some(buffer) = buffer {
+                if let Err(e) = self.surface_manager.handle_surface_commit(surface_id(surface), &buffer) {
+                    warn!("Failed to upload surface buffer: {}", e);
+                }
+            }
+        }
+        
+        // Layer surfaces need their initial configure once the client has committed its state
+        let layer_output = self.space.outputs().find(|output| {
+            layer_map_for_output(output)
+                .layer_for_surface(surface, smithay::desktop::WindowSurfaceType::TOPLEVEL)
+                .is_some()
+        }).cloned();
+        if let Some(output) = layer_output {
+            let initial_configure_sent = with_states(surface, |states| {
+                states
+                    .data_map
+                    .get::<LayerSurfaceData>()
+                    .map(|data| data.lock().unwrap().initial_configure_sent)
+                    .unwrap_or(true)
+            });
+            let mut map = layer_map_for_output(&output);
+            map.arrange();
+            if !initial_configure_sent {
+                if let Some(layer) = map.layer_for_surface(surface, smithay::desktop::WindowSurfaceType::TOPLEVEL) {
+                    layer.layer_surface().send_configure();
+                }
+            }
+        }
         
         // Schedule a repaint for this surface
         self.space.refresh();
+        self.popup_manager.cleanup();
         
         // TODO: Trigger actual frame rendering in compositor
         debug!("Surface commit processed, space refreshed");
@@ -799,16 +1067,19 @@ impl XdgShellHandler for WaylandServerState {
     }
     
     fn new_toplevel(&mut self, surface: ToplevelSurface) {
-        info!("New toplevel window created");
-        
         // Create window and add to space using the new API
         let window = Window::new_wayland_window(surface);
+        match self.window_client_identity(&window) {
+            Some(identity) => info!("New toplevel window created by {}", identity),
+            None => info!("New toplevel window created"),
+        }
+        self.workspace_state.assign_window_id(&window);
         self.space.map_element(window, (100, 100), false);
     }
     
-    fn new_popup(&mut self, _surface: PopupSurface, _positioner: PositionerState) {
+    fn new_popup(&mut self, surface: PopupSurface, positioner: PositionerState) {
         debug!("New popup created");
-        // TODO: Handle popups
+        self.popup_created(surface, positioner);
     }
     
     fn toplevel_destroyed(&mut self, _surface: ToplevelSurface) {
@@ -816,9 +1087,14 @@ impl XdgShellHandler for WaylandServerState {
         // TODO: Remove window from space
     }
     
+    fn parent_changed(&mut self, surface: ToplevelSurface) {
+        debug!("Toplevel parent changed");
+        self.dialog_changed(surface.wl_surface());
+    }
+    
     fn popup_destroyed(&mut self, _surface: PopupSurface) {
         debug!("Popup destroyed");
-        // TODO: Handle popup destruction
+        self.popup_manager.cleanup();
     }
     
     fn grab(&mut self, _surface: PopupSurface, _seat: WlSeat, _serial: Serial) {
@@ -826,9 +1102,9 @@ impl XdgShellHandler for WaylandServerState {
         // TODO: Handle popup grabs
     }
     
-    fn reposition_request(&mut self, _surface: PopupSurface, _positioner: PositionerState, _token: u32) {
+    fn reposition_request(&mut self, surface: PopupSurface, positioner: PositionerState, token: u32) {
         debug!("Popup reposition requested");
-        // TODO: Handle popup repositioning
+        self.popup_repositioned(surface, positioner, token);
     }
 }
 
@@ -841,21 +1117,46 @@ impl WlrLayerShellHandler for WaylandServerState {
         &mut self.wlr_layer_shell_state
     }
     
-    fn new_layer_surface(&mut self, _surface: LayerSurface, _wl_output: Option<wayland_server::protocol::wl_output::WlOutput>, layer: Layer, namespace: String) {
+    fn new_layer_surface(&mut self, surface: LayerSurface, wl_output: Option<wayland_server::protocol::wl_output::WlOutput>, layer: Layer, namespace: String) {
         info!("New layer surface created with namespace: {} on layer: {:?}", namespace, layer);
         
-        // TODO: Add layer surface to appropriate layer in space
-        // TODO: Handle surface configuration based on layer (background, bottom, top, overlay)
-        // TODO: Apply exclusive zones and anchoring
-        debug!("Layer surface added to compositor space on layer: {:?}", layer);
+        // Use the requested output, falling back to the primary output
+        let output = wl_output
+            .as_ref()
+            .and_then(Output::from_resource)
+            .or_else(|| self.space.outputs().next().cloned());
+        
+        let Some(output) = output else {
+            warn!("No output available for layer surface {}, closing it", namespace);
+            surface.send_close();
+            return;
+        };
+        
+        // Layer maps handle anchoring and exclusive zones; outputs keep their layers across reconfiguration
+        let layer_surface = DesktopLayerSurface::new(surface, namespace);
+        if let Err(e) = layer_map_for_output(&output).map_layer(&layer_surface) {
+            error!("Failed to map layer surface: {}", e);
+            return;
+        }
+        send_output_preferences(layer_surface.wl_surface(), &output);
+        
+        debug!("Layer surface added to {} on layer: {:?}", output.name(), layer);
     }
     
-    fn layer_destroyed(&mut self, _surface: LayerSurface) {
+    fn layer_destroyed(&mut self, surface: LayerSurface) {
         info!("Layer surface destroyed");
         
-        // TODO: Remove layer surface from space
-        // TODO: Recalculate layout and exclusive zones
-        debug!("Layer surface removed from compositor space");
+        for output in self.output_management_state.outputs() {
+            let mut map = layer_map_for_output(output);
+            let layer = map
+                .layers()
+                .find(|layer| layer.layer_surface() == &surface)
+                .cloned();
+            if let Some(layer) = layer {
+                map.unmap_layer(&layer);
+                debug!("Layer surface removed from {}", output.name());
+            }
+        }
     }
 }
 
@@ -881,12 +1182,20 @@ impl SeatHandler for WaylandServerState {
         &mut self.seat_state
     }
     
-    fn focus_changed(&mut self, _seat: &Seat<Self>, _focused: Option<&Self::KeyboardFocus>) {
+    fn focus_changed(&mut self, seat: &Seat<Self>, focused: Option<&Self::KeyboardFocus>) {
         debug!("Focus changed for seat");
+        
+        // Selections follow keyboard focus
+        let client = focused.and_then(|surface| self.display_handle.get_client(surface.id()).ok());
+        set_data_device_focus(&self.display_handle, seat, client.clone());
+        set_primary_focus(&self.display_handle, seat, client);
+        self.bell_focus_changed(focused);
+        self.log_focus_decision(seat, focused);
     }
     
-    fn cursor_image(&mut self, _seat: &Seat<Self>, _image: smithay::input::pointer::CursorImageStatus) {
-        debug!("Cursor image changed for seat");
+    fn cursor_image(&mut self, seat: &Seat<Self>, image: CursorImageStatus) {
+        debug!("Cursor image changed for {}", seat.name());
+        set_seat_cursor_status(seat, image);
     }
 }
 
@@ -985,8 +1294,19 @@ impl XdgDecorationHandler for WaylandServerState {
 // Selection Handler Implementation
 // ============================================================================
 
+// Client selections are read into the clipboard service so they outlive their
+// owner; compositor-owned selections carry the captured entry as user data.
+
 impl SelectionHandler for WaylandServerState {
-    type SelectionUserData = ();
+    type SelectionUserData = Arc<ClipboardEntry>;
+    
+    fn new_selection(&mut self, ty: SelectionTarget, source: Option<SelectionSource>, seat: Seat<Self>) {
+        self.clipboard_selection_changed(ty, source, &seat);
+    }
+    
+    fn send_selection(&mut self, _ty: SelectionTarget, mime_type: String, fd: OwnedFd, _seat: Seat<Self>, user_data: &Self::SelectionUserData) {
+        self.send_clipboard_data(&mime_type, fd, user_data);
+    }
 }
 
 // ============================================================================
@@ -1007,44 +1327,76 @@ impl DataDeviceHandler for WaylandServerState {
     fn data_device_state(&self) -> &DataDeviceState {
         &self.data_device_state
     }
+    
+    fn action_choice(&mut self, available: DndAction, preferred: DndAction) -> DndAction {
+        let action = default_action_chooser(available, preferred);
+        self.drag_action_chosen(action);
+        action
+    }
+}
+
+// ============================================================================
+// Data Control Handler Implementation
+// ============================================================================
+
+// Clipboard managers watch and set the clipboard and primary selection through
+// data control devices; smithay forwards offers between them and the data
+// device and primary selection devices of each seat.
+
+impl ExtDataControlHandler for WaylandServerState {
+    fn data_control_state(&self) -> &ExtDataControlState {
+        &self.ext_data_control_state
+    }
+}
+
+impl WlrDataControlHandler for WaylandServerState {
+    fn data_control_state(&self) -> &WlrDataControlState {
+        &self.wlr_data_control_state
+    }
 }
 
+// Drags update the drag icon and cursor shown by the scene; compositor drags
+// serve their data from memory until the target finishes or the drag is cancelled.
+
 impl ClientDndGrabHandler for WaylandServerState {
-    fn started(&mut self, _source: Option<wayland_server::protocol::wl_data_source::WlDataSource>, icon: Option<wayland_server::protocol::wl_surface::WlSurface>, _seat: smithay::input::Seat<Self>) {
+    fn started(&mut self, _source: Option<wayland_server::protocol::wl_data_source::WlDataSource>, icon: Option<WlSurface>, _seat: Seat<Self>) {
         info!("Drag and drop operation started");
-        if let Some(icon_surface) = icon {
-            debug!("DnD operation includes drag icon surface: {:?}", icon_surface.id());
-            // TODO: Handle drag icon rendering and positioning
-        }
-        // TODO: Begin drag operation state management
-        // TODO: Update cursor appearance for drag operation
+        self.drag_started(icon);
     }
     
-    fn dropped(&mut self, _target: Option<WlSurface>, _validated: bool, _seat: smithay::input::Seat<Self>) {
-        info!("Drag and drop operation completed - item dropped");
-        // TODO: Handle drop completion and cleanup drag state
-        // TODO: Reset cursor appearance after drag operation
-        // TODO: Process drop target actions
+    fn dropped(&mut self, _target: Option<WlSurface>, validated: bool, _seat: Seat<Self>) {
+        info!("Drag and drop operation completed - item dropped (accepted: {})", validated);
+        self.drag_dropped();
     }
 }
 
 impl ServerDndGrabHandler for WaylandServerState {
-    fn send(&mut self, _mime_type: String, _fd: std::os::fd::OwnedFd, _seat: smithay::input::Seat<Self>) {
-        info!("Server-side DnD: Sending data with mime type");
-        // TODO: Handle server-side drag and drop data transfer
-        // TODO: Write data to the provided file descriptor
+    fn accept(&mut self, mime_type: Option<String>, _seat: Seat<Self>) {
+        self.drag_target_accepts(mime_type.is_some());
+    }
+    
+    fn action(&mut self, action: DndAction, _seat: Seat<Self>) {
+        self.drag_action_chosen(action);
+    }
+    
+    fn dropped(&mut self, _seat: Seat<Self>) {
+        info!("Server-side DnD: item dropped");
+        self.drag_dropped();
+    }
+    
+    fn send(&mut self, mime_type: String, fd: OwnedFd, _seat: Seat<Self>) {
+        debug!("Server-side DnD: sending {}", mime_type);
+        self.send_drag_data(&mime_type, fd);
     }
     
-    fn finished(&mut self, _seat: smithay::input::Seat<Self>) {
+    fn finished(&mut self, _seat: Seat<Self>) {
         info!("Server-side DnD operation finished");
-        // TODO: Clean up server-side drag state
-        // TODO: Release any held resources
+        self.drag_finished();
     }
     
-    fn cancelled(&mut self, _seat: smithay::input::Seat<Self>) {
+    fn cancelled(&mut self, _seat: Seat<Self>) {
         info!("Server-side DnD operation cancelled");
-        // TODO: Handle cancellation cleanup
-        // TODO: Reset drag state
+        self.drag_finished();
     }
 }
 
@@ -1071,10 +1423,17 @@ impl FractionalScaleHandler for WaylandServerState {
     fn new_fractional_scale(&mut self, surface: WlSurface) {
         info!("New fractional scale instantiated for surface: {:?}", surface.id());
         
-        // TODO: Implement fractional scale calculation based on output configuration
-        // TODO: Send appropriate scale factor to client for 4K display optimization
-        // TODO: Integrate with output scale management for consistent scaling
-        debug!("Fractional scale handler ready for sub-pixel precision scaling");
+        // Use the output the surface is shown on; new surfaces start on the primary output
+        let window_output = self
+            .space
+            .elements()
+            .find(|window| window.toplevel().map(|toplevel| toplevel.wl_surface() == &surface).unwrap_or(false))
+            .and_then(|window| self.space.outputs_for_element(window).into_iter().next());
+        
+        if let Some(output) = window_output.or_else(|| self.space.outputs().next().cloned()) {
+            send_output_preferences(&surface, &output);
+            debug!("Sent preferred scale {} from {}", output.current_scale().fractional_scale(), output.name());
+        }
     }
 }
 
@@ -1123,29 +1482,23 @@ impl IdleInhibitHandler for WaylandServerState {
 // ============================================================================
 
 impl InputMethodHandler for WaylandServerState {
-    fn new_popup(&mut self, _surface: smithay::wayland::input_method::PopupSurface) {
+    fn new_popup(&mut self, surface: InputMethodPopup) {
         info!("New input method popup created");
-        // TODO: Handle input method popup surface management
-        // TODO: Position popup relative to text input focus
-        // TODO: Track popup lifecycle for proper cleanup
+        self.input_method_popup_mapped(surface);
     }
     
-    fn dismiss_popup(&mut self, _surface: smithay::wayland::input_method::PopupSurface) {
+    fn dismiss_popup(&mut self, surface: InputMethodPopup) {
         info!("Input method popup dismissed");
-        // TODO: Handle popup dismissal
-        // TODO: Clean up any resources associated with the popup
+        self.input_method_popup_dismissed(&surface);
     }
     
-    fn popup_repositioned(&mut self, _surface: smithay::wayland::input_method::PopupSurface) {
-        info!("Input method popup repositioned");
-        // TODO: Handle popup repositioning
-        // TODO: Update popup position relative to text input focus
+    fn popup_repositioned(&mut self, surface: InputMethodPopup) {
+        debug!("Input method popup repositioned");
+        self.input_method_popup_repositioned(surface);
     }
     
-    fn parent_geometry(&self, _surface: &WlSurface) -> smithay::utils::Rectangle<i32, smithay::utils::Logical> {
-        // TODO: Implement proper parent geometry calculation for positioning the popup
-        // This is a placeholder that returns a default rectangle
-        smithay::utils::Rectangle::from_size((100, 50).into())
+    fn parent_geometry(&self, parent: &WlSurface) -> smithay::utils::Rectangle<i32, smithay::utils::Logical> {
+        self.surface_geometry(parent).unwrap_or_default()
     }
 }
 
@@ -1211,14 +1564,23 @@ impl SessionLockHandler for WaylandServerState {
 // ============================================================================
 
 impl SecurityContextHandler for WaylandServerState {
-    fn context_created(&mut self, _source: smithay::wayland::security_context::SecurityContextListenerSource, _security_context: smithay::wayland::security_context::SecurityContext) {
-        info!("Security context created for sandboxed application");
+    fn context_created(&mut self, source: smithay::wayland::security_context::SecurityContextListenerSource, security_context: SecurityContext) {
+        info!("Security context created for sandboxed application: {:?}", security_context.app_id);
+        
+        // Clients accepted on the context's listener carry the context and lose access to privileged globals
+        let result = self.loop_handle.insert_source(source, move |client_stream, _, state| {
+            let client_state = ClientState {
+                security_context: Some(security_context.clone()),
+                ..ClientState::default()
+            };
+            if let Err(err) = state.accept_client(client_stream, client_state) {
+                error!("Failed to insert sandboxed client: {}", err);
+            }
+        });
         
-        // TODO: Implement sandboxed execution environment
-        // TODO: Apply security restrictions based on context capabilities
-        // TODO: Isolate context from sensitive system resources
-        // TODO: Track context permissions and enforce access controls
-        debug!("Security context established with capability-based permissions");
+        if let Err(e) = result {
+            error!("Failed to listen on security context socket: {}", e);
+        }
     }
 }
 
@@ -1231,14 +1593,11 @@ impl XdgActivationHandler for WaylandServerState {
         &mut self.xdg_activation_state
     }
     
-    fn request_activation(&mut self, _token: smithay::wayland::xdg_activation::XdgActivationToken, _token_data: smithay::wayland::xdg_activation::XdgActivationTokenData, _surface: WlSurface) {
+    fn request_activation(&mut self, _token: smithay::wayland::xdg_activation::XdgActivationToken, token_data: smithay::wayland::xdg_activation::XdgActivationTokenData, surface: WlSurface) {
         info!("Window activation requested for surface with token");
         
-        // TODO: Implement focus management and window activation
-        // TODO: Validate activation request against security policies
-        // TODO: Switch focus to requested surface if authorized
-        // TODO: Update window stack order and input focus
-        debug!("Processing window activation request");
+        // Valid tokens focus the window, or its topmost modal dialog, see dialog.rs
+        self.activate_surface(&token_data, &surface);
     }
 }
 
@@ -1252,19 +1611,71 @@ impl ForeignToplevelListHandler for WaylandServerState {
     }
 }
 
+// ============================================================================
+// XDG Toplevel Icon Handler Implementation
+// ============================================================================
+
+// Icons are read from the surface state once committed, see toplevel_icon.rs
+impl XdgToplevelIconHandler for WaylandServerState {}
+
+// ============================================================================
+// XDG System Bell Handler Implementation
+// ============================================================================
+
+impl XdgSystemBellHandler for WaylandServerState {
+    fn ring(&mut self, surface: Option<WlSurface>) {
+        self.ring_bell(surface.as_ref());
+    }
+}
+
+// ============================================================================
+// XDG Dialog Handler Implementation
+// ============================================================================
+
+impl XdgDialogHandler for WaylandServerState {
+    fn modal_changed(&mut self, toplevel: ToplevelSurface, is_modal: bool) {
+        debug!("Toplevel modal hint changed: {}", is_modal);
+        self.dialog_changed(toplevel.wl_surface());
+    }
+}
+
 // Delegate handlers to implementations
 smithay::delegate_compositor!(WaylandServerState);
 smithay::delegate_xdg_shell!(WaylandServerState);
+smithay::delegate_xdg_dialog!(WaylandServerState);
+smithay::delegate_xdg_system_bell!(WaylandServerState);
 smithay::delegate_layer_shell!(WaylandServerState);
 smithay::delegate_output!(WaylandServerState);
-smithay::delegate_shm!(WaylandServerState);
+// wl_shm requests are dispatched through the resource limits, see resource_limits.rs
+smithay::reexports::wayland_server::delegate_global_dispatch!(WaylandServerState: [
+    smithay::reexports::wayland_server::protocol::wl_shm::WlShm: ()
+] => ShmState);
 smithay::delegate_dmabuf!(WaylandServerState);
 smithay::delegate_seat!(WaylandServerState);
 smithay::delegate_relative_pointer!(WaylandServerState);
 smithay::delegate_pointer_constraints!(WaylandServerState);
 smithay::delegate_presentation!(WaylandServerState);
-smithay::delegate_primary_selection!(WaylandServerState);
-smithay::delegate_data_device!(WaylandServerState);
+// Selection device requests are dispatched through the clipboard, see clipboard.rs
+smithay::reexports::wayland_server::delegate_global_dispatch!(WaylandServerState: [
+    smithay::reexports::wayland_protocols::wp::primary_selection::zv1::server::zwp_primary_selection_device_manager_v1::ZwpPrimarySelectionDeviceManagerV1: smithay::wayland::selection::primary_selection::PrimaryDeviceManagerGlobalData
+] => smithay::wayland::selection::primary_selection::PrimarySelectionState);
+smithay::reexports::wayland_server::delegate_dispatch!(WaylandServerState: [
+    smithay::reexports::wayland_protocols::wp::primary_selection::zv1::server::zwp_primary_selection_device_manager_v1::ZwpPrimarySelectionDeviceManagerV1: ()
+] => smithay::wayland::selection::primary_selection::PrimarySelectionState);
+smithay::reexports::wayland_server::delegate_dispatch!(WaylandServerState: [
+    smithay::reexports::wayland_protocols::wp::primary_selection::zv1::server::zwp_primary_selection_source_v1::ZwpPrimarySelectionSourceV1: smithay::wayland::selection::primary_selection::PrimarySourceUserData
+] => smithay::wayland::selection::primary_selection::PrimarySelectionState);
+smithay::reexports::wayland_server::delegate_global_dispatch!(WaylandServerState: [
+    smithay::reexports::wayland_server::protocol::wl_data_device_manager::WlDataDeviceManager: ()
+] => smithay::wayland::selection::data_device::DataDeviceState);
+smithay::reexports::wayland_server::delegate_dispatch!(WaylandServerState: [
+    smithay::reexports::wayland_server::protocol::wl_data_device_manager::WlDataDeviceManager: ()
+] => smithay::wayland::selection::data_device::DataDeviceState);
+smithay::reexports::wayland_server::delegate_dispatch!(WaylandServerState: [
+    smithay::reexports::wayland_server::protocol::wl_data_source::WlDataSource: smithay::wayland::selection::data_device::DataSourceUserData
+] => smithay::wayland::selection::data_device::DataDeviceState);
+smithay::delegate_ext_data_control!(WaylandServerState);
+smithay::delegate_data_control!(WaylandServerState);
 smithay::delegate_xdg_decoration!(WaylandServerState);
 smithay::delegate_xdg_foreign!(WaylandServerState);
 smithay::delegate_tablet_manager!(WaylandServerState);
@@ -1286,4 +1697,5 @@ smithay::delegate_session_lock!(WaylandServerState);
 smithay::delegate_security_context!(WaylandServerState);
 smithay::delegate_xdg_activation!(WaylandServerState);
 smithay::delegate_foreign_toplevel_list!(WaylandServerState);
+smithay::delegate_xdg_toplevel_icon!(WaylandServerState);
 smithay::delegate_drm_syncobj!(WaylandServerState);
@@ -0,0 +1,353 @@
+// Workspace - Virtual desktops and the overview
+//
+// Windows of the active workspace live in the space; those of other
+// workspaces are unmapped and kept here with their locations, so they take no
+// input and are not drawn. Every window gets an identifier when it is created,
+// which desktop components use to refer to it over IPC.
+//
+// The overview lays the windows of the active workspace out in a grid on
+// each output, shrunk to fit their cell. The scene moves and shrinks windows
+// from their place towards their cell as the overview opens, so it follows
+// the same progress as its transition. It is only shown: input still goes to
+// windows at their place on the workspace.
+//
+// Switching workspaces and opening the overview can follow the fingers of a
+// touchpad swipe. While the fingers move, the transition's progress is set
+// directly; once they lift, it is animated to its end, where the switch is
+// carried out or abandoned.
+
+use compositor_utils::prelude::*;
+use smithay::{
+    desktop::Window,
+    output::Output,
+    utils::{IsAlive, Logical, Monotonic, Point, Time},
+};
+use std::time::Duration;
+
+use crate::wayland::WaylandServerState;
+
+/// Space between the cells of the overview and around them, in logical pixels
+const OVERVIEW_GAP: f64 = 32.0;
+
+/// Identifier of a window, unique for the lifetime of the compositor
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WindowId(pub u32);
+
+/// Place of a window in the open overview
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OverviewTile {
+    /// Top-left corner of the window geometry in global compositor coordinates
+    pub location: Point<f64, Logical>,
+    /// Factor the window is shrunk by, at most 1.0
+    pub scale: f64,
+}
+
+/// Progress of a transition that follows the fingers, then settles
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Progress {
+    /// Following the fingers
+    Tracking(f64),
+    /// Animating to its end once released
+    Settling {
+        from: f64,
+        to: f64,
+        start: Time<Monotonic>,
+        duration: Duration,
+    },
+}
+
+impl Progress {
+    fn fraction(start: Time<Monotonic>, duration: Duration, now: Time<Monotonic>) -> f64 {
+        if duration.is_zero() {
+            return 1.0;
+        }
+        (Time::elapsed(&start, now).as_secs_f64() / duration.as_secs_f64()).min(1.0)
+    }
+
+    fn value(&self, now: Time<Monotonic>) -> f64 {
+        match *self {
+            Progress::Tracking(value) => value,
+            Progress::Settling { from, to, start, duration } => {
+                // Ease out, as the fingers left it moving
+                let eased = 1.0 - (1.0 - Self::fraction(start, duration, now)).powi(3);
+                from + (to - from) * eased
+            }
+        }
+    }
+
+    /// End value once the animation is over
+    fn settled(&self, now: Time<Monotonic>) -> Option<f64> {
+        match *self {
+            Progress::Settling { to, start, duration, .. } if Self::fraction(start, duration, now) >= 1.0 => Some(to),
+            _ => None,
+        }
+    }
+}
+
+/// Workspaces of the desktop
+#[derive(Debug)]
+pub struct WorkspaceState {
+    active: usize,
+    /// Windows of each workspace while it is inactive, bottom to top
+    hidden: Vec<Vec<(Window, Point<i32, Logical>)>>,
+    /// Offset of a workspace switch in workspace widths, negative towards the next one
+    switch: Option<Progress>,
+    overview_open: bool,
+    /// Openness of the overview while it opens or closes, from 0.0 to 1.0
+    overview: Option<Progress>,
+    next_window_id: u32,
+}
+
+impl WorkspaceState {
+    pub fn new(count: usize) -> Self {
+        Self {
+            active: 0,
+            hidden: vec![Vec::new(); count.max(1)],
+            switch: None,
+            overview_open: false,
+            overview: None,
+            next_window_id: 1,
+        }
+    }
+
+    /// Give a new window its identifier
+    pub(crate) fn assign_window_id(&mut self, window: &Window) -> WindowId {
+        let id = WindowId(self.next_window_id);
+        self.next_window_id += 1;
+        window.user_data().insert_if_missing(|| id);
+        id
+    }
+
+    /// Index of the active workspace
+    pub fn active(&self) -> usize {
+        self.active
+    }
+
+    /// Number of workspaces
+    pub fn count(&self) -> usize {
+        self.hidden.len()
+    }
+
+    /// Whether the overview is open, not counting a transition in progress
+    pub fn is_overview_open(&self) -> bool {
+        self.overview_open
+    }
+
+    /// Workspace a switch offset moves towards
+    fn switch_target(&self, offset: f64) -> Option<usize> {
+        if offset < 0.0 {
+            Some(self.active + 1).filter(|&next| next < self.count())
+        } else if offset > 0.0 {
+            self.active.checked_sub(1)
+        } else {
+            None
+        }
+    }
+
+    /// Keep a switch offset within one workspace width, towards existing workspaces
+    fn clamp_switch_offset(&self, offset: f64) -> f64 {
+        let min = if self.active + 1 < self.count() { -1.0 } else { 0.0 };
+        let max = if self.active > 0 { 1.0 } else { 0.0 };
+        offset.clamp(min, max)
+    }
+}
+
+impl WaylandServerState {
+    /// Switch to a workspace right away
+    pub fn switch_workspace(&mut self, index: usize) -> Result<()> {
+        if index >= self.workspace_state.count() {
+            return Err(CompositorError::runtime(format!("No workspace {}", index)));
+        }
+        self.workspace_state.switch = None;
+        if index == self.workspace_state.active {
+            return Ok(());
+        }
+
+        info!("Switching to workspace {}", index);
+        let active = self.workspace_state.active;
+        let windows: Vec<_> = self
+            .space
+            .elements()
+            .filter_map(|window| Some((window.clone(), self.space.element_location(window)?)))
+            .collect();
+        for (window, _) in &windows {
+            self.space.unmap_elem(window);
+        }
+        self.workspace_state.hidden[active] = windows;
+
+        for (window, location) in std::mem::take(&mut self.workspace_state.hidden[index]) {
+            self.space.map_element(window, location, false);
+        }
+        // Assign the windows to their outputs before the next frame is built
+        self.space.refresh();
+        self.workspace_state.active = index;
+        Ok(())
+    }
+
+    /// Workspace being switched to and the offset of the switch, while one is in progress
+    ///
+    /// The offset is in workspace widths, negative when moving to the next
+    /// workspace and positive when moving to the previous one.
+    pub fn workspace_switch(&self) -> Option<(usize, f64)> {
+        let offset = self.workspace_state.switch?.value(self.clock.now());
+        Some((self.workspace_state.switch_target(offset)?, offset))
+    }
+
+    /// Live windows of all workspaces with the index of their workspace
+    pub fn windows(&self) -> impl Iterator<Item = (usize, &Window)> {
+        let active = self.space.elements().map(|window| (self.workspace_state.active, window));
+        let hidden = self
+            .workspace_state
+            .hidden
+            .iter()
+            .enumerate()
+            .flat_map(|(index, windows)| windows.iter().map(move |(window, _)| (index, window)));
+        active.chain(hidden).filter(|(_, window)| window.alive())
+    }
+
+    /// Live window with an identifier and the index of its workspace
+    pub fn window_by_id(&self, id: WindowId) -> Option<(usize, &Window)> {
+        self.windows().find(|(_, window)| window_id(window) == Some(id))
+    }
+
+    /// Location of a window, wherever its workspace is
+    pub fn window_location(&self, window: &Window) -> Option<Point<i32, Logical>> {
+        self.space.element_location(window).or_else(|| {
+            self.workspace_state
+                .hidden
+                .iter()
+                .flatten()
+                .find(|(hidden, _)| hidden == window)
+                .map(|(_, location)| *location)
+        })
+    }
+
+    /// Windows of an inactive workspace with their locations
+    pub fn hidden_windows(&self, index: usize) -> &[(Window, Point<i32, Logical>)] {
+        self.workspace_state.hidden.get(index).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// How far the overview is open, from 0.0 to 1.0
+    pub fn overview_progress(&self) -> f64 {
+        match self.workspace_state.overview {
+            Some(progress) => progress.value(self.clock.now()),
+            None if self.workspace_state.overview_open => 1.0,
+            None => 0.0,
+        }
+    }
+
+    /// Places of the windows of the active workspace on an output in the open overview
+    ///
+    /// Windows fill a grid of equal cells in the order they were created,
+    /// centered in their cell and shrunk when larger than it.
+    pub fn overview_tiles(&self, output: &Output) -> Vec<(Window, OverviewTile)> {
+        let Some(area) = self.space.output_geometry(output) else {
+            return Vec::new();
+        };
+        let mut windows: Vec<&Window> = self.space.elements_for_output(output).collect();
+        if windows.is_empty() {
+            return Vec::new();
+        }
+        windows.sort_by_key(|window| window_id(window).map(|id| id.0));
+        let columns = (windows.len() as f64).sqrt().ceil() as usize;
+        let rows = windows.len().div_ceil(columns);
+        let cell_w = ((area.size.w as f64 - OVERVIEW_GAP * (columns + 1) as f64) / columns as f64).max(1.0);
+        let cell_h = ((area.size.h as f64 - OVERVIEW_GAP * (rows + 1) as f64) / rows as f64).max(1.0);
+
+        windows
+            .into_iter()
+            .enumerate()
+            .map(|(index, window)| {
+                let size = window.geometry().size.to_f64();
+                let scale = if size.w > 0.0 && size.h > 0.0 {
+                    (cell_w / size.w).min(cell_h / size.h).min(1.0)
+                } else {
+                    1.0
+                };
+                let (column, row) = ((index % columns) as f64, (index / columns) as f64);
+                let cell = Point::<f64, Logical>::from((
+                    area.loc.x as f64 + OVERVIEW_GAP + column * (cell_w + OVERVIEW_GAP),
+                    area.loc.y as f64 + OVERVIEW_GAP + row * (cell_h + OVERVIEW_GAP),
+                ));
+                let centering = Point::from(((cell_w - size.w * scale) / 2.0, (cell_h - size.h * scale) / 2.0));
+                (window.clone(), OverviewTile { location: cell + centering, scale })
+            })
+            .collect()
+    }
+
+    /// Open or close the overview right away
+    pub fn set_overview_open(&mut self, open: bool) {
+        self.workspace_state.overview = None;
+        self.workspace_state.overview_open = open;
+    }
+
+    /// Follow the fingers of a workspace switch, by an offset in workspace widths
+    pub(crate) fn track_workspace_switch(&mut self, offset: f64) {
+        let offset = self.workspace_state.clamp_switch_offset(offset);
+        self.workspace_state.switch = Some(Progress::Tracking(offset));
+    }
+
+    /// Follow the fingers of the overview opening or closing, by how far it is open
+    pub(crate) fn track_overview(&mut self, progress: f64) {
+        self.workspace_state.overview = Some(Progress::Tracking(progress.clamp(0.0, 1.0)));
+    }
+
+    /// Animate transitions following the fingers to their end once released
+    ///
+    /// Transitions past their halfway point complete, others are reverted.
+    /// Cancelled gestures always revert.
+    pub(crate) fn release_transitions(&mut self, cancelled: bool) {
+        let start = self.clock.now();
+        let duration = if self.config.theme.animations {
+            Duration::from_millis(self.config.theme.animation_duration)
+        } else {
+            Duration::ZERO
+        };
+
+        if let Some(Progress::Tracking(from)) = self.workspace_state.switch {
+            let to = if !cancelled && from.abs() >= 0.5 { from.signum() } else { 0.0 };
+            self.workspace_state.switch = Some(Progress::Settling { from, to, start, duration });
+        }
+        if let Some(Progress::Tracking(from)) = self.workspace_state.overview {
+            let open = if cancelled { self.workspace_state.overview_open } else { from >= 0.5 };
+            let to = if open { 1.0 } else { 0.0 };
+            self.workspace_state.overview = Some(Progress::Settling { from, to, start, duration });
+        }
+        self.settle_transitions(false);
+    }
+
+    /// Carry out transitions whose animation is over
+    ///
+    /// With `force`, animations end right away and transitions still following
+    /// the fingers are reverted.
+    pub(crate) fn settle_transitions(&mut self, force: bool) {
+        let now = self.clock.now();
+        let settled = |progress: &Option<Progress>, reverted: f64| match progress {
+            Some(Progress::Tracking(_)) if force => Some(reverted),
+            Some(progress @ Progress::Settling { to, .. }) => progress.settled(now).or(force.then_some(*to)),
+            _ => None,
+        };
+
+        if let Some(offset) = settled(&self.workspace_state.switch, 0.0) {
+            self.workspace_state.switch = None;
+            if offset.abs() >= 1.0 {
+                if let Some(target) = self.workspace_state.switch_target(offset) {
+                    if let Err(e) = self.switch_workspace(target) {
+                        warn!("Failed to finish workspace switch: {}", e);
+                    }
+                }
+            }
+        }
+
+        let was_open = if self.workspace_state.overview_open { 1.0 } else { 0.0 };
+        if let Some(progress) = settled(&self.workspace_state.overview, was_open) {
+            self.workspace_state.overview = None;
+            self.workspace_state.overview_open = progress >= 1.0;
+        }
+    }
+}
+
+/// Identifier of a window
+pub fn window_id(window: &Window) -> Option<WindowId> {
+    window.user_data().get::<WindowId>().copied()
+}
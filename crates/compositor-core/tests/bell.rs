@@ -0,0 +1,173 @@
+//! System bell integration tests
+//!
+//! Rings the bell over xdg_system_bell_v1 and checks the flashes, window
+//! urgency and bell command configured in the bell section.
+
+mod common;
+
+use common::{create_shm_buffer, PingPong, TestClient, TestServer};
+use compositor_core::wayland::WaylandServerState;
+use config::{BellFlash, CompositorConfig};
+use ipc::protocol::IPCMessage;
+use smithay::{desktop::Window, utils::Rectangle};
+use std::time::{Duration, Instant};
+use wayland_client::protocol::{
+    wl_buffer::WlBuffer,
+    wl_compositor::WlCompositor,
+    wl_shm::WlShm,
+    wl_shm_pool::WlShmPool,
+    wl_surface::WlSurface,
+};
+use wayland_protocols::xdg::shell::client::{
+    xdg_surface::XdgSurface,
+    xdg_toplevel::XdgToplevel,
+    xdg_wm_base::XdgWmBase,
+};
+use wayland_protocols::xdg::system_bell::v1::client::xdg_system_bell_v1::XdgSystemBellV1;
+
+#[derive(Default)]
+struct App;
+
+struct BellApp {
+    client: TestClient<App>,
+    state: App,
+    bell: XdgSystemBellV1,
+    surface: WlSurface,
+    _toplevel: XdgToplevel,
+    _xdg_surface: XdgSurface,
+}
+
+/// Connect a client with a 400x300 toplevel titled "Terminal"
+fn connect_bell_app(server: &mut TestServer) -> BellApp {
+    let mut client = server.connect::<App>();
+    let mut state = App;
+
+    let compositor: WlCompositor = client.bind(6, ());
+    let shm: WlShm = client.bind(1, ());
+    let wm_base: XdgWmBase = client.bind(6, ());
+    let bell: XdgSystemBellV1 = client.bind(1, ());
+
+    let surface = compositor.create_surface(&client.qh, ());
+    let xdg_surface = wm_base.get_xdg_surface(&surface, &client.qh, ());
+    let toplevel = xdg_surface.get_toplevel(&client.qh, ());
+    toplevel.set_title("Terminal".to_string());
+    toplevel.set_app_id("org.example.Terminal".to_string());
+    let buffer = create_shm_buffer(&shm, &client.qh, 400, 300);
+    surface.attach(Some(&buffer), 0, 0);
+    surface.commit();
+    server.roundtrip(&mut client, &mut state);
+
+    BellApp { client, state, bell, surface, _toplevel: toplevel, _xdg_surface: xdg_surface }
+}
+
+fn only_window(state: &WaylandServerState) -> Window {
+    state.windows().next().map(|(_, window)| window.clone()).expect("Window should be mapped")
+}
+
+/// Test that a bell flashes its window, marks it urgent until focused and runs the command
+#[test]
+fn bell_flashes_window_and_sets_urgency() {
+    let marker = std::env::temp_dir().join(format!("compositor-test-bell-{}", std::process::id()));
+    let _ = std::fs::remove_file(&marker);
+
+    let mut config = CompositorConfig::default();
+    config.bell.flash_duration = 60_000;
+    config.bell.command = Some(format!("echo \"$COMPOSITOR_BELL_APP_ID\" > '{}'", marker.display()));
+    let mut server = TestServer::with_config(config);
+    let mut app = connect_bell_app(&mut server);
+
+    app.bell.ring(Some(&app.surface));
+    server.roundtrip(&mut app.client, &mut app.state);
+
+    let state = &mut server.server.state;
+    let output = state.space.outputs().next().cloned().expect("Output should exist");
+    let window = only_window(state);
+    let flashes = state.bell_flashes(&output);
+    assert_eq!(flashes.len(), 1);
+    assert_eq!(flashes[0].area, Rectangle::new((100, 100).into(), (400, 300).into()));
+    assert!(flashes[0].intensity > 0.9 && flashes[0].intensity <= 1.0);
+    let draws = state.flash_draws(&output);
+    assert_eq!(draws.len(), 1);
+    assert_eq!((draws[0].position, draws[0].destination), ([100.0, 100.0], Some([400.0, 300.0])));
+    assert_eq!(draws[0].color, Some([1.0, 1.0, 1.0, 1.0]));
+    assert!(draws[0].alpha > 0.0 && draws[0].alpha <= 0.5);
+    assert!(state.is_window_urgent(&window));
+
+    let IPCMessage::WindowList { windows } = state.handle_ipc_message(IPCMessage::ListWindows) else {
+        panic!("Expected a window list");
+    };
+    assert!(windows[0].urgent);
+
+    // The command sees the ringing window
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let output_line = loop {
+        match std::fs::read_to_string(&marker) {
+            Ok(content) if content.ends_with('\n') => break content,
+            _ if Instant::now() < deadline => std::thread::sleep(Duration::from_millis(10)),
+            _ => panic!("Bell command did not run"),
+        }
+    };
+    assert_eq!(output_line, "org.example.Terminal\n");
+    let _ = std::fs::remove_file(&marker);
+
+    // Focusing the window clears its urgency, bells while focused do not set it
+    state.focus_window(&window);
+    assert!(!state.is_window_urgent(&window));
+    app.bell.ring(Some(&app.surface));
+    server.roundtrip(&mut app.client, &mut app.state);
+    assert!(!server.server.state.is_window_urgent(&window));
+}
+
+/// Test output flashes, their fade and expiry, and disabled feedback
+#[test]
+fn bell_flashes_output_and_expires() {
+    let mut config = CompositorConfig::default();
+    config.bell.flash = BellFlash::Output;
+    config.bell.flash_duration = 50;
+    config.bell.urgency = false;
+    let mut server = TestServer::with_config(config);
+    let mut app = connect_bell_app(&mut server);
+
+    // Bells without a surface flash the output as well
+    app.bell.ring(None);
+    server.roundtrip(&mut app.client, &mut app.state);
+
+    let state = &mut server.server.state;
+    let output = state.space.outputs().next().cloned().expect("Output should exist");
+    let output_geometry = state.space.output_geometry(&output).expect("Output should be mapped");
+    let flashes = state.bell_flashes(&output);
+    assert_eq!(flashes.len(), 1);
+    assert_eq!(flashes[0].area, output_geometry);
+
+    std::thread::sleep(Duration::from_millis(60));
+    assert!(state.bell_flashes(&output).is_empty());
+    state.refresh();
+    assert!(state.bell_flashes(&output).is_empty());
+
+    app.bell.ring(Some(&app.surface));
+    server.roundtrip(&mut app.client, &mut app.state);
+    let state = &mut server.server.state;
+    assert!(!state.is_window_urgent(&only_window(state)));
+
+    // Without a flash, bells leave nothing to draw
+    state.config.bell.flash = BellFlash::None;
+    std::thread::sleep(Duration::from_millis(60));
+    app.bell.ring(Some(&app.surface));
+    server.roundtrip(&mut app.client, &mut app.state);
+    assert!(server.server.state.bell_flashes(&output).is_empty());
+}
+
+// ============================================================================
+// Client-side dispatch
+// ============================================================================
+
+wayland_client::delegate_noop!(App: ignore WlSurface);
+wayland_client::delegate_noop!(App: ignore WlShm);
+wayland_client::delegate_noop!(App: ignore WlBuffer);
+wayland_client::delegate_noop!(App: ignore XdgSurface);
+wayland_client::delegate_noop!(App: ignore XdgToplevel);
+wayland_client::delegate_noop!(App: WlCompositor);
+wayland_client::delegate_noop!(App: WlShmPool);
+wayland_client::delegate_noop!(App: XdgSystemBellV1);
+
+wayland_client::delegate_dispatch!(App: [XdgWmBase: ()] => PingPong);
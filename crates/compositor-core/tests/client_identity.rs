@@ -0,0 +1,121 @@
+//! Client identity integration tests
+//!
+//! Records the credentials, executable, cgroup and security context of
+//! connecting clients and reports them for windows over IPC.
+
+mod common;
+
+use common::{create_shm_buffer, PingPong, TestServer};
+use compositor_core::wayland::ClientState;
+use compositor_core::workspace::window_id;
+use ipc::protocol::IPCMessage;
+use smithay::wayland::security_context::SecurityContext;
+use std::os::unix::fs::MetadataExt;
+use std::time::SystemTime;
+use wayland_client::protocol::{
+    wl_buffer::WlBuffer,
+    wl_compositor::WlCompositor,
+    wl_shm::WlShm,
+    wl_shm_pool::WlShmPool,
+    wl_surface::WlSurface,
+};
+use wayland_protocols::xdg::shell::client::{
+    xdg_surface::XdgSurface,
+    xdg_toplevel::XdgToplevel,
+    xdg_wm_base::XdgWmBase,
+};
+
+#[derive(Default)]
+struct App;
+
+/// Test that clients are identified by the credentials of their socket
+#[test]
+fn identity_from_socket_credentials() {
+    let started = SystemTime::now();
+    let mut server = TestServer::new();
+    let client = server.connect::<App>();
+
+    // Test clients run in this process
+    let process = std::fs::metadata("/proc/self").expect("Failed to read process metadata");
+    let identity = server
+        .server
+        .state
+        .client_identity(&client.server_client)
+        .expect("Client should have an identity");
+    assert_eq!(identity.pid, std::process::id() as i32);
+    assert_eq!((identity.uid, identity.gid), (process.uid(), process.gid()));
+    assert_eq!(identity.executable, std::env::current_exe().ok());
+    assert_eq!(identity.cgroup.is_some(), std::path::Path::new("/proc/self/cgroup").exists());
+    assert!(identity.connected_at >= started);
+    assert_eq!(identity.sandbox_app_id, None);
+    assert!(identity.to_string().contains(&format!("pid {}", std::process::id())));
+
+    let sandboxed = server.connect_with::<App>(ClientState {
+        security_context: Some(SecurityContext {
+            sandbox_engine: Some("org.flatpak".to_string()),
+            app_id: Some("org.example.App".to_string()),
+            instance_id: Some("1234".to_string()),
+            creator_client_id: client.server_client.id(),
+        }),
+        ..ClientState::default()
+    });
+    let identity = server
+        .server
+        .state
+        .client_identity(&sandboxed.server_client)
+        .expect("Sandboxed client should have an identity");
+    assert_eq!(identity.pid, std::process::id() as i32);
+    assert_eq!(identity.sandbox_engine.as_deref(), Some("org.flatpak"));
+    assert_eq!(identity.sandbox_app_id.as_deref(), Some("org.example.App"));
+    assert_eq!(identity.sandbox_instance_id.as_deref(), Some("1234"));
+}
+
+/// Test that windows report the identity of their client over IPC
+#[test]
+fn window_client_identity_over_ipc() {
+    let mut server = TestServer::new();
+    let mut client = server.connect::<App>();
+    let compositor: WlCompositor = client.bind(6, ());
+    let shm: WlShm = client.bind(1, ());
+    let wm_base: XdgWmBase = client.bind(6, ());
+
+    let qh = client.qh.clone();
+    let surface = compositor.create_surface(&qh, ());
+    let xdg_surface = wm_base.get_xdg_surface(&surface, &qh, ());
+    let _toplevel = xdg_surface.get_toplevel(&qh, ());
+    let buffer = create_shm_buffer(&shm, &qh, 64, 64);
+    surface.attach(Some(&buffer), 0, 0);
+    surface.commit();
+    server.roundtrip(&mut client, &mut App);
+
+    let state = &mut server.server.state;
+    let (_, window) = state.windows().next().expect("Window should be mapped");
+    let id = window_id(window).expect("Window should have an identifier").0;
+    assert_eq!(state.window_client_identity(window), state.client_identity(&client.server_client));
+
+    let IPCMessage::WindowList { windows } = state.handle_ipc_message(IPCMessage::ListWindows) else {
+        panic!("Expected a window list");
+    };
+    let info = windows[0].client.as_ref().expect("Window should report its client");
+    assert_eq!(info.pid, std::process::id() as i32);
+    assert_eq!(info.executable, std::env::current_exe().ok().map(|path| path.display().to_string()));
+
+    match state.handle_ipc_message(IPCMessage::GetWindowInfo { window_id: id }) {
+        IPCMessage::WindowInfo { client: Some(info), .. } => assert_eq!(info.pid, std::process::id() as i32),
+        other => panic!("Unexpected response: {:?}", other),
+    }
+}
+
+// ============================================================================
+// Client-side dispatch
+// ============================================================================
+
+wayland_client::delegate_noop!(App: ignore WlSurface);
+wayland_client::delegate_noop!(App: ignore WlShm);
+wayland_client::delegate_noop!(App: ignore WlBuffer);
+wayland_client::delegate_noop!(App: ignore XdgSurface);
+wayland_client::delegate_noop!(App: ignore XdgToplevel);
+wayland_client::delegate_noop!(App: WlCompositor);
+wayland_client::delegate_noop!(App: WlShmPool);
+
+wayland_client::delegate_dispatch!(App: [XdgWmBase: ()] => PingPong);
@@ -0,0 +1,219 @@
+//! Commit queue integration tests
+//!
+//! Commits surfaces with wp_fifo_v1 barriers and wp_commit_timer_v1 target
+//! times and checks when the frame scheduler lets them apply, including for
+//! hidden windows and synchronized subsurfaces.
+
+mod common;
+
+use common::{create_shm_buffer, present_frame, PingPong, TestClient, TestServer};
+use compositor_core::wayland::WaylandServerState;
+use smithay::backend::renderer::utils::with_renderer_surface_state;
+use smithay::reexports::wayland_protocols::wp::presentation_time::server::wp_presentation_feedback::Kind;
+use smithay::reexports::wayland_server::protocol::wl_surface::WlSurface as ServerSurface;
+use smithay::wayland::compositor::get_children;
+use std::time::Duration;
+use wayland_client::protocol::{
+    wl_buffer::WlBuffer,
+    wl_compositor::WlCompositor,
+    wl_shm::WlShm,
+    wl_shm_pool::WlShmPool,
+    wl_subcompositor::WlSubcompositor,
+    wl_subsurface::WlSubsurface,
+    wl_surface::WlSurface,
+};
+use wayland_protocols::wp::commit_timing::v1::client::{
+    wp_commit_timer_v1::WpCommitTimerV1, wp_commit_timing_manager_v1::WpCommitTimingManagerV1,
+};
+use wayland_protocols::wp::fifo::v1::client::{wp_fifo_manager_v1::WpFifoManagerV1, wp_fifo_v1::WpFifoV1};
+use wayland_protocols::xdg::shell::client::{
+    xdg_surface::XdgSurface,
+    xdg_toplevel::XdgToplevel,
+    xdg_wm_base::XdgWmBase,
+};
+
+#[derive(Default)]
+struct App;
+
+struct QueueApp {
+    client: TestClient<App>,
+    state: App,
+    compositor: WlCompositor,
+    shm: WlShm,
+    fifo_manager: WpFifoManagerV1,
+    timing_manager: WpCommitTimingManagerV1,
+    surface: WlSurface,
+    _toplevel: XdgToplevel,
+    _xdg_surface: XdgSurface,
+}
+
+impl QueueApp {
+    /// Connect a client with a toplevel, leaving its first commit to the test
+    fn connect(server: &mut TestServer) -> Self {
+        let client = server.connect::<App>();
+        let compositor: WlCompositor = client.bind(6, ());
+        let shm: WlShm = client.bind(1, ());
+        let wm_base: XdgWmBase = client.bind(6, ());
+        let fifo_manager: WpFifoManagerV1 = client.bind(1, ());
+        let timing_manager: WpCommitTimingManagerV1 = client.bind(1, ());
+
+        let surface = compositor.create_surface(&client.qh, ());
+        let xdg_surface = wm_base.get_xdg_surface(&surface, &client.qh, ());
+        let toplevel = xdg_surface.get_toplevel(&client.qh, ());
+        Self {
+            client,
+            state: App,
+            compositor,
+            shm,
+            fifo_manager,
+            timing_manager,
+            surface,
+            _toplevel: toplevel,
+            _xdg_surface: xdg_surface,
+        }
+    }
+
+    /// Attach a buffer `width` pixels wide to a surface
+    fn attach(&self, surface: &WlSurface, width: i32) {
+        let buffer = create_shm_buffer(&self.shm, &self.client.qh, width, 100);
+        surface.attach(Some(&buffer), 0, 0);
+    }
+
+    fn roundtrip(&mut self, server: &mut TestServer) {
+        server.roundtrip(&mut self.client, &mut self.state);
+    }
+}
+
+/// Server-side surface of the only window
+fn window_surface(state: &WaylandServerState) -> ServerSurface {
+    let (_, window) = state.windows().next().expect("Window should exist");
+    window.toplevel().expect("Window should be a toplevel").wl_surface().clone()
+}
+
+/// Width of the buffer applied to a surface
+fn applied_width(surface: &ServerSurface) -> Option<i32> {
+    with_renderer_surface_state(surface, |state| state.buffer_size().map(|size| size.w)).flatten()
+}
+
+/// Test that FIFO commits apply one presented frame at a time
+#[test]
+fn fifo_commits_wait_for_presentation() {
+    let mut server = TestServer::new();
+    let mut app = QueueApp::connect(&mut server);
+    let fifo = app.fifo_manager.get_fifo(&app.surface, &app.client.qh, ());
+
+    app.attach(&app.surface, 200);
+    fifo.set_barrier();
+    app.surface.commit();
+    for width in [300, 400] {
+        app.attach(&app.surface, width);
+        fifo.wait_barrier();
+        fifo.set_barrier();
+        app.surface.commit();
+    }
+    app.roundtrip(&mut server);
+
+    let surface = window_surface(&server.server.state);
+    assert_eq!(applied_width(&surface), Some(200));
+
+    present_frame(&mut server.server.state);
+    assert_eq!(applied_width(&surface), Some(300), "Presenting the first content releases the second");
+    app.roundtrip(&mut server);
+    assert_eq!(applied_width(&surface), Some(300));
+
+    present_frame(&mut server.server.state);
+    assert_eq!(applied_width(&surface), Some(400));
+}
+
+/// Test that hidden windows and synchronized subsurfaces do not stall on FIFO barriers
+#[test]
+fn fifo_does_not_stall_hidden_surfaces() {
+    let mut server = TestServer::new();
+    let mut app = QueueApp::connect(&mut server);
+    let subcompositor: WlSubcompositor = app.client.bind(1, ());
+    let fifo = app.fifo_manager.get_fifo(&app.surface, &app.client.qh, ());
+
+    // Synchronized subsurfaces apply with their parent, barriers or not
+    let child = app.compositor.create_surface(&app.client.qh, ());
+    let _subsurface = subcompositor.get_subsurface(&child, &app.surface, &app.client.qh, ());
+    let child_fifo = app.fifo_manager.get_fifo(&child, &app.client.qh, ());
+    app.attach(&child, 50);
+    child_fifo.set_barrier();
+    child.commit();
+    app.attach(&child, 60);
+    child_fifo.wait_barrier();
+    child.commit();
+
+    app.attach(&app.surface, 200);
+    fifo.set_barrier();
+    app.surface.commit();
+    app.roundtrip(&mut server);
+
+    let surface = window_surface(&server.server.state);
+    let child_surface = get_children(&surface).pop().expect("Subsurface should exist");
+    assert_eq!(applied_width(&surface), Some(200));
+    assert_eq!(applied_width(&child_surface), Some(60));
+
+    // Windows of inactive workspaces are released with the frames of other content
+    server.server.state.switch_workspace(1).expect("Failed to switch workspace");
+    app.attach(&app.surface, 300);
+    fifo.wait_barrier();
+    app.surface.commit();
+    app.roundtrip(&mut server);
+    assert_eq!(applied_width(&surface), Some(200));
+
+    present_frame(&mut server.server.state);
+    assert_eq!(applied_width(&surface), Some(300));
+}
+
+/// Test that timed commits apply with the first frame shown at or after their target time
+#[test]
+fn commit_timer_waits_for_target_time() {
+    let mut server = TestServer::new();
+    let mut app = QueueApp::connect(&mut server);
+    let timer = app.timing_manager.get_timer(&app.surface, &app.client.qh, ());
+
+    app.attach(&app.surface, 200);
+    app.surface.commit();
+    app.roundtrip(&mut server);
+
+    let target = Duration::from(server.server.state.clock.now()) + Duration::from_secs(10);
+    let seconds = target.as_secs();
+    timer.set_timestamp((seconds >> 32) as u32, seconds as u32, target.subsec_nanos());
+    app.attach(&app.surface, 300);
+    app.surface.commit();
+    app.roundtrip(&mut server);
+
+    let state = &mut server.server.state;
+    let surface = window_surface(state);
+    assert_eq!(applied_width(&surface), Some(200));
+    present_frame(state);
+    assert_eq!(applied_width(&surface), Some(200), "Frames before the target keep the commit queued");
+
+    // The frame after one presented just before the target is shown after it
+    let output = state.space.outputs().next().cloned().expect("Output should exist");
+    state.frame_presented(&output, target - Duration::from_millis(10), None, Kind::Vsync);
+    assert!(state.next_presentation_time(&output) >= target);
+    state.render_frame(&output);
+    assert_eq!(applied_width(&surface), Some(300));
+}
+
+// ============================================================================
+// Client-side dispatch
+// ============================================================================
+
+wayland_client::delegate_noop!(App: ignore WlSurface);
+wayland_client::delegate_noop!(App: ignore WlShm);
+wayland_client::delegate_noop!(App: ignore WlBuffer);
+wayland_client::delegate_noop!(App: ignore XdgSurface);
+wayland_client::delegate_noop!(App: ignore XdgToplevel);
+wayland_client::delegate_noop!(App: WlCompositor);
+wayland_client::delegate_noop!(App: WlShmPool);
+wayland_client::delegate_noop!(App: WlSubcompositor);
+wayland_client::delegate_noop!(App: WlSubsurface);
+wayland_client::delegate_noop!(App: WpFifoManagerV1);
+wayland_client::delegate_noop!(App: WpFifoV1);
+wayland_client::delegate_noop!(App: WpCommitTimingManagerV1);
+wayland_client::delegate_noop!(App: WpCommitTimerV1);
+
+wayland_client::delegate_dispatch!(App: [XdgWmBase: ()] => PingPong);
@@ -0,0 +1,347 @@
+//! Shared helpers for protocol-level integration tests
+//!
+//! Runs a `WaylandServer` in-process and connects real `wayland-client`
+//! connections to it over socket pairs. Both sides are dispatched in lockstep
+//! on the test thread, so no background threads or sockets on disk are needed.
+
+#![allow(dead_code)]
+
+use compositor_core::input::BackendInput;
+use compositor_core::wayland::{ClientState, WaylandServer, WaylandServerState};
+use compositor_core::workspace::WindowId;
+use config::CompositorConfig;
+use smithay::input::Seat;
+use smithay::reexports::wayland_protocols::wp::presentation_time::server::wp_presentation_feedback;
+use std::os::fd::AsFd;
+use std::os::unix::net::UnixStream;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use wayland_client::{
+    protocol::{
+        wl_buffer::WlBuffer,
+        wl_callback::{self, WlCallback},
+        wl_compositor::WlCompositor,
+        wl_registry::{self, WlRegistry},
+        wl_shm::{self, WlShm},
+        wl_shm_pool::WlShmPool,
+        wl_surface::WlSurface,
+    },
+    Connection, Dispatch, EventQueue, Proxy, QueueHandle,
+};
+use wayland_protocols::xdg::shell::client::{
+    xdg_surface::XdgSurface,
+    xdg_toplevel::XdgToplevel,
+    xdg_wm_base::{self, XdgWmBase},
+};
+
+/// BTN_LEFT
+pub const LEFT_BUTTON: u32 = 0x110;
+
+/// Upper bound on dispatch iterations before a roundtrip is considered stuck
+const MAX_ROUNDTRIP_ITERATIONS: usize = 1000;
+
+/// In-process compositor under test
+pub struct TestServer {
+    pub server: WaylandServer,
+}
+
+impl TestServer {
+    pub fn new() -> Self {
+        Self {
+            server: WaylandServer::new().expect("Failed to create Wayland server"),
+        }
+    }
+
+    pub fn with_config(config: CompositorConfig) -> Self {
+        Self {
+            server: WaylandServer::with_config(config).expect("Failed to create Wayland server"),
+        }
+    }
+
+    /// Connect a client with default server-side client data
+    pub fn connect<S: 'static>(&mut self) -> TestClient<S> {
+        self.connect_with(ClientState::default())
+    }
+
+    /// Connect a client with custom server-side client data
+    pub fn connect_with<S: 'static>(&mut self, client_state: ClientState) -> TestClient<S> {
+        self.connect_stream(|state, stream| state.insert_client(stream, client_state))
+    }
+
+    /// Connect a client through a protocol trace relay
+    pub fn connect_traced<S: 'static>(&mut self) -> TestClient<S> {
+        self.connect_stream(|state, stream| state.insert_traced_client(stream, ClientState::default()))
+    }
+
+    /// Connect a client the way clients of the listening socket are accepted
+    pub fn connect_accepted<S: 'static>(&mut self) -> TestClient<S> {
+        self.connect_stream(|state, stream| state.accept_client(stream, ClientState::default()))
+    }
+
+    fn connect_stream<S: 'static>(
+        &mut self,
+        insert: impl FnOnce(&mut WaylandServerState, UnixStream) -> std::io::Result<wayland_server::Client>,
+    ) -> TestClient<S> {
+        let (server_stream, client_stream) = UnixStream::pair().expect("Failed to create socket pair");
+
+        let server_client = insert(&mut self.server.state, server_stream).expect("Failed to insert client");
+
+        let connection = Connection::from_socket(client_stream).expect("Failed to connect client");
+        let queue = connection.new_event_queue::<S>();
+        let qh = queue.handle();
+
+        let mut registry_queue = connection.new_event_queue::<RegistryState>();
+        let registry = connection.display().get_registry(&registry_queue.handle(), ());
+        let mut registry_state = RegistryState::default();
+        self.pump_until(&connection, || {
+            registry_queue
+                .dispatch_pending(&mut registry_state)
+                .expect("Failed to dispatch registry events");
+        });
+
+        TestClient {
+            connection,
+            queue,
+            qh,
+            registry,
+            globals: registry_state.globals,
+            server_client,
+        }
+    }
+
+    /// Process pending requests from all clients, run ready event sources and flush events
+    pub fn dispatch(&mut self) {
+        self.server
+            .display
+            .dispatch_clients(&mut self.server.state)
+            .expect("Failed to dispatch clients");
+        self.server
+            .event_loop
+            .dispatch(Some(Duration::ZERO), &mut self.server.state)
+            .expect("Failed to dispatch event loop");
+        self.server.state.refresh();
+        self.server.display.flush_clients().expect("Failed to flush clients");
+    }
+
+    /// Dispatch both sides until the server has answered everything sent so far
+    pub fn roundtrip<S: 'static>(&mut self, client: &mut TestClient<S>, state: &mut S) {
+        let connection = client.connection.clone();
+        let queue = &mut client.queue;
+        self.pump_until(&connection, || {
+            queue.dispatch_pending(state).expect("Failed to dispatch client events");
+        });
+    }
+
+    /// Exchange messages over a connection until a sync callback fires
+    ///
+    /// `dispatch` runs after every read to drain the caller's queues.
+    fn pump_until(&mut self, connection: &Connection, mut dispatch: impl FnMut()) {
+        let mut sync_queue = connection.new_event_queue::<SyncState>();
+        let mut sync = SyncState::default();
+        connection.display().sync(&sync_queue.handle(), ());
+
+        for _ in 0..MAX_ROUNDTRIP_ITERATIONS {
+            connection.flush().expect("Failed to flush client");
+            self.dispatch();
+
+            if let Some(guard) = connection.prepare_read() {
+                let _ = guard.read();
+            }
+            dispatch();
+            sync_queue.dispatch_pending(&mut sync).expect("Failed to dispatch sync events");
+
+            if sync.done {
+                return;
+            }
+        }
+
+        panic!("Roundtrip did not complete");
+    }
+}
+
+/// Client side of a test connection
+pub struct TestClient<S> {
+    pub connection: Connection,
+    pub queue: EventQueue<S>,
+    pub qh: QueueHandle<S>,
+    pub registry: WlRegistry,
+    /// Globals advertised at connection time as (name, interface, version)
+    pub globals: Vec<(u32, String, u32)>,
+    /// Server-side handle of this client
+    pub server_client: wayland_server::Client,
+}
+
+impl<S: 'static> TestClient<S> {
+    /// Whether the registry advertises a global with this interface name
+    pub fn has_global(&self, interface: &str) -> bool {
+        self.globals.iter().any(|(_, name, _)| name == interface)
+    }
+
+    /// Bind the first global of the given interface
+    pub fn bind<I, U>(&self, version: u32, user_data: U) -> I
+    where
+        I: Proxy + 'static,
+        U: Send + Sync + 'static,
+        S: Dispatch<I, U>,
+    {
+        let interface = I::interface().name;
+        let (name, _, advertised) = self
+            .globals
+            .iter()
+            .find(|(_, global, _)| global == interface)
+            .unwrap_or_else(|| panic!("Global {} is not advertised", interface));
+
+        self.registry
+            .bind::<I, U, S>(*name, version.min(*advertised), &self.qh, user_data)
+    }
+}
+
+/// Create a zero-filled ARGB8888 shm buffer
+pub fn create_shm_buffer<S>(shm: &WlShm, qh: &QueueHandle<S>, width: i32, height: i32) -> WlBuffer
+where
+    S: Dispatch<WlShmPool, ()> + Dispatch<WlBuffer, ()> + 'static,
+{
+    static POOL_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+    let stride = width * 4;
+    let path = std::env::temp_dir().join(format!(
+        "compositor-test-shm-{}-{}",
+        std::process::id(),
+        POOL_COUNT.fetch_add(1, Ordering::Relaxed)
+    ));
+    let file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .expect("Failed to create shm file");
+    let _ = std::fs::remove_file(&path);
+    file.set_len((stride * height) as u64).expect("Failed to size shm file");
+
+    let pool = shm.create_pool(file.as_fd(), stride * height, qh, ());
+    let buffer = pool.create_buffer(0, width, height, stride, wl_shm::Format::Argb8888, qh, ());
+    pool.destroy();
+    buffer
+}
+
+/// Client objects of a mapped window
+pub type WindowObjects = (WlSurface, XdgSurface, XdgToplevel, WlBuffer);
+
+/// Map windows side by side, 200 by 200 and 500 apart
+///
+/// Expects the client to have no windows yet, so they get the first window ids.
+pub fn map_windows<S>(server: &mut TestServer, client: &mut TestClient<S>, state: &mut S, count: i32) -> Vec<WindowObjects>
+where
+    S: Dispatch<WlCompositor, ()>
+        + Dispatch<WlShm, ()>
+        + Dispatch<XdgWmBase, ()>
+        + Dispatch<WlSurface, ()>
+        + Dispatch<XdgSurface, ()>
+        + Dispatch<XdgToplevel, ()>
+        + Dispatch<WlShmPool, ()>
+        + Dispatch<WlBuffer, ()>
+        + 'static,
+{
+    let compositor: WlCompositor = client.bind(6, ());
+    let shm: WlShm = client.bind(1, ());
+    let wm_base: XdgWmBase = client.bind(6, ());
+    let qh = client.qh.clone();
+
+    let mut windows = Vec::new();
+    for index in 0..count {
+        let surface = compositor.create_surface(&qh, ());
+        let xdg_surface = wm_base.get_xdg_surface(&surface, &qh, ());
+        let toplevel = xdg_surface.get_toplevel(&qh, ());
+        let buffer = create_shm_buffer(&shm, &qh, 200, 200);
+        surface.attach(Some(&buffer), 0, 0);
+        surface.commit();
+        server.roundtrip(client, state);
+
+        let state = &mut server.server.state;
+        let (_, window) = state.window_by_id(WindowId(index as u32 + 1)).expect("Window should be mapped");
+        let window = window.clone();
+        state.move_window(&window, (100 + index * 500, 100).into()).expect("Failed to move window");
+        windows.push((surface, xdg_surface, toplevel, buffer));
+    }
+    windows
+}
+
+/// Click the left button at a location with the default seat
+pub fn click(state: &mut WaylandServerState, location: (f64, f64)) {
+    let seat = state.seat.clone();
+    seat_click(state, &seat, location);
+}
+
+/// Click the left button at a location with a seat
+pub fn seat_click(state: &mut WaylandServerState, seat: &Seat<WaylandServerState>, location: (f64, f64)) {
+    state.apply_seat_input(seat, BackendInput::PointerMotionAbsolute { location });
+    state.apply_seat_input(seat, BackendInput::PointerButton { button: LEFT_BUTTON, pressed: true });
+    state.apply_seat_input(seat, BackendInput::PointerButton { button: LEFT_BUTTON, pressed: false });
+}
+
+/// Build and present a frame of the first output, as the backend does
+pub fn present_frame(state: &mut WaylandServerState) {
+    let output = state.space.outputs().next().cloned().expect("Output should exist");
+    state.render_frame(&output);
+    let time = Duration::from(state.clock.now());
+    state.frame_presented(&output, time, None, wp_presentation_feedback::Kind::Vsync);
+}
+
+/// Answers `xdg_wm_base` pings, for test clients to delegate to
+///
+/// `wayland_client::delegate_dispatch!(App: [XdgWmBase: ()] => PingPong);`
+pub struct PingPong;
+
+impl<S: Dispatch<XdgWmBase, ()>> Dispatch<XdgWmBase, (), S> for PingPong {
+    fn event(_: &mut S, wm_base: &XdgWmBase, event: xdg_wm_base::Event, _: &(), _: &Connection, _: &QueueHandle<S>) {
+        if let xdg_wm_base::Event::Ping { serial } = event {
+            wm_base.pong(serial);
+        }
+    }
+}
+
+#[derive(Default)]
+struct RegistryState {
+    globals: Vec<(u32, String, u32)>,
+}
+
+impl Dispatch<WlRegistry, ()> for RegistryState {
+    fn event(
+        state: &mut Self,
+        _registry: &WlRegistry,
+        event: wl_registry::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            wl_registry::Event::Global { name, interface, version } => {
+                state.globals.push((name, interface, version));
+            }
+            wl_registry::Event::GlobalRemove { name } => {
+                state.globals.retain(|(global, _, _)| *global != name);
+            }
+            _ => {}
+        }
+    }
+}
+
+#[derive(Default)]
+struct SyncState {
+    done: bool,
+}
+
+impl Dispatch<WlCallback, ()> for SyncState {
+    fn event(
+        state: &mut Self,
+        _callback: &WlCallback,
+        event: wl_callback::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        if let wl_callback::Event::Done { .. } = event {
+            state.done = true;
+        }
+    }
+}
@@ -0,0 +1,318 @@
+//! Cursor integration tests
+//!
+//! Loads a small XCursor theme written to a temporary directory and checks
+//! the cursor images picked for each output scale, shape requests over
+//! wp_cursor_shape_v1 and the hotspot of client cursor surfaces.
+
+mod common;
+
+use common::{create_shm_buffer, PingPong, TestClient, TestServer};
+use config::CompositorConfig;
+use smithay::input::pointer::{CursorIcon, CursorImageStatus, MotionEvent};
+use smithay::input::SeatHandler;
+use smithay::output::Scale;
+use smithay::utils::{Physical, Rectangle, SERIAL_COUNTER};
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::Duration;
+use wayland_client::{
+    protocol::{
+        wl_buffer::WlBuffer,
+        wl_compositor::WlCompositor,
+        wl_pointer::{self, WlPointer},
+        wl_seat::WlSeat,
+        wl_shm::WlShm,
+        wl_shm_pool::WlShmPool,
+        wl_surface::WlSurface,
+    },
+    Connection, Dispatch, Proxy, QueueHandle,
+};
+use wayland_protocols::wp::cursor_shape::v1::client::{
+    wp_cursor_shape_device_v1::{Shape, WpCursorShapeDeviceV1},
+    wp_cursor_shape_manager_v1::WpCursorShapeManagerV1,
+};
+use wayland_protocols::xdg::shell::client::{
+    xdg_surface::XdgSurface,
+    xdg_toplevel::XdgToplevel,
+    xdg_wm_base::XdgWmBase,
+};
+use wayland_server::Resource;
+
+/// Image of a theme cursor: nominal size, hotspot and delay
+type TestImage = (u32, (u32, u32), u32);
+
+/// Encode images as an XCursor file, each image as large as its nominal size
+fn xcursor_file(images: &[TestImage]) -> Vec<u8> {
+    const IMAGE_TYPE: u32 = 0xfffd0002;
+    let mut file = Vec::new();
+    let push = |file: &mut Vec<u8>, value: u32| file.extend_from_slice(&value.to_le_bytes());
+
+    file.extend_from_slice(b"Xcur");
+    push(&mut file, 16);
+    push(&mut file, 0x10000);
+    push(&mut file, images.len() as u32);
+
+    let mut position = 16 + 12 * images.len() as u32;
+    for &(size, _, _) in images {
+        push(&mut file, IMAGE_TYPE);
+        push(&mut file, size);
+        push(&mut file, position);
+        position += 36 + 4 * size * size;
+    }
+    for &(size, (xhot, yhot), delay) in images {
+        for value in [36, IMAGE_TYPE, size, 1, size, size, xhot, yhot, delay] {
+            push(&mut file, value);
+        }
+        file.resize(file.len() + 4 * (size * size) as usize, 0xff);
+    }
+    file
+}
+
+/// Directory of the test theme, set as the XCursor search path
+fn theme_path() -> &'static PathBuf {
+    static PATH: OnceLock<PathBuf> = OnceLock::new();
+    PATH.get_or_init(|| {
+        let path = std::env::temp_dir().join(format!("compositor-cursor-test-{}", std::process::id()));
+        let cursors = path.join("test-theme").join("cursors");
+        std::fs::create_dir_all(&cursors).expect("Failed to create cursor theme");
+        // Only the X11 names exist, as in many older themes
+        std::fs::write(cursors.join("left_ptr"), xcursor_file(&[(24, (4, 2), 0), (48, (8, 4), 0)]))
+            .expect("Failed to write cursor");
+        std::fs::write(
+            cursors.join("watch"),
+            xcursor_file(&[(24, (12, 12), 100), (24, (11, 11), 50), (48, (24, 24), 100), (48, (22, 22), 50)]),
+        )
+        .expect("Failed to write cursor");
+        std::env::set_var("XCURSOR_PATH", &path);
+        path
+    })
+}
+
+fn cursor_server() -> TestServer {
+    theme_path();
+    let mut config = CompositorConfig::default();
+    config.cursor.theme = Some("test-theme".to_string());
+    config.cursor.size = Some(24);
+    TestServer::with_config(config)
+}
+
+#[derive(Default)]
+struct App {
+    enter_serial: Option<u32>,
+}
+
+/// An application window of 400x300 with the pointer of the default seat
+struct CursorApp {
+    client: TestClient<App>,
+    state: App,
+    compositor: WlCompositor,
+    shm: WlShm,
+    surface: WlSurface,
+    pointer: WlPointer,
+    _objects: (XdgSurface, XdgToplevel),
+}
+
+fn connect_cursor_app(server: &mut TestServer) -> CursorApp {
+    let mut client = server.connect::<App>();
+    let mut state = App::default();
+
+    let compositor: WlCompositor = client.bind(6, ());
+    let shm: WlShm = client.bind(1, ());
+    let seat: WlSeat = client.bind(7, ());
+    let wm_base: XdgWmBase = client.bind(6, ());
+
+    let surface = compositor.create_surface(&client.qh, ());
+    let xdg_surface = wm_base.get_xdg_surface(&surface, &client.qh, ());
+    let toplevel = xdg_surface.get_toplevel(&client.qh, ());
+    let buffer = create_shm_buffer(&shm, &client.qh, 400, 300);
+    surface.attach(Some(&buffer), 0, 0);
+    surface.commit();
+
+    let pointer = seat.get_pointer(&client.qh, ());
+    server.roundtrip(&mut client, &mut state);
+
+    CursorApp { client, state, compositor, shm, surface, pointer, _objects: (xdg_surface, toplevel) }
+}
+
+impl CursorApp {
+    /// Put the pointer over the window, mapped at (100, 100), returning the enter serial
+    fn hover(&mut self, server: &mut TestServer) -> u32 {
+        let surface = self
+            .client
+            .server_client
+            .object_from_protocol_id(&server.server.display.handle(), self.surface.id().protocol_id())
+            .expect("Surface should exist on the server");
+        let state = &mut server.server.state;
+        let pointer = state.seat.get_pointer().expect("Default seat should have a pointer");
+        let event = MotionEvent { location: (150.0, 150.0).into(), serial: SERIAL_COUNTER.next_serial(), time: 0 };
+        pointer.motion(state, Some((surface, (100.0, 100.0).into())), &event);
+        pointer.frame(state);
+        server.roundtrip(&mut self.client, &mut self.state);
+        self.state.enter_serial.expect("Pointer should have entered the window")
+    }
+}
+
+/// Test that theme cursors are picked at the output scale and animated
+#[test]
+fn theme_cursor_follows_output_scale() {
+    let mut server = cursor_server();
+    let state = &mut server.server.state;
+    let output = state.space.outputs().next().cloned().expect("Default output should be mapped");
+    assert_eq!(state.cursor_theme_state.name(), "test-theme");
+
+    // Nothing is drawn until the pointer is over the output
+    let pointer = state.seat.get_pointer().expect("Default seat should have a pointer");
+    let event = MotionEvent { location: (-10.0, -10.0).into(), serial: SERIAL_COUNTER.next_serial(), time: 0 };
+    pointer.motion(state, None, &event);
+    assert!(state.cursor_frame(&output).is_none());
+
+    let event = MotionEvent { location: (200.0, 100.0).into(), serial: SERIAL_COUNTER.next_serial(), time: 0 };
+    pointer.motion(state, None, &event);
+    let frame = state.cursor_frame(&output).expect("Default cursor should be drawn");
+    assert_eq!(frame.image.nominal_size, 24);
+    assert_eq!(frame.scale, 1.0);
+    assert_eq!(frame.location, (196.0, 98.0).into());
+
+    // HiDPI outputs get the larger images, placed at the same logical position
+    output.change_current_state(None, None, Some(Scale::Integer(2)), None);
+    let frame = state.cursor_frame(&output).expect("Default cursor should be drawn");
+    assert_eq!(frame.image.nominal_size, 48);
+    assert_eq!(frame.image.size, (48, 48).into());
+    assert_eq!(frame.scale, 2.0);
+    assert_eq!(frame.location, (196.0, 98.0).into());
+
+    // Frames follow each other by their delays and loop
+    let theme = &state.cursor_theme_state;
+    let hotspot_at = |millis| theme.frame(CursorIcon::Wait, 1.0, Duration::from_millis(millis)).unwrap().hotspot;
+    assert_eq!(hotspot_at(0), (12, 12).into());
+    assert_eq!(hotspot_at(120), (11, 11).into());
+    assert_eq!(hotspot_at(160), (12, 12).into());
+    let frame = theme.frame(CursorIcon::Wait, 1.75, Duration::from_millis(1180)).unwrap();
+    assert_eq!((frame.nominal_size, frame.delay), (48, Duration::from_millis(50)));
+
+    // Cursors missing from the theme fall back to the arrow
+    assert_eq!(theme.cursor(CursorIcon::Crosshair).unwrap().len(), 2);
+}
+
+/// Test that theme cursors are drawn above the scene and damage the output as they move
+#[test]
+fn theme_cursor_drawn_and_damaged() {
+    let mut server = cursor_server();
+    let state = &mut server.server.state;
+    let output = state.space.outputs().next().cloned().expect("Default output should be mapped");
+    let rect = |x, y, w, h| Rectangle::<i32, Physical>::new((x, y).into(), (w, h).into());
+    let pointer = state.seat.get_pointer().expect("Default seat should have a pointer");
+    let event = MotionEvent { location: (200.0, 100.0).into(), serial: SERIAL_COUNTER.next_serial(), time: 0 };
+    pointer.motion(state, None, &event);
+
+    let draws = state.cursor_draws(&output);
+    assert_eq!(draws.len(), 1);
+    assert_eq!(draws[0].seat, "seat0");
+    assert_eq!(draws[0].geometry(), rect(196, 98, 24, 24));
+    assert_eq!(draws[0].state.source, Some([0.0, 0.0, 24.0, 24.0]));
+
+    // Moving the cursor damages where it was and where it is
+    state.render_frame(&output);
+    state.render_frame(&output);
+    assert_eq!(state.output_damage(&output, 1), Some(Vec::new()));
+    let event = MotionEvent { location: (300.0, 100.0).into(), serial: SERIAL_COUNTER.next_serial(), time: 0 };
+    pointer.motion(state, None, &event);
+    state.render_frame(&output);
+    assert_eq!(state.output_damage(&output, 1), Some(vec![rect(196, 98, 24, 24), rect(296, 98, 24, 24)]));
+
+    // HiDPI outputs draw the larger image at the same logical size
+    output.change_current_state(None, None, Some(Scale::Integer(2)), None);
+    let draws = state.cursor_draws(&output);
+    assert_eq!(draws[0].geometry(), rect(592, 196, 48, 48));
+    assert_eq!(draws[0].state.source, Some([0.0, 0.0, 48.0, 48.0]));
+
+    // Hidden cursors draw nothing
+    let seat = state.seat.clone();
+    state.cursor_image(&seat, CursorImageStatus::Hidden);
+    assert!(state.cursor_draws(&output).is_empty());
+    state.render_frame(&output);
+    state.cursor_image(&seat, CursorImageStatus::default_named());
+    state.render_frame(&output);
+    assert_eq!(state.output_damage(&output, 1), Some(vec![rect(592, 196, 48, 48)]));
+}
+
+/// Test that cursor shapes requested by clients map to theme cursors
+#[test]
+fn cursor_shape_uses_theme() {
+    let mut server = cursor_server();
+    let mut app = connect_cursor_app(&mut server);
+    let serial = app.hover(&mut server);
+
+    let manager: WpCursorShapeManagerV1 = app.client.bind(1, ());
+    let device = manager.get_pointer(&app.pointer, &app.client.qh, ());
+    device.set_shape(serial, Shape::Wait);
+    server.roundtrip(&mut app.client, &mut app.state);
+
+    let state = &server.server.state;
+    let output = state.space.outputs().next().expect("Default output should be mapped");
+    let frame = state.cursor_frame(output).expect("Wait cursor should be drawn");
+    assert!(frame.image.hotspot == (12, 12).into() || frame.image.hotspot == (11, 11).into());
+    assert_eq!(frame.location, (150.0 - frame.image.hotspot.x as f64, 150.0 - frame.image.hotspot.y as f64).into());
+
+    // Stale serials are ignored
+    device.set_shape(serial.wrapping_sub(1), Shape::Crosshair);
+    server.roundtrip(&mut app.client, &mut app.state);
+    assert_eq!(server.server.state.cursor_image(), smithay::input::pointer::CursorImageStatus::Named(CursorIcon::Wait));
+}
+
+/// Test that client cursor surfaces are drawn at their hotspot
+#[test]
+fn cursor_surface_follows_hotspot() {
+    let mut server = cursor_server();
+    let mut app = connect_cursor_app(&mut server);
+    let serial = app.hover(&mut server);
+
+    let cursor = app.compositor.create_surface(&app.client.qh, ());
+    let buffer = create_shm_buffer(&app.shm, &app.client.qh, 16, 16);
+    cursor.attach(Some(&buffer), 0, 0);
+    cursor.commit();
+    app.pointer.set_cursor(serial, Some(&cursor), 4, 6);
+    server.roundtrip(&mut app.client, &mut app.state);
+
+    let cursor_location = |server: &TestServer| {
+        let state = &server.server.state;
+        let output = state.space.outputs().next().expect("Default output should be mapped");
+        assert!(state.cursor_frame(output).is_none());
+        let element = state.render_elements(output).pop().expect("Scene should not be empty");
+        assert_eq!(element.surface.id().protocol_id(), cursor.id().protocol_id());
+        (element.location.x, element.location.y)
+    };
+    assert_eq!(cursor_location(&server), (146, 144));
+
+    // Buffer offsets move the image, keeping the hotspot on the same pixel
+    cursor.attach(Some(&buffer), 0, 0);
+    cursor.offset(-2, 3);
+    cursor.commit();
+    server.roundtrip(&mut app.client, &mut app.state);
+    assert_eq!(cursor_location(&server), (144, 147));
+}
+
+// ============================================================================
+// Client-side dispatch
+// ============================================================================
+
+wayland_client::delegate_noop!(App: ignore WlSeat);
+wayland_client::delegate_noop!(App: ignore WlSurface);
+wayland_client::delegate_noop!(App: ignore WlShm);
+wayland_client::delegate_noop!(App: ignore WlBuffer);
+wayland_client::delegate_noop!(App: ignore XdgSurface);
+wayland_client::delegate_noop!(App: ignore XdgToplevel);
+wayland_client::delegate_noop!(App: WlCompositor);
+wayland_client::delegate_noop!(App: WlShmPool);
+wayland_client::delegate_noop!(App: WpCursorShapeManagerV1);
+wayland_client::delegate_noop!(App: WpCursorShapeDeviceV1);
+
+impl Dispatch<WlPointer, ()> for App {
+    fn event(state: &mut Self, _: &WlPointer, event: wl_pointer::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+        if let wl_pointer::Event::Enter { serial, .. } = event {
+            state.enter_serial = Some(serial);
+        }
+    }
+}
+
+wayland_client::delegate_dispatch!(App: [XdgWmBase: ()] => PingPong);
@@ -0,0 +1,184 @@
+//! Damage tracking integration tests
+//!
+//! Commits buffer damage from clients and checks the output damage each frame
+//! records: transformed through buffer scale, viewport and position, empty for
+//! idle frames, accumulated by buffer age and covering moved windows.
+
+mod common;
+
+use common::{create_shm_buffer, PingPong, TestClient, TestServer};
+use smithay::{
+    output::Output,
+    utils::{Physical, Rectangle},
+};
+use wayland_client::protocol::{
+    wl_buffer::WlBuffer,
+    wl_compositor::WlCompositor,
+    wl_shm::WlShm,
+    wl_shm_pool::WlShmPool,
+    wl_surface::WlSurface,
+};
+use wayland_protocols::wp::viewporter::client::{wp_viewport::WpViewport, wp_viewporter::WpViewporter};
+use wayland_protocols::xdg::shell::client::{
+    xdg_surface::XdgSurface,
+    xdg_toplevel::XdgToplevel,
+    xdg_wm_base::XdgWmBase,
+};
+
+#[derive(Default)]
+struct App;
+
+struct DamageApp {
+    client: TestClient<App>,
+    state: App,
+    shm: WlShm,
+    viewporter: WpViewporter,
+    surface: WlSurface,
+    _toplevel: XdgToplevel,
+    _xdg_surface: XdgSurface,
+}
+
+impl DamageApp {
+    /// Connect a client with a toplevel, leaving its first commit to the test
+    fn connect(server: &mut TestServer) -> Self {
+        let client = server.connect::<App>();
+        let compositor: WlCompositor = client.bind(6, ());
+        let shm: WlShm = client.bind(1, ());
+        let wm_base: XdgWmBase = client.bind(6, ());
+        let viewporter: WpViewporter = client.bind(1, ());
+
+        let surface = compositor.create_surface(&client.qh, ());
+        let xdg_surface = wm_base.get_xdg_surface(&surface, &client.qh, ());
+        let toplevel = xdg_surface.get_toplevel(&client.qh, ());
+        Self { client, state: App, shm, viewporter, surface, _toplevel: toplevel, _xdg_surface: xdg_surface }
+    }
+
+    /// Commit a new 200x100 buffer damaged in `damage`, in buffer pixels
+    fn commit_damage(&mut self, server: &mut TestServer, damage: (i32, i32, i32, i32)) {
+        let buffer = create_shm_buffer(&self.shm, &self.client.qh, 200, 100);
+        self.surface.attach(Some(&buffer), 0, 0);
+        self.surface.damage_buffer(damage.0, damage.1, damage.2, damage.3);
+        self.surface.commit();
+        server.roundtrip(&mut self.client, &mut self.state);
+    }
+}
+
+fn rect(x: i32, y: i32, w: i32, h: i32) -> Rectangle<i32, Physical> {
+    Rectangle::new((x, y).into(), (w, h).into())
+}
+
+/// Build a frame of the first output and return its damage
+fn frame_damage(server: &mut TestServer) -> (Output, Option<Vec<Rectangle<i32, Physical>>>) {
+    let state = &mut server.server.state;
+    let output = state.space.outputs().next().cloned().expect("Output should exist");
+    state.render_frame(&output);
+    let damage = state.output_damage(&output, 1);
+    (output, damage)
+}
+
+/// Test that only committed damage is repainted, and nothing in idle frames
+#[test]
+fn damage_follows_commits() {
+    let mut server = TestServer::new();
+    let mut app = DamageApp::connect(&mut server);
+    app.commit_damage(&mut server, (0, 0, 200, 100));
+
+    let (output, damage) = frame_damage(&mut server);
+    assert_eq!(damage, Some(vec![rect(0, 0, 3840, 2160)]), "First frames repaint the whole output");
+    assert_eq!(frame_damage(&mut server).1, Some(Vec::new()), "Idle frames have no damage");
+
+    // A blinking cursor in a terminal damages a few pixels of the window at (100, 100)
+    app.commit_damage(&mut server, (10, 20, 8, 16));
+    assert_eq!(frame_damage(&mut server).1, Some(vec![rect(110, 120, 8, 16)]));
+    app.commit_damage(&mut server, (30, 20, 8, 16));
+    assert_eq!(frame_damage(&mut server).1, Some(vec![rect(130, 120, 8, 16)]));
+
+    // Older images also need the damage of the frames since they were drawn
+    let state = &server.server.state;
+    assert_eq!(state.output_damage(&output, 2), Some(vec![rect(130, 120, 8, 16), rect(110, 120, 8, 16)]));
+    assert_eq!(state.output_damage(&output, 3), Some(vec![rect(0, 0, 3840, 2160)]));
+    assert_eq!(state.output_damage(&output, 4), None, "Images older than the history are repainted in full");
+}
+
+/// Test that idle frames leave buffer ages and the damage history in step
+#[test]
+fn idle_frames_keep_buffer_ages() {
+    let mut server = TestServer::new();
+    let mut app = DamageApp::connect(&mut server);
+    app.commit_damage(&mut server, (0, 0, 200, 100));
+    let (output, _) = frame_damage(&mut server);
+
+    // The renderer ages swapchain images only in frames it draws
+    app.commit_damage(&mut server, (10, 20, 8, 16));
+    assert_eq!(frame_damage(&mut server).1, Some(vec![rect(110, 120, 8, 16)]));
+    for _ in 0..3 {
+        assert_eq!(frame_damage(&mut server).1, Some(Vec::new()), "Idle frames have no damage");
+    }
+    let state = &server.server.state;
+    assert_eq!(state.output_damage(&output, 0), Some(Vec::new()), "Nothing is drawn in idle frames");
+
+    // An image drawn two drawn frames ago still needs the damage of the frame before the idle ones
+    app.commit_damage(&mut server, (30, 20, 8, 16));
+    assert_eq!(frame_damage(&mut server).1, Some(vec![rect(130, 120, 8, 16)]));
+    let state = &server.server.state;
+    assert_eq!(state.output_damage(&output, 2), Some(vec![rect(130, 120, 8, 16), rect(110, 120, 8, 16)]));
+    assert_eq!(state.output_damage(&output, 3), Some(vec![rect(0, 0, 3840, 2160)]));
+    assert_eq!(state.output_damage(&output, 4), None);
+}
+
+/// Test that buffer damage is carried through buffer scale and viewport scaling
+#[test]
+fn damage_through_scale_and_viewport() {
+    let mut server = TestServer::new();
+    let mut app = DamageApp::connect(&mut server);
+    let viewport: WpViewport = app.viewporter.get_viewport(&app.surface, &app.client.qh, ());
+
+    // 200x100 buffer pixels at scale 2 are 100x50 surface pixels, shown at 300x150
+    app.surface.set_buffer_scale(2);
+    viewport.set_destination(300, 150);
+    app.commit_damage(&mut server, (0, 0, 200, 100));
+    frame_damage(&mut server);
+
+    app.commit_damage(&mut server, (20, 20, 40, 20));
+    assert_eq!(frame_damage(&mut server).1, Some(vec![rect(130, 130, 60, 30)]));
+
+    // Cropped away damage is not shown, the 50x50 crop is stretched to 300x150
+    viewport.set_source(50.0, 0.0, 50.0, 50.0);
+    app.commit_damage(&mut server, (0, 0, 200, 100));
+    frame_damage(&mut server);
+    app.commit_damage(&mut server, (0, 0, 40, 40));
+    assert_eq!(frame_damage(&mut server).1, Some(Vec::new()));
+    app.commit_damage(&mut server, (100, 0, 10, 10));
+    assert_eq!(frame_damage(&mut server).1, Some(vec![rect(100, 100, 30, 15)]));
+}
+
+/// Test that moving a window damages both its old and new area
+#[test]
+fn damage_for_moved_windows() {
+    let mut server = TestServer::new();
+    let mut app = DamageApp::connect(&mut server);
+    app.commit_damage(&mut server, (0, 0, 200, 100));
+    frame_damage(&mut server);
+
+    let state = &mut server.server.state;
+    let window = state.windows().next().map(|(_, window)| window.clone()).expect("Window should be mapped");
+    state.space.map_element(window, (500, 400), false);
+    assert_eq!(frame_damage(&mut server).1, Some(vec![rect(100, 100, 200, 100), rect(500, 400, 200, 100)]));
+    assert_eq!(frame_damage(&mut server).1, Some(Vec::new()));
+}
+
+// ============================================================================
+// Client-side dispatch
+// ============================================================================
+
+wayland_client::delegate_noop!(App: ignore WlSurface);
+wayland_client::delegate_noop!(App: ignore WlShm);
+wayland_client::delegate_noop!(App: ignore WlBuffer);
+wayland_client::delegate_noop!(App: ignore XdgSurface);
+wayland_client::delegate_noop!(App: ignore XdgToplevel);
+wayland_client::delegate_noop!(App: WlCompositor);
+wayland_client::delegate_noop!(App: WlShmPool);
+wayland_client::delegate_noop!(App: WpViewporter);
+wayland_client::delegate_noop!(App: WpViewport);
+
+wayland_client::delegate_dispatch!(App: [XdgWmBase: ()] => PingPong);
@@ -0,0 +1,600 @@
+//! Clipboard integration tests
+//!
+//! Connects regular clients and clipboard managers to an in-process server and
+//! checks that selections flow between wl_data_device, zwp_primary_selection
+//! and the ext/wlr data control protocols, and that the compositor keeps
+//! selections and their history after the owning client exits.
+
+mod common;
+
+use common::{TestClient, TestServer};
+use compositor_core::ipc_server::encode_frame;
+use compositor_core::wayland::ClientState;
+use config::CompositorConfig;
+use ipc::protocol::{IPCMessage, ProtocolHandler};
+use smithay::utils::SERIAL_COUNTER;
+use smithay::wayland::{security_context::SecurityContext, selection::SelectionTarget};
+use std::collections::HashMap;
+use std::io::{ErrorKind, Read, Write};
+use std::os::fd::{AsFd, AsRawFd};
+use std::os::unix::net::UnixStream;
+use wayland_client::{
+    backend::ObjectId,
+    event_created_child,
+    protocol::{
+        wl_compositor::WlCompositor,
+        wl_data_device::{self, WlDataDevice},
+        wl_data_device_manager::WlDataDeviceManager,
+        wl_data_offer::{self, WlDataOffer},
+        wl_data_source::{self, WlDataSource},
+        wl_seat::WlSeat,
+        wl_surface::WlSurface,
+    },
+    Connection, Dispatch, Proxy, QueueHandle,
+};
+use wayland_protocols::ext::data_control::v1::client::{
+    ext_data_control_device_v1::{self, ExtDataControlDeviceV1},
+    ext_data_control_manager_v1::ExtDataControlManagerV1,
+    ext_data_control_offer_v1::{self, ExtDataControlOfferV1},
+    ext_data_control_source_v1::{self, ExtDataControlSourceV1},
+};
+use wayland_protocols::wp::primary_selection::zv1::client::{
+    zwp_primary_selection_device_manager_v1::ZwpPrimarySelectionDeviceManagerV1,
+    zwp_primary_selection_device_v1::{self, ZwpPrimarySelectionDeviceV1},
+    zwp_primary_selection_offer_v1::{self, ZwpPrimarySelectionOfferV1},
+};
+use wayland_protocols_wlr::data_control::v1::client::{
+    zwlr_data_control_device_v1::{self, ZwlrDataControlDeviceV1},
+    zwlr_data_control_manager_v1::ZwlrDataControlManagerV1,
+    zwlr_data_control_offer_v1::{self, ZwlrDataControlOfferV1},
+};
+
+const TEXT: &str = "text/plain;charset=utf-8";
+
+/// Client-side bookkeeping shared by regular clients and clipboard managers
+#[derive(Default)]
+struct Clipboard {
+    /// Mime types announced per offer
+    offers: HashMap<ObjectId, Vec<String>>,
+    selection: Option<ObjectId>,
+    primary_selection: Option<ObjectId>,
+    /// Transfer requests received by our own sources as (mime type, payload written)
+    sent: Vec<String>,
+    payload: &'static str,
+    /// Threads writing the payload to the compositor
+    writers: Vec<std::thread::JoinHandle<()>>,
+}
+
+impl Clipboard {
+    fn with_payload(payload: &'static str) -> Self {
+        Self { payload, ..Self::default() }
+    }
+
+    fn selection_mime_types(&self) -> Vec<String> {
+        self.selection
+            .as_ref()
+            .and_then(|offer| self.offers.get(offer))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    fn primary_mime_types(&self) -> Vec<String> {
+        self.primary_selection
+            .as_ref()
+            .and_then(|offer| self.offers.get(offer))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    fn write_payload(&mut self, mime_type: String, fd: std::os::fd::OwnedFd) {
+        // Payloads larger than the pipe only go through while the compositor reads
+        let payload = self.payload;
+        self.writers.push(std::thread::spawn(move || {
+            let mut file = std::fs::File::from(fd);
+            file.write_all(payload.as_bytes()).expect("Failed to write selection");
+        }));
+        self.sent.push(mime_type);
+    }
+
+    /// Let the compositor read everything the writer threads send
+    fn finish_writes(&mut self, server: &mut TestServer) {
+        for writer in std::mem::take(&mut self.writers) {
+            while !writer.is_finished() {
+                server.dispatch();
+            }
+            writer.join().expect("Selection writer panicked");
+        }
+    }
+}
+
+/// A regular client with a focused surface and data devices
+struct RegularClient {
+    client: TestClient<Clipboard>,
+    state: Clipboard,
+    data_device: WlDataDevice,
+    manager: WlDataDeviceManager,
+}
+
+fn connect_regular(server: &mut TestServer, payload: &'static str) -> RegularClient {
+    let mut client = server.connect::<Clipboard>();
+    let mut state = Clipboard::with_payload(payload);
+
+    let compositor: WlCompositor = client.bind(6, ());
+    let seat: WlSeat = client.bind(7, ());
+    let manager: WlDataDeviceManager = client.bind(3, ());
+    let primary_manager: ZwpPrimarySelectionDeviceManagerV1 = client.bind(1, ());
+
+    let surface = compositor.create_surface(&client.qh, ());
+    let data_device = manager.get_data_device(&seat, &client.qh, ());
+    primary_manager.get_device(&seat, &client.qh, ());
+    server.roundtrip(&mut client, &mut state);
+
+    // Give the client keyboard focus, as clicking into its window would
+    let dh = server.server.display.handle();
+    let server_surface = client
+        .server_client
+        .object_from_protocol_id::<wayland_server::protocol::wl_surface::WlSurface>(&dh, surface.id().protocol_id())
+        .expect("Surface should exist on the server");
+    let keyboard = server.server.state.seat.get_keyboard().expect("Default seat should have a keyboard");
+    keyboard.set_focus(&mut server.server.state, Some(server_surface), SERIAL_COUNTER.next_serial());
+    server.roundtrip(&mut client, &mut state);
+
+    RegularClient { client, state, data_device, manager }
+}
+
+fn read_pipe(reader: std::io::PipeReader) -> String {
+    let mut contents = String::new();
+    let mut reader = reader;
+    reader.read_to_string(&mut contents).expect("Failed to read selection");
+    contents
+}
+
+impl RegularClient {
+    /// Offer the client's payload as the clipboard selection
+    fn copy(&mut self, server: &mut TestServer) {
+        let source = self.manager.create_data_source(&self.client.qh, ());
+        source.offer(TEXT.to_string());
+        self.data_device.set_selection(Some(&source), 0);
+        server.roundtrip(&mut self.client, &mut self.state);
+        // The compositor reads the selection while the client writes it
+        self.state.finish_writes(server);
+        server.roundtrip(&mut self.client, &mut self.state);
+    }
+
+    /// Read the current clipboard selection
+    fn paste(&mut self, server: &mut TestServer) -> String {
+        let offer_id = self.state.selection.clone().expect("Client should have a selection");
+        let offer = WlDataOffer::from_id(&self.client.connection, offer_id).unwrap();
+        let (reader, writer) = std::io::pipe().expect("Failed to create pipe");
+        offer.receive(TEXT.to_string(), writer.as_fd());
+        drop(writer);
+        server.roundtrip(&mut self.client, &mut self.state);
+        read_pipe(reader)
+    }
+}
+
+/// Send a request over the IPC socket and wait for the response
+fn ipc_request(server: &mut TestServer, stream: &mut UnixStream, message: IPCMessage) -> IPCMessage {
+    stream.write_all(&encode_frame(&message).unwrap()).expect("Failed to send IPC request");
+    stream.set_nonblocking(true).unwrap();
+
+    let mut response = Vec::new();
+    for _ in 0..100 {
+        server.dispatch();
+
+        let mut chunk = [0; 4096];
+        match stream.read(&mut chunk) {
+            Ok(read) => response.extend_from_slice(&chunk[..read]),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => continue,
+            Err(e) => panic!("Failed to read IPC response: {}", e),
+        }
+
+        if response.len() >= 4 {
+            let length = u32::from_be_bytes([response[0], response[1], response[2], response[3]]) as usize;
+            if response.len() >= 4 + length {
+                return ProtocolHandler::new().deserialize_message(&response[4..4 + length]).unwrap();
+            }
+        }
+    }
+
+    panic!("No IPC response received");
+}
+
+/// Test that a clipboard manager sees and reads a client's clipboard selection
+#[test]
+fn clipboard_manager_watches_selection() {
+    let mut server = TestServer::new();
+    let mut regular = connect_regular(&mut server, "copied text");
+
+    let source = regular.manager.create_data_source(&regular.client.qh, ());
+    source.offer(TEXT.to_string());
+    regular.data_device.set_selection(Some(&source), 0);
+    server.roundtrip(&mut regular.client, &mut regular.state);
+
+    let mut manager_client = server.connect::<Clipboard>();
+    let mut manager_state = Clipboard::default();
+    let seat: WlSeat = manager_client.bind(7, ());
+    let manager: ExtDataControlManagerV1 = manager_client.bind(1, ());
+    manager.get_data_device(&seat, &manager_client.qh, ());
+    server.roundtrip(&mut manager_client, &mut manager_state);
+
+    assert_eq!(manager_state.selection_mime_types(), vec![TEXT.to_string()]);
+
+    // Reading goes through the owning client's data source
+    let offer_id = manager_state.selection.clone().unwrap();
+    let offer = ExtDataControlOfferV1::from_id(&manager_client.connection, offer_id).unwrap();
+    let (reader, writer) = std::io::pipe().expect("Failed to create pipe");
+    offer.receive(TEXT.to_string(), writer.as_fd());
+    drop(writer);
+    server.roundtrip(&mut manager_client, &mut manager_state);
+    server.roundtrip(&mut regular.client, &mut regular.state);
+
+    // Once for the compositor's clipboard history, once for the manager
+    assert_eq!(regular.state.sent, vec![TEXT.to_string(), TEXT.to_string()]);
+    assert_eq!(read_pipe(reader), "copied text");
+}
+
+/// Test that a clipboard manager can set clipboard and primary selection for other clients
+#[test]
+fn clipboard_manager_sets_selection() {
+    let mut server = TestServer::new();
+    let mut regular = connect_regular(&mut server, "");
+
+    let mut manager_client = server.connect::<Clipboard>();
+    let mut manager_state = Clipboard::with_payload("restored text");
+    let seat: WlSeat = manager_client.bind(7, ());
+    let manager: ExtDataControlManagerV1 = manager_client.bind(1, ());
+    let device = manager.get_data_device(&seat, &manager_client.qh, ());
+
+    let source = manager.create_data_source(&manager_client.qh, ());
+    source.offer(TEXT.to_string());
+    device.set_selection(Some(&source));
+    let primary = manager.create_data_source(&manager_client.qh, ());
+    primary.offer("text/plain".to_string());
+    device.set_primary_selection(Some(&primary));
+    server.roundtrip(&mut manager_client, &mut manager_state);
+    server.roundtrip(&mut regular.client, &mut regular.state);
+
+    assert_eq!(regular.state.selection_mime_types(), vec![TEXT.to_string()]);
+    assert_eq!(regular.state.primary_mime_types(), vec!["text/plain".to_string()]);
+
+    // Pasting in the regular client reads from the clipboard manager
+    let offer_id = regular.state.selection.clone().unwrap();
+    let offer = WlDataOffer::from_id(&regular.client.connection, offer_id).unwrap();
+    let (reader, writer) = std::io::pipe().expect("Failed to create pipe");
+    offer.receive(TEXT.to_string(), writer.as_fd());
+    drop(writer);
+    server.roundtrip(&mut regular.client, &mut regular.state);
+    server.roundtrip(&mut manager_client, &mut manager_state);
+
+    assert_eq!(read_pipe(reader), "restored text");
+}
+
+/// Test that the wlr variant observes selections made through ext-data-control
+#[test]
+fn wlr_data_control_compatibility() {
+    let mut server = TestServer::new();
+
+    let mut ext_client = server.connect::<Clipboard>();
+    let mut ext_state = Clipboard::default();
+    let seat: WlSeat = ext_client.bind(7, ());
+    let manager: ExtDataControlManagerV1 = ext_client.bind(1, ());
+    let device = manager.get_data_device(&seat, &ext_client.qh, ());
+    let source = manager.create_data_source(&ext_client.qh, ());
+    source.offer(TEXT.to_string());
+    device.set_selection(Some(&source));
+    server.roundtrip(&mut ext_client, &mut ext_state);
+
+    let mut wlr_client = server.connect::<Clipboard>();
+    let mut wlr_state = Clipboard::default();
+    let seat: WlSeat = wlr_client.bind(7, ());
+    let manager: ZwlrDataControlManagerV1 = wlr_client.bind(2, ());
+    manager.get_data_device(&seat, &wlr_client.qh, ());
+    server.roundtrip(&mut wlr_client, &mut wlr_state);
+
+    assert_eq!(wlr_state.selection_mime_types(), vec![TEXT.to_string()]);
+}
+
+/// Test that sandboxed clients cannot see privileged globals
+#[test]
+fn sandboxed_clients_cannot_control_clipboard() {
+    let mut server = TestServer::new();
+
+    let regular = server.connect::<Clipboard>();
+    assert!(regular.has_global("ext_data_control_manager_v1"));
+    assert!(regular.has_global("zwlr_data_control_manager_v1"));
+    assert!(regular.has_global("zwlr_output_manager_v1"));
+
+    let sandboxed = server.connect_with::<Clipboard>(ClientState {
+        security_context: Some(SecurityContext {
+            sandbox_engine: Some("org.flatpak".to_string()),
+            app_id: Some("org.example.App".to_string()),
+            instance_id: None,
+            creator_client_id: regular.server_client.id(),
+        }),
+        ..ClientState::default()
+    });
+    assert!(!sandboxed.has_global("ext_data_control_manager_v1"));
+    assert!(!sandboxed.has_global("zwlr_data_control_manager_v1"));
+    assert!(sandboxed.has_global("zwlr_output_manager_v1"));
+    assert!(!sandboxed.has_global("wp_security_context_manager_v1"));
+    assert!(sandboxed.has_global("wl_data_device_manager"));
+}
+
+/// Test that the clipboard survives the client that copied it
+#[test]
+fn selection_persists_after_owner_disconnects() {
+    let mut server = TestServer::new();
+    let mut owner = connect_regular(&mut server, "persisted text");
+    owner.copy(&mut server);
+
+    let captured = server.server.state.clipboard_state.current(SelectionTarget::Clipboard);
+    assert_eq!(captured.map(|entry| entry.mime_types()), Some(vec![TEXT.to_string()]));
+
+    drop(owner);
+    server.dispatch();
+
+    let mut paste = connect_regular(&mut server, "");
+    assert_eq!(paste.state.selection_mime_types(), vec![TEXT.to_string()]);
+    assert_eq!(paste.paste(&mut server), "persisted text");
+}
+
+/// Test that a data control selection is not attributed to the focused client
+#[test]
+fn manager_selection_outlives_focused_client() {
+    let mut server = TestServer::new();
+    let mut regular = connect_regular(&mut server, "");
+    // A source the focused client created but never set as the selection
+    let _unused = regular.manager.create_data_source(&regular.client.qh, ());
+    server.roundtrip(&mut regular.client, &mut regular.state);
+
+    let mut manager_client = server.connect::<Clipboard>();
+    let mut manager_state = Clipboard::with_payload("manager text");
+    let seat: WlSeat = manager_client.bind(7, ());
+    let manager: ExtDataControlManagerV1 = manager_client.bind(1, ());
+    let device = manager.get_data_device(&seat, &manager_client.qh, ());
+    let source = manager.create_data_source(&manager_client.qh, ());
+    source.offer(TEXT.to_string());
+    device.set_selection(Some(&source));
+    server.roundtrip(&mut manager_client, &mut manager_state);
+    server.roundtrip(&mut manager_client, &mut manager_state);
+    manager_state.finish_writes(&mut server);
+    server.dispatch();
+    assert!(server.server.state.clipboard_state.current(SelectionTarget::Clipboard).is_some());
+
+    drop(regular);
+    server.dispatch();
+
+    // The selection still belongs to the manager, which serves the paste itself
+    let mut paste = connect_regular(&mut server, "");
+    let offer_id = paste.state.selection.clone().expect("Client should have a selection");
+    let offer = WlDataOffer::from_id(&paste.client.connection, offer_id).unwrap();
+    let (reader, writer) = std::io::pipe().expect("Failed to create pipe");
+    offer.receive(TEXT.to_string(), writer.as_fd());
+    drop(writer);
+    server.roundtrip(&mut paste.client, &mut paste.state);
+    server.roundtrip(&mut manager_client, &mut manager_state);
+
+    assert_eq!(read_pipe(reader), "manager text");
+    assert_eq!(manager_state.sent, vec![TEXT.to_string(), TEXT.to_string()]);
+}
+
+/// Test that pasting a selection larger than the pipe keeps the compositor responsive
+#[test]
+fn large_selection_pasted_from_compositor() {
+    let mut server = TestServer::new();
+    let payload: &'static str = "x".repeat(1024 * 1024).leak();
+    let mut owner = connect_regular(&mut server, payload);
+    owner.copy(&mut server);
+    drop(owner);
+    server.dispatch();
+
+    // The compositor writes as the client reads, dispatching in between
+    let mut paste = connect_regular(&mut server, "");
+    let offer_id = paste.state.selection.clone().expect("Client should have a selection");
+    let offer = WlDataOffer::from_id(&paste.client.connection, offer_id).unwrap();
+    let (mut reader, writer) = std::io::pipe().expect("Failed to create pipe");
+    offer.receive(TEXT.to_string(), writer.as_fd());
+    drop(writer);
+    server.roundtrip(&mut paste.client, &mut paste.state);
+    nix::fcntl::fcntl(reader.as_raw_fd(), nix::fcntl::FcntlArg::F_SETFL(nix::fcntl::OFlag::O_NONBLOCK)).unwrap();
+
+    let mut received = Vec::new();
+    let mut chunk = vec![0; 64 * 1024];
+    for _ in 0..10_000 {
+        match reader.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(read) => received.extend_from_slice(&chunk[..read]),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => server.dispatch(),
+            Err(e) => panic!("Failed to read selection: {}", e),
+        }
+    }
+    assert_eq!(received.len(), payload.len());
+}
+
+/// Test that the clipboard history can be listed and restored over IPC
+#[test]
+fn clipboard_history_over_ipc() {
+    let mut server = TestServer::new();
+    let socket_path = std::env::temp_dir().join(format!("compositor-clipboard-test-{}.sock", std::process::id()));
+    server.server.start_ipc(&socket_path).expect("Failed to start IPC server");
+
+    let mut regular = connect_regular(&mut server, "first entry");
+    regular.copy(&mut server);
+    regular.state.payload = "second entry";
+    regular.copy(&mut server);
+
+    let mut stream = UnixStream::connect(&socket_path).expect("Failed to connect to IPC socket");
+    let entries = match ipc_request(&mut server, &mut stream, IPCMessage::GetClipboardHistory) {
+        IPCMessage::ClipboardHistory { entries } => entries,
+        other => panic!("Unexpected response: {:?}", other),
+    };
+    let previews: Vec<_> = entries.iter().map(|entry| entry.preview.as_deref()).collect();
+    assert_eq!(previews, vec![Some("second entry"), Some("first entry")]);
+    assert_eq!(entries[1].mime_types, vec![TEXT.to_string()]);
+    assert_eq!(entries[1].size, "first entry".len() as u64);
+
+    let entry_id = entries[1].entry_id;
+    let response = ipc_request(&mut server, &mut stream, IPCMessage::RestoreClipboardEntry { entry_id });
+    assert!(matches!(response, IPCMessage::ClipboardEntryRestored { entry_id: restored } if restored == entry_id));
+    server.roundtrip(&mut regular.client, &mut regular.state);
+    assert_eq!(regular.paste(&mut server), "first entry");
+
+    let response = ipc_request(&mut server, &mut stream, IPCMessage::RestoreClipboardEntry { entry_id: 9999 });
+    assert!(matches!(response, IPCMessage::Error { .. }));
+
+    let _ = std::fs::remove_file(&socket_path);
+}
+
+/// Test that selections over the size limit or marked secret are not stored
+#[test]
+fn clipboard_capture_limits() {
+    let mut config = CompositorConfig::default();
+    config.clipboard.max_entry_size = 8;
+    let mut server = TestServer::with_config(config);
+
+    let mut regular = connect_regular(&mut server, "more than eight bytes");
+    regular.copy(&mut server);
+    assert!(server.server.state.clipboard_state.current(SelectionTarget::Clipboard).is_none());
+
+    // Password managers mark secrets so they stay out of the history
+    regular.state.payload = "hunter2";
+    let source = regular.manager.create_data_source(&regular.client.qh, ());
+    source.offer(TEXT.to_string());
+    source.offer("x-kde-passwordManagerHint".to_string());
+    regular.data_device.set_selection(Some(&source), 0);
+    server.roundtrip(&mut regular.client, &mut regular.state);
+    server.roundtrip(&mut regular.client, &mut regular.state);
+
+    assert!(server.server.state.clipboard_state.current(SelectionTarget::Clipboard).is_none());
+    assert_eq!(server.server.state.clipboard_state.history().count(), 0);
+}
+
+// ============================================================================
+// Client-side dispatch
+// ============================================================================
+
+wayland_client::delegate_noop!(Clipboard: ignore WlSeat);
+wayland_client::delegate_noop!(Clipboard: ignore WlSurface);
+wayland_client::delegate_noop!(Clipboard: WlCompositor);
+wayland_client::delegate_noop!(Clipboard: WlDataDeviceManager);
+wayland_client::delegate_noop!(Clipboard: ZwpPrimarySelectionDeviceManagerV1);
+wayland_client::delegate_noop!(Clipboard: ExtDataControlManagerV1);
+wayland_client::delegate_noop!(Clipboard: ZwlrDataControlManagerV1);
+
+impl Dispatch<WlDataDevice, ()> for Clipboard {
+    fn event(state: &mut Self, _: &WlDataDevice, event: wl_data_device::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+        match event {
+            wl_data_device::Event::DataOffer { id } => {
+                state.offers.insert(id.id(), Vec::new());
+            }
+            wl_data_device::Event::Selection { id } => state.selection = id.map(|offer| offer.id()),
+            _ => {}
+        }
+    }
+
+    event_created_child!(Clipboard, WlDataDevice, [
+        wl_data_device::EVT_DATA_OFFER_OPCODE => (WlDataOffer, ()),
+    ]);
+}
+
+impl Dispatch<WlDataOffer, ()> for Clipboard {
+    fn event(state: &mut Self, offer: &WlDataOffer, event: wl_data_offer::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+        if let wl_data_offer::Event::Offer { mime_type } = event {
+            state.offers.entry(offer.id()).or_default().push(mime_type);
+        }
+    }
+}
+
+impl Dispatch<WlDataSource, ()> for Clipboard {
+    fn event(state: &mut Self, _: &WlDataSource, event: wl_data_source::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+        if let wl_data_source::Event::Send { mime_type, fd } = event {
+            state.write_payload(mime_type, fd);
+        }
+    }
+}
+
+impl Dispatch<ZwpPrimarySelectionDeviceV1, ()> for Clipboard {
+    fn event(state: &mut Self, _: &ZwpPrimarySelectionDeviceV1, event: zwp_primary_selection_device_v1::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+        match event {
+            zwp_primary_selection_device_v1::Event::DataOffer { offer } => {
+                state.offers.insert(offer.id(), Vec::new());
+            }
+            zwp_primary_selection_device_v1::Event::Selection { id } => {
+                state.primary_selection = id.map(|offer| offer.id())
+            }
+            _ => {}
+        }
+    }
+
+    event_created_child!(Clipboard, ZwpPrimarySelectionDeviceV1, [
+        zwp_primary_selection_device_v1::EVT_DATA_OFFER_OPCODE => (ZwpPrimarySelectionOfferV1, ()),
+    ]);
+}
+
+impl Dispatch<ZwpPrimarySelectionOfferV1, ()> for Clipboard {
+    fn event(state: &mut Self, offer: &ZwpPrimarySelectionOfferV1, event: zwp_primary_selection_offer_v1::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+        if let zwp_primary_selection_offer_v1::Event::Offer { mime_type } = event {
+            state.offers.entry(offer.id()).or_default().push(mime_type);
+        }
+    }
+}
+
+impl Dispatch<ExtDataControlDeviceV1, ()> for Clipboard {
+    fn event(state: &mut Self, _: &ExtDataControlDeviceV1, event: ext_data_control_device_v1::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+        match event {
+            ext_data_control_device_v1::Event::DataOffer { id } => {
+                state.offers.insert(id.id(), Vec::new());
+            }
+            ext_data_control_device_v1::Event::Selection { id } => state.selection = id.map(|offer| offer.id()),
+            ext_data_control_device_v1::Event::PrimarySelection { id } => {
+                state.primary_selection = id.map(|offer| offer.id())
+            }
+            _ => {}
+        }
+    }
+
+    event_created_child!(Clipboard, ExtDataControlDeviceV1, [
+        ext_data_control_device_v1::EVT_DATA_OFFER_OPCODE => (ExtDataControlOfferV1, ()),
+    ]);
+}
+
+impl Dispatch<ExtDataControlOfferV1, ()> for Clipboard {
+    fn event(state: &mut Self, offer: &ExtDataControlOfferV1, event: ext_data_control_offer_v1::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+        if let ext_data_control_offer_v1::Event::Offer { mime_type } = event {
+            state.offers.entry(offer.id()).or_default().push(mime_type);
+        }
+    }
+}
+
+impl Dispatch<ExtDataControlSourceV1, ()> for Clipboard {
+    fn event(state: &mut Self, _: &ExtDataControlSourceV1, event: ext_data_control_source_v1::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+        if let ext_data_control_source_v1::Event::Send { mime_type, fd } = event {
+            state.write_payload(mime_type, fd);
+        }
+    }
+}
+
+impl Dispatch<ZwlrDataControlDeviceV1, ()> for Clipboard {
+    fn event(state: &mut Self, _: &ZwlrDataControlDeviceV1, event: zwlr_data_control_device_v1::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+        match event {
+            zwlr_data_control_device_v1::Event::DataOffer { id } => {
+                state.offers.insert(id.id(), Vec::new());
+            }
+            zwlr_data_control_device_v1::Event::Selection { id } => state.selection = id.map(|offer| offer.id()),
+            _ => {}
+        }
+    }
+
+    event_created_child!(Clipboard, ZwlrDataControlDeviceV1, [
+        zwlr_data_control_device_v1::EVT_DATA_OFFER_OPCODE => (ZwlrDataControlOfferV1, ()),
+    ]);
+}
+
+impl Dispatch<ZwlrDataControlOfferV1, ()> for Clipboard {
+    fn event(state: &mut Self, offer: &ZwlrDataControlOfferV1, event: zwlr_data_control_offer_v1::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+        if let zwlr_data_control_offer_v1::Event::Offer { mime_type } = event {
+            state.offers.entry(offer.id()).or_default().push(mime_type);
+        }
+    }
+}
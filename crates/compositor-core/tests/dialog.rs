@@ -0,0 +1,281 @@
+//! Modal dialog integration tests
+//!
+//! Marks toplevels as modal over a parent with xdg_dialog_v1 and checks their
+//! placement, stacking, input and focus against the parent, and which
+//! activation tokens may focus them.
+
+mod common;
+
+use common::{click, create_shm_buffer, PingPong, TestClient, TestServer};
+use compositor_core::wayland::WaylandServerState;
+use compositor_core::workspace::window_id;
+use ipc::protocol::IPCMessage;
+use smithay::desktop::Window;
+use smithay::utils::{Serial, SERIAL_COUNTER};
+use smithay::wayland::xdg_activation::XdgActivationTokenData;
+use std::time::{Duration, Instant};
+use wayland_client::{
+    protocol::{
+        wl_buffer::WlBuffer,
+        wl_compositor::WlCompositor,
+        wl_shm::WlShm,
+        wl_seat::WlSeat,
+        wl_shm_pool::WlShmPool,
+        wl_surface::WlSurface,
+    },
+    Proxy,
+};
+use wayland_protocols::xdg::activation::v1::client::xdg_activation_v1::XdgActivationV1;
+use wayland_protocols::xdg::dialog::v1::client::{xdg_dialog_v1::XdgDialogV1, xdg_wm_dialog_v1::XdgWmDialogV1};
+use wayland_protocols::xdg::shell::client::{
+    xdg_surface::XdgSurface,
+    xdg_toplevel::XdgToplevel,
+    xdg_wm_base::XdgWmBase,
+};
+use wayland_server::Resource;
+
+#[derive(Default)]
+struct App;
+
+/// A toplevel of the test client
+struct Toplevel {
+    surface: WlSurface,
+    toplevel: XdgToplevel,
+    _xdg_surface: XdgSurface,
+}
+
+struct DialogApp {
+    client: TestClient<App>,
+    state: App,
+    compositor: WlCompositor,
+    shm: WlShm,
+    wm_base: XdgWmBase,
+    wm_dialog: XdgWmDialogV1,
+}
+
+impl DialogApp {
+    fn connect(server: &mut TestServer) -> Self {
+        let client = server.connect::<App>();
+        let compositor = client.bind(6, ());
+        let shm = client.bind(1, ());
+        let wm_base = client.bind(6, ());
+        let wm_dialog = client.bind(1, ());
+        Self { client, state: App, compositor, shm, wm_base, wm_dialog }
+    }
+
+    /// Create a toplevel with a buffer of the given size, without committing it
+    fn toplevel(&self, width: i32, height: i32) -> Toplevel {
+        let qh = &self.client.qh;
+        let surface = self.compositor.create_surface(qh, ());
+        let xdg_surface = self.wm_base.get_xdg_surface(&surface, qh, ());
+        let toplevel = xdg_surface.get_toplevel(qh, ());
+        self.attach(&surface, width, height);
+        Toplevel { surface, toplevel, _xdg_surface: xdg_surface }
+    }
+
+    fn attach(&self, surface: &WlSurface, width: i32, height: i32) {
+        let buffer = create_shm_buffer(&self.shm, &self.client.qh, width, height);
+        surface.attach(Some(&buffer), 0, 0);
+    }
+
+    fn roundtrip(&mut self, server: &mut TestServer) {
+        server.roundtrip(&mut self.client, &mut self.state);
+    }
+}
+
+/// Server-side window of a client toplevel
+fn window(state: &WaylandServerState, toplevel: &Toplevel) -> Window {
+    state
+        .windows()
+        .map(|(_, window)| window)
+        .find(|window| {
+            window.toplevel().map(|surface| surface.wl_surface().id().protocol_id()) == Some(toplevel.surface.id().protocol_id())
+        })
+        .cloned()
+        .expect("Window should exist")
+}
+
+fn location(state: &WaylandServerState, toplevel: &Toplevel) -> (i32, i32) {
+    let location = state.space.element_location(&window(state, toplevel)).expect("Window should be mapped");
+    (location.x, location.y)
+}
+
+fn is_topmost(state: &WaylandServerState, toplevel: &Toplevel) -> bool {
+    state.space.elements().last() == Some(&window(state, toplevel))
+}
+
+fn keyboard_focus(state: &WaylandServerState) -> Option<u32> {
+    let focus = state.seat.get_keyboard()?.current_focus()?;
+    Some(focus.id().protocol_id())
+}
+
+/// Test that a modal dialog is centered on its parent and moves with it
+#[test]
+fn modal_dialog_follows_parent() {
+    let mut server = TestServer::new();
+    let mut app = DialogApp::connect(&mut server);
+
+    let parent = app.toplevel(400, 300);
+    parent.surface.commit();
+    let dialog = app.toplevel(200, 100);
+    dialog.toplevel.set_parent(Some(&parent.toplevel));
+    let xdg_dialog = app.wm_dialog.get_xdg_dialog(&dialog.toplevel, &app.client.qh, ());
+    xdg_dialog.set_modal();
+    dialog.surface.commit();
+    app.roundtrip(&mut server);
+
+    let state = &mut server.server.state;
+    assert_eq!(location(state, &parent), (100, 100));
+    assert_eq!(location(state, &dialog), (200, 200));
+    assert!(is_topmost(state, &dialog));
+
+    // Raising the parent keeps the dialog above it
+    let parent_window = window(state, &parent);
+    state.raise_window(&parent_window);
+    assert!(is_topmost(state, &dialog));
+
+    state.move_window(&parent_window, (500, 400).into()).expect("Failed to move window");
+    assert_eq!(location(state, &parent), (500, 400));
+    assert_eq!(location(state, &dialog), (600, 500));
+    assert!(is_topmost(state, &dialog));
+
+    // Resized dialogs stay centered
+    app.attach(&dialog.surface, 300, 200);
+    dialog.surface.commit();
+    app.roundtrip(&mut server);
+    assert_eq!(location(&server.server.state, &dialog), (550, 450));
+}
+
+/// Test that a modal dialog takes the input and focus of its parent
+#[test]
+fn modal_dialog_blocks_parent() {
+    let mut server = TestServer::new();
+    let mut app = DialogApp::connect(&mut server);
+
+    let parent = app.toplevel(400, 300);
+    parent.surface.commit();
+    app.roundtrip(&mut server);
+
+    let state = &mut server.server.state;
+    let parent_window = window(state, &parent);
+    state.focus_window(&parent_window);
+    assert_eq!(keyboard_focus(state), Some(parent.surface.id().protocol_id()));
+
+    // A dialog over the focused window takes focus as it becomes modal
+    let dialog = app.toplevel(200, 100);
+    dialog.toplevel.set_parent(Some(&parent.toplevel));
+    dialog.surface.commit();
+    app.roundtrip(&mut server);
+    let state = &server.server.state;
+    assert!(state.surface_under((120.0, 120.0).into()).is_some());
+    assert_eq!(keyboard_focus(state), Some(parent.surface.id().protocol_id()));
+
+    let xdg_dialog = app.wm_dialog.get_xdg_dialog(&dialog.toplevel, &app.client.qh, ());
+    xdg_dialog.set_modal();
+    app.roundtrip(&mut server);
+    let state = &mut server.server.state;
+    assert_eq!(keyboard_focus(state), Some(dialog.surface.id().protocol_id()));
+
+    // Input over the parent is dropped, the dialog still gets its own
+    assert!(state.surface_under((120.0, 120.0).into()).is_none());
+    let (surface, _) = state.surface_under((250.0, 250.0).into()).expect("Dialog should take input");
+    assert_eq!(surface.id().protocol_id(), dialog.surface.id().protocol_id());
+
+    // Clicking the parent focuses the dialog
+    state.seat.get_keyboard().unwrap().set_focus(state, None, SERIAL_COUNTER.next_serial());
+    click(state, (120.0, 120.0));
+    assert_eq!(keyboard_focus(state), Some(dialog.surface.id().protocol_id()));
+
+    // Focusing the parent focuses the dialog, also over IPC
+    let focused = state.focus_window(&parent_window);
+    assert_eq!(focused, window(state, &dialog));
+    assert_eq!(keyboard_focus(state), Some(dialog.surface.id().protocol_id()));
+    let parent_id = window_id(&parent_window).expect("Window should have an identifier").0;
+    let dialog_id = window_id(&focused).expect("Window should have an identifier").0;
+    match state.handle_ipc_message(IPCMessage::FocusWindow { window_id: parent_id }) {
+        IPCMessage::WindowInfo { window_id, .. } => assert_eq!(window_id, dialog_id),
+        other => panic!("Unexpected response: {:?}", other),
+    }
+
+    // Once no longer modal, the parent takes input and focus again
+    xdg_dialog.unset_modal();
+    app.roundtrip(&mut server);
+    let state = &mut server.server.state;
+    assert!(state.surface_under((120.0, 120.0).into()).is_some());
+    state.focus_window(&parent_window);
+    assert_eq!(keyboard_focus(state), Some(parent.surface.id().protocol_id()));
+}
+
+/// Test that only valid activation tokens focus a window and its modal dialog
+#[test]
+fn activation_needs_valid_token() {
+    let mut server = TestServer::new();
+    let mut app = DialogApp::connect(&mut server);
+    let activation: XdgActivationV1 = app.client.bind(1, ());
+    let wl_seat: WlSeat = app.client.bind(7, ());
+
+    let parent = app.toplevel(400, 300);
+    parent.surface.commit();
+    let dialog = app.toplevel(200, 100);
+    dialog.toplevel.set_parent(Some(&parent.toplevel));
+    let xdg_dialog = app.wm_dialog.get_xdg_dialog(&dialog.toplevel, &app.client.qh, ());
+    xdg_dialog.set_modal();
+    dialog.surface.commit();
+    let other = app.toplevel(100, 100);
+    other.surface.commit();
+    app.roundtrip(&mut server);
+
+    let focus_other = |state: &mut WaylandServerState| {
+        let other_window = window(state, &other);
+        state.focus_window(&other_window);
+        assert_eq!(keyboard_focus(state), Some(other.surface.id().protocol_id()));
+    };
+    let activate = |server: &mut TestServer, app: &mut DialogApp, data: XdgActivationTokenData| {
+        let (token, _) = server.server.state.xdg_activation_state.create_external_token(data);
+        activation.activate(token.as_str().to_string(), &parent.surface);
+        app.roundtrip(server);
+    };
+
+    // A fresh token focuses the topmost dialog of the window
+    focus_other(&mut server.server.state);
+    activate(&mut server, &mut app, XdgActivationTokenData::default());
+    assert_eq!(keyboard_focus(&server.server.state), Some(dialog.surface.id().protocol_id()));
+
+    // Expired tokens are ignored
+    focus_other(&mut server.server.state);
+    let expired = XdgActivationTokenData { timestamp: Instant::now() - Duration::from_secs(20), ..Default::default() };
+    activate(&mut server, &mut app, expired);
+    assert_eq!(keyboard_focus(&server.server.state), Some(other.surface.id().protocol_id()));
+
+    // Tokens for input events need a serial no older than the last keyboard enter of their seat
+    let seat: wayland_server::protocol::wl_seat::WlSeat = app
+        .client
+        .server_client
+        .object_from_protocol_id(&server.server.display.handle(), wl_seat.id().protocol_id())
+        .expect("Seat should exist on the server");
+    let last_enter = server.server.state.seat.get_keyboard().and_then(|keyboard| keyboard.last_enter()).expect("Keyboard should have entered");
+    let stale = Serial::from(u32::from(last_enter).wrapping_sub(1));
+    let for_event = |serial| XdgActivationTokenData { serial: Some((serial, seat.clone())), ..Default::default() };
+    activate(&mut server, &mut app, for_event(stale));
+    assert_eq!(keyboard_focus(&server.server.state), Some(other.surface.id().protocol_id()));
+    activate(&mut server, &mut app, for_event(last_enter));
+    assert_eq!(keyboard_focus(&server.server.state), Some(dialog.surface.id().protocol_id()));
+}
+
+// ============================================================================
+// Client-side dispatch
+// ============================================================================
+
+wayland_client::delegate_noop!(App: ignore WlSurface);
+wayland_client::delegate_noop!(App: ignore WlShm);
+wayland_client::delegate_noop!(App: ignore WlBuffer);
+wayland_client::delegate_noop!(App: ignore XdgSurface);
+wayland_client::delegate_noop!(App: ignore XdgToplevel);
+wayland_client::delegate_noop!(App: WlCompositor);
+wayland_client::delegate_noop!(App: WlShmPool);
+wayland_client::delegate_noop!(App: XdgWmDialogV1);
+wayland_client::delegate_noop!(App: XdgDialogV1);
+wayland_client::delegate_noop!(App: ignore WlSeat);
+wayland_client::delegate_noop!(App: XdgActivationV1);
+
+wayland_client::delegate_dispatch!(App: [XdgWmBase: ()] => PingPong);
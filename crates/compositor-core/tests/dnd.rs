@@ -0,0 +1,262 @@
+//! Drag-and-drop integration tests
+//!
+//! Drives the default seat's pointer directly, as the input backend would, and
+//! checks the drag icon placement, cursor feedback and compositor-originated
+//! drops, started directly or over IPC, against real `wayland-client`
+//! connections.
+
+mod common;
+
+use common::{TestClient, TestServer};
+use compositor_core::render::RenderElement;
+use ipc::protocol::IPCMessage;
+use smithay::backend::input::ButtonState;
+use smithay::input::pointer::{ButtonEvent, CursorIcon, CursorImageStatus, MotionEvent};
+use smithay::utils::{Logical, Point, SERIAL_COUNTER};
+use std::io::Read;
+use std::os::fd::AsFd;
+use wayland_client::{
+    event_created_child,
+    protocol::{
+        wl_compositor::WlCompositor,
+        wl_data_device::{self, WlDataDevice},
+        wl_data_device_manager::{DndAction, WlDataDeviceManager},
+        wl_data_offer::{self, WlDataOffer},
+        wl_data_source::WlDataSource,
+        wl_seat::WlSeat,
+        wl_surface::WlSurface,
+    },
+    Connection, Dispatch, Proxy, QueueHandle,
+};
+use wayland_server::{protocol::wl_data_device_manager::DndAction as ServerDndAction, Resource};
+
+const TEXT: &str = "text/plain;charset=utf-8";
+const URI_LIST: &str = "text/uri-list";
+const BTN_LEFT: u32 = 0x110;
+
+/// Client-side view of the drag
+#[derive(Default)]
+struct Drag {
+    /// Offer and serial of the last enter event
+    entered: Option<(WlDataOffer, u32)>,
+    offered: Vec<String>,
+    dropped: bool,
+}
+
+/// A client with a surface and a data device
+struct DragClient {
+    client: TestClient<Drag>,
+    state: Drag,
+    compositor: WlCompositor,
+    manager: WlDataDeviceManager,
+    data_device: WlDataDevice,
+    surface: WlSurface,
+}
+
+fn connect_drag_client(server: &mut TestServer) -> DragClient {
+    let mut client = server.connect::<Drag>();
+    let mut state = Drag::default();
+
+    let compositor: WlCompositor = client.bind(6, ());
+    let seat: WlSeat = client.bind(7, ());
+    let manager: WlDataDeviceManager = client.bind(3, ());
+    let surface = compositor.create_surface(&client.qh, ());
+    let data_device = manager.get_data_device(&seat, &client.qh, ());
+    server.roundtrip(&mut client, &mut state);
+
+    DragClient { client, state, compositor, manager, data_device, surface }
+}
+
+impl DragClient {
+    /// Server-side handle of the client's surface
+    fn server_surface(&self, server: &TestServer) -> wayland_server::protocol::wl_surface::WlSurface {
+        self.client
+            .server_client
+            .object_from_protocol_id(&server.server.display.handle(), self.surface.id().protocol_id())
+            .expect("Surface should exist on the server")
+    }
+
+    /// Accept the entered offer with the given mime type and preferred action
+    fn accept(&mut self, server: &mut TestServer, mime_type: &str, actions: DndAction, preferred: DndAction) {
+        let (offer, serial) = self.state.entered.clone().expect("Client should have a drag entered");
+        offer.accept(serial, Some(mime_type.to_string()));
+        offer.set_actions(actions, preferred);
+        server.roundtrip(&mut self.client, &mut self.state);
+    }
+}
+
+/// Move the pointer, over the given surface if any
+fn move_pointer(
+    server: &mut TestServer,
+    focus: Option<wayland_server::protocol::wl_surface::WlSurface>,
+    location: Point<f64, Logical>,
+) {
+    let state = &mut server.server.state;
+    let pointer = state.seat.get_pointer().expect("Default seat should have a pointer");
+    let focus = focus.map(|surface| (surface, Point::default()));
+    let event = MotionEvent { location, serial: SERIAL_COUNTER.next_serial(), time: 0 };
+    pointer.motion(state, focus, &event);
+    pointer.frame(state);
+}
+
+/// Press or release the left button, returning the event serial
+fn left_button(server: &mut TestServer, button_state: ButtonState) -> u32 {
+    let state = &mut server.server.state;
+    let pointer = state.seat.get_pointer().expect("Default seat should have a pointer");
+    let serial = SERIAL_COUNTER.next_serial();
+    let event = ButtonEvent { serial, time: 0, button: BTN_LEFT, state: button_state };
+    pointer.button(state, &event);
+    pointer.frame(state);
+    serial.into()
+}
+
+fn scene(server: &TestServer) -> Vec<RenderElement> {
+    let state = &server.server.state;
+    let output = state.space.outputs().next().expect("Default output should be mapped");
+    state.render_elements(output)
+}
+
+/// Test that a client drag icon follows the pointer and the cursor shows the negotiated action
+#[test]
+fn client_drag_icon_and_cursor() {
+    let mut server = TestServer::new();
+    let mut drag = connect_drag_client(&mut server);
+    let origin = drag.server_surface(&server);
+
+    move_pointer(&mut server, Some(origin.clone()), (10.0, 10.0).into());
+    let serial = left_button(&mut server, ButtonState::Pressed);
+
+    let source = drag.manager.create_data_source(&drag.client.qh, ());
+    source.offer(TEXT.to_string());
+    source.set_actions(DndAction::Copy | DndAction::Move);
+    let icon = drag.compositor.create_surface(&drag.client.qh, ());
+    drag.data_device.start_drag(Some(&source), &drag.surface, Some(&icon), serial);
+    icon.offset(-4, -6);
+    icon.commit();
+    server.roundtrip(&mut drag.client, &mut drag.state);
+
+    assert!(server.server.state.dnd_state.is_active());
+
+    // Dragging over a surface that takes the data as a move
+    move_pointer(&mut server, Some(origin), (50.0, 60.0).into());
+    server.roundtrip(&mut drag.client, &mut drag.state);
+    assert_eq!(drag.state.offered, vec![TEXT.to_string()]);
+    drag.accept(&mut server, TEXT, DndAction::Copy | DndAction::Move, DndAction::Move);
+
+    assert_eq!(server.server.state.cursor_image(), CursorImageStatus::Named(CursorIcon::Move));
+    let icon_element = scene(&server).pop().expect("Scene should contain the drag icon");
+    assert_eq!(icon_element.surface.id().protocol_id(), icon.id().protocol_id());
+    assert_eq!(icon_element.location, (46, 54).into());
+
+    left_button(&mut server, ButtonState::Released);
+    server.roundtrip(&mut drag.client, &mut drag.state);
+
+    assert!(drag.state.dropped);
+    assert!(!server.server.state.dnd_state.is_active());
+    assert!(scene(&server).iter().all(|element| element.surface.id().protocol_id() != icon.id().protocol_id()));
+    assert_eq!(server.server.state.cursor_image(), CursorImageStatus::default_named());
+}
+
+/// Test that a file dragged from compositor UI is dropped into a client as a URI
+#[test]
+fn compositor_file_drag_drops_uri() {
+    let mut server = TestServer::new();
+    let mut target = connect_drag_client(&mut server);
+    let target_surface = target.server_surface(&server);
+
+    // Drags need a held button, as when pressing on an app bar item
+    let drag = |server: &mut TestServer, path: &str| {
+        server.server.state.handle_ipc_message(IPCMessage::StartFileDrag { paths: vec![path.to_string()] })
+    };
+    assert!(matches!(drag(&mut server, "/tmp/file"), IPCMessage::Error { .. }));
+
+    move_pointer(&mut server, None, (5.0, 5.0).into());
+    left_button(&mut server, ButtonState::Pressed);
+    assert!(matches!(drag(&mut server, "/home/user/My File.txt"), IPCMessage::DragStarted));
+    assert_eq!(server.server.state.cursor_image(), CursorImageStatus::Named(CursorIcon::NoDrop));
+
+    move_pointer(&mut server, Some(target_surface), (100.0, 100.0).into());
+    server.roundtrip(&mut target.client, &mut target.state);
+    assert_eq!(target.state.offered, vec![URI_LIST.to_string()]);
+    target.accept(&mut server, URI_LIST, DndAction::Copy, DndAction::Copy);
+    assert_eq!(server.server.state.cursor_image(), CursorImageStatus::Named(CursorIcon::Copy));
+
+    left_button(&mut server, ButtonState::Released);
+    server.roundtrip(&mut target.client, &mut target.state);
+    assert!(target.state.dropped);
+    assert!(!server.server.state.dnd_state.is_active());
+
+    // The target reads the data after the drop, then finishes
+    let (offer, _) = target.state.entered.clone().unwrap();
+    let (mut reader, writer) = std::io::pipe().expect("Failed to create pipe");
+    offer.receive(URI_LIST.to_string(), writer.as_fd());
+    drop(writer);
+    server.roundtrip(&mut target.client, &mut target.state);
+    let mut uri_list = String::new();
+    reader.read_to_string(&mut uri_list).expect("Failed to read drop data");
+    assert_eq!(uri_list, "file:///home/user/My%20File.txt\r\n");
+
+    assert!(server.server.state.dnd_state.has_pending_data());
+    offer.finish();
+    server.roundtrip(&mut target.client, &mut target.state);
+    assert!(!server.server.state.dnd_state.has_pending_data());
+}
+
+/// Test that a compositor drag released over nothing is cancelled and cleaned up
+#[test]
+fn compositor_drag_cancelled() {
+    let mut server = TestServer::new();
+
+    move_pointer(&mut server, None, (5.0, 5.0).into());
+    left_button(&mut server, ButtonState::Pressed);
+    let contents = vec![(TEXT.to_string(), b"dragged".to_vec())];
+    server
+        .server
+        .state
+        .start_server_drag(contents, ServerDndAction::Copy)
+        .expect("Failed to start drag");
+    assert!(server.server.state.start_file_drag(&["/tmp/file"]).is_err(), "Only one drag may run at a time");
+
+    move_pointer(&mut server, None, (20.0, 20.0).into());
+    left_button(&mut server, ButtonState::Released);
+    server.dispatch();
+
+    let state = &server.server.state;
+    assert!(!state.dnd_state.is_active());
+    assert!(!state.dnd_state.has_pending_data());
+    assert_eq!(state.cursor_image(), CursorImageStatus::default_named());
+}
+
+// ============================================================================
+// Client-side dispatch
+// ============================================================================
+
+wayland_client::delegate_noop!(Drag: ignore WlSeat);
+wayland_client::delegate_noop!(Drag: ignore WlSurface);
+wayland_client::delegate_noop!(Drag: ignore WlDataSource);
+wayland_client::delegate_noop!(Drag: WlCompositor);
+wayland_client::delegate_noop!(Drag: WlDataDeviceManager);
+
+impl Dispatch<WlDataDevice, ()> for Drag {
+    fn event(state: &mut Self, _: &WlDataDevice, event: wl_data_device::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+        match event {
+            wl_data_device::Event::Enter { serial, id, .. } => {
+                state.entered = id.map(|offer| (offer, serial));
+            }
+            wl_data_device::Event::Drop => state.dropped = true,
+            _ => {}
+        }
+    }
+
+    event_created_child!(Drag, WlDataDevice, [
+        wl_data_device::EVT_DATA_OFFER_OPCODE => (WlDataOffer, ()),
+    ]);
+}
+
+impl Dispatch<WlDataOffer, ()> for Drag {
+    fn event(state: &mut Self, _: &WlDataOffer, event: wl_data_offer::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+        if let wl_data_offer::Event::Offer { mime_type } = event {
+            state.offered.push(mime_type);
+        }
+    }
+}
@@ -0,0 +1,231 @@
+//! Surface draw state integration tests
+//!
+//! Sets viewports, alpha multipliers and content types on surfaces and checks
+//! the per-draw state handed to the renderer and to frame scheduling, and that
+//! single-pixel buffers are drawn as solid-color quads.
+
+mod common;
+
+use common::{create_shm_buffer, PingPong, TestClient, TestServer};
+use compositor_core::render::SurfaceDraw;
+use compositor_core::surface_manager::SurfaceManager;
+use smithay::backend::renderer::utils::with_renderer_surface_state;
+use vulkan_renderer::ContentType;
+use wayland_client::{
+    protocol::{
+        wl_buffer::WlBuffer,
+        wl_compositor::WlCompositor,
+        wl_shm::WlShm,
+        wl_shm_pool::WlShmPool,
+        wl_surface::WlSurface,
+    },
+    Proxy,
+};
+use wayland_protocols::wp::alpha_modifier::v1::client::{
+    wp_alpha_modifier_surface_v1::WpAlphaModifierSurfaceV1, wp_alpha_modifier_v1::WpAlphaModifierV1,
+};
+use wayland_protocols::wp::content_type::v1::client::{
+    wp_content_type_manager_v1::WpContentTypeManagerV1,
+    wp_content_type_v1::{self, WpContentTypeV1},
+};
+use wayland_protocols::wp::single_pixel_buffer::v1::client::wp_single_pixel_buffer_manager_v1::WpSinglePixelBufferManagerV1;
+use wayland_protocols::wp::viewporter::client::{wp_viewport::WpViewport, wp_viewporter::WpViewporter};
+use wayland_protocols::xdg::shell::client::{
+    xdg_surface::XdgSurface,
+    xdg_toplevel::XdgToplevel,
+    xdg_wm_base::XdgWmBase,
+};
+use wayland_server::Resource;
+
+#[derive(Default)]
+struct App;
+
+struct DrawApp {
+    client: TestClient<App>,
+    state: App,
+    shm: WlShm,
+    viewporter: WpViewporter,
+    alpha_modifier: WpAlphaModifierV1,
+    content_type_manager: WpContentTypeManagerV1,
+    surface: WlSurface,
+    _toplevel: XdgToplevel,
+    _xdg_surface: XdgSurface,
+}
+
+impl DrawApp {
+    /// Connect a client with a toplevel, leaving its first commit to the test
+    fn connect(server: &mut TestServer) -> Self {
+        let client = server.connect::<App>();
+        let compositor: WlCompositor = client.bind(6, ());
+        let shm: WlShm = client.bind(1, ());
+        let wm_base: XdgWmBase = client.bind(6, ());
+        let viewporter: WpViewporter = client.bind(1, ());
+        let alpha_modifier: WpAlphaModifierV1 = client.bind(1, ());
+        let content_type_manager: WpContentTypeManagerV1 = client.bind(1, ());
+
+        let surface = compositor.create_surface(&client.qh, ());
+        let xdg_surface = wm_base.get_xdg_surface(&surface, &client.qh, ());
+        let toplevel = xdg_surface.get_toplevel(&client.qh, ());
+        Self {
+            client,
+            state: App,
+            shm,
+            viewporter,
+            alpha_modifier,
+            content_type_manager,
+            surface,
+            _toplevel: toplevel,
+            _xdg_surface: xdg_surface,
+        }
+    }
+
+    /// Attach a 200x100 buffer and commit
+    fn commit_buffer(&mut self, server: &mut TestServer) {
+        let buffer = create_shm_buffer(&self.shm, &self.client.qh, 200, 100);
+        self.surface.attach(Some(&buffer), 0, 0);
+        self.surface.commit();
+        server.roundtrip(&mut self.client, &mut self.state);
+    }
+}
+
+/// Draw of the client's surface on the first output
+fn surface_draw(server: &TestServer, surface: &WlSurface) -> SurfaceDraw {
+    let state = &server.server.state;
+    let output = state.space.outputs().next().expect("Output should exist");
+    state
+        .surface_draws(output)
+        .into_iter()
+        .find(|draw| draw.surface.id().protocol_id() == surface.id().protocol_id())
+        .expect("Surface should be drawn")
+}
+
+/// Test that surfaces without viewport, alpha or content type draw their whole buffer as is
+#[test]
+fn draw_state_defaults() {
+    let mut server = TestServer::new();
+    let mut app = DrawApp::connect(&mut server);
+    app.commit_buffer(&mut server);
+
+    let draw = surface_draw(&server, &app.surface);
+    assert_eq!(draw.state.position, [100.0, 100.0]);
+    assert_eq!(draw.state.source, Some([0.0, 0.0, 200.0, 100.0]));
+    assert_eq!(draw.state.destination, Some([200.0, 100.0]));
+    assert_eq!(draw.state.alpha, 1.0);
+    assert_eq!(draw.state.content_type, ContentType::None);
+
+    let push_constants = draw.state.push_constants([[0.0; 4]; 4], 200, 100);
+    assert_eq!(push_constants.scale, [1.0, 1.0]);
+    assert_eq!(push_constants.tex_offset, [0.0, 0.0]);
+    assert_eq!(push_constants.tex_scale, [1.0, 1.0]);
+}
+
+/// Test that viewport crop and scale, alpha and content type reach the draw state
+#[test]
+fn draw_state_from_surface_protocols() {
+    let mut server = TestServer::new();
+    let mut app = DrawApp::connect(&mut server);
+    let qh = app.client.qh.clone();
+    let viewport: WpViewport = app.viewporter.get_viewport(&app.surface, &qh, ());
+    let alpha: WpAlphaModifierSurfaceV1 = app.alpha_modifier.get_surface(&app.surface, &qh, ());
+    let content_type: WpContentTypeV1 = app.content_type_manager.get_surface_content_type(&app.surface, &qh, ());
+
+    viewport.set_source(50.0, 25.0, 100.0, 50.0);
+    viewport.set_destination(400, 200);
+    alpha.set_multiplier(u32::MAX / 2);
+    content_type.set_content_type(wp_content_type_v1::Type::Video);
+    app.commit_buffer(&mut server);
+
+    let draw = surface_draw(&server, &app.surface);
+    assert_eq!(draw.state.source, Some([50.0, 25.0, 100.0, 50.0]));
+    assert_eq!(draw.state.destination, Some([400.0, 200.0]));
+    assert!((draw.state.alpha - 0.5).abs() < 0.001);
+    assert_eq!(draw.state.content_type, ContentType::Video);
+
+    // Crop and scale are applied through texture coordinates of the buffer-sized quad
+    let push_constants = draw.state.push_constants([[0.0; 4]; 4], 200, 100);
+    assert_eq!(push_constants.scale, [2.0, 2.0]);
+    assert_eq!(push_constants.tex_offset, [0.25, 0.25]);
+    assert_eq!(push_constants.tex_scale, [0.5, 0.5]);
+
+    let state = &server.server.state;
+    let output = state.space.outputs().next().expect("Output should exist");
+    assert_eq!(state.frame_content_type(output), ContentType::Video);
+
+    // Crops are in surface coordinates, converted to buffer pixels
+    viewport.set_destination(-1, -1);
+    viewport.set_source(10.0, 10.0, 40.0, 20.0);
+    app.surface.set_buffer_scale(2);
+    content_type.set_content_type(wp_content_type_v1::Type::Game);
+    app.commit_buffer(&mut server);
+
+    let draw = surface_draw(&server, &app.surface);
+    assert_eq!(draw.state.source, Some([20.0, 20.0, 80.0, 40.0]));
+    assert_eq!(draw.state.destination, Some([40.0, 20.0]));
+    let state = &server.server.state;
+    let output = state.space.outputs().next().expect("Output should exist");
+    assert_eq!(state.frame_content_type(output), ContentType::Game);
+}
+
+/// Test that single-pixel buffers draw as solid quads scaled to their destination
+#[test]
+fn single_pixel_buffer_draws_solid_quad() {
+    let mut server = TestServer::new();
+    let mut app = DrawApp::connect(&mut server);
+    let qh = app.client.qh.clone();
+    let single_pixel: WpSinglePixelBufferManagerV1 = app.client.bind(1, ());
+    let viewport: WpViewport = app.viewporter.get_viewport(&app.surface, &qh, ());
+
+    // Premultiplied half-transparent red, stretched like a letterbox background
+    let half = u32::MAX / 2;
+    let buffer = single_pixel.create_u32_rgba_buffer(half, 0, 0, half, &qh, ());
+    viewport.set_destination(400, 300);
+    app.surface.attach(Some(&buffer), 0, 0);
+    app.surface.commit();
+    server.roundtrip(&mut app.client, &mut app.state);
+
+    let draw = surface_draw(&server, &app.surface);
+    let [r, g, b, a] = draw.state.color.expect("Single-pixel buffer should draw a solid color");
+    assert_eq!((r, g, b), (1.0, 0.0, 0.0));
+    assert!((a - 0.5).abs() < 0.001);
+    assert_eq!(draw.state.destination, Some([400.0, 300.0]));
+
+    // The solid quad spans one pixel scaled to the destination
+    let push_constants = draw.state.push_constants([[0.0; 4]; 4], 1, 1);
+    assert_eq!(push_constants.solid, 1);
+    assert_eq!(push_constants.scale, [400.0, 300.0]);
+
+    // Commits of single-pixel buffers skip the texture upload path
+    let server_buffer = with_renderer_surface_state(&draw.surface, |state| state.buffer().map(|buffer| (**buffer).clone()))
+        .flatten()
+        .expect("Surface should have a buffer");
+    let mut surface_manager = SurfaceManager::new();
+    let surface_id = draw.surface.id().protocol_id() as u64;
+    surface_manager
+        .handle_surface_commit(surface_id, &server_buffer)
+        .expect("Single-pixel buffers should be accepted without upload");
+
+    // Regular buffers draw from textures again
+    app.commit_buffer(&mut server);
+    assert_eq!(surface_draw(&server, &app.surface).state.color, None);
+}
+
+// ============================================================================
+// Client-side dispatch
+// ============================================================================
+
+wayland_client::delegate_noop!(App: ignore WlSurface);
+wayland_client::delegate_noop!(App: ignore WlShm);
+wayland_client::delegate_noop!(App: ignore WlBuffer);
+wayland_client::delegate_noop!(App: ignore XdgSurface);
+wayland_client::delegate_noop!(App: ignore XdgToplevel);
+wayland_client::delegate_noop!(App: WlCompositor);
+wayland_client::delegate_noop!(App: WlShmPool);
+wayland_client::delegate_noop!(App: WpViewporter);
+wayland_client::delegate_noop!(App: WpViewport);
+wayland_client::delegate_noop!(App: WpAlphaModifierV1);
+wayland_client::delegate_noop!(App: WpAlphaModifierSurfaceV1);
+wayland_client::delegate_noop!(App: WpContentTypeManagerV1);
+wayland_client::delegate_noop!(App: WpContentTypeV1);
+wayland_client::delegate_noop!(App: WpSinglePixelBufferManagerV1);
+
+wayland_client::delegate_dispatch!(App: [XdgWmBase: ()] => PingPong);
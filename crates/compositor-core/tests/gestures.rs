@@ -0,0 +1,344 @@
+//! Touchpad gesture integration tests
+//!
+//! Injects gestures as the input backend would and checks which reach clients
+//! over zwp_pointer_gestures_v1 and which drive workspaces and the overview.
+
+mod common;
+
+use common::{create_shm_buffer, PingPong, TestClient, TestServer};
+use compositor_core::wayland::WaylandServerState;
+use smithay::input::pointer::{
+    GestureHoldBeginEvent, GestureHoldEndEvent, GesturePinchBeginEvent, GesturePinchEndEvent, GesturePinchUpdateEvent,
+    GestureSwipeBeginEvent, GestureSwipeEndEvent, GestureSwipeUpdateEvent, MotionEvent,
+};
+use smithay::output::Scale;
+use smithay::utils::{Point, SERIAL_COUNTER};
+use std::time::Duration;
+use wayland_client::{
+    protocol::{
+        wl_buffer::WlBuffer,
+        wl_compositor::WlCompositor,
+        wl_pointer::WlPointer,
+        wl_seat::WlSeat,
+        wl_shm::WlShm,
+        wl_shm_pool::WlShmPool,
+        wl_surface::WlSurface,
+    },
+    Connection, Dispatch, Proxy, QueueHandle,
+};
+use wayland_protocols::wp::pointer_gestures::zv1::client::{
+    zwp_pointer_gesture_hold_v1::{self, ZwpPointerGestureHoldV1},
+    zwp_pointer_gesture_pinch_v1::{self, ZwpPointerGesturePinchV1},
+    zwp_pointer_gesture_swipe_v1::{self, ZwpPointerGestureSwipeV1},
+    zwp_pointer_gestures_v1::ZwpPointerGesturesV1,
+};
+use wayland_protocols::xdg::shell::client::{
+    xdg_surface::XdgSurface,
+    xdg_toplevel::XdgToplevel,
+    xdg_wm_base::XdgWmBase,
+};
+use wayland_server::Resource;
+
+/// Gesture events as seen by the client
+#[derive(Debug, PartialEq)]
+enum Gesture {
+    SwipeBegin(u32),
+    SwipeUpdate(f64, f64),
+    SwipeEnd(bool),
+    PinchBegin(u32),
+    PinchUpdate(f64),
+    PinchEnd(bool),
+    HoldBegin(u32),
+    HoldEnd(bool),
+}
+
+#[derive(Default)]
+struct App {
+    gestures: Vec<Gesture>,
+}
+
+/// An application window of 400x300 listening to gestures
+struct GestureApp {
+    client: TestClient<App>,
+    state: App,
+    surface: WlSurface,
+    _objects: (XdgSurface, XdgToplevel, ZwpPointerGestureSwipeV1, ZwpPointerGesturePinchV1, ZwpPointerGestureHoldV1),
+}
+
+fn connect_gesture_app(server: &mut TestServer) -> GestureApp {
+    let mut client = server.connect::<App>();
+    let mut state = App::default();
+
+    let compositor: WlCompositor = client.bind(6, ());
+    let shm: WlShm = client.bind(1, ());
+    let seat: WlSeat = client.bind(7, ());
+    let wm_base: XdgWmBase = client.bind(6, ());
+    let gestures: ZwpPointerGesturesV1 = client.bind(3, ());
+
+    let surface = compositor.create_surface(&client.qh, ());
+    let xdg_surface = wm_base.get_xdg_surface(&surface, &client.qh, ());
+    let toplevel = xdg_surface.get_toplevel(&client.qh, ());
+    let buffer = create_shm_buffer(&shm, &client.qh, 400, 300);
+    surface.attach(Some(&buffer), 0, 0);
+    surface.commit();
+
+    let pointer = seat.get_pointer(&client.qh, ());
+    let swipe = gestures.get_swipe_gesture(&pointer, &client.qh, ());
+    let pinch = gestures.get_pinch_gesture(&pointer, &client.qh, ());
+    let hold = gestures.get_hold_gesture(&pointer, &client.qh, ());
+    server.roundtrip(&mut client, &mut state);
+
+    GestureApp { client, state, surface, _objects: (xdg_surface, toplevel, swipe, pinch, hold) }
+}
+
+impl GestureApp {
+    /// Put the pointer over the window, mapped at (100, 100)
+    fn hover(&mut self, server: &mut TestServer) {
+        let surface = self
+            .client
+            .server_client
+            .object_from_protocol_id(&server.server.display.handle(), self.surface.id().protocol_id())
+            .expect("Surface should exist on the server");
+        let state = &mut server.server.state;
+        let pointer = state.seat.get_pointer().expect("Default seat should have a pointer");
+        let event = MotionEvent { location: (150.0, 150.0).into(), serial: SERIAL_COUNTER.next_serial(), time: 0 };
+        pointer.motion(state, Some((surface, (100.0, 100.0).into())), &event);
+        pointer.frame(state);
+        server.roundtrip(&mut self.client, &mut self.state);
+    }
+
+    fn take_gestures(&mut self, server: &mut TestServer) -> Vec<Gesture> {
+        server.roundtrip(&mut self.client, &mut self.state);
+        std::mem::take(&mut self.state.gestures)
+    }
+
+    /// Location of the window in the scene, if drawn
+    fn scene_location(&self, server: &TestServer) -> Option<(i32, i32)> {
+        let state = &server.server.state;
+        let output = state.space.outputs().next().expect("Default output should be mapped");
+        state
+            .render_elements(output)
+            .into_iter()
+            .find(|element| element.surface.id().protocol_id() == self.surface.id().protocol_id())
+            .map(|element| (element.location.x, element.location.y))
+    }
+}
+
+fn swipe(state: &mut WaylandServerState, fingers: u32, deltas: &[(f64, f64)], cancelled: bool) {
+    swipe_begin(state, fingers, deltas);
+    swipe_end(state, cancelled);
+}
+
+fn swipe_begin(state: &mut WaylandServerState, fingers: u32, deltas: &[(f64, f64)]) {
+    let seat = state.seat.clone();
+    state.gesture_swipe_begin(&seat, GestureSwipeBeginEvent { serial: SERIAL_COUNTER.next_serial(), time: 0, fingers });
+    for &delta in deltas {
+        state.gesture_swipe_update(&seat, GestureSwipeUpdateEvent { time: 0, delta: Point::from(delta) });
+    }
+}
+
+fn swipe_end(state: &mut WaylandServerState, cancelled: bool) {
+    let seat = state.seat.clone();
+    state.gesture_swipe_end(&seat, GestureSwipeEndEvent { serial: SERIAL_COUNTER.next_serial(), time: 0, cancelled });
+}
+
+/// Test that unbound gestures reach the client under the pointer and bound swipes do not
+#[test]
+fn unbound_gestures_reach_client() {
+    let mut server = TestServer::new();
+    let mut app = connect_gesture_app(&mut server);
+    app.hover(&mut server);
+
+    let state = &mut server.server.state;
+    swipe(state, 5, &[(10.0, -4.0)], false);
+    let seat = state.seat.clone();
+    state.gesture_pinch_begin(&seat, GesturePinchBeginEvent { serial: SERIAL_COUNTER.next_serial(), time: 0, fingers: 2 });
+    state.gesture_pinch_update(&seat, GesturePinchUpdateEvent { time: 0, delta: Point::default(), scale: 1.5, rotation: 0.0 });
+    state.gesture_pinch_end(&seat, GesturePinchEndEvent { serial: SERIAL_COUNTER.next_serial(), time: 0, cancelled: true });
+    state.gesture_hold_begin(&seat, GestureHoldBeginEvent { serial: SERIAL_COUNTER.next_serial(), time: 0, fingers: 3 });
+    state.gesture_hold_end(&seat, GestureHoldEndEvent { serial: SERIAL_COUNTER.next_serial(), time: 0, cancelled: false });
+    assert_eq!(
+        app.take_gestures(&mut server),
+        [
+            Gesture::SwipeBegin(5),
+            Gesture::SwipeUpdate(10.0, -4.0),
+            Gesture::SwipeEnd(false),
+            Gesture::PinchBegin(2),
+            Gesture::PinchUpdate(1.5),
+            Gesture::PinchEnd(true),
+            Gesture::HoldBegin(3),
+            Gesture::HoldEnd(false),
+        ]
+    );
+
+    // Workspace and overview swipes are consumed by the compositor
+    let state = &mut server.server.state;
+    swipe(state, 3, &[(-20.0, 0.0)], false);
+    swipe(state, 4, &[(0.0, -20.0)], false);
+    assert!(state.gesture_state.bound_swipe().is_none());
+    assert!(app.take_gestures(&mut server).is_empty());
+}
+
+/// Test that a three finger swipe slides between workspaces following the fingers
+#[test]
+fn three_finger_swipe_switches_workspace() {
+    let mut server = TestServer::new();
+    server.server.state.config.theme.animations = false;
+    let app = connect_gesture_app(&mut server);
+    assert_eq!(app.scene_location(&server), Some((100, 100)));
+
+    // There is no workspace before the first one
+    swipe_begin(&mut server.server.state, 3, &[(120.0, 0.0)]);
+    assert_eq!(server.server.state.workspace_switch(), None);
+    swipe_end(&mut server.server.state, false);
+
+    // Halfway to the next workspace the window is half an output width to the left
+    swipe_begin(&mut server.server.state, 3, &[(-100.0, 0.0), (-50.0, 0.0)]);
+    assert_eq!(server.server.state.workspace_switch(), Some((1, -0.5)));
+    assert_eq!(app.scene_location(&server), Some((100 - 1920, 100)));
+    swipe_end(&mut server.server.state, false);
+
+    let state = &server.server.state;
+    assert_eq!(state.workspace_state.active(), 1);
+    assert_eq!(state.space.elements().count(), 0);
+    assert_eq!(state.hidden_windows(0).len(), 1);
+    assert_eq!(app.scene_location(&server), None);
+
+    // A short swipe back is reverted
+    swipe(&mut server.server.state, 3, &[(90.0, 0.0)], false);
+    assert_eq!(server.server.state.workspace_state.active(), 1);
+
+    // The previous workspace slides in from the left
+    swipe_begin(&mut server.server.state, 3, &[(75.0, 0.0)]);
+    assert_eq!(app.scene_location(&server), Some((100 - 2880, 100)));
+    let seat = server.server.state.seat.clone();
+    server.server.state.gesture_swipe_update(&seat, GestureSwipeUpdateEvent { time: 0, delta: (125.0, 0.0).into() });
+    swipe_end(&mut server.server.state, false);
+    assert_eq!(server.server.state.workspace_state.active(), 0);
+    assert_eq!(app.scene_location(&server), Some((100, 100)));
+
+    // Cancelled swipes never switch
+    swipe(&mut server.server.state, 3, &[(-290.0, 0.0)], true);
+    assert_eq!(server.server.state.workspace_state.active(), 0);
+
+    assert!(server.server.state.switch_workspace(4).is_err());
+    server.server.state.switch_workspace(3).expect("Failed to switch to the last workspace");
+    assert_eq!(app.scene_location(&server), None);
+}
+
+/// Test that a four finger swipe up opens the overview with an animation
+#[test]
+fn four_finger_swipe_opens_overview() {
+    let mut server = TestServer::new();
+    let state = &mut server.server.state;
+    state.config.theme.animations = true;
+    state.config.theme.animation_duration = 30;
+
+    swipe_begin(state, 4, &[(0.0, -120.0), (0.0, -120.0)]);
+    assert_eq!(state.overview_progress(), 0.8);
+    swipe_end(state, false);
+
+    // Released past halfway, the overview animates open
+    assert!(!state.workspace_state.is_overview_open());
+    assert!(state.overview_progress() >= 0.8);
+    std::thread::sleep(Duration::from_millis(40));
+    state.refresh();
+    assert!(state.workspace_state.is_overview_open());
+    assert_eq!(state.overview_progress(), 1.0);
+
+    // A cancelled swipe down leaves it open
+    swipe_begin(state, 4, &[(0.0, 150.0)]);
+    assert_eq!(state.overview_progress(), 0.5);
+    swipe_end(state, true);
+    std::thread::sleep(Duration::from_millis(40));
+    state.refresh();
+    assert!(state.workspace_state.is_overview_open());
+
+    // A new swipe finishes the running animation first
+    swipe(state, 4, &[(0.0, 200.0)], false);
+    swipe_begin(state, 4, &[]);
+    assert!(!state.workspace_state.is_overview_open());
+    assert_eq!(state.overview_progress(), 0.0);
+    swipe_end(state, false);
+}
+
+/// Test that windows move and shrink into the overview grid as it opens
+#[test]
+fn overview_lays_out_windows() {
+    let mut server = TestServer::new();
+    server.server.state.config.theme.animations = false;
+    let app = connect_gesture_app(&mut server);
+    assert_eq!(app.scene_location(&server), Some((100, 100)));
+
+    // Half open, the window is halfway to the center of its cell
+    swipe_begin(&mut server.server.state, 4, &[(0.0, -150.0)]);
+    assert_eq!(server.server.state.overview_progress(), 0.5);
+    assert_eq!(app.scene_location(&server), Some((910, 515)));
+    swipe_end(&mut server.server.state, true);
+    assert_eq!(app.scene_location(&server), Some((100, 100)));
+
+    // Windows larger than their cell are shrunk to fit it
+    let state = &mut server.server.state;
+    let output = state.space.outputs().next().cloned().expect("Default output should be mapped");
+    output.change_current_state(None, None, Some(Scale::Integer(8)), None);
+    state.set_overview_open(true);
+    let (_, tile) = state.overview_tiles(&output).pop().expect("Window should have a tile");
+    assert_eq!(tile.scale, 206.0 / 300.0);
+    let element = state.render_elements(&output).pop().expect("Scene should not be empty");
+    assert_eq!((element.location.x, element.location.y), (103, 32));
+    assert_eq!(element.scale, tile.scale);
+    let draw = state.surface_draws(&output).pop().expect("Window should be drawn");
+    assert_eq!(draw.geometry().size, (2198, 1648).into());
+
+    state.set_overview_open(false);
+    assert_eq!(state.render_elements(&output).pop().map(|element| element.scale), Some(1.0));
+}
+
+// ============================================================================
+// Client-side dispatch
+// ============================================================================
+
+wayland_client::delegate_noop!(App: ignore WlSeat);
+wayland_client::delegate_noop!(App: ignore WlSurface);
+wayland_client::delegate_noop!(App: ignore WlShm);
+wayland_client::delegate_noop!(App: ignore WlBuffer);
+wayland_client::delegate_noop!(App: ignore WlPointer);
+wayland_client::delegate_noop!(App: ignore XdgSurface);
+wayland_client::delegate_noop!(App: ignore XdgToplevel);
+wayland_client::delegate_noop!(App: WlCompositor);
+wayland_client::delegate_noop!(App: WlShmPool);
+wayland_client::delegate_noop!(App: ZwpPointerGesturesV1);
+
+wayland_client::delegate_dispatch!(App: [XdgWmBase: ()] => PingPong);
+
+impl Dispatch<ZwpPointerGestureSwipeV1, ()> for App {
+    fn event(state: &mut Self, _: &ZwpPointerGestureSwipeV1, event: zwp_pointer_gesture_swipe_v1::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+        state.gestures.push(match event {
+            zwp_pointer_gesture_swipe_v1::Event::Begin { fingers, .. } => Gesture::SwipeBegin(fingers),
+            zwp_pointer_gesture_swipe_v1::Event::Update { dx, dy, .. } => Gesture::SwipeUpdate(dx, dy),
+            zwp_pointer_gesture_swipe_v1::Event::End { cancelled, .. } => Gesture::SwipeEnd(cancelled != 0),
+            _ => return,
+        });
+    }
+}
+
+impl Dispatch<ZwpPointerGesturePinchV1, ()> for App {
+    fn event(state: &mut Self, _: &ZwpPointerGesturePinchV1, event: zwp_pointer_gesture_pinch_v1::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+        state.gestures.push(match event {
+            zwp_pointer_gesture_pinch_v1::Event::Begin { fingers, .. } => Gesture::PinchBegin(fingers),
+            zwp_pointer_gesture_pinch_v1::Event::Update { scale, .. } => Gesture::PinchUpdate(scale),
+            zwp_pointer_gesture_pinch_v1::Event::End { cancelled, .. } => Gesture::PinchEnd(cancelled != 0),
+            _ => return,
+        });
+    }
+}
+
+impl Dispatch<ZwpPointerGestureHoldV1, ()> for App {
+    fn event(state: &mut Self, _: &ZwpPointerGestureHoldV1, event: zwp_pointer_gesture_hold_v1::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+        state.gestures.push(match event {
+            zwp_pointer_gesture_hold_v1::Event::Begin { fingers, .. } => Gesture::HoldBegin(fingers),
+            zwp_pointer_gesture_hold_v1::Event::End { cancelled, .. } => Gesture::HoldEnd(cancelled != 0),
+            _ => return,
+        });
+    }
+}
@@ -0,0 +1,186 @@
+//! Input method popup integration tests
+//!
+//! Connects an application using zwp_text_input_v3 and an input method using
+//! zwp_input_method_v2, and checks where the compositor places the input
+//! method's candidate popup.
+
+mod common;
+
+use common::{create_shm_buffer, PingPong, TestClient, TestServer};
+use compositor_core::input_method::place_popup;
+use smithay::utils::{Logical, Rectangle, Size, SERIAL_COUNTER};
+use wayland_client::{
+    protocol::{
+        wl_buffer::WlBuffer,
+        wl_compositor::WlCompositor,
+        wl_seat::WlSeat,
+        wl_shm::WlShm,
+        wl_shm_pool::WlShmPool,
+        wl_surface::WlSurface,
+    },
+    Proxy,
+};
+use wayland_protocols::wp::text_input::zv3::client::{
+    zwp_text_input_manager_v3::ZwpTextInputManagerV3,
+    zwp_text_input_v3::ZwpTextInputV3,
+};
+use wayland_protocols::xdg::shell::client::{
+    xdg_surface::XdgSurface,
+    xdg_toplevel::XdgToplevel,
+    xdg_wm_base::XdgWmBase,
+};
+use wayland_protocols_misc::zwp_input_method_v2::client::{
+    zwp_input_method_manager_v2::ZwpInputMethodManagerV2,
+    zwp_input_method_v2::ZwpInputMethodV2,
+    zwp_input_popup_surface_v2::ZwpInputPopupSurfaceV2,
+};
+use wayland_server::Resource;
+
+#[derive(Default)]
+struct Client;
+
+/// An application window with a text field
+struct TextApp {
+    client: TestClient<Client>,
+    text_input: ZwpTextInputV3,
+    _window: (WlSurface, XdgSurface, XdgToplevel),
+}
+
+fn connect_text_app(server: &mut TestServer) -> TextApp {
+    let mut client = server.connect::<Client>();
+
+    let compositor: WlCompositor = client.bind(6, ());
+    let seat: WlSeat = client.bind(7, ());
+    let wm_base: XdgWmBase = client.bind(6, ());
+    let text_input_manager: ZwpTextInputManagerV3 = client.bind(1, ());
+
+    let surface = compositor.create_surface(&client.qh, ());
+    let xdg_surface = wm_base.get_xdg_surface(&surface, &client.qh, ());
+    let toplevel = xdg_surface.get_toplevel(&client.qh, ());
+    let text_input = text_input_manager.get_text_input(&seat, &client.qh, ());
+    server.roundtrip(&mut client, &mut Client);
+
+    // Focus the window so text input and the input method follow it
+    let server_surface = client
+        .server_client
+        .object_from_protocol_id::<wayland_server::protocol::wl_surface::WlSurface>(
+            &server.server.display.handle(),
+            surface.id().protocol_id(),
+        )
+        .expect("Surface should exist on the server");
+    let keyboard = server.server.state.seat.get_keyboard().expect("Default seat should have a keyboard");
+    keyboard.set_focus(&mut server.server.state, Some(server_surface), SERIAL_COUNTER.next_serial());
+    server.roundtrip(&mut client, &mut Client);
+
+    TextApp { client, text_input, _window: (surface, xdg_surface, toplevel) }
+}
+
+impl TextApp {
+    fn set_cursor(&mut self, server: &mut TestServer, x: i32, y: i32) {
+        self.text_input.enable();
+        self.text_input.set_cursor_rectangle(x, y, 2, 16);
+        self.text_input.commit();
+        server.roundtrip(&mut self.client, &mut Client);
+    }
+}
+
+/// Connect an input method showing a 200x100 candidate popup
+fn connect_input_method(server: &mut TestServer) -> (TestClient<Client>, WlSurface) {
+    let mut client = server.connect::<Client>();
+
+    let compositor: WlCompositor = client.bind(6, ());
+    let shm: WlShm = client.bind(1, ());
+    let seat: WlSeat = client.bind(7, ());
+    let manager: ZwpInputMethodManagerV2 = client.bind(1, ());
+
+    let input_method = manager.get_input_method(&seat, &client.qh, ());
+    let surface = compositor.create_surface(&client.qh, ());
+    input_method.get_input_popup_surface(&surface, &client.qh, ());
+    let buffer = create_shm_buffer(&shm, &client.qh, 200, 100);
+    surface.attach(Some(&buffer), 0, 0);
+    surface.commit();
+    server.roundtrip(&mut client, &mut Client);
+
+    (client, surface)
+}
+
+fn scene_top(server: &TestServer) -> Option<(u32, (i32, i32))> {
+    let state = &server.server.state;
+    let output = state.space.outputs().next().expect("Default output should be mapped");
+    state
+        .render_elements(output)
+        .pop()
+        .map(|element| (element.surface.id().protocol_id(), (element.location.x, element.location.y)))
+}
+
+fn rect(x: i32, y: i32, w: i32, h: i32) -> Rectangle<i32, Logical> {
+    Rectangle::new((x, y).into(), (w, h).into())
+}
+
+/// Test popup placement around the text cursor within output bounds
+#[test]
+fn popup_placement_flips_at_output_edges() {
+    let bounds = rect(0, 0, 1920, 1080);
+    let size = Size::from((300, 200));
+
+    // Below the cursor, left-aligned with it
+    assert_eq!(place_popup(rect(100, 100, 2, 20), size, bounds), (100, 120).into());
+    // Above the cursor near the bottom edge
+    assert_eq!(place_popup(rect(100, 1000, 2, 20), size, bounds), (100, 800).into());
+    // Right-aligned with the cursor near the right edge
+    assert_eq!(place_popup(rect(1800, 100, 2, 20), size, bounds), (1502, 120).into());
+    // Pushed inside when neither side has room
+    assert_eq!(place_popup(rect(10, 100, 2, 20), Size::from((300, 1000)), bounds), (10, 80).into());
+    // Outputs away from the origin
+    assert_eq!(place_popup(rect(3100, 600, 10, 20), size, rect(1920, 0, 1280, 720)), (2810, 400).into());
+}
+
+/// Test that the candidate popup follows the text cursor of the focused window
+#[test]
+fn input_method_popup_follows_text_cursor() {
+    let mut server = TestServer::new();
+    let mut app = connect_text_app(&mut server);
+    let (mut ime, popup) = connect_input_method(&mut server);
+    let popup_id = popup.id().protocol_id();
+
+    // Windows are mapped at (100, 100), so the cursor is at (110, 120) globally
+    app.set_cursor(&mut server, 10, 20);
+    server.roundtrip(&mut ime, &mut Client);
+
+    let tracked = &server.server.state.input_method_popups;
+    assert_eq!(tracked.len(), 1);
+    let geometry = server.server.state.input_method_popup_geometry(&tracked[0]);
+    assert_eq!(geometry, Some(rect(110, 136, 200, 100)));
+    assert_eq!(scene_top(&server), Some((popup_id, (110, 136))));
+
+    // Near the bottom-right corner of the 3840x2160 output the popup flips
+    app.set_cursor(&mut server, 3700, 2000);
+    assert_eq!(scene_top(&server), Some((popup_id, (3602, 2000))));
+
+    // Moving focus away deactivates the input method and hides the popup
+    let keyboard = server.server.state.seat.get_keyboard().unwrap();
+    keyboard.set_focus(&mut server.server.state, None, SERIAL_COUNTER.next_serial());
+    server.roundtrip(&mut app.client, &mut Client);
+    assert!(server.server.state.input_method_popups.is_empty());
+    assert_ne!(scene_top(&server).map(|(id, _)| id), Some(popup_id));
+}
+
+// ============================================================================
+// Client-side dispatch
+// ============================================================================
+
+wayland_client::delegate_noop!(Client: ignore WlSeat);
+wayland_client::delegate_noop!(Client: ignore WlSurface);
+wayland_client::delegate_noop!(Client: ignore WlShm);
+wayland_client::delegate_noop!(Client: ignore WlBuffer);
+wayland_client::delegate_noop!(Client: ignore XdgSurface);
+wayland_client::delegate_noop!(Client: ignore XdgToplevel);
+wayland_client::delegate_noop!(Client: ignore ZwpTextInputV3);
+wayland_client::delegate_noop!(Client: ignore ZwpInputMethodV2);
+wayland_client::delegate_noop!(Client: ignore ZwpInputPopupSurfaceV2);
+wayland_client::delegate_noop!(Client: WlCompositor);
+wayland_client::delegate_noop!(Client: WlShmPool);
+wayland_client::delegate_noop!(Client: ZwpTextInputManagerV3);
+wayland_client::delegate_noop!(Client: ZwpInputMethodManagerV2);
+
+wayland_client::delegate_dispatch!(Client: [XdgWmBase: ()] => PingPong);
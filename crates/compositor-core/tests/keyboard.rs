@@ -0,0 +1,187 @@
+//! Keyboard layout integration tests
+//!
+//! Configures an xkb keymap with several layouts, switches layouts with the
+//! configured binding and through IPC, and checks what a focused client sees:
+//! key repeat settings, the active layout in modifier events, and no key
+//! events for the binding. Per-window layouts are checked by moving the focus
+//! between windows.
+
+mod common;
+
+use common::{click, map_windows, PingPong, TestServer};
+use compositor_core::input::BackendInput;
+use compositor_core::wayland::WaylandServerState;
+use config::{CompositorConfig, KeyboardConfig};
+use ipc::protocol::{IPCMessage, KeyboardLayoutSwitch};
+use wayland_client::protocol::{
+    wl_buffer::WlBuffer,
+    wl_compositor::WlCompositor,
+    wl_keyboard::{self, WlKeyboard},
+    wl_seat::WlSeat,
+    wl_shm::WlShm,
+    wl_shm_pool::WlShmPool,
+    wl_surface::WlSurface,
+};
+use wayland_client::{Connection, Dispatch, QueueHandle, WEnum};
+use wayland_protocols::xdg::shell::client::{
+    xdg_surface::XdgSurface,
+    xdg_toplevel::XdgToplevel,
+    xdg_wm_base::XdgWmBase,
+};
+
+/// Keycodes of the evdev keymap
+const SUPER_KEYCODE: u32 = 133;
+const SPACE_KEYCODE: u32 = 65;
+const A_KEYCODE: u32 = 38;
+
+#[derive(Default)]
+struct App {
+    repeat_info: Option<(i32, i32)>,
+    /// Keys pressed and released, as evdev codes
+    keys: Vec<(u32, bool)>,
+    /// Layout of the last modifiers event
+    group: Option<u32>,
+}
+
+fn keyboard_server(keyboard: KeyboardConfig) -> TestServer {
+    TestServer::with_config(CompositorConfig { keyboard, ..Default::default() })
+}
+
+fn layout_config() -> KeyboardConfig {
+    KeyboardConfig {
+        layout: "us,de".to_string(),
+        repeat_rate: 30,
+        repeat_delay: 400,
+        numlock: true,
+        switch_layout: Some("Super+space".to_string()),
+        ..Default::default()
+    }
+}
+
+fn key(state: &mut WaylandServerState, keycode: u32, pressed: bool) {
+    state.apply_input(BackendInput::Key { keycode, pressed });
+}
+
+fn active_layout(state: &mut WaylandServerState) -> u32 {
+    let seat = state.seat.clone();
+    state.keyboard_layouts(&seat).expect("Seat should have a keyboard").1
+}
+
+/// Test that the configured keymap, repeat and num lock apply and that layouts switch
+#[test]
+fn configured_layouts_switch() {
+    let mut server = keyboard_server(layout_config());
+    let mut client = server.connect::<App>();
+    let mut app = App::default();
+    let seat: WlSeat = client.bind(7, ());
+    let _keyboard = seat.get_keyboard(&client.qh, ());
+    let _windows = map_windows(&mut server, &mut client, &mut app, 1);
+
+    let state = &mut server.server.state;
+    let default_seat = state.seat.clone();
+    let (layouts, active) = state.keyboard_layouts(&default_seat).expect("Seat should have a keyboard");
+    assert_eq!(layouts, ["English (US)", "German"]);
+    assert_eq!(active, 0);
+    assert!(default_seat.get_keyboard().expect("Seat should have a keyboard").modifier_state().num_lock);
+
+    // The binding switches layouts without the client seeing its key
+    click(state, (150.0, 150.0));
+    key(state, SUPER_KEYCODE, true);
+    key(state, SPACE_KEYCODE, true);
+    key(state, SPACE_KEYCODE, false);
+    key(state, SUPER_KEYCODE, false);
+    assert_eq!(active_layout(state), 1);
+    key(state, A_KEYCODE, true);
+    key(state, A_KEYCODE, false);
+    server.roundtrip(&mut client, &mut app);
+    assert_eq!(app.repeat_info, Some((30, 400)));
+    assert_eq!(app.group, Some(1));
+    assert_eq!(app.keys, [(SUPER_KEYCODE - 8, true), (SUPER_KEYCODE - 8, false), (A_KEYCODE - 8, true), (A_KEYCODE - 8, false)]);
+
+    // The binding matches its key whichever layout is active
+    let state = &mut server.server.state;
+    key(state, SUPER_KEYCODE, true);
+    key(state, SPACE_KEYCODE, true);
+    key(state, SPACE_KEYCODE, false);
+    key(state, SUPER_KEYCODE, false);
+    assert_eq!(active_layout(state), 0);
+
+    // Layouts are exposed and switched over IPC
+    let layout = |state: &mut WaylandServerState, layout| state.handle_ipc_message(IPCMessage::SwitchKeyboardLayout { layout });
+    match layout(state, KeyboardLayoutSwitch::Previous) {
+        IPCMessage::KeyboardLayout { layouts, active: 1 } => assert_eq!(layouts.len(), 2),
+        other => panic!("Unexpected response: {:?}", other),
+    }
+    assert!(matches!(layout(state, KeyboardLayoutSwitch::Index(0)), IPCMessage::KeyboardLayout { active: 0, .. }));
+    assert!(matches!(layout(state, KeyboardLayoutSwitch::Index(2)), IPCMessage::Error { .. }));
+    assert!(matches!(layout(state, KeyboardLayoutSwitch::Next), IPCMessage::KeyboardLayout { active: 1, .. }));
+    assert!(matches!(state.handle_ipc_message(IPCMessage::GetKeyboardLayout), IPCMessage::KeyboardLayout { active: 1, .. }));
+    server.roundtrip(&mut client, &mut app);
+    assert_eq!(app.group, Some(1));
+
+    // Keymaps that do not compile fall back to the default keymap
+    let mut server = keyboard_server(KeyboardConfig { layout: "no-such-layout".to_string(), ..Default::default() });
+    let state = &mut server.server.state;
+    let seat = state.seat.clone();
+    let (layouts, _) = state.keyboard_layouts(&seat).expect("Seat should have a keyboard");
+    assert_eq!(layouts.len(), 1);
+}
+
+/// Test that per-window layouts follow the keyboard focus
+#[test]
+fn layouts_follow_windows() {
+    let mut server = keyboard_server(KeyboardConfig { per_window_layout: true, ..layout_config() });
+    let mut client = server.connect::<App>();
+    let mut app = App::default();
+    let _windows = map_windows(&mut server, &mut client, &mut app, 2);
+
+    let state = &mut server.server.state;
+    click(state, (150.0, 150.0));
+    state.handle_ipc_message(IPCMessage::SwitchKeyboardLayout { layout: KeyboardLayoutSwitch::Index(1) });
+
+    // A window focused for the first time starts on the first layout
+    click(state, (650.0, 150.0));
+    assert_eq!(active_layout(state), 0);
+    click(state, (150.0, 150.0));
+    assert_eq!(active_layout(state), 1);
+    click(state, (650.0, 150.0));
+    assert_eq!(active_layout(state), 0);
+
+    // Without per-window layouts the layout stays with the keyboard
+    let mut server = keyboard_server(layout_config());
+    let mut client = server.connect::<App>();
+    let _windows = map_windows(&mut server, &mut client, &mut app, 2);
+    let state = &mut server.server.state;
+    click(state, (150.0, 150.0));
+    state.handle_ipc_message(IPCMessage::SwitchKeyboardLayout { layout: KeyboardLayoutSwitch::Index(1) });
+    click(state, (650.0, 150.0));
+    assert_eq!(active_layout(state), 1);
+}
+
+// ============================================================================
+// Client-side dispatch
+// ============================================================================
+
+wayland_client::delegate_noop!(App: ignore WlSurface);
+wayland_client::delegate_noop!(App: ignore WlShm);
+wayland_client::delegate_noop!(App: ignore WlBuffer);
+wayland_client::delegate_noop!(App: ignore WlSeat);
+wayland_client::delegate_noop!(App: ignore XdgSurface);
+wayland_client::delegate_noop!(App: ignore XdgToplevel);
+wayland_client::delegate_noop!(App: WlCompositor);
+wayland_client::delegate_noop!(App: WlShmPool);
+
+wayland_client::delegate_dispatch!(App: [XdgWmBase: ()] => PingPong);
+
+impl Dispatch<WlKeyboard, ()> for App {
+    fn event(state: &mut Self, _: &WlKeyboard, event: wl_keyboard::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+        match event {
+            wl_keyboard::Event::RepeatInfo { rate, delay } => state.repeat_info = Some((rate, delay)),
+            wl_keyboard::Event::Key { key, state: WEnum::Value(key_state), .. } => {
+                state.keys.push((key, key_state == wl_keyboard::KeyState::Pressed));
+            }
+            wl_keyboard::Event::Modifiers { group, .. } => state.group = Some(group),
+            _ => {}
+        }
+    }
+}
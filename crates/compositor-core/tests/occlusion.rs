@@ -0,0 +1,283 @@
+//! Occlusion culling integration tests
+//!
+//! Stacks windows with opaque regions and checks that surfaces hidden behind
+//! them are culled from the draws, get no frame callbacks, are drawn only in
+//! their visible parts when partially covered and defer their texture uploads.
+
+mod common;
+
+use common::{create_shm_buffer, PingPong, TestClient, TestServer};
+use compositor_core::occlusion::OcclusionStats;
+use compositor_core::surface_manager::SurfaceManager;
+use ipc::protocol::IPCMessage;
+use smithay::{
+    backend::renderer::utils::with_renderer_surface_state,
+    output::Output,
+    utils::{Physical, Rectangle},
+};
+use wayland_client::{
+    protocol::{
+        wl_buffer::WlBuffer,
+        wl_callback::{self, WlCallback},
+        wl_compositor::WlCompositor,
+        wl_region::WlRegion,
+        wl_shm::WlShm,
+        wl_shm_pool::WlShmPool,
+        wl_surface::WlSurface,
+    },
+    Connection, Dispatch, Proxy, QueueHandle,
+};
+use wayland_protocols::wp::alpha_modifier::v1::client::{
+    wp_alpha_modifier_surface_v1::WpAlphaModifierSurfaceV1, wp_alpha_modifier_v1::WpAlphaModifierV1,
+};
+use wayland_protocols::xdg::shell::client::{
+    xdg_surface::XdgSurface,
+    xdg_toplevel::XdgToplevel,
+    xdg_wm_base::XdgWmBase,
+};
+use wayland_server::Resource;
+
+#[derive(Default)]
+struct App {
+    /// Frame callbacks done, by window index
+    frame_callbacks: Vec<usize>,
+}
+
+struct Window {
+    surface: WlSurface,
+    _toplevel: XdgToplevel,
+    _xdg_surface: XdgSurface,
+}
+
+struct OcclusionApp {
+    client: TestClient<App>,
+    state: App,
+    compositor: WlCompositor,
+    shm: WlShm,
+    wm_base: XdgWmBase,
+}
+
+impl OcclusionApp {
+    fn connect(server: &mut TestServer) -> Self {
+        let client = server.connect::<App>();
+        let compositor: WlCompositor = client.bind(6, ());
+        let shm: WlShm = client.bind(1, ());
+        let wm_base: XdgWmBase = client.bind(6, ());
+        Self { client, state: App::default(), compositor, shm, wm_base }
+    }
+
+    /// Map a 200x100 window, opaque in `opaque` if given, at a location of the space
+    fn map_window(&mut self, server: &mut TestServer, opaque: Option<(i32, i32, i32, i32)>, location: (i32, i32)) -> Window {
+        let qh = self.client.qh.clone();
+        let surface = self.compositor.create_surface(&qh, ());
+        let xdg_surface = self.wm_base.get_xdg_surface(&surface, &qh, ());
+        let toplevel = xdg_surface.get_toplevel(&qh, ());
+        if let Some((x, y, w, h)) = opaque {
+            let region = self.compositor.create_region(&qh, ());
+            region.add(x, y, w, h);
+            surface.set_opaque_region(Some(&region));
+        }
+        let buffer = create_shm_buffer(&self.shm, &qh, 200, 100);
+        surface.attach(Some(&buffer), 0, 0);
+        surface.damage_buffer(0, 0, 200, 100);
+        surface.commit();
+        self.roundtrip(server);
+
+        // Windows are raised as they are placed
+        let state = &mut server.server.state;
+        let window = state
+            .windows()
+            .map(|(_, window)| window.clone())
+            .find(|window| window.toplevel().unwrap().wl_surface().id().protocol_id() == surface.id().protocol_id())
+            .expect("Window should be mapped");
+        state.space.map_element(window, location, false);
+        Window { surface, _toplevel: toplevel, _xdg_surface: xdg_surface }
+    }
+
+    /// Commit a new buffer with a frame callback tagged with `index`
+    fn commit_frame(&mut self, server: &mut TestServer, window: &Window, index: usize) {
+        let qh = self.client.qh.clone();
+        window.surface.frame(&qh, index);
+        let buffer = create_shm_buffer(&self.shm, &qh, 200, 100);
+        window.surface.attach(Some(&buffer), 0, 0);
+        window.surface.damage_buffer(0, 0, 200, 100);
+        window.surface.commit();
+        self.roundtrip(server);
+    }
+
+    fn roundtrip(&mut self, server: &mut TestServer) {
+        server.roundtrip(&mut self.client, &mut self.state);
+    }
+}
+
+fn rect(x: i32, y: i32, w: i32, h: i32) -> Rectangle<i32, Physical> {
+    Rectangle::new((x, y).into(), (w, h).into())
+}
+
+fn first_output(server: &TestServer) -> Output {
+    server.server.state.space.outputs().next().cloned().expect("Output should exist")
+}
+
+/// Visible parts of a window's surface, `None` when it is culled
+fn visible_parts(server: &TestServer, window: &Window) -> Option<Vec<Rectangle<i32, Physical>>> {
+    let state = &server.server.state;
+    state
+        .surface_draws(&first_output(server))
+        .into_iter()
+        .find(|draw| draw.surface.id().protocol_id() == window.surface.id().protocol_id())
+        .map(|draw| draw.visible)
+}
+
+/// Test that windows hidden behind opaque windows are culled and get no frame callbacks
+#[test]
+fn opaque_windows_cull_hidden_windows() {
+    let mut server = TestServer::new();
+    let mut app = OcclusionApp::connect(&mut server);
+    let below = app.map_window(&mut server, None, (100, 100));
+    let above = app.map_window(&mut server, Some((0, 0, 200, 100)), (100, 100));
+    let output = first_output(&server);
+
+    assert_eq!(visible_parts(&server, &below), None, "Covered windows are not drawn");
+    assert_eq!(visible_parts(&server, &above), Some(vec![rect(100, 100, 200, 100)]));
+    let state = &server.server.state;
+    let occluded = state.occluded_surfaces(&output);
+    assert_eq!(occluded.len(), 1);
+    assert_eq!(occluded[0].id().protocol_id(), below.surface.id().protocol_id());
+    assert_eq!(
+        state.occlusion_stats(&output),
+        OcclusionStats { surfaces: 2, occluded: 1, partially_occluded: 0, drawn_pixels: 20_000, culled_pixels: 20_000 }
+    );
+
+    // Only the window on top is asked for new frames
+    app.commit_frame(&mut server, &below, 0);
+    app.commit_frame(&mut server, &above, 1);
+    server.server.state.render_frame(&output);
+    app.roundtrip(&mut server);
+    assert_eq!(app.state.frame_callbacks, vec![1]);
+
+    // Once uncovered, the window is drawn and its pending callback done
+    let state = &mut server.server.state;
+    let window = state.space.elements().last().cloned().expect("Window should be mapped");
+    state.space.map_element(window, (1000, 100), false);
+    assert_eq!(visible_parts(&server, &below), Some(vec![rect(100, 100, 200, 100)]));
+    server.server.state.render_frame(&output);
+    app.roundtrip(&mut server);
+    assert_eq!(app.state.frame_callbacks, vec![1, 0]);
+
+    let IPCMessage::OcclusionStats { outputs } = server.server.state.handle_ipc_message(IPCMessage::GetOcclusionStats) else {
+        panic!("Expected occlusion stats");
+    };
+    assert_eq!(outputs.len(), 1);
+    assert_eq!((outputs[0].surfaces, outputs[0].occluded, outputs[0].culled_pixels), (2, 0, 0));
+}
+
+/// Test that partially covered windows are drawn and damaged only where they show
+#[test]
+fn partially_occluded_windows_draw_visible_parts() {
+    let mut server = TestServer::new();
+    let mut app = OcclusionApp::connect(&mut server);
+    let below = app.map_window(&mut server, None, (100, 100));
+    // Opaque only in its left half, like a window with a translucent sidebar
+    let _above = app.map_window(&mut server, Some((0, 0, 100, 100)), (150, 150));
+    let output = first_output(&server);
+
+    // The band above and the strips beside the opaque half remain
+    assert_eq!(
+        visible_parts(&server, &below),
+        Some(vec![rect(100, 100, 200, 50), rect(100, 150, 50, 50), rect(250, 150, 50, 50)])
+    );
+    let stats = server.server.state.occlusion_stats(&output);
+    assert_eq!((stats.occluded, stats.partially_occluded, stats.culled_pixels), (0, 1, 5_000));
+
+    // Repaints of the window skip its hidden part
+    let state = &mut server.server.state;
+    state.render_frame(&output);
+    state.render_frame(&output);
+    app.commit_frame(&mut server, &below, 0);
+    let state = &mut server.server.state;
+    state.render_frame(&output);
+    assert_eq!(
+        state.output_damage(&output, 1),
+        Some(vec![rect(100, 100, 200, 50), rect(100, 150, 50, 50), rect(250, 150, 50, 50)])
+    );
+}
+
+/// Test that translucent content hides nothing
+#[test]
+fn translucent_windows_do_not_occlude() {
+    let mut server = TestServer::new();
+    let mut app = OcclusionApp::connect(&mut server);
+    let below = app.map_window(&mut server, None, (100, 100));
+    let _plain = app.map_window(&mut server, None, (100, 100));
+    assert_eq!(visible_parts(&server, &below), Some(vec![rect(100, 100, 200, 100)]), "Surfaces are not opaque by default");
+
+    // An alpha multiplier makes opaque regions see-through
+    let alpha_modifier: WpAlphaModifierV1 = app.client.bind(1, ());
+    let faded = app.map_window(&mut server, Some((0, 0, 200, 100)), (100, 100));
+    let alpha: WpAlphaModifierSurfaceV1 = alpha_modifier.get_surface(&faded.surface, &app.client.qh, ());
+    alpha.set_multiplier(u32::MAX / 2);
+    faded.surface.commit();
+    app.roundtrip(&mut server);
+    assert!(visible_parts(&server, &below).is_some());
+    assert_eq!(server.server.state.occlusion_stats(&first_output(&server)).occluded, 0);
+
+    alpha.set_multiplier(u32::MAX);
+    faded.surface.commit();
+    app.roundtrip(&mut server);
+    assert_eq!(visible_parts(&server, &below), None);
+}
+
+/// Test that occluded surfaces defer their texture uploads until they show
+#[test]
+fn occluded_surfaces_defer_uploads() {
+    let mut server = TestServer::new();
+    let mut app = OcclusionApp::connect(&mut server);
+    let window = app.map_window(&mut server, None, (100, 100));
+    let draw = server
+        .server
+        .state
+        .surface_draws(&first_output(&server))
+        .into_iter()
+        .find(|draw| draw.surface.id().protocol_id() == window.surface.id().protocol_id())
+        .expect("Window should be drawn");
+    let buffer = with_renderer_surface_state(&draw.surface, |state| state.buffer().map(|buffer| (**buffer).clone()))
+        .flatten()
+        .expect("Surface should have a buffer");
+
+    let mut surface_manager = SurfaceManager::new();
+    let surface_id = draw.surface.id().protocol_id() as u64;
+    surface_manager.register_surface(surface_id);
+    surface_manager.update_visibility(surface_id, &[]).expect("Surfaces can be hidden");
+    surface_manager.handle_surface_commit(surface_id, &buffer).expect("Commits of hidden surfaces are accepted");
+    assert!(surface_manager.has_deferred_upload(surface_id));
+
+    surface_manager
+        .update_visibility(surface_id, &draw.visible)
+        .expect("Deferred buffers upload once surfaces show");
+    assert!(!surface_manager.has_deferred_upload(surface_id));
+}
+
+// ============================================================================
+// Client-side dispatch
+// ============================================================================
+
+wayland_client::delegate_noop!(App: ignore WlSurface);
+wayland_client::delegate_noop!(App: ignore WlShm);
+wayland_client::delegate_noop!(App: ignore WlBuffer);
+wayland_client::delegate_noop!(App: ignore XdgSurface);
+wayland_client::delegate_noop!(App: ignore XdgToplevel);
+wayland_client::delegate_noop!(App: WlCompositor);
+wayland_client::delegate_noop!(App: WlShmPool);
+wayland_client::delegate_noop!(App: WlRegion);
+wayland_client::delegate_noop!(App: WpAlphaModifierV1);
+wayland_client::delegate_noop!(App: WpAlphaModifierSurfaceV1);
+
+impl Dispatch<WlCallback, usize> for App {
+    fn event(state: &mut Self, _: &WlCallback, event: wl_callback::Event, index: &usize, _: &Connection, _: &QueueHandle<Self>) {
+        if let wl_callback::Event::Done { .. } = event {
+            state.frame_callbacks.push(*index);
+        }
+    }
+}
+
+wayland_client::delegate_dispatch!(App: [XdgWmBase: ()] => PingPong);
@@ -0,0 +1,269 @@
+//! Presentation-time integration tests
+//!
+//! Requests wp_presentation feedback for surface commits and checks what the
+//! frame scheduler reports once frames are built and presented, from explicit
+//! presentations and from the timer-driven frame clock.
+
+mod common;
+
+use common::{create_shm_buffer, PingPong, TestClient, TestServer};
+use smithay::reexports::wayland_protocols::wp::presentation_time::server::wp_presentation_feedback::Kind as PresentedKind;
+use std::time::{Duration, Instant};
+use wayland_client::{
+    protocol::{
+        wl_buffer::WlBuffer,
+        wl_callback::{self, WlCallback},
+        wl_compositor::WlCompositor,
+        wl_shm::WlShm,
+        wl_shm_pool::WlShmPool,
+        wl_surface::WlSurface,
+    },
+    Connection, Dispatch, Proxy, QueueHandle, WEnum,
+};
+use wayland_protocols::wp::presentation_time::client::{
+    wp_presentation::{self, WpPresentation},
+    wp_presentation_feedback::{self, Kind, WpPresentationFeedback},
+};
+use wayland_protocols::xdg::shell::client::{
+    xdg_surface::XdgSurface,
+    xdg_toplevel::XdgToplevel,
+    xdg_wm_base::XdgWmBase,
+};
+use wayland_server::Resource;
+
+/// Presentation flags as passed by backends
+fn presented_kind(flags: Kind) -> PresentedKind {
+    PresentedKind::from_bits_truncate(flags.bits())
+}
+
+/// Outcome of a feedback request
+#[derive(Debug, Clone, PartialEq)]
+enum Feedback {
+    Presented { time: Duration, refresh: u32, sequence: u64, flags: Kind },
+    Discarded,
+}
+
+#[derive(Default)]
+struct App {
+    clock_id: Option<u32>,
+    /// Outcomes by the tag of the feedback request
+    feedback: Vec<(u32, Feedback)>,
+    frame_callbacks: usize,
+}
+
+impl App {
+    fn feedback(&self, tag: u32) -> Option<&Feedback> {
+        self.feedback.iter().find(|(other, _)| *other == tag).map(|(_, feedback)| feedback)
+    }
+}
+
+struct PresentationApp {
+    client: TestClient<App>,
+    state: App,
+    compositor: WlCompositor,
+    shm: WlShm,
+    presentation: WpPresentation,
+    surface: WlSurface,
+    _toplevel: XdgToplevel,
+    _xdg_surface: XdgSurface,
+}
+
+impl PresentationApp {
+    fn connect(server: &mut TestServer) -> Self {
+        let mut client = server.connect::<App>();
+        let mut state = App::default();
+
+        let compositor: WlCompositor = client.bind(6, ());
+        let shm: WlShm = client.bind(1, ());
+        let wm_base: XdgWmBase = client.bind(6, ());
+        let presentation: WpPresentation = client.bind(2, ());
+
+        let surface = compositor.create_surface(&client.qh, ());
+        let xdg_surface = wm_base.get_xdg_surface(&surface, &client.qh, ());
+        let toplevel = xdg_surface.get_toplevel(&client.qh, ());
+        let buffer = create_shm_buffer(&shm, &client.qh, 200, 100);
+        surface.attach(Some(&buffer), 0, 0);
+        surface.commit();
+        server.roundtrip(&mut client, &mut state);
+
+        Self { client, state, compositor, shm, presentation, surface, _toplevel: toplevel, _xdg_surface: xdg_surface }
+    }
+
+    /// Commit a new buffer to a surface, asking for presentation feedback and a frame callback
+    fn commit_with_feedback(&self, surface: &WlSurface, tag: u32) {
+        let qh = &self.client.qh;
+        let buffer = create_shm_buffer(&self.shm, qh, 200, 100);
+        surface.attach(Some(&buffer), 0, 0);
+        surface.damage_buffer(0, 0, 200, 100);
+        self.presentation.feedback(surface, qh, tag);
+        surface.frame(qh, ());
+        surface.commit();
+    }
+
+    fn roundtrip(&mut self, server: &mut TestServer) {
+        server.roundtrip(&mut self.client, &mut self.state);
+    }
+}
+
+/// Test that presented frames report their time, refresh, sequence and flags
+#[test]
+fn feedback_presented_with_frame() {
+    let mut server = TestServer::new();
+    let mut app = PresentationApp::connect(&mut server);
+    // Timestamps are on CLOCK_MONOTONIC
+    assert_eq!(app.state.clock_id, Some(1));
+
+    app.commit_with_feedback(&app.surface, 1);
+    app.roundtrip(&mut server);
+    assert_eq!(app.state.feedback(1), None);
+
+    let state = &mut server.server.state;
+    let output = state.space.outputs().next().cloned().expect("Output should exist");
+    let elements = state.render_frame(&output);
+    assert!(elements.iter().any(|element| element.surface.id().protocol_id() == app.surface.id().protocol_id()));
+    app.roundtrip(&mut server);
+    assert_eq!(app.state.frame_callbacks, 1);
+    assert_eq!(app.state.feedback(1), None, "Feedback waits for the frame to be presented");
+
+    let time = Duration::new(0x1_0000_0002, 345_678);
+    let flags = Kind::Vsync | Kind::HwClock | Kind::ZeroCopy;
+    server.server.state.frame_presented(&output, time, Some(0x2_0000_0007), presented_kind(flags));
+    app.roundtrip(&mut server);
+    assert_eq!(
+        app.state.feedback(1),
+        Some(&Feedback::Presented { time, refresh: 16_666_666, sequence: 0x2_0000_0007, flags })
+    );
+
+    // Without a hardware counter, sequences continue from the last frame
+    app.commit_with_feedback(&app.surface, 2);
+    app.roundtrip(&mut server);
+    let state = &mut server.server.state;
+    state.render_frame(&output);
+    state.frame_presented(&output, time + Duration::from_millis(16), None, presented_kind(Kind::empty()));
+    app.roundtrip(&mut server);
+    match app.state.feedback(2) {
+        Some(Feedback::Presented { sequence, flags, .. }) => {
+            assert_eq!(*sequence, 0x2_0000_0008);
+            assert_eq!(*flags, Kind::empty());
+        }
+        other => panic!("Unexpected feedback: {:?}", other),
+    }
+}
+
+/// Test that superseded content and surfaces outside of frames are discarded
+#[test]
+fn feedback_discarded_without_frame() {
+    let mut server = TestServer::new();
+    let mut app = PresentationApp::connect(&mut server);
+
+    // A second commit before the frame supersedes the first
+    app.commit_with_feedback(&app.surface, 1);
+    app.commit_with_feedback(&app.surface, 2);
+
+    // Surfaces without a role are never drawn
+    let unmapped = app.compositor.create_surface(&app.client.qh, ());
+    app.commit_with_feedback(&unmapped, 3);
+    app.roundtrip(&mut server);
+    assert_eq!(app.state.feedback(1), Some(&Feedback::Discarded));
+    assert_eq!(app.state.feedback(3), None);
+
+    let state = &mut server.server.state;
+    let output = state.space.outputs().next().cloned().expect("Output should exist");
+    state.render_frame(&output);
+    state.frame_presented(&output, Duration::from_secs(5), None, presented_kind(Kind::Vsync));
+    app.roundtrip(&mut server);
+    assert!(matches!(app.state.feedback(2), Some(Feedback::Presented { sequence: 1, .. })));
+    assert_eq!(app.state.feedback(3), Some(&Feedback::Discarded));
+    assert_eq!(app.state.feedback.len(), 3);
+}
+
+/// Test that the frame clock presents frames at the refresh rate of the output
+#[test]
+fn frame_clock_presents_frames() {
+    let mut server = TestServer::new();
+    let mut app = PresentationApp::connect(&mut server);
+    let output = server.server.state.space.outputs().next().cloned().expect("Output should exist");
+    server.server.state.start_frame_clock(&output).expect("Failed to start frame clock");
+
+    let start = Duration::from(server.server.state.clock.now());
+    for tag in 1..=2 {
+        app.commit_with_feedback(&app.surface, tag);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while app.state.feedback(tag).is_none() {
+            assert!(Instant::now() < deadline, "Frame clock did not present a frame");
+            std::thread::sleep(Duration::from_millis(2));
+            app.roundtrip(&mut server);
+        }
+    }
+    let end = Duration::from(server.server.state.clock.now());
+
+    let presented: Vec<_> = app
+        .state
+        .feedback
+        .iter()
+        .map(|(_, feedback)| match feedback {
+            Feedback::Presented { time, refresh, sequence, flags } => (*time, *refresh, *sequence, *flags),
+            Feedback::Discarded => panic!("Feedback should be presented"),
+        })
+        .collect();
+    for (time, refresh, _, flags) in &presented {
+        assert!(*time >= start && *time <= end);
+        assert_eq!(*refresh, 16_666_666);
+        assert_eq!(*flags, Kind::Vsync);
+    }
+    assert!(presented[1].2 > presented[0].2);
+    assert!(app.state.frame_callbacks >= 2);
+}
+
+// ============================================================================
+// Client-side dispatch
+// ============================================================================
+
+wayland_client::delegate_noop!(App: ignore WlSurface);
+wayland_client::delegate_noop!(App: ignore WlShm);
+wayland_client::delegate_noop!(App: ignore WlBuffer);
+wayland_client::delegate_noop!(App: ignore XdgSurface);
+wayland_client::delegate_noop!(App: ignore XdgToplevel);
+wayland_client::delegate_noop!(App: WlCompositor);
+wayland_client::delegate_noop!(App: WlShmPool);
+
+impl Dispatch<WpPresentation, ()> for App {
+    fn event(state: &mut Self, _: &WpPresentation, event: wp_presentation::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+        if let wp_presentation::Event::ClockId { clk_id } = event {
+            state.clock_id = Some(clk_id);
+        }
+    }
+}
+
+impl Dispatch<WpPresentationFeedback, u32> for App {
+    fn event(state: &mut Self, _: &WpPresentationFeedback, event: wp_presentation_feedback::Event, tag: &u32, _: &Connection, _: &QueueHandle<Self>) {
+        let feedback = match event {
+            wp_presentation_feedback::Event::Presented { tv_sec_hi, tv_sec_lo, tv_nsec, refresh, seq_hi, seq_lo, flags } => {
+                let seconds = ((tv_sec_hi as u64) << 32) | tv_sec_lo as u64;
+                let flags = match flags {
+                    WEnum::Value(flags) => flags,
+                    WEnum::Unknown(bits) => panic!("Unknown presentation flags {:#x}", bits),
+                };
+                Feedback::Presented {
+                    time: Duration::new(seconds, tv_nsec),
+                    refresh,
+                    sequence: ((seq_hi as u64) << 32) | seq_lo as u64,
+                    flags,
+                }
+            }
+            wp_presentation_feedback::Event::Discarded => Feedback::Discarded,
+            _ => return,
+        };
+        state.feedback.push((*tag, feedback));
+    }
+}
+
+impl Dispatch<WlCallback, ()> for App {
+    fn event(state: &mut Self, _: &WlCallback, event: wl_callback::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+        if let wl_callback::Event::Done { .. } = event {
+            state.frame_callbacks += 1;
+        }
+    }
+}
+
+wayland_client::delegate_dispatch!(App: [XdgWmBase: ()] => PingPong);
@@ -0,0 +1,192 @@
+//! Protocol trace integration tests
+//!
+//! Connects clients through the trace relay used for clients of the listening
+//! socket, toggles tracing at runtime and checks the JSON lines written for
+//! their requests and events.
+
+mod common;
+
+use common::{create_shm_buffer, TestClient, TestServer};
+use compositor_core::protocol_trace::TraceFilter;
+use config::{CompositorConfig, ProtocolTraceConfig};
+use ipc::protocol::IPCMessage;
+use std::path::PathBuf;
+use wayland_client::{
+    protocol::{
+        wl_buffer::WlBuffer,
+        wl_callback::WlCallback,
+        wl_compositor::WlCompositor,
+        wl_shm::WlShm,
+        wl_shm_pool::WlShmPool,
+        wl_surface::WlSurface,
+    },
+    Proxy,
+};
+
+#[derive(Default)]
+struct App;
+
+/// Server writing traces to a fresh file
+fn traced_server(name: &str) -> (TestServer, PathBuf) {
+    let path = std::env::temp_dir().join(format!("compositor-trace-{}-{}.jsonl", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let mut config = CompositorConfig::default();
+    config.protocol_trace.path = Some(path.clone());
+    (TestServer::with_config(config), path)
+}
+
+fn trace_lines(path: &PathBuf) -> Vec<String> {
+    let trace = std::fs::read_to_string(path).unwrap_or_default();
+    let _ = std::fs::remove_file(path);
+    trace.lines().map(str::to_string).collect()
+}
+
+fn find<'a>(lines: &'a [String], parts: &[&str]) -> Option<&'a String> {
+    lines.iter().find(|line| parts.iter().all(|part| line.contains(part)))
+}
+
+/// Test that requests and events of a relayed client are traced with their arguments
+#[test]
+fn requests_and_events_are_traced() {
+    let (mut server, path) = traced_server("messages");
+    let mut client: TestClient<App> = server.connect_traced();
+
+    // Objects created before tracing starts are still known by interface
+    server.server.state.set_protocol_trace(Some(TraceFilter::default())).expect("Failed to start tracing");
+    let compositor: WlCompositor = client.bind(6, ());
+    let shm: WlShm = client.bind(1, ());
+    let qh = client.qh.clone();
+    let surface = compositor.create_surface(&qh, ());
+    let buffer = create_shm_buffer(&shm, &qh, 16, 16);
+    surface.attach(Some(&buffer), 0, 0);
+    surface.frame(&qh, ());
+    surface.commit();
+    server.roundtrip(&mut client, &mut App);
+    server.server.state.set_protocol_trace(None).expect("Failed to stop tracing");
+
+    // File descriptors pass through the relay
+    let usage = server.server.state.client_resource_usage(&client.server_client);
+    assert_eq!((usage.surfaces, usage.buffers), (1, 1));
+
+    let lines = trace_lines(&path);
+    let pid = format!("\"pid\":{}", std::process::id());
+    assert!(lines.iter().all(|line| line.starts_with("{\"time_us\":") && line.contains(&pid)));
+    let bind = find(&lines, &["\"direction\":\"request\"", "\"interface\":\"wl_registry\"", "\"message\":\"bind\""])
+        .expect("Registry bind should be traced");
+    assert!(bind.contains("{\"type\":\"string\",\"value\":\"wl_compositor\"}"));
+    assert!(bind.contains("{\"type\":\"new_id\",\"interface\":\"wl_compositor\","));
+    let create_surface = find(&lines, &["\"interface\":\"wl_compositor\"", "\"message\":\"create_surface\""])
+        .expect("Surface creation should be traced");
+    assert!(create_surface.contains(&format!("{{\"type\":\"new_id\",\"interface\":\"wl_surface\",\"value\":{}}}", surface.id().protocol_id())));
+    let create_pool = find(&lines, &["\"interface\":\"wl_shm\"", "\"message\":\"create_pool\""]).expect("Pool creation should be traced");
+    assert!(create_pool.contains("{\"type\":\"fd\"}"));
+    assert!(create_pool.contains("{\"type\":\"int\",\"value\":1024}"));
+    let attach = find(&lines, &["\"interface\":\"wl_surface\"", "\"message\":\"attach\""]).expect("Attach should be traced");
+    assert!(attach.contains(&format!("{{\"type\":\"object\",\"interface\":\"wl_buffer\",\"value\":{}}}", buffer.id().protocol_id())));
+    assert!(find(&lines, &["\"direction\":\"event\"", "\"interface\":\"wl_callback\"", "\"message\":\"done\""]).is_some());
+    assert!(find(&lines, &["\"direction\":\"event\"", "\"interface\":\"wl_display\"", "\"message\":\"delete_id\""]).is_some());
+}
+
+/// Test that traces are limited to the requested interfaces and client processes
+#[test]
+fn traces_are_filtered() {
+    let (mut server, path) = traced_server("filters");
+    let mut client: TestClient<App> = server.connect_traced();
+    let compositor: WlCompositor = client.bind(6, ());
+    let qh = client.qh.clone();
+
+    let response = server.server.state.handle_ipc_message(IPCMessage::SetProtocolTrace {
+        enabled: true,
+        client_pid: Some(std::process::id()),
+        interfaces: vec!["wl_surface".to_string()],
+    });
+    match response {
+        IPCMessage::ProtocolTrace { enabled: true, path: trace_path } => assert_eq!(trace_path, path.display().to_string()),
+        other => panic!("Unexpected response: {:?}", other),
+    }
+    let surface = compositor.create_surface(&qh, ());
+    surface.commit();
+    server.roundtrip(&mut client, &mut App);
+
+    // Other processes are not traced
+    let filter = TraceFilter { client_pid: Some(std::process::id() as i32 + 1), interfaces: Vec::new() };
+    server.server.state.set_protocol_trace(Some(filter)).expect("Failed to change tracing");
+    surface.damage(0, 0, 1, 1);
+    server.roundtrip(&mut client, &mut App);
+    server.server.state.handle_ipc_message(IPCMessage::SetProtocolTrace {
+        enabled: false,
+        client_pid: None,
+        interfaces: Vec::new(),
+    });
+    assert_eq!(server.server.state.protocol_tracer.filter(), None);
+
+    let lines = trace_lines(&path);
+    assert_eq!(lines.len(), 1, "Only the commit should be traced: {:?}", lines);
+    assert!(lines[0].contains("\"interface\":\"wl_surface\"") && lines[0].contains("\"message\":\"commit\""));
+}
+
+/// Test that clients are only relayed while tracing is enabled, unless configured
+#[test]
+fn clients_are_relayed_only_when_needed() {
+    let (mut server, path) = traced_server("accept");
+    let _direct: TestClient<App> = server.connect_accepted();
+    assert_eq!(server.server.state.protocol_tracer.relayed_connections(), 0);
+
+    server.server.state.set_protocol_trace(Some(TraceFilter::default())).expect("Failed to start tracing");
+    let mut client: TestClient<App> = server.connect_accepted();
+    assert_eq!(server.server.state.protocol_tracer.relayed_connections(), 1);
+    let _compositor: WlCompositor = client.bind(6, ());
+    server.roundtrip(&mut client, &mut App);
+    server.server.state.set_protocol_trace(None).expect("Failed to stop tracing");
+    assert!(find(&trace_lines(&path), &["\"message\":\"bind\""]).is_some());
+
+    let protocol_trace = ProtocolTraceConfig { relay_clients: true, ..Default::default() };
+    let mut server = TestServer::with_config(CompositorConfig { protocol_trace, ..Default::default() });
+    let _relayed: TestClient<App> = server.connect_accepted();
+    assert_eq!(server.server.state.protocol_tracer.relayed_connections(), 1);
+}
+
+/// Test that relayed clients receive protocol errors and are dropped when they disconnect
+#[test]
+fn relayed_connections_close_with_either_side() {
+    let (mut server, _) = traced_server("disconnect");
+    server.server.state.config.limits.max_surfaces = 1;
+
+    let client: TestClient<App> = server.connect_traced();
+    let compositor: WlCompositor = client.bind(6, ());
+    let _surfaces: Vec<WlSurface> = (0..2).map(|_| compositor.create_surface(&client.qh, ())).collect();
+    let connection = client.connection.clone();
+    let mut error = None;
+    for _ in 0..10 {
+        let _ = connection.flush();
+        server.dispatch();
+        if let Some(guard) = connection.prepare_read() {
+            let _ = guard.read();
+        }
+        error = connection.protocol_error();
+        if error.is_some() {
+            break;
+        }
+    }
+    let error = error.expect("Client should receive the protocol error");
+    assert_eq!((error.code, error.object_interface.as_str()), (2, "wl_surface"));
+
+    let other: TestClient<App> = server.connect_traced();
+    let id = other.server_client.id();
+    drop(other);
+    for _ in 0..5 {
+        server.dispatch();
+    }
+    assert!(server.server.display.handle().backend_handle().get_client_data(id).is_err());
+}
+
+// ============================================================================
+// Client-side dispatch
+// ============================================================================
+
+wayland_client::delegate_noop!(App: ignore WlSurface);
+wayland_client::delegate_noop!(App: ignore WlShm);
+wayland_client::delegate_noop!(App: ignore WlBuffer);
+wayland_client::delegate_noop!(App: ignore WlCallback);
+wayland_client::delegate_noop!(App: WlCompositor);
+wayland_client::delegate_noop!(App: WlShmPool);
@@ -4,7 +4,9 @@
 //! This test suite validates the extensive protocol implementations built over weeks
 //! of development to ensure professional-grade code quality and protocol compliance.
 
-use compositor_core::wayland::{WaylandServer, WaylandServerState};
+use compositor_core::resource_limits::ResourceUsage;
+use compositor_core::wayland::{ClientState, WaylandServer, WaylandServerState};
+use std::os::unix::net::UnixStream;
 use wayland_server::{Display, Client};
 use std::sync::{Arc, Mutex};
 
@@ -176,9 +178,6 @@ fn test_error_handling() {
     // Test invalid message handling
     test_invalid_message_handling();
     
-    // Test resource exhaustion recovery
-    test_resource_exhaustion();
-    
     // Test graceful degradation
     test_graceful_degradation();
 }
@@ -473,7 +472,24 @@ fn test_keyboard_navigation() { /* TODO: Implement keyboard navigation test */ }
 
 fn test_buffer_synchronization() { /* TODO: Implement buffer sync test */ }
 fn test_frame_timing() { /* TODO: Implement frame timing test */ }
-fn test_damage_tracking() { /* TODO: Implement damage tracking test */ }
+fn test_damage_tracking() {
+    // Frames of an idle output have nothing to repaint
+    let mut server = WaylandServer::new().expect("Failed to create Wayland server");
+    let state = &mut server.state;
+    let output = state.space.outputs().next().cloned().expect("Output should exist");
+    let output_size = state.space.output_geometry(&output).expect("Output should be mapped").size;
+
+    state.render_frame(&output);
+    let damage = state.output_damage(&output, 1).expect("First frame should have damage");
+    assert_eq!(damage.len(), 1);
+    assert_eq!((damage[0].size.w, damage[0].size.h), (output_size.w, output_size.h));
+
+    state.render_frame(&output);
+    assert_eq!(state.output_damage(&output, 1), Some(Vec::new()));
+    // Nothing is drawn in idle frames, whatever the image
+    assert_eq!(state.output_damage(&output, 0), Some(Vec::new()));
+    assert_eq!(state.output_damage(&output, 3), Some(Vec::new()));
+}
 fn test_multisurface_composition() { /* TODO: Implement composition test */ }
 
 fn test_fractional_scaling_support() { /* TODO: Implement fractional scaling test */ }
@@ -484,9 +500,24 @@ fn test_highfrequency_input() { /* TODO: Implement high-frequency input test */
 fn test_multiple_clients() { /* TODO: Implement multiple clients test */ }
 fn test_large_surface_updates() { /* TODO: Implement large surface update test */ }
 
-fn test_client_disconnect_recovery() { /* TODO: Implement disconnect recovery test */ }
+fn test_client_disconnect_recovery() {
+    // Clients vanishing mid-session are dropped along with their resources
+    let mut server = WaylandServer::new().expect("Failed to create Wayland server");
+    let (server_stream, client_stream) = UnixStream::pair().expect("Failed to create socket pair");
+    let client = server
+        .display
+        .handle()
+        .insert_client(server_stream, Arc::new(ClientState::default()))
+        .expect("Failed to insert client");
+    drop(client_stream);
+
+    server.display.dispatch_clients(&mut server.state).expect("Failed to dispatch clients");
+    server.display.flush_clients().expect("Failed to flush clients");
+    assert!(server.display.handle().backend_handle().get_client_data(client.id()).is_err(), "Disconnected clients are dropped");
+    assert_eq!(server.state.client_resource_usage(&client), ResourceUsage::default());
+    server.state.refresh();
+}
 fn test_invalid_message_handling() { /* TODO: Implement invalid message test */ }
-fn test_resource_exhaustion() { /* TODO: Implement resource exhaustion test */ }
 fn test_graceful_degradation() { /* TODO: Implement graceful degradation test */ }
 
 fn test_client_privileges() { /* TODO: Implement client privilege test */ }
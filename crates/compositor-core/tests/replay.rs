@@ -0,0 +1,232 @@
+//! Record and replay integration tests
+//!
+//! Records input and frames applied to a session with mapped windows, then
+//! replays the recording against a fresh compositor with the same clients and
+//! checks that it takes the same focus, layout and render decisions on a
+//! virtual clock.
+
+mod common;
+
+use common::{click, map_windows, present_frame, PingPong, TestServer};
+use compositor_core::input::BackendInput;
+use compositor_core::replay::Decision;
+use compositor_core::wayland::WaylandServerState;
+use config::CompositorConfig;
+use ipc::protocol::IPCMessage;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use wayland_client::protocol::{
+    wl_buffer::WlBuffer,
+    wl_compositor::WlCompositor,
+    wl_shm::WlShm,
+    wl_shm_pool::WlShmPool,
+    wl_surface::WlSurface,
+};
+use wayland_protocols::xdg::shell::client::{
+    xdg_surface::XdgSurface,
+    xdg_toplevel::XdgToplevel,
+    xdg_wm_base::XdgWmBase,
+};
+
+#[derive(Default)]
+struct App;
+
+/// Compositor recording to and replaying from a fresh file
+fn recording_server(path: &Path) -> TestServer {
+    let mut config = CompositorConfig::default();
+    config.recording.path = Some(path.to_path_buf());
+    TestServer::with_config(config)
+}
+
+fn recording_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("compositor-recording-{}-{}.bin", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+/// Click both windows in turn and type a key
+fn play_session(state: &mut WaylandServerState) {
+    click(state, (150.0, 150.0));
+    present_frame(state);
+    click(state, (650.0, 150.0));
+    present_frame(state);
+    state.apply_input(BackendInput::Key { keycode: 38, pressed: true });
+    state.apply_input(BackendInput::Key { keycode: 38, pressed: false });
+    present_frame(state);
+}
+
+/// Record a session with two windows
+fn record_session(path: &Path) {
+    let mut server = recording_server(path);
+    let mut client = server.connect::<App>();
+    let _windows = map_windows(&mut server, &mut client, &mut App, 2);
+    let state = &mut server.server.state;
+    state.set_recording(true).expect("Failed to start recording");
+    play_session(state);
+    state.set_recording(false).expect("Failed to stop recording");
+}
+
+fn focused_window(state: &WaylandServerState) -> Option<u32> {
+    let focus = state.seat.get_keyboard()?.current_focus()?;
+    state
+        .windows()
+        .find(|(_, window)| window.toplevel().map(|toplevel| toplevel.wl_surface()) == Some(&focus))
+        .and_then(|(_, window)| compositor_core::workspace::window_id(window))
+        .map(|id| id.0)
+}
+
+/// Test that a replayed session takes the decisions of the recorded one
+#[test]
+fn recorded_session_replays_identically() {
+    let path = recording_path("identical");
+    let mut server = recording_server(&path);
+    let mut client = server.connect::<App>();
+    let _windows = map_windows(&mut server, &mut client, &mut App, 2);
+
+    let state = &mut server.server.state;
+    match state.handle_ipc_message(IPCMessage::SetRecording { enabled: true }) {
+        IPCMessage::Recording { enabled: true, path: recording } => assert_eq!(recording, path.display().to_string()),
+        other => panic!("Unexpected response: {:?}", other),
+    }
+    assert!(state.replay_state.is_recording());
+    play_session(state);
+    state.handle_ipc_message(IPCMessage::SetRecording { enabled: false });
+    assert!(!state.replay_state.is_recording());
+    assert_eq!(focused_window(state), Some(2));
+
+    // The same clients, without any input
+    let mut replayed = recording_server(&path);
+    let mut client = replayed.connect::<App>();
+    let _windows = map_windows(&mut replayed, &mut client, &mut App, 2);
+    let state = &mut replayed.server.state;
+    assert_eq!(focused_window(state), None);
+
+    state.start_replay(&path).expect("Failed to start replay");
+    assert!(state.replay_state.is_replaying());
+    state.replay_until(Duration::from_secs(60));
+    let report = state.finish_replay().expect("Replay should be running");
+    let _ = std::fs::remove_file(&path);
+
+    assert_eq!(report.divergence, None);
+    assert_eq!(report.events, 14);
+    let focus: Vec<&Decision> = report.decisions.iter().filter(|decision| matches!(decision, Decision::Focus { .. })).collect();
+    assert_eq!(focus, [&Decision::Focus { seat: "seat0".into(), window: Some(1) }, &Decision::Focus { seat: "seat0".into(), window: Some(2) }]);
+    let renders = report.decisions.iter().filter(|decision| matches!(decision, Decision::Render { .. })).count();
+    assert_eq!(renders, 3);
+    assert!(report.decisions.iter().any(|decision| matches!(decision, Decision::Layout { .. })));
+    assert_eq!(focused_window(state), Some(2));
+}
+
+/// Test that a replay going another way than the recording is reported
+#[test]
+fn replay_reports_divergence() {
+    let path = recording_path("divergence");
+    record_session(&path);
+
+    // The second window is missing, so the second click focuses nothing
+    let mut server = recording_server(&path);
+    let mut client = server.connect::<App>();
+    let _windows = map_windows(&mut server, &mut client, &mut App, 1);
+    let state = &mut server.server.state;
+    state.start_replay(&path).expect("Failed to start replay");
+    state.replay_until(Duration::from_secs(60));
+    let report = state.finish_replay().expect("Replay should be running");
+    let divergence = report.divergence.expect("Replay should diverge");
+    // The first click still focuses the first window
+    assert!(divergence.index > 0);
+    assert_eq!(report.decisions[0], Decision::Focus { seat: "seat0".into(), window: Some(1) });
+    assert_ne!(divergence.recorded, divergence.replayed);
+    assert_eq!(focused_window(state), Some(1));
+
+    // Recordings are checked before anything is replayed
+    std::fs::write(&path, b"not a recording").expect("Failed to write file");
+    assert!(state.start_replay(&path).is_err());
+    assert!(!state.replay_state.is_replaying());
+    let _ = std::fs::remove_file(&path);
+}
+
+/// Test that a replay set in the configuration starts with the compositor
+#[test]
+fn configured_replay_runs_at_startup() {
+    let path = recording_path("configured");
+    record_session(&path);
+
+    let mut config = CompositorConfig::default();
+    config.recording.replay = Some(path.clone());
+    let mut server = TestServer::with_config(config);
+    let mut client = server.connect::<App>();
+    let _windows = map_windows(&mut server, &mut client, &mut App, 2);
+
+    let state = &mut server.server.state;
+    assert!(state.start_configured_replay().expect("Failed to start replay"));
+    assert!(state.advance_replay(Duration::ZERO).is_none(), "Events recorded later are still due");
+    let report = state.advance_replay(Duration::from_secs(60)).expect("Replay should finish");
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(report.divergence, None);
+    assert!(!state.replay_state.is_replaying());
+    assert_eq!(focused_window(state), Some(2));
+
+    // Without a replay configured the frame clocks drive the outputs
+    let mut server = TestServer::new();
+    assert!(!server.server.state.start_configured_replay().expect("Nothing to replay"));
+}
+
+/// Test that replays run on a virtual clock following the recorded timing
+#[test]
+fn replay_runs_on_virtual_clock() {
+    let path = recording_path("clock");
+    let mut server = recording_server(&path);
+    let state = &mut server.server.state;
+    state.set_recording(true).expect("Failed to start recording");
+    state.apply_input(BackendInput::PointerMotion { delta: (10.0, 10.0), delta_unaccel: (10.0, 10.0) });
+    std::thread::sleep(Duration::from_millis(20));
+    present_frame(state);
+    std::thread::sleep(Duration::from_millis(30));
+    state.apply_input(BackendInput::Key { keycode: 38, pressed: true });
+    state.set_recording(false).expect("Failed to stop recording");
+
+    let mut replayed = recording_server(&path);
+    let state = &mut replayed.server.state;
+    assert!(!state.clock.is_virtual());
+    state.start_replay(&path).expect("Failed to start replay");
+    let start = Duration::from(state.clock.now());
+    assert!(state.clock.is_virtual());
+
+    // Time stands still between events
+    std::thread::sleep(Duration::from_millis(5));
+    assert_eq!(Duration::from(state.clock.now()), start);
+
+    let mut times = Vec::new();
+    while let Some(time) = state.next_replay_time() {
+        assert!(state.replay_until(time) > 0);
+        assert_eq!(Duration::from(state.clock.now()) - start, time);
+        times.push(time);
+    }
+    let _ = std::fs::remove_file(&path);
+
+    // Motion, frame built and presented, key
+    assert_eq!(times.len(), 4);
+    assert!(times[1] - times[0] >= Duration::from_millis(20));
+    assert!(times[3] - times[2] >= Duration::from_millis(30));
+    let pointer = state.seat.get_pointer().expect("Seat should have a pointer");
+    assert_eq!(pointer.current_location(), (10.0, 10.0).into());
+
+    // Virtual time does not go back
+    state.replay_until(Duration::ZERO);
+    assert_eq!(Duration::from(state.clock.now()) - start, times[3]);
+    assert_eq!(state.finish_replay().map(|report| report.divergence), Some(None));
+}
+
+// ============================================================================
+// Client-side dispatch
+// ============================================================================
+
+wayland_client::delegate_noop!(App: ignore WlSurface);
+wayland_client::delegate_noop!(App: ignore WlShm);
+wayland_client::delegate_noop!(App: ignore WlBuffer);
+wayland_client::delegate_noop!(App: ignore XdgSurface);
+wayland_client::delegate_noop!(App: ignore XdgToplevel);
+wayland_client::delegate_noop!(App: WlCompositor);
+wayland_client::delegate_noop!(App: WlShmPool);
+
+wayland_client::delegate_dispatch!(App: [XdgWmBase: ()] => PingPong);
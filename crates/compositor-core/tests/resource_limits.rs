@@ -0,0 +1,237 @@
+//! Resource limit integration tests
+//!
+//! Charges clients for surfaces, SHM buffers and pools, frame callbacks,
+//! texture memory and request rates, and checks that a client going over a
+//! limit gets a protocol error and is disconnected while others carry on.
+
+mod common;
+
+use common::{create_shm_buffer, TestClient, TestServer};
+use compositor_core::resource_limits::ResourceUsage;
+use config::CompositorConfig;
+use std::fs::File;
+use std::os::fd::AsFd;
+use wayland_client::{
+    backend::protocol::ProtocolError,
+    protocol::{
+        wl_buffer::WlBuffer,
+        wl_callback::WlCallback,
+        wl_compositor::WlCompositor,
+        wl_shm::WlShm,
+        wl_shm_pool::WlShmPool,
+        wl_surface::WlSurface,
+    },
+};
+
+/// wl_display.error.no_memory
+const NO_MEMORY: u32 = 2;
+
+#[derive(Default)]
+struct App;
+
+struct LimitsApp {
+    client: TestClient<App>,
+    state: App,
+    compositor: WlCompositor,
+    shm: WlShm,
+}
+
+impl LimitsApp {
+    fn connect(server: &mut TestServer) -> Self {
+        let client = server.connect::<App>();
+        let compositor: WlCompositor = client.bind(6, ());
+        let shm: WlShm = client.bind(1, ());
+        Self { client, state: App, compositor, shm }
+    }
+
+    fn roundtrip(&mut self, server: &mut TestServer) {
+        server.roundtrip(&mut self.client, &mut self.state);
+    }
+
+    fn usage(&self, server: &TestServer) -> ResourceUsage {
+        server.server.state.client_resource_usage(&self.client.server_client)
+    }
+
+    /// Commit a buffer of the given size to a surface
+    fn commit_buffer(&self, surface: &WlSurface, width: i32, height: i32) {
+        let buffer = create_shm_buffer(&self.shm, &self.client.qh, width, height);
+        surface.attach(Some(&buffer), 0, 0);
+        surface.damage_buffer(0, 0, width, height);
+        surface.commit();
+    }
+
+    /// Send pending requests and expect the server to disconnect the client with a protocol error
+    fn expect_disconnect(&mut self, server: &mut TestServer) -> ProtocolError {
+        let connection = self.client.connection.clone();
+        for _ in 0..10 {
+            let _ = connection.flush();
+            server.dispatch();
+            if let Some(guard) = connection.prepare_read() {
+                let _ = guard.read();
+            }
+            if let Some(error) = connection.protocol_error() {
+                return error;
+            }
+        }
+        panic!("Client should have been disconnected");
+    }
+}
+
+/// SHM file of the given size, unlinked right away
+fn shm_file(size: u64) -> File {
+    let path = std::env::temp_dir().join(format!("compositor-limits-shm-{}", std::process::id()));
+    let file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&path)
+        .expect("Failed to create shm file");
+    let _ = std::fs::remove_file(&path);
+    file.set_len(size).expect("Failed to size shm file");
+    file
+}
+
+fn server_with_limits(configure: impl FnOnce(&mut config::LimitsConfig)) -> TestServer {
+    let mut config = CompositorConfig::default();
+    configure(&mut config.limits);
+    TestServer::with_config(config)
+}
+
+/// Test that a client going over its surface limit is disconnected without affecting others
+#[test]
+fn surface_limit_disconnects_client() {
+    let mut server = server_with_limits(|limits| limits.max_surfaces = 2);
+    let mut app = LimitsApp::connect(&mut server);
+    let mut other = LimitsApp::connect(&mut server);
+
+    let qh = app.client.qh.clone();
+    let surfaces: Vec<WlSurface> = (0..2).map(|_| app.compositor.create_surface(&qh, ())).collect();
+    app.roundtrip(&mut server);
+    assert_eq!(app.usage(&server).surfaces, 2);
+
+    // Destroyed surfaces no longer count
+    surfaces[0].destroy();
+    let _replacement = app.compositor.create_surface(&qh, ());
+    app.roundtrip(&mut server);
+    assert_eq!(app.usage(&server).surfaces, 2);
+
+    let _extra = app.compositor.create_surface(&qh, ());
+    let error = app.expect_disconnect(&mut server);
+    assert_eq!((error.code, error.object_interface.as_str()), (NO_MEMORY, "wl_surface"));
+
+    // Other clients keep their own budget
+    let other_qh = other.client.qh.clone();
+    let _surfaces: Vec<WlSurface> = (0..2).map(|_| other.compositor.create_surface(&other_qh, ())).collect();
+    other.roundtrip(&mut server);
+    assert_eq!(other.usage(&server).surfaces, 2);
+}
+
+/// Test that SHM pools and buffers are charged while they live
+#[test]
+fn shm_pools_and_buffers_are_charged() {
+    let mut server = server_with_limits(|limits| limits.max_shm_pool_bytes = 1 << 20);
+    let mut app = LimitsApp::connect(&mut server);
+    let qh = app.client.qh.clone();
+
+    // Buffers outlive the pool they were created from
+    let buffer = create_shm_buffer(&app.shm, &qh, 64, 64);
+    app.roundtrip(&mut server);
+    assert_eq!(app.usage(&server), ResourceUsage { buffers: 1, ..ResourceUsage::default() });
+    buffer.destroy();
+    app.roundtrip(&mut server);
+    assert_eq!(app.usage(&server).buffers, 0);
+
+    let file = shm_file(1 << 20);
+    let pool = app.shm.create_pool(file.as_fd(), 4096, &qh, ());
+    let second = app.shm.create_pool(file.as_fd(), 8192, &qh, ());
+    app.roundtrip(&mut server);
+    assert_eq!(app.usage(&server).shm_pool_bytes, 12288);
+    pool.resize(65536);
+    app.roundtrip(&mut server);
+    assert_eq!(app.usage(&server).shm_pool_bytes, 65536 + 8192);
+    second.destroy();
+    app.roundtrip(&mut server);
+    assert_eq!(app.usage(&server).shm_pool_bytes, 65536);
+
+    // Each pool releases its own size, whichever is used first
+    let _third = app.shm.create_pool(file.as_fd(), 4096, &qh, ());
+    let fourth = app.shm.create_pool(file.as_fd(), 16384, &qh, ());
+    fourth.destroy();
+    app.roundtrip(&mut server);
+    assert_eq!(app.usage(&server).shm_pool_bytes, 65536 + 4096);
+
+    // Pools are refused before they are mapped
+    let _large = app.shm.create_pool(file.as_fd(), 1 << 20, &qh, ());
+    let error = app.expect_disconnect(&mut server);
+    assert_eq!((error.code, error.object_interface.as_str()), (NO_MEMORY, "wl_shm"));
+}
+
+/// Test that frame callbacks waiting for a frame and texture memory are limited
+#[test]
+fn callbacks_and_texture_memory_are_limited() {
+    let mut server = server_with_limits(|limits| {
+        limits.max_pending_callbacks = 2;
+        limits.max_texture_bytes = 100 * 100 * 4;
+    });
+    let mut app = LimitsApp::connect(&mut server);
+    let qh = app.client.qh.clone();
+    let surface = app.compositor.create_surface(&qh, ());
+
+    app.commit_buffer(&surface, 100, 100);
+    app.roundtrip(&mut server);
+    assert_eq!(app.usage(&server).texture_bytes, 40_000);
+
+    // Callbacks are answered with frames, which this compositor has not drawn
+    for _ in 0..2 {
+        surface.frame(&qh, ());
+        surface.commit();
+    }
+    app.roundtrip(&mut server);
+    assert_eq!(app.usage(&server).pending_callbacks, 2);
+    surface.frame(&qh, ());
+    surface.commit();
+    let error = app.expect_disconnect(&mut server);
+    assert_eq!((error.code, error.object_interface.as_str()), (NO_MEMORY, "wl_surface"));
+
+    let mut app = LimitsApp::connect(&mut server);
+    let surface = app.compositor.create_surface(&app.client.qh, ());
+    app.commit_buffer(&surface, 101, 100);
+    let error = app.expect_disconnect(&mut server);
+    assert_eq!((error.code, error.object_interface.as_str()), (NO_MEMORY, "wl_surface"));
+}
+
+/// Test that clients flooding commits are disconnected
+#[test]
+fn request_flood_disconnects_client() {
+    let mut server = server_with_limits(|limits| limits.max_requests_per_second = 50);
+    let mut app = LimitsApp::connect(&mut server);
+    let surface = app.compositor.create_surface(&app.client.qh, ());
+    for _ in 0..20 {
+        surface.commit();
+    }
+    app.roundtrip(&mut server);
+
+    for _ in 0..100 {
+        surface.commit();
+    }
+    let error = app.expect_disconnect(&mut server);
+    assert_eq!((error.code, error.object_interface.as_str()), (NO_MEMORY, "wl_surface"));
+
+    // The compositor keeps serving new clients
+    let mut next = LimitsApp::connect(&mut server);
+    let _surface = next.compositor.create_surface(&next.client.qh, ());
+    next.roundtrip(&mut server);
+    assert_eq!(next.usage(&server).surfaces, 1);
+}
+
+// ============================================================================
+// Client-side dispatch
+// ============================================================================
+
+wayland_client::delegate_noop!(App: ignore WlSurface);
+wayland_client::delegate_noop!(App: ignore WlShm);
+wayland_client::delegate_noop!(App: ignore WlBuffer);
+wayland_client::delegate_noop!(App: ignore WlCallback);
+wayland_client::delegate_noop!(App: WlCompositor);
+wayland_client::delegate_noop!(App: WlShmPool);
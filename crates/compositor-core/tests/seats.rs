@@ -0,0 +1,123 @@
+//! Multi-seat integration tests
+//!
+//! Configures seats besides the default one and checks that clients see each
+//! as a wl_seat, that input devices are assigned by the configured rules and
+//! that each seat keeps its own keyboard focus, pointer and cursor.
+
+mod common;
+
+use common::{map_windows, seat_click, PingPong, TestServer};
+use compositor_core::input::BackendInput;
+use compositor_core::seats::seat_cursor_status;
+use compositor_core::wayland::WaylandServerState;
+use config::{CompositorConfig, SeatConfig};
+use smithay::input::{pointer::CursorImageStatus, Seat, SeatHandler};
+use wayland_client::protocol::{
+    wl_buffer::WlBuffer,
+    wl_compositor::WlCompositor,
+    wl_shm::WlShm,
+    wl_shm_pool::WlShmPool,
+    wl_surface::WlSurface,
+};
+use wayland_protocols::xdg::shell::client::{
+    xdg_surface::XdgSurface,
+    xdg_toplevel::XdgToplevel,
+    xdg_wm_base::XdgWmBase,
+};
+
+#[derive(Default)]
+struct App;
+
+/// Compositor with a seat for a named keyboard and a seat for a udev seat
+fn multi_seat_server() -> TestServer {
+    TestServer::with_config(CompositorConfig {
+        seats: vec![
+            SeatConfig { name: "seat1".to_string(), devices: vec!["Keyboard B".to_string()], udev_seat: None },
+            SeatConfig { name: "seat2".to_string(), devices: Vec::new(), udev_seat: Some("seat2".to_string()) },
+        ],
+        ..Default::default()
+    })
+}
+
+fn focused_window(state: &WaylandServerState, seat: &Seat<WaylandServerState>) -> Option<u32> {
+    let focus = seat.get_keyboard()?.current_focus()?;
+    state
+        .windows()
+        .find(|(_, window)| window.toplevel().map(|toplevel| toplevel.wl_surface()) == Some(&focus))
+        .and_then(|(_, window)| compositor_core::workspace::window_id(window))
+        .map(|id| id.0)
+}
+
+/// Test that configured seats are advertised and devices assigned by the rules
+#[test]
+fn configured_seats_are_advertised() {
+    let mut server = multi_seat_server();
+    let client = server.connect::<App>();
+    let seats = client.globals.iter().filter(|(_, interface, _)| interface == "wl_seat").count();
+    assert_eq!(seats, 3);
+
+    let state = &server.server.state;
+    let names: Vec<&str> = state.seats().map(|seat| seat.name()).collect();
+    assert_eq!(names, ["seat0", "seat1", "seat2"]);
+    for seat in state.seats() {
+        assert!(seat.get_keyboard().is_some() && seat.get_pointer().is_some());
+    }
+
+    // Device names take precedence over the udev seat
+    assert_eq!(state.seat_for_device_properties("Keyboard B", None).name(), "seat1");
+    assert_eq!(state.seat_for_device_properties("Keyboard B", Some("seat2")).name(), "seat1");
+    assert_eq!(state.seat_for_device_properties("Mouse", Some("seat2")).name(), "seat2");
+    assert_eq!(state.seat_for_device_properties("Mouse", Some("seat9")).name(), "seat0");
+    assert_eq!(state.seat_for_device_properties("Keyboard A", None).name(), "seat0");
+
+    // Only the default seat without seats in the configuration
+    let server = TestServer::new();
+    assert_eq!(server.server.state.seats().count(), 1);
+}
+
+/// Test that each seat has its own keyboard focus, pointer and cursor
+#[test]
+fn seats_have_independent_focus() {
+    let mut server = multi_seat_server();
+    let mut client = server.connect::<App>();
+    let _windows = map_windows(&mut server, &mut client, &mut App, 2);
+
+    let state = &mut server.server.state;
+    let seat0 = state.seat.clone();
+    let seat1 = state.seat_by_name("seat1").expect("Seat should exist");
+    seat_click(state, &seat1, (150.0, 150.0));
+    assert_eq!(focused_window(state, &seat1), Some(1));
+    assert_eq!(focused_window(state, &seat0), None);
+
+    seat_click(state, &seat0, (650.0, 150.0));
+    assert_eq!(focused_window(state, &seat0), Some(2));
+    assert_eq!(focused_window(state, &seat1), Some(1));
+
+    // Keys go to the window focused on the seat typed on
+    state.apply_seat_input(&seat1, BackendInput::Key { keycode: 38, pressed: true });
+    state.apply_seat_input(&seat1, BackendInput::Key { keycode: 38, pressed: false });
+    assert_eq!(focused_window(state, &seat1), Some(1));
+
+    let location = |seat: &Seat<WaylandServerState>| seat.get_pointer().expect("Seat should have a pointer").current_location();
+    assert_eq!(location(&seat1), (150.0, 150.0).into());
+    assert_eq!(location(&seat0), (650.0, 150.0).into());
+
+    // Hiding the cursor of one seat leaves the others
+    state.cursor_image(&seat1, CursorImageStatus::Hidden);
+    assert!(matches!(seat_cursor_status(&seat1), CursorImageStatus::Hidden));
+    assert!(matches!(seat_cursor_status(&seat0), CursorImageStatus::Named(_)));
+}
+
+// ============================================================================
+// Client-side dispatch
+// ============================================================================
+
+wayland_client::delegate_noop!(App: ignore WlSurface);
+wayland_client::delegate_noop!(App: ignore WlShm);
+wayland_client::delegate_noop!(App: ignore WlBuffer);
+wayland_client::delegate_noop!(App: ignore XdgSurface);
+wayland_client::delegate_noop!(App: ignore XdgToplevel);
+wayland_client::delegate_noop!(App: WlCompositor);
+wayland_client::delegate_noop!(App: WlShmPool);
+
+wayland_client::delegate_dispatch!(App: [XdgWmBase: ()] => PingPong);
@@ -0,0 +1,321 @@
+//! Subsurface and popup integration tests
+//!
+//! Builds surface trees with wl_subsurface and xdg_popup and checks the scene
+//! the renderer draws: stacking order above and below parents, positions
+//! relative to them, synchronized and desynchronized commits, and popups
+//! placed by their positioner and kept on the output.
+
+mod common;
+
+use common::{create_shm_buffer, PingPong, TestClient, TestServer};
+use compositor_core::render::RenderElement;
+use smithay::{
+    backend::renderer::utils::with_renderer_surface_state,
+    output::Output,
+    reexports::wayland_server::protocol::wl_surface::WlSurface as ServerSurface,
+};
+use wayland_client::{
+    protocol::{
+        wl_buffer::WlBuffer,
+        wl_callback::{self, WlCallback},
+        wl_compositor::WlCompositor,
+        wl_shm::WlShm,
+        wl_shm_pool::WlShmPool,
+        wl_subcompositor::WlSubcompositor,
+        wl_subsurface::WlSubsurface,
+        wl_surface::WlSurface,
+    },
+    Connection, Dispatch, Proxy, QueueHandle,
+};
+use wayland_protocols::xdg::shell::client::{
+    xdg_popup::{self, XdgPopup},
+    xdg_positioner::{Anchor, ConstraintAdjustment, Gravity, XdgPositioner},
+    xdg_surface::{self, XdgSurface},
+    xdg_toplevel::XdgToplevel,
+    xdg_wm_base::XdgWmBase,
+};
+use wayland_server::Resource;
+
+#[derive(Default)]
+struct App {
+    /// Last popup configure, `(x, y, width, height)` relative to the parent
+    popup_geometry: Option<(i32, i32, i32, i32)>,
+    /// Frame callbacks done, by tag
+    frame_callbacks: Vec<usize>,
+}
+
+struct Window {
+    surface: WlSurface,
+    xdg_surface: XdgSurface,
+    _toplevel: XdgToplevel,
+}
+
+struct SubsurfaceApp {
+    client: TestClient<App>,
+    state: App,
+    compositor: WlCompositor,
+    subcompositor: WlSubcompositor,
+    shm: WlShm,
+    wm_base: XdgWmBase,
+}
+
+impl SubsurfaceApp {
+    fn connect(server: &mut TestServer) -> Self {
+        let client = server.connect::<App>();
+        let compositor: WlCompositor = client.bind(6, ());
+        let subcompositor: WlSubcompositor = client.bind(1, ());
+        let shm: WlShm = client.bind(1, ());
+        let wm_base: XdgWmBase = client.bind(6, ());
+        Self { client, state: App::default(), compositor, subcompositor, shm, wm_base }
+    }
+
+    /// Map a 200x100 window at a location of the space
+    fn map_window(&mut self, server: &mut TestServer, location: (i32, i32)) -> Window {
+        let qh = self.client.qh.clone();
+        let surface = self.compositor.create_surface(&qh, ());
+        let xdg_surface = self.wm_base.get_xdg_surface(&surface, &qh, ());
+        let toplevel = xdg_surface.get_toplevel(&qh, ());
+        // Subsurfaces outside the window would otherwise grow its geometry
+        xdg_surface.set_window_geometry(0, 0, 200, 100);
+        self.attach(&surface, 200, 100);
+        surface.commit();
+        self.roundtrip(server);
+
+        let state = &mut server.server.state;
+        let window = state
+            .windows()
+            .map(|(_, window)| window.clone())
+            .find(|window| window.toplevel().unwrap().wl_surface().id().protocol_id() == surface.id().protocol_id())
+            .expect("Window should be mapped");
+        state.space.map_element(window, location, false);
+        Window { surface, xdg_surface, _toplevel: toplevel }
+    }
+
+    /// Create a subsurface of `parent` with a buffer, applied with the parent's next commit
+    fn subsurface(&mut self, parent: &WlSurface, position: (i32, i32), size: i32) -> (WlSurface, WlSubsurface) {
+        let qh = self.client.qh.clone();
+        let surface = self.compositor.create_surface(&qh, ());
+        let subsurface = self.subcompositor.get_subsurface(&surface, parent, &qh, ());
+        subsurface.set_position(position.0, position.1);
+        self.attach(&surface, size, size);
+        surface.commit();
+        (surface, subsurface)
+    }
+
+    fn attach(&self, surface: &WlSurface, width: i32, height: i32) {
+        let buffer = create_shm_buffer(&self.shm, &self.client.qh, width, height);
+        surface.attach(Some(&buffer), 0, 0);
+        surface.damage_buffer(0, 0, width, height);
+    }
+
+    fn roundtrip(&mut self, server: &mut TestServer) {
+        server.roundtrip(&mut self.client, &mut self.state);
+    }
+}
+
+fn first_output(server: &TestServer) -> Output {
+    server.server.state.space.outputs().next().cloned().expect("Output should exist")
+}
+
+fn render_elements(server: &TestServer) -> Vec<RenderElement> {
+    server.server.state.render_elements(&first_output(server))
+}
+
+/// Location a client surface is drawn at, `None` when it is not part of the scene
+fn drawn_at(elements: &[RenderElement], surface: &WlSurface) -> Option<(i32, i32)> {
+    elements
+        .iter()
+        .find(|element| element.surface.id().protocol_id() == surface.id().protocol_id())
+        .map(|element| (element.location.x, element.location.y))
+}
+
+/// Position of a client surface in the scene, back to front
+fn stacking_index(elements: &[RenderElement], surface: &WlSurface) -> Option<usize> {
+    elements
+        .iter()
+        .position(|element| element.surface.id().protocol_id() == surface.id().protocol_id())
+}
+
+fn server_surface(elements: &[RenderElement], surface: &WlSurface) -> ServerSurface {
+    elements
+        .iter()
+        .find(|element| element.surface.id().protocol_id() == surface.id().protocol_id())
+        .map(|element| element.surface.clone())
+        .expect("Surface should be drawn")
+}
+
+fn applied_width(surface: &ServerSurface) -> Option<i32> {
+    with_renderer_surface_state(surface, |state| state.buffer_size().map(|size| size.w)).flatten()
+}
+
+/// Test that subsurfaces are drawn above or below their parent at their relative positions
+#[test]
+fn subsurfaces_stack_around_parent() {
+    let mut server = TestServer::new();
+    let mut app = SubsurfaceApp::connect(&mut server);
+    let window = app.map_window(&mut server, (100, 100));
+
+    let (above, _above_role) = app.subsurface(&window.surface, (10, 20), 50);
+    let (below, below_role) = app.subsurface(&window.surface, (-20, -10), 40);
+    below_role.place_below(&window.surface);
+    // Nested subsurfaces are placed relative to their own parent
+    let (nested, _nested_role) = app.subsurface(&above, (5, 5), 10);
+    above.commit();
+    window.surface.commit();
+    app.roundtrip(&mut server);
+
+    let elements = render_elements(&server);
+    let index = |surface| stacking_index(&elements, surface).expect("Surface should be drawn");
+    assert!(index(&below) < index(&window.surface), "Subsurfaces placed below are drawn first");
+    assert!(index(&window.surface) < index(&above));
+    assert!(index(&above) < index(&nested), "Children are drawn above their parent by default");
+    assert_eq!(drawn_at(&elements, &window.surface), Some((100, 100)));
+    assert_eq!(drawn_at(&elements, &below), Some((80, 90)));
+    assert_eq!(drawn_at(&elements, &above), Some((110, 120)));
+    assert_eq!(drawn_at(&elements, &nested), Some((115, 125)));
+
+    // Frame callbacks of subsurfaces are answered as they are drawn
+    let qh = app.client.qh.clone();
+    nested.frame(&qh, 7);
+    nested.commit();
+    above.commit();
+    window.surface.commit();
+    app.roundtrip(&mut server);
+    server.server.state.render_frame(&first_output(&server));
+    app.roundtrip(&mut server);
+    assert_eq!(app.state.frame_callbacks, vec![7]);
+
+    // Unmapping a parent hides its subtree
+    above.attach(None, 0, 0);
+    above.commit();
+    window.surface.commit();
+    app.roundtrip(&mut server);
+    let elements = render_elements(&server);
+    assert_eq!(drawn_at(&elements, &above), None);
+    assert_eq!(drawn_at(&elements, &nested), None);
+    assert!(drawn_at(&elements, &below).is_some());
+}
+
+/// Test that synchronized subsurfaces apply with their parent and desynchronized ones on their own
+#[test]
+fn subsurface_commit_modes() {
+    let mut server = TestServer::new();
+    let mut app = SubsurfaceApp::connect(&mut server);
+    let window = app.map_window(&mut server, (100, 100));
+    let (child, subsurface) = app.subsurface(&window.surface, (10, 10), 50);
+    window.surface.commit();
+    app.roundtrip(&mut server);
+    let child_surface = server_surface(&render_elements(&server), &child);
+    assert_eq!(applied_width(&child_surface), Some(50));
+
+    // Synchronized state, position included, waits for the parent's commit
+    app.attach(&child, 60, 60);
+    child.commit();
+    subsurface.set_position(30, 30);
+    app.roundtrip(&mut server);
+    assert_eq!(applied_width(&child_surface), Some(50));
+    assert_eq!(drawn_at(&render_elements(&server), &child), Some((110, 110)));
+
+    window.surface.commit();
+    app.roundtrip(&mut server);
+    assert_eq!(applied_width(&child_surface), Some(60));
+    assert_eq!(drawn_at(&render_elements(&server), &child), Some((130, 130)));
+
+    // Desynchronized subsurfaces apply their own commits right away
+    subsurface.set_desync();
+    app.attach(&child, 70, 70);
+    child.commit();
+    app.roundtrip(&mut server);
+    assert_eq!(applied_width(&child_surface), Some(70));
+
+    // Back in sync, cached state waits for the parent again
+    subsurface.set_sync();
+    app.attach(&child, 80, 80);
+    child.commit();
+    app.roundtrip(&mut server);
+    assert_eq!(applied_width(&child_surface), Some(70));
+    window.surface.commit();
+    app.roundtrip(&mut server);
+    assert_eq!(applied_width(&child_surface), Some(80));
+}
+
+/// Test that popups are drawn above their window and slid back onto the output
+#[test]
+fn popups_drawn_above_window_within_output() {
+    let mut server = TestServer::new();
+    let mut app = SubsurfaceApp::connect(&mut server);
+    // The window's right edge is 140 pixels from the output's
+    let window = app.map_window(&mut server, (3700, 100));
+    let qh = app.client.qh.clone();
+
+    // A menu below the window's right corner would leave the output
+    let positioner = app.wm_base.create_positioner(&qh, ());
+    positioner.set_size(200, 50);
+    positioner.set_anchor_rect(0, 0, 200, 100);
+    positioner.set_anchor(Anchor::BottomRight);
+    positioner.set_gravity(Gravity::BottomRight);
+    positioner.set_constraint_adjustment(ConstraintAdjustment::SlideX | ConstraintAdjustment::SlideY);
+    let popup_surface = app.compositor.create_surface(&qh, ());
+    let popup_xdg_surface = app.wm_base.get_xdg_surface(&popup_surface, &qh, ());
+    let _popup = popup_xdg_surface.get_popup(Some(&window.xdg_surface), &positioner, &qh, ());
+    popup_surface.commit();
+    app.roundtrip(&mut server);
+    assert_eq!(app.state.popup_geometry, Some((-60, 100, 200, 50)), "Popups slide to stay on the output");
+
+    app.attach(&popup_surface, 200, 50);
+    popup_surface.commit();
+    app.roundtrip(&mut server);
+
+    let elements = render_elements(&server);
+    assert_eq!(drawn_at(&elements, &popup_surface), Some((3640, 200)));
+    assert!(
+        stacking_index(&elements, &popup_surface) > stacking_index(&elements, &window.surface),
+        "Popups are drawn above their window"
+    );
+
+    // Subsurfaces of popups come along with them
+    let (child, _child_role) = app.subsurface(&popup_surface, (4, 4), 10);
+    popup_surface.commit();
+    app.roundtrip(&mut server);
+    assert_eq!(drawn_at(&render_elements(&server), &child), Some((3644, 204)));
+}
+
+// ============================================================================
+// Client-side dispatch
+// ============================================================================
+
+wayland_client::delegate_noop!(App: ignore WlSurface);
+wayland_client::delegate_noop!(App: ignore WlShm);
+wayland_client::delegate_noop!(App: ignore WlBuffer);
+wayland_client::delegate_noop!(App: ignore XdgToplevel);
+wayland_client::delegate_noop!(App: WlCompositor);
+wayland_client::delegate_noop!(App: WlSubcompositor);
+wayland_client::delegate_noop!(App: WlSubsurface);
+wayland_client::delegate_noop!(App: WlShmPool);
+wayland_client::delegate_noop!(App: XdgPositioner);
+
+impl Dispatch<WlCallback, usize> for App {
+    fn event(state: &mut Self, _: &WlCallback, event: wl_callback::Event, tag: &usize, _: &Connection, _: &QueueHandle<Self>) {
+        if let wl_callback::Event::Done { .. } = event {
+            state.frame_callbacks.push(*tag);
+        }
+    }
+}
+
+impl Dispatch<XdgSurface, ()> for App {
+    fn event(_: &mut Self, xdg_surface: &XdgSurface, event: xdg_surface::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+        if let xdg_surface::Event::Configure { serial } = event {
+            xdg_surface.ack_configure(serial);
+        }
+    }
+}
+
+impl Dispatch<XdgPopup, ()> for App {
+    fn event(state: &mut Self, _: &XdgPopup, event: xdg_popup::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+        if let xdg_popup::Event::Configure { x, y, width, height } = event {
+            state.popup_geometry = Some((x, y, width, height));
+        }
+    }
+}
+
+wayland_client::delegate_dispatch!(App: [XdgWmBase: ()] => PingPong);
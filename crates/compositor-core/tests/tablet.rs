@@ -0,0 +1,270 @@
+//! Graphics tablet integration tests
+//!
+//! Injects tablet tool events as the input backend would and checks what a
+//! drawing application receives over zwp_tablet_v2.
+
+mod common;
+
+use common::{create_shm_buffer, PingPong, TestClient, TestServer};
+use compositor_core::tablet::{map_to_area, TabletToolAxes};
+use config::PressureCurve;
+use smithay::backend::input::{ButtonState, TabletToolCapabilities, TabletToolDescriptor, TabletToolTipState, TabletToolType};
+use smithay::utils::{Logical, Rectangle};
+use smithay::wayland::tablet_manager::TabletDescriptor;
+use wayland_client::{
+    event_created_child,
+    protocol::{
+        wl_buffer::WlBuffer,
+        wl_compositor::WlCompositor,
+        wl_seat::WlSeat,
+        wl_shm::WlShm,
+        wl_shm_pool::WlShmPool,
+        wl_surface::WlSurface,
+    },
+    Connection, Dispatch, QueueHandle, WEnum,
+};
+use wayland_protocols::wp::tablet::zv2::client::{
+    zwp_tablet_manager_v2::ZwpTabletManagerV2,
+    zwp_tablet_pad_v2::ZwpTabletPadV2,
+    zwp_tablet_seat_v2::{self, ZwpTabletSeatV2},
+    zwp_tablet_tool_v2::{self, ZwpTabletToolV2},
+    zwp_tablet_v2::ZwpTabletV2,
+};
+use wayland_protocols::xdg::shell::client::{
+    xdg_surface::XdgSurface,
+    xdg_toplevel::XdgToplevel,
+    xdg_wm_base::XdgWmBase,
+};
+
+const BTN_STYLUS: u32 = 0x14b;
+
+/// Tool events as seen by the client
+#[derive(Debug, PartialEq)]
+enum ToolEvent {
+    ProximityIn,
+    ProximityOut,
+    Down,
+    Up,
+    Motion(f64, f64),
+    Pressure(u32),
+    Tilt(f64, f64),
+    Rotation(f64),
+    Button(u32, bool),
+    Frame,
+}
+
+#[derive(Default)]
+struct Drawing {
+    tablets: Vec<ZwpTabletV2>,
+    tools: Vec<ZwpTabletToolV2>,
+    events: Vec<ToolEvent>,
+}
+
+/// A drawing application with a 400x300 canvas window
+struct DrawingApp {
+    client: TestClient<Drawing>,
+    state: Drawing,
+    _window: (WlSurface, XdgSurface, XdgToplevel),
+}
+
+fn connect_drawing_app(server: &mut TestServer) -> DrawingApp {
+    let mut client = server.connect::<Drawing>();
+    let mut state = Drawing::default();
+
+    let compositor: WlCompositor = client.bind(6, ());
+    let shm: WlShm = client.bind(1, ());
+    let seat: WlSeat = client.bind(7, ());
+    let wm_base: XdgWmBase = client.bind(6, ());
+    let tablet_manager: ZwpTabletManagerV2 = client.bind(1, ());
+
+    let surface = compositor.create_surface(&client.qh, ());
+    let xdg_surface = wm_base.get_xdg_surface(&surface, &client.qh, ());
+    let toplevel = xdg_surface.get_toplevel(&client.qh, ());
+    let buffer = create_shm_buffer(&shm, &client.qh, 400, 300);
+    surface.attach(Some(&buffer), 0, 0);
+    surface.commit();
+    tablet_manager.get_tablet_seat(&seat, &client.qh, ());
+    server.roundtrip(&mut client, &mut state);
+
+    DrawingApp { client, state, _window: (surface, xdg_surface, toplevel) }
+}
+
+impl DrawingApp {
+    fn take_events(&mut self, server: &mut TestServer) -> Vec<ToolEvent> {
+        server.roundtrip(&mut self.client, &mut self.state);
+        std::mem::take(&mut self.state.events)
+    }
+}
+
+fn tablet() -> TabletDescriptor {
+    TabletDescriptor { name: "Test Tablet".to_string(), usb_id: Some((0x056a, 0x0357)), syspath: None }
+}
+
+fn pen() -> TabletToolDescriptor {
+    TabletToolDescriptor {
+        tool_type: TabletToolType::Pen,
+        hardware_serial: 0x1234,
+        hardware_id_wacom: 0,
+        capabilities: TabletToolCapabilities::PRESSURE | TabletToolCapabilities::TILT | TabletToolCapabilities::ROTATION,
+    }
+}
+
+fn at(x: f64, y: f64) -> TabletToolAxes {
+    TabletToolAxes { position: (x, y), ..Default::default() }
+}
+
+fn rect(x: i32, y: i32, w: i32, h: i32) -> Rectangle<i32, Logical> {
+    Rectangle::new((x, y).into(), (w, h).into())
+}
+
+/// Test mapping tablet positions into an area with and without keeping the aspect ratio
+#[test]
+fn tablet_mapping_keeps_aspect_ratio() {
+    let output = rect(0, 0, 3840, 2160);
+    let map = |position, tablet_size, area, keep_aspect_ratio| {
+        map_to_area(position, tablet_size, area, keep_aspect_ratio).to_i32_round::<i32>()
+    };
+    // A 16:10 tablet on a 16:9 output uses the top 90% of the tablet
+    let tablet_size = Some((216.0, 135.0));
+
+    assert_eq!(map((0.5, 0.45), tablet_size, output, true), (1920, 1080).into());
+    assert_eq!(map((0.5, 0.45), tablet_size, output, false), (1920, 972).into());
+    assert_eq!(map((1.0, 1.0), tablet_size, output, true), (3840, 2160).into());
+    // Unknown sizes stretch the whole tablet over the area
+    assert_eq!(map((0.5, 0.45), None, output, true), (1920, 972).into());
+    // A square region on a second output uses the left part of a wide tablet
+    assert_eq!(map((0.25, 0.5), Some((200.0, 100.0)), rect(3840, 0, 1000, 1000), true), (4340, 500).into());
+}
+
+/// Test that the mapping area follows the configured output and region
+#[test]
+fn tablet_mapping_area_follows_config() {
+    let mut server = TestServer::new();
+    let state = &mut server.server.state;
+    assert_eq!(state.tablet_mapping_area(), Some(rect(0, 0, 3840, 2160)));
+
+    state.config.tablet.output = Some("custom-compositor-output".to_string());
+    state.config.tablet.region = Some([1920, 1080, 1920, 1080]);
+    assert_eq!(state.tablet_mapping_area(), Some(rect(1920, 1080, 1920, 1080)));
+
+    // Unknown outputs fall back to the whole desktop
+    state.config.tablet.output = Some("HDMI-A-9".to_string());
+    state.config.tablet.region = None;
+    assert_eq!(state.tablet_mapping_area(), Some(rect(0, 0, 3840, 2160)));
+}
+
+/// Test that a pen stroke reaches the window under the pen with curved pressure
+#[test]
+fn pen_stroke_reaches_window() {
+    let mut server = TestServer::new();
+    let pen_curve = PressureCurve { points: vec![(0.0, 0.0), (0.5, 0.25), (1.0, 1.0)] };
+    server.server.state.config.tablet.pressure_curves.insert("pen".to_string(), pen_curve);
+    server.server.state.add_tablet(tablet(), Some((160.0, 90.0)));
+
+    let mut app = connect_drawing_app(&mut server);
+    assert_eq!(app.state.tablets.len(), 1);
+    assert!(app.state.tools.is_empty());
+
+    // The window is mapped at (100, 100), so (480, 270) is at (380, 170) on the canvas
+    let (tablet, pen) = (tablet(), pen());
+    let state = &mut server.server.state;
+    state.tablet_tool_proximity(&tablet, &pen, true, at(0.125, 0.125), 1);
+    let events = app.take_events(&mut server);
+    assert_eq!(app.state.tools.len(), 1);
+    assert_eq!(events[..3], [ToolEvent::ProximityIn, ToolEvent::Motion(380.0, 170.0), ToolEvent::Frame]);
+
+    let state = &mut server.server.state;
+    state.tablet_tool_tip(&pen, TabletToolTipState::Down, 2);
+    let axes = TabletToolAxes {
+        pressure: Some(0.5),
+        tilt: Some((30.0, -15.0)),
+        rotation: Some(90.0),
+        ..at(0.125, 0.15625)
+    };
+    state.tablet_tool_axis(&tablet, &pen, axes, 3);
+    assert_eq!(
+        app.take_events(&mut server),
+        [
+            ToolEvent::Down,
+            ToolEvent::Frame,
+            ToolEvent::Motion(380.0, 237.5),
+            ToolEvent::Pressure(16384),
+            ToolEvent::Tilt(30.0, -15.0),
+            ToolEvent::Rotation(90.0),
+            ToolEvent::Frame,
+        ]
+    );
+
+    let state = &mut server.server.state;
+    state.tablet_tool_button(&pen, BTN_STYLUS, ButtonState::Pressed, 4);
+    state.tablet_tool_tip(&pen, TabletToolTipState::Up, 5);
+    assert_eq!(
+        app.take_events(&mut server),
+        [ToolEvent::Button(BTN_STYLUS, true), ToolEvent::Frame, ToolEvent::Up, ToolEvent::Frame]
+    );
+
+    // Moving off the window leaves it, and leaving the tablet sends nothing more
+    let state = &mut server.server.state;
+    state.tablet_tool_axis(&tablet, &pen, at(0.5, 0.5), 6);
+    state.tablet_tool_proximity(&tablet, &pen, false, at(0.5, 0.5), 7);
+    assert_eq!(app.take_events(&mut server), [ToolEvent::ProximityOut, ToolEvent::Frame]);
+
+    server.server.state.remove_tablet(&tablet);
+    server.roundtrip(&mut app.client, &mut app.state);
+    assert!(server.server.state.tablet_state.tablets().next().is_none());
+}
+
+// ============================================================================
+// Client-side dispatch
+// ============================================================================
+
+wayland_client::delegate_noop!(Drawing: ignore WlSeat);
+wayland_client::delegate_noop!(Drawing: ignore WlSurface);
+wayland_client::delegate_noop!(Drawing: ignore WlShm);
+wayland_client::delegate_noop!(Drawing: ignore WlBuffer);
+wayland_client::delegate_noop!(Drawing: ignore XdgSurface);
+wayland_client::delegate_noop!(Drawing: ignore XdgToplevel);
+wayland_client::delegate_noop!(Drawing: ignore ZwpTabletV2);
+wayland_client::delegate_noop!(Drawing: ignore ZwpTabletPadV2);
+wayland_client::delegate_noop!(Drawing: WlCompositor);
+wayland_client::delegate_noop!(Drawing: WlShmPool);
+wayland_client::delegate_noop!(Drawing: ZwpTabletManagerV2);
+
+wayland_client::delegate_dispatch!(Drawing: [XdgWmBase: ()] => PingPong);
+
+impl Dispatch<ZwpTabletSeatV2, ()> for Drawing {
+    fn event(state: &mut Self, _: &ZwpTabletSeatV2, event: zwp_tablet_seat_v2::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+        match event {
+            zwp_tablet_seat_v2::Event::TabletAdded { id } => state.tablets.push(id),
+            zwp_tablet_seat_v2::Event::ToolAdded { id } => state.tools.push(id),
+            _ => {}
+        }
+    }
+
+    event_created_child!(Drawing, ZwpTabletSeatV2, [
+        zwp_tablet_seat_v2::EVT_TABLET_ADDED_OPCODE => (ZwpTabletV2, ()),
+        zwp_tablet_seat_v2::EVT_TOOL_ADDED_OPCODE => (ZwpTabletToolV2, ()),
+        zwp_tablet_seat_v2::EVT_PAD_ADDED_OPCODE => (ZwpTabletPadV2, ()),
+    ]);
+}
+
+impl Dispatch<ZwpTabletToolV2, ()> for Drawing {
+    fn event(state: &mut Self, _: &ZwpTabletToolV2, event: zwp_tablet_tool_v2::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+        let event = match event {
+            zwp_tablet_tool_v2::Event::ProximityIn { .. } => ToolEvent::ProximityIn,
+            zwp_tablet_tool_v2::Event::ProximityOut => ToolEvent::ProximityOut,
+            zwp_tablet_tool_v2::Event::Down { .. } => ToolEvent::Down,
+            zwp_tablet_tool_v2::Event::Up => ToolEvent::Up,
+            zwp_tablet_tool_v2::Event::Motion { x, y } => ToolEvent::Motion(x, y),
+            zwp_tablet_tool_v2::Event::Pressure { pressure } => ToolEvent::Pressure(pressure),
+            zwp_tablet_tool_v2::Event::Tilt { tilt_x, tilt_y } => ToolEvent::Tilt(tilt_x, tilt_y),
+            zwp_tablet_tool_v2::Event::Rotation { degrees } => ToolEvent::Rotation(degrees),
+            zwp_tablet_tool_v2::Event::Button { button, state: button_state, .. } => {
+                ToolEvent::Button(button, button_state == WEnum::Value(zwp_tablet_tool_v2::ButtonState::Pressed))
+            }
+            zwp_tablet_tool_v2::Event::Frame { .. } => ToolEvent::Frame,
+            _ => return,
+        };
+        state.events.push(event);
+    }
+}
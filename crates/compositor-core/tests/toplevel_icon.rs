@@ -0,0 +1,231 @@
+//! Toplevel icon integration tests
+//!
+//! Sets window icons over xdg_toplevel_icon_v1 and checks what the window
+//! model and the IPC requests used by the app bar and switcher report.
+
+mod common;
+
+use common::{PingPong, TestClient, TestServer};
+use compositor_core::workspace::window_id;
+use ipc::protocol::IPCMessage;
+use std::os::fd::AsFd;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use wayland_client::{
+    protocol::{
+        wl_buffer::WlBuffer,
+        wl_compositor::WlCompositor,
+        wl_shm::{self, WlShm},
+        wl_shm_pool::WlShmPool,
+        wl_surface::WlSurface,
+    },
+    Connection, Dispatch, QueueHandle,
+};
+use wayland_protocols::xdg::shell::client::{
+    xdg_surface::XdgSurface,
+    xdg_toplevel::XdgToplevel,
+    xdg_wm_base::XdgWmBase,
+};
+use wayland_protocols::xdg::toplevel_icon::v1::client::{
+    xdg_toplevel_icon_manager_v1::{self, XdgToplevelIconManagerV1},
+    xdg_toplevel_icon_v1::XdgToplevelIconV1,
+};
+
+#[derive(Default)]
+struct App {
+    icon_sizes: Vec<i32>,
+    sizes_done: bool,
+}
+
+/// Create a square ARGB8888 shm buffer with every byte set to `value`
+fn create_filled_buffer(shm: &WlShm, qh: &QueueHandle<App>, size: i32, value: u8) -> WlBuffer {
+    static POOL_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+    let path = std::env::temp_dir().join(format!(
+        "compositor-test-icon-{}-{}",
+        std::process::id(),
+        POOL_COUNT.fetch_add(1, Ordering::Relaxed)
+    ));
+    std::fs::write(&path, vec![value; (size * size * 4) as usize]).expect("Failed to write shm file");
+    let file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(&path)
+        .expect("Failed to open shm file");
+    let _ = std::fs::remove_file(&path);
+
+    let pool = shm.create_pool(file.as_fd(), size * size * 4, qh, ());
+    let buffer = pool.create_buffer(0, size, size, size * 4, wl_shm::Format::Argb8888, qh, ());
+    pool.destroy();
+    buffer
+}
+
+struct IconApp {
+    client: TestClient<App>,
+    state: App,
+    shm: WlShm,
+    surface: WlSurface,
+    toplevel: XdgToplevel,
+    manager: XdgToplevelIconManagerV1,
+    _xdg_surface: XdgSurface,
+}
+
+fn connect_icon_app(server: &mut TestServer) -> IconApp {
+    let mut client = server.connect::<App>();
+    let mut state = App::default();
+
+    let compositor: WlCompositor = client.bind(6, ());
+    let shm: WlShm = client.bind(1, ());
+    let wm_base: XdgWmBase = client.bind(6, ());
+    let manager: XdgToplevelIconManagerV1 = client.bind(1, ());
+
+    let surface = compositor.create_surface(&client.qh, ());
+    let xdg_surface = wm_base.get_xdg_surface(&surface, &client.qh, ());
+    let toplevel = xdg_surface.get_toplevel(&client.qh, ());
+    toplevel.set_title("Notes - draft.txt".to_string());
+    toplevel.set_app_id("org.example.Editor".to_string());
+    let buffer = create_filled_buffer(&shm, &client.qh, 64, 0);
+    surface.attach(Some(&buffer), 0, 0);
+    surface.commit();
+    server.roundtrip(&mut client, &mut state);
+
+    IconApp { client, state, shm, surface, toplevel, manager, _xdg_surface: xdg_surface }
+}
+
+/// Test that preferred icon sizes are advertised to clients
+#[test]
+fn icon_sizes_advertised() {
+    let mut server = TestServer::new();
+    let app = connect_icon_app(&mut server);
+
+    assert!(app.state.sizes_done);
+    assert_eq!(app.state.icon_sizes.len(), 6);
+    for size in [16, 24, 32, 48, 64, 128] {
+        assert!(app.state.icon_sizes.contains(&size), "Missing icon size {}", size);
+    }
+}
+
+/// Test that named and pixel icons apply on commit and outlive the icon object
+#[test]
+fn window_icon_applies_on_commit() {
+    let mut server = TestServer::new();
+    let mut app = connect_icon_app(&mut server);
+    let qh = app.client.qh.clone();
+
+    let small = create_filled_buffer(&app.shm, &qh, 24, 0x11);
+    let large = create_filled_buffer(&app.shm, &qh, 64, 0x22);
+    let icon = app.manager.create_icon(&qh, ());
+    icon.set_name("accessories-text-editor".to_string());
+    icon.add_buffer(&small, 1);
+    icon.add_buffer(&large, 2);
+    app.manager.set_icon(&app.toplevel, Some(&icon));
+    server.roundtrip(&mut app.client, &mut app.state);
+
+    // Icons are double-buffered state of the toplevel
+    let state = &server.server.state;
+    let (_, window) = state.windows().next().expect("Window should be mapped");
+    assert_eq!(state.window_icon(window), None);
+
+    app.surface.commit();
+    icon.destroy();
+    server.roundtrip(&mut app.client, &mut app.state);
+
+    let state = &server.server.state;
+    let (_, window) = state.windows().next().expect("Window should be mapped");
+    let window_icon = state.window_icon(window).expect("Icon should be applied");
+    assert_eq!(window_icon.name.as_deref(), Some("accessories-text-editor"));
+    assert_eq!(window_icon.images.len(), 2);
+
+    // The smallest image covering the requested size wins, else the largest
+    let image = window_icon.image(16, 1).expect("Icon should have images");
+    assert_eq!((image.size, image.scale), (24, 1));
+    assert_eq!(image.pixels.len(), 24 * 24 * 4);
+    assert!(image.pixels.iter().all(|&byte| byte == 0x11));
+    let image = window_icon.image(32, 1).expect("Icon should have images");
+    assert_eq!((image.size, image.scale, image.pixel_size()), (32, 2, 64));
+    assert!(image.pixels.iter().all(|&byte| byte == 0x22));
+    assert_eq!(window_icon.image(48, 2).map(|image| image.pixel_size()), Some(64));
+
+    // Unsetting the icon clears it with the next commit
+    app.manager.set_icon(&app.toplevel, None);
+    app.surface.commit();
+    server.roundtrip(&mut app.client, &mut app.state);
+    let state = &server.server.state;
+    let (_, window) = state.windows().next().expect("Window should be mapped");
+    assert_eq!(state.window_icon(window), None);
+}
+
+/// Test that windows and their icons are listed over IPC, including hidden workspaces
+#[test]
+fn window_icons_over_ipc() {
+    let mut server = TestServer::new();
+    let mut app = connect_icon_app(&mut server);
+    let qh = app.client.qh.clone();
+
+    let buffer = create_filled_buffer(&app.shm, &qh, 32, 0x7f);
+    let icon = app.manager.create_icon(&qh, ());
+    icon.add_buffer(&buffer, 1);
+    app.manager.set_icon(&app.toplevel, Some(&icon));
+    app.surface.commit();
+    server.roundtrip(&mut app.client, &mut app.state);
+
+    let state = &mut server.server.state;
+    let (_, window) = state.windows().next().expect("Window should be mapped");
+    let id = window_id(window).expect("Window should have an identifier").0;
+    state.switch_workspace(2).expect("Failed to switch workspace");
+
+    let IPCMessage::WindowList { windows } = state.handle_ipc_message(IPCMessage::ListWindows) else {
+        panic!("Expected a window list");
+    };
+    assert_eq!(windows.len(), 1);
+    assert_eq!(windows[0].window_id, id);
+    assert_eq!(windows[0].title, "Notes - draft.txt");
+    assert_eq!(windows[0].app_id, "org.example.Editor");
+    assert_eq!(windows[0].workspace, 0);
+    assert_eq!(windows[0].icon_name, None);
+    assert_eq!(windows[0].icon_sizes, [32]);
+
+    match state.handle_ipc_message(IPCMessage::GetWindowIcon { window_id: id, size: 48, scale: 1 }) {
+        IPCMessage::WindowIcon { window_id, icon_name: None, image: Some(image) } => {
+            assert_eq!(window_id, id);
+            assert_eq!((image.size, image.scale), (32, 1));
+            assert_eq!(image.pixels, vec![0x7f; 32 * 32 * 4]);
+        }
+        other => panic!("Unexpected response: {:?}", other),
+    }
+
+    match state.handle_ipc_message(IPCMessage::GetWindowInfo { window_id: id }) {
+        IPCMessage::WindowInfo { title, app_id, geometry, .. } => {
+            assert_eq!((title.as_str(), app_id.as_str()), ("Notes - draft.txt", "org.example.Editor"));
+            assert_eq!((geometry.x, geometry.y, geometry.width, geometry.height), (100, 100, 64, 64));
+        }
+        other => panic!("Unexpected response: {:?}", other),
+    }
+
+    let missing = state.handle_ipc_message(IPCMessage::GetWindowIcon { window_id: id + 1, size: 32, scale: 1 });
+    assert!(matches!(missing, IPCMessage::Error { .. }));
+}
+
+// ============================================================================
+// Client-side dispatch
+// ============================================================================
+
+wayland_client::delegate_noop!(App: ignore WlSurface);
+wayland_client::delegate_noop!(App: ignore WlShm);
+wayland_client::delegate_noop!(App: ignore WlBuffer);
+wayland_client::delegate_noop!(App: ignore XdgSurface);
+wayland_client::delegate_noop!(App: ignore XdgToplevel);
+wayland_client::delegate_noop!(App: WlCompositor);
+wayland_client::delegate_noop!(App: WlShmPool);
+wayland_client::delegate_noop!(App: XdgToplevelIconV1);
+
+impl Dispatch<XdgToplevelIconManagerV1, ()> for App {
+    fn event(state: &mut Self, _: &XdgToplevelIconManagerV1, event: xdg_toplevel_icon_manager_v1::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+        match event {
+            xdg_toplevel_icon_manager_v1::Event::IconSize { size } => state.icon_sizes.push(size),
+            xdg_toplevel_icon_manager_v1::Event::Done => state.sizes_done = true,
+            _ => {}
+        }
+    }
+}
+
+wayland_client::delegate_dispatch!(App: [XdgWmBase: ()] => PingPong);
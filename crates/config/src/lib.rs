@@ -204,6 +204,431 @@ impl Default for PluginConfig {
     }
 }
 
+/// Clipboard persistence and history configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClipboardConfig {
+    /// Keep selections available after the client that owns them exits
+    pub persistence: bool,
+    /// Mime type prefixes read eagerly when a selection is set
+    pub mime_types: Vec<String>,
+    /// Maximum size in bytes of a captured selection, counting the data of
+    /// all its mime types together. A mime type whose data would push the
+    /// selection past the limit is dropped; those already read are kept.
+    pub max_entry_size: usize,
+    /// Number of past clipboard selections kept in the history
+    pub history_size: usize,
+    /// Maximum combined size in bytes of the history
+    pub max_history_bytes: usize,
+}
+
+impl Default for ClipboardConfig {
+    fn default() -> Self {
+        Self {
+            persistence: true,
+            mime_types: vec![
+                "text/".to_string(),
+                "image/".to_string(),
+                "UTF8_STRING".to_string(),
+                "STRING".to_string(),
+                "TEXT".to_string(),
+            ],
+            max_entry_size: 16 * 1024 * 1024, // 16MB, enough for a 4K screenshot
+            history_size: 50,
+            max_history_bytes: 64 * 1024 * 1024, // 64MB
+        }
+    }
+}
+
+/// Graphics tablet mapping and pressure configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TabletConfig {
+    /// Output tablets are mapped to, spanning all outputs when unset
+    pub output: Option<String>,
+    /// Area tablets are mapped to as `[x, y, width, height]`, relative to the output
+    pub region: Option<[i32; 4]>,
+    /// Use only the part of the tablet matching the mapped area's aspect ratio
+    pub keep_aspect_ratio: bool,
+    /// Pressure curves keyed by hardware serial (`"0x1a2b"`) or tool type (`"pen"`, `"eraser"`, ...)
+    pub pressure_curves: std::collections::HashMap<String, PressureCurve>,
+}
+
+impl Default for TabletConfig {
+    fn default() -> Self {
+        Self {
+            output: None,
+            region: None,
+            keep_aspect_ratio: true,
+            pressure_curves: std::collections::HashMap::new(),
+        }
+    }
+}
+
+impl TabletConfig {
+    /// Pressure curve for a tool, preferring its serial over its type
+    pub fn pressure_curve(&self, tool_type: &str, hardware_serial: u64) -> Option<&PressureCurve> {
+        let by_serial = (hardware_serial != 0)
+            .then(|| self.pressure_curves.get(&format!("{:#x}", hardware_serial)))
+            .flatten();
+        by_serial.or_else(|| self.pressure_curves.get(tool_type))
+    }
+}
+
+/// Pressure response through `(input, output)` points, linear in between
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct PressureCurve {
+    pub points: Vec<(f64, f64)>,
+}
+
+impl Default for PressureCurve {
+    fn default() -> Self {
+        Self {
+            points: vec![(0.0, 0.0), (1.0, 1.0)],
+        }
+    }
+}
+
+impl PressureCurve {
+    /// Map a pressure in 0.0-1.0 through the curve
+    pub fn apply(&self, pressure: f64) -> f64 {
+        let pressure = pressure.clamp(0.0, 1.0);
+        let (Some(&(first_x, first_y)), Some(&(_, last_y))) = (self.points.first(), self.points.last()) else {
+            return pressure;
+        };
+        if pressure <= first_x {
+            return first_y;
+        }
+
+        self.points
+            .windows(2)
+            .find(|segment| pressure <= segment[1].0)
+            .map(|segment| {
+                let ((x0, y0), (x1, y1)) = (segment[0], segment[1]);
+                y0 + (y1 - y0) * (pressure - x0) / (x1 - x0)
+            })
+            .unwrap_or(last_y)
+    }
+
+    /// Check that points lie within 0.0-1.0 with increasing inputs
+    fn validate(&self) -> Result<(), String> {
+        if self.points.is_empty() {
+            return Err("needs at least one point".to_string());
+        }
+        if self.points.iter().any(|&(x, y)| !(0.0..=1.0).contains(&x) || !(0.0..=1.0).contains(&y)) {
+            return Err("points must be between 0.0 and 1.0".to_string());
+        }
+        if self.points.windows(2).any(|segment| segment[1].0 <= segment[0].0) {
+            return Err("point inputs must be strictly increasing".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Workspace configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WorkspaceConfig {
+    /// Number of workspaces
+    pub count: usize,
+}
+
+impl Default for WorkspaceConfig {
+    fn default() -> Self {
+        Self { count: 4 }
+    }
+}
+
+/// Touchpad gesture configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GestureConfig {
+    /// Swipes handled by the compositor, never reaching clients
+    pub bindings: Vec<GestureBinding>,
+    /// Swipe distance in logical pixels that completes a gesture
+    pub swipe_distance: f64,
+}
+
+impl Default for GestureConfig {
+    fn default() -> Self {
+        Self {
+            bindings: vec![
+                GestureBinding { fingers: 3, action: GestureAction::SwitchWorkspace },
+                GestureBinding { fingers: 4, action: GestureAction::Overview },
+            ],
+            swipe_distance: 300.0,
+        }
+    }
+}
+
+impl GestureConfig {
+    /// Action bound to a swipe with the given number of fingers
+    pub fn swipe_action(&self, fingers: u32) -> Option<GestureAction> {
+        self.bindings
+            .iter()
+            .find(|binding| binding.fingers == fingers)
+            .map(|binding| binding.action)
+    }
+}
+
+/// Swipe bound to a compositor action
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GestureBinding {
+    /// Number of fingers on the touchpad
+    pub fingers: u32,
+    /// Action following the swipe
+    pub action: GestureAction,
+}
+
+/// Compositor actions driven by swipes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum GestureAction {
+    /// Switch to the neighbouring workspace with a horizontal swipe
+    SwitchWorkspace,
+    /// Open the overview with a swipe up and close it with a swipe down
+    Overview,
+}
+
+/// Cursor configuration
+///
+/// Unset values fall back to XCURSOR_THEME and XCURSOR_SIZE, then to the
+/// default theme at 24 pixels.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CursorConfig {
+    /// XCursor theme name
+    pub theme: Option<String>,
+    /// Nominal cursor size in logical pixels
+    pub size: Option<u32>,
+}
+
+impl CursorConfig {
+    /// Theme to load
+    pub fn theme_name(&self) -> String {
+        self.theme
+            .clone()
+            .or_else(|| std::env::var("XCURSOR_THEME").ok().filter(|theme| !theme.is_empty()))
+            .unwrap_or_else(|| "default".to_string())
+    }
+
+    /// Cursor size in logical pixels
+    pub fn size(&self) -> u32 {
+        self.size
+            .or_else(|| std::env::var("XCURSOR_SIZE").ok()?.parse().ok())
+            .filter(|&size| size > 0)
+            .unwrap_or(24)
+    }
+}
+
+/// Keyboard keymap, repeat and layout switching
+///
+/// Empty xkb names fall back to the XKB_DEFAULT_* environment variables, then
+/// to the xkb defaults. Several layouts are separated by commas, and layouts
+/// are switched with `switch_layout`, through IPC or with xkb options such as
+/// `grp:alt_shift_toggle`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeyboardConfig {
+    /// xkb rules file
+    pub rules: String,
+    /// xkb keyboard model
+    pub model: String,
+    /// xkb layouts, comma separated
+    pub layout: String,
+    /// xkb variants, one per layout
+    pub variant: String,
+    /// xkb options, comma separated
+    pub options: Option<String>,
+    /// Key repeats per second, 0 to disable repeat
+    pub repeat_rate: i32,
+    /// Delay before keys repeat in milliseconds
+    pub repeat_delay: i32,
+    /// Turn num lock on at startup
+    pub numlock: bool,
+    /// Key binding switching to the next layout, such as `Super+space`
+    pub switch_layout: Option<String>,
+    /// Remember the active layout of each window and restore it when the
+    /// window is focused again
+    pub per_window_layout: bool,
+}
+
+impl Default for KeyboardConfig {
+    fn default() -> Self {
+        Self {
+            rules: String::new(),
+            model: String::new(),
+            layout: String::new(),
+            variant: String::new(),
+            options: None,
+            repeat_rate: 25,
+            repeat_delay: 200,
+            numlock: false,
+            switch_layout: None,
+            per_window_layout: false,
+        }
+    }
+}
+
+/// Key with the modifiers held for it, written as modifiers and an xkb keysym
+/// name joined by `+`, such as `Ctrl+Shift+space`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeyBinding {
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+    /// The Super or Windows key
+    pub logo: bool,
+    /// xkb keysym name
+    pub key: String,
+}
+
+impl std::str::FromStr for KeyBinding {
+    type Err = String;
+
+    fn from_str(binding: &str) -> Result<Self, Self::Err> {
+        let mut parts: Vec<&str> = binding.split('+').map(str::trim).collect();
+        let key = parts
+            .pop()
+            .filter(|key| !key.is_empty())
+            .ok_or_else(|| format!("Key binding {} has no key", binding))?;
+        let mut result = Self { key: key.to_string(), ..Self::default() };
+        for modifier in parts {
+            let held = match modifier.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => &mut result.ctrl,
+                "alt" => &mut result.alt,
+                "shift" => &mut result.shift,
+                "super" | "logo" | "mod4" => &mut result.logo,
+                _ => return Err(format!("Unknown modifier {} in key binding {}", modifier, binding)),
+            };
+            *held = true;
+        }
+        Ok(result)
+    }
+}
+
+/// System bell configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BellConfig {
+    /// What flashes when a bell rings
+    pub flash: BellFlash,
+    /// Flash duration in milliseconds
+    pub flash_duration: u64,
+    /// Mark the ringing window as urgent until it is focused
+    pub urgency: bool,
+    /// Shell command run for every bell, for example to play a sound
+    pub command: Option<String>,
+}
+
+impl Default for BellConfig {
+    fn default() -> Self {
+        Self {
+            flash: BellFlash::Window,
+            flash_duration: 150,
+            urgency: true,
+            command: None,
+        }
+    }
+}
+
+/// Visual feedback of a bell
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BellFlash {
+    /// No flash
+    None,
+    /// Flash the ringing window, or the output for bells without a window
+    Window,
+    /// Flash the whole output
+    Output,
+}
+
+/// Per-client resource limits
+///
+/// A client going over any of them gets a protocol error and is disconnected.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LimitsConfig {
+    /// Surfaces a client may hold, subsurfaces and popups included
+    pub max_surfaces: usize,
+    /// SHM buffers a client may hold
+    pub max_buffers: usize,
+    /// Bytes of SHM pools a client may hold
+    pub max_shm_pool_bytes: u64,
+    /// Frame callbacks a client may have waiting for a frame
+    pub max_pending_callbacks: usize,
+    /// Bytes of texture memory the committed buffers of a client may take
+    pub max_texture_bytes: u64,
+    /// Commits and object creations a client may send per second
+    pub max_requests_per_second: u32,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            max_surfaces: 4096,
+            max_buffers: 16384,
+            max_shm_pool_bytes: 2 << 30,
+            max_pending_callbacks: 4096,
+            max_texture_bytes: 2 << 30,
+            max_requests_per_second: 100_000,
+        }
+    }
+}
+
+/// Wayland protocol tracing
+///
+/// Traces are written as JSON lines, one record per request or event.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProtocolTraceConfig {
+    /// Start tracing all clients at startup
+    pub enabled: bool,
+    /// Trace file, in the runtime directory when unset
+    pub path: Option<PathBuf>,
+    /// Interfaces to trace, all when empty
+    pub interfaces: Vec<String>,
+    /// Connect every client through the tracer, so tracing can start for
+    /// clients already connected; otherwise only clients connecting while
+    /// tracing is enabled go through it
+    pub relay_clients: bool,
+}
+
+/// Recording of input and output timing for replay
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RecordingConfig {
+    /// Start recording at startup
+    pub enabled: bool,
+    /// Recording file, in the runtime directory when unset
+    pub path: Option<PathBuf>,
+    /// Recording replayed at startup instead of driving the outputs with
+    /// frame clocks, for clients started alongside to reproduce a session.
+    /// The compositor stops once the recording has been replayed.
+    pub replay: Option<PathBuf>,
+}
+
+/// Name of the seat that input devices without a rule are assigned to
+pub const DEFAULT_SEAT: &str = "seat0";
+
+/// Seat besides the default one and the rules assigning devices to it
+///
+/// Devices are matched by their name first, then by the `ID_SEAT` udev
+/// property of the device.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SeatConfig {
+    /// Seat name advertised to clients
+    pub name: String,
+    /// Names of the input devices of the seat, as reported by libinput
+    #[serde(default)]
+    pub devices: Vec<String>,
+    /// `ID_SEAT` udev property of the devices of the seat
+    #[serde(default)]
+    pub udev_seat: Option<String>,
+}
+
 /// Main configuration structure
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CompositorConfig {
@@ -217,6 +642,39 @@ pub struct CompositorConfig {
     pub performance: PerformanceConfig,
     /// Plugin configuration
     pub plugins: PluginConfig,
+    /// Clipboard configuration
+    #[serde(default)]
+    pub clipboard: ClipboardConfig,
+    /// Graphics tablet configuration
+    #[serde(default)]
+    pub tablet: TabletConfig,
+    /// Workspace configuration
+    #[serde(default)]
+    pub workspaces: WorkspaceConfig,
+    /// Touchpad gesture configuration
+    #[serde(default)]
+    pub gestures: GestureConfig,
+    /// Cursor configuration
+    #[serde(default)]
+    pub cursor: CursorConfig,
+    /// System bell configuration
+    #[serde(default)]
+    pub bell: BellConfig,
+    /// Per-client resource limits
+    #[serde(default)]
+    pub limits: LimitsConfig,
+    /// Wayland protocol tracing
+    #[serde(default)]
+    pub protocol_trace: ProtocolTraceConfig,
+    /// Recording of input and output timing for replay
+    #[serde(default)]
+    pub recording: RecordingConfig,
+    /// Seats besides the default one
+    #[serde(default)]
+    pub seats: Vec<SeatConfig>,
+    /// Keyboard keymap, repeat and layout switching
+    #[serde(default)]
+    pub keyboard: KeyboardConfig,
 }
 
 impl Default for CompositorConfig {
@@ -227,6 +685,17 @@ impl Default for CompositorConfig {
             theme: ThemeConfig::default(),
             performance: PerformanceConfig::default(),
             plugins: PluginConfig::default(),
+            clipboard: ClipboardConfig::default(),
+            tablet: TabletConfig::default(),
+            workspaces: WorkspaceConfig::default(),
+            gestures: GestureConfig::default(),
+            cursor: CursorConfig::default(),
+            bell: BellConfig::default(),
+            limits: LimitsConfig::default(),
+            protocol_trace: ProtocolTraceConfig::default(),
+            recording: RecordingConfig::default(),
+            seats: Vec::new(),
+            keyboard: KeyboardConfig::default(),
         }
     }
 }
@@ -276,9 +745,131 @@ impl CompositorConfig {
                 message: "Maximum FPS must be positive".to_string(),
             });
         }
-        
+
+        // Validate clipboard configuration
+        if self.clipboard.max_entry_size == 0 {
+            return Err(ConfigError::Validation {
+                message: "Clipboard entry size limit must be positive".to_string(),
+            });
+        }
+
+        if self.clipboard.max_history_bytes < self.clipboard.max_entry_size && self.clipboard.history_size > 0 {
+            return Err(ConfigError::Validation {
+                message: "Clipboard history size limit must fit at least one entry".to_string(),
+            });
+        }
+
+        // Validate tablet configuration
+        if let Some([_, _, width, height]) = self.tablet.region {
+            if width <= 0 || height <= 0 {
+                return Err(ConfigError::Validation {
+                    message: "Tablet region must have a positive size".to_string(),
+                });
+            }
+        }
+
+        for (tool, curve) in &self.tablet.pressure_curves {
+            curve.validate().map_err(|reason| ConfigError::Validation {
+                message: format!("Pressure curve for {} {}", tool, reason),
+            })?;
+        }
+
+        // Validate workspace and gesture configuration
+        if self.workspaces.count == 0 {
+            return Err(ConfigError::Validation {
+                message: "At least one workspace is required".to_string(),
+            });
+        }
+
+        if self.gestures.swipe_distance <= 0.0 {
+            return Err(ConfigError::Validation {
+                message: "Gesture swipe distance must be positive".to_string(),
+            });
+        }
+
+        for (index, binding) in self.gestures.bindings.iter().enumerate() {
+            if binding.fingers < 3 {
+                return Err(ConfigError::Validation {
+                    message: "Swipe gestures need at least three fingers".to_string(),
+                });
+            }
+            if self.gestures.bindings[..index].iter().any(|other| other.fingers == binding.fingers) {
+                return Err(ConfigError::Validation {
+                    message: format!("Several gestures are bound to {} finger swipes", binding.fingers),
+                });
+            }
+        }
+
+        // Validate cursor configuration
+        if self.cursor.size == Some(0) {
+            return Err(ConfigError::Validation {
+                message: "Cursor size must be positive".to_string(),
+            });
+        }
+
+        // Validate bell configuration
+        if self.bell.flash != BellFlash::None && self.bell.flash_duration == 0 {
+            return Err(ConfigError::Validation {
+                message: "Bell flash duration must be positive".to_string(),
+            });
+        }
+
+        // Validate resource limits
+        let limits = &self.limits;
+        if limits.max_surfaces == 0
+            || limits.max_buffers == 0
+            || limits.max_shm_pool_bytes == 0
+            || limits.max_pending_callbacks == 0
+            || limits.max_texture_bytes == 0
+            || limits.max_requests_per_second == 0
+        {
+            return Err(ConfigError::Validation {
+                message: "Resource limits must be positive".to_string(),
+            });
+        }
+
+        // Validate seats
+        for (index, seat) in self.seats.iter().enumerate() {
+            if seat.name.is_empty() || seat.name == DEFAULT_SEAT {
+                return Err(ConfigError::Validation {
+                    message: format!("Seats need a name other than {}", DEFAULT_SEAT),
+                });
+            }
+            if self.seats[..index].iter().any(|other| other.name == seat.name) {
+                return Err(ConfigError::Validation {
+                    message: format!("Seat {} is configured several times", seat.name),
+                });
+            }
+        }
+
+        // Validate keyboard configuration
+        if self.keyboard.repeat_rate < 0 || self.keyboard.repeat_delay < 0 {
+            return Err(ConfigError::Validation {
+                message: "Key repeat rate and delay cannot be negative".to_string(),
+            });
+        }
+
+        if let Some(binding) = &self.keyboard.switch_layout {
+            binding.parse::<KeyBinding>().map_err(|message| ConfigError::Validation { message })?;
+        }
+
         Ok(())
     }
+
+    /// Seat an input device is assigned to
+    ///
+    /// Rules naming the device take precedence over udev seat rules. Devices
+    /// matching no rule belong to the default seat.
+    pub fn device_seat(&self, device_name: &str, udev_seat: Option<&str>) -> &str {
+        self.seats
+            .iter()
+            .find(|seat| seat.devices.iter().any(|device| device == device_name))
+            .or_else(|| {
+                let udev_seat = udev_seat?;
+                self.seats.iter().find(|seat| seat.udev_seat.as_deref() == Some(udev_seat))
+            })
+            .map_or(DEFAULT_SEAT, |seat| seat.name.as_str())
+    }
     
     /// Apply environment variable overrides
     pub fn apply_env_overrides(&mut self) -> Result<(), ConfigError> {
@@ -496,6 +1087,161 @@ mod tests {
         assert!(deserialized.validate().is_ok());
     }
     
+    #[tokio::test]
+    async fn test_clipboard_config() {
+        // Configuration files written before the clipboard section existed still load
+        let mut value = toml::Value::try_from(CompositorConfig::default()).unwrap();
+        value.as_table_mut().unwrap().remove("clipboard");
+        let config: CompositorConfig = toml::from_str(&toml::to_string(&value).unwrap()).unwrap();
+        assert!(config.clipboard.persistence);
+        assert_eq!(config.clipboard.history_size, 50);
+
+        let mut config = CompositorConfig::default();
+        config.clipboard.max_entry_size = 0;
+        assert!(config.validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_tablet_config() {
+        let tablet: toml::Value = toml::from_str(
+            "region = [0, 0, 1920, 1080]\n[pressure_curves]\npen = [[0.0, 0.0], [0.5, 0.25], [1.0, 1.0]]\n0x2a = [[0.0, 0.5], [1.0, 1.0]]\n",
+        )
+        .unwrap();
+        let mut value = toml::Value::try_from(CompositorConfig::default()).unwrap();
+        value.as_table_mut().unwrap().insert("tablet".to_string(), tablet);
+        let config: CompositorConfig = value.try_into().unwrap();
+        assert!(config.validate().is_ok());
+        assert!(config.tablet.keep_aspect_ratio);
+        assert_eq!(config.tablet.region, Some([0, 0, 1920, 1080]));
+
+        // Serials take precedence over tool types, which fall back to linear
+        let pen = config.tablet.pressure_curve("pen", 7).unwrap();
+        assert_eq!(pen.apply(0.5), 0.25);
+        assert_eq!(pen.apply(0.75), 0.625);
+        assert_eq!(pen.apply(2.0), 1.0);
+        assert_eq!(config.tablet.pressure_curve("pen", 0x2a).unwrap().apply(0.0), 0.5);
+        assert!(config.tablet.pressure_curve("eraser", 7).is_none());
+
+        let mut config = CompositorConfig::default();
+        config.tablet.pressure_curves.insert("pen".to_string(), PressureCurve { points: vec![(0.5, 0.0), (0.2, 1.0)] });
+        assert!(config.validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_gesture_config() {
+        let gestures: toml::Value = toml::from_str(
+            "swipe_distance = 200.0\n[[bindings]]\nfingers = 4\naction = \"switch-workspace\"\n",
+        )
+        .unwrap();
+        let mut value = toml::Value::try_from(CompositorConfig::default()).unwrap();
+        value.as_table_mut().unwrap().insert("gestures".to_string(), gestures);
+        let config: CompositorConfig = value.try_into().unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.gestures.swipe_action(4), Some(GestureAction::SwitchWorkspace));
+        assert_eq!(config.gestures.swipe_action(3), None);
+        assert_eq!(config.workspaces.count, 4);
+
+        let mut config = CompositorConfig::default();
+        config.gestures.bindings.push(GestureBinding { fingers: 3, action: GestureAction::Overview });
+        assert!(config.validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_cursor_config() {
+        let cursor: toml::Value = toml::from_str("theme = \"Adwaita\"\nsize = 32\n").unwrap();
+        let mut value = toml::Value::try_from(CompositorConfig::default()).unwrap();
+        value.as_table_mut().unwrap().insert("cursor".to_string(), cursor);
+        let config: CompositorConfig = value.try_into().unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.cursor.theme_name(), "Adwaita");
+        assert_eq!(config.cursor.size(), 32);
+
+        let mut config = CompositorConfig::default();
+        config.cursor.size = Some(0);
+        assert!(config.validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_bell_config() {
+        let bell: toml::Value = toml::from_str("flash = \"output\"\nurgency = false\ncommand = \"paplay bell.oga\"\n").unwrap();
+        let mut value = toml::Value::try_from(CompositorConfig::default()).unwrap();
+        value.as_table_mut().unwrap().insert("bell".to_string(), bell);
+        let config: CompositorConfig = value.try_into().unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.bell.flash, BellFlash::Output);
+        assert_eq!(config.bell.flash_duration, 150);
+        assert!(!config.bell.urgency);
+        assert_eq!(config.bell.command.as_deref(), Some("paplay bell.oga"));
+
+        let mut config = CompositorConfig::default();
+        config.bell.flash_duration = 0;
+        assert!(config.validate().is_err());
+        config.bell.flash = BellFlash::None;
+        assert!(config.validate().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_limits_config() {
+        let limits: toml::Value = toml::from_str("max_surfaces = 64\nmax_requests_per_second = 500\n").unwrap();
+        let mut value = toml::Value::try_from(CompositorConfig::default()).unwrap();
+        value.as_table_mut().unwrap().insert("limits".to_string(), limits);
+        let config: CompositorConfig = value.try_into().unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.limits.max_surfaces, 64);
+        assert_eq!(config.limits.max_requests_per_second, 500);
+        assert_eq!(config.limits.max_buffers, LimitsConfig::default().max_buffers);
+
+        let mut config = CompositorConfig::default();
+        config.limits.max_shm_pool_bytes = 0;
+        assert!(config.validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_seat_config() {
+        let config: toml::Value = toml::from_str(
+            "[[seats]]\nname = \"seat1\"\ndevices = [\"Keyboard B\", \"Mouse B\"]\n[[seats]]\nname = \"seat2\"\nudev_seat = \"seat2\"\n",
+        )
+        .unwrap();
+        let mut value = toml::Value::try_from(CompositorConfig::default()).unwrap();
+        value.as_table_mut().unwrap().insert("seats".to_string(), config["seats"].clone());
+        let config: CompositorConfig = value.try_into().unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.device_seat("Mouse B", None), "seat1");
+        assert_eq!(config.device_seat("Mouse B", Some("seat2")), "seat1");
+        assert_eq!(config.device_seat("Mouse C", Some("seat2")), "seat2");
+        assert_eq!(config.device_seat("Mouse C", Some("seat3")), DEFAULT_SEAT);
+        assert_eq!(config.device_seat("Mouse C", None), DEFAULT_SEAT);
+
+        let mut invalid = config.clone();
+        invalid.seats[1].name = "seat1".to_string();
+        assert!(invalid.validate().is_err());
+        invalid.seats[1].name = DEFAULT_SEAT.to_string();
+        assert!(invalid.validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_keyboard_config() {
+        let mut value = toml::Value::try_from(CompositorConfig::default()).unwrap();
+        value.as_table_mut().unwrap().remove("keyboard");
+        let config: CompositorConfig = toml::from_str(&toml::to_string(&value).unwrap()).unwrap();
+        assert_eq!((config.keyboard.repeat_rate, config.keyboard.repeat_delay), (25, 200));
+        assert!(config.keyboard.layout.is_empty());
+
+        let binding: KeyBinding = "Super+Shift+space".parse().unwrap();
+        assert_eq!(binding, KeyBinding { shift: true, logo: true, key: "space".to_string(), ..KeyBinding::default() });
+        assert_eq!("Ctrl+Alt+a".parse::<KeyBinding>().unwrap().key, "a");
+        assert!("Hyper+space".parse::<KeyBinding>().is_err());
+        assert!("Super+".parse::<KeyBinding>().is_err());
+
+        let mut config = CompositorConfig::default();
+        config.keyboard.switch_layout = Some("Meta+space".to_string());
+        assert!(config.validate().is_err());
+        config.keyboard.switch_layout = Some("Alt+Shift_L".to_string());
+        assert!(config.validate().is_ok());
+        config.keyboard.repeat_delay = -1;
+        assert!(config.validate().is_err());
+    }
+
     #[tokio::test]
     async fn test_config_manager() {
         let temp_dir = TempDir::new().unwrap();
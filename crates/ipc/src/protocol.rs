@@ -7,6 +7,9 @@ use compositor_utils::prelude::*;
 use serde::{Deserialize, Serialize};
 
 /// IPC message types
+///
+/// Messages are encoded with bincode, which identifies variants by their
+/// index, so new variants are added at the end.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum IPCMessage {
     /// Request window information
@@ -18,9 +21,12 @@ pub enum IPCMessage {
         title: String,
         app_id: String,
         geometry: WindowGeometry,
+        /// Identity of the client owning the window
+        client: Option<ClientInfo>,
     },
     
-    /// Request to focus a window
+    /// Request to focus a window, answered with the window that took focus,
+    /// which is its topmost modal dialog if it has any
     FocusWindow { window_id: u32 },
     
     /// Request compositor status
@@ -35,6 +41,85 @@ pub enum IPCMessage {
     
     /// Error response
     Error { message: String },
+    
+    /// Request the clipboard history, newest entry first
+    GetClipboardHistory,
+    
+    /// Clipboard history response
+    ClipboardHistory { entries: Vec<ClipboardEntryInfo> },
+    
+    /// Request to make a history entry the current clipboard selection
+    RestoreClipboardEntry { entry_id: u64 },
+    
+    /// Clipboard entry restored response
+    ClipboardEntryRestored { entry_id: u64 },
+    
+    /// Request the windows of all workspaces
+    ListWindows,
+    
+    /// Window list response
+    WindowList { windows: Vec<WindowSummary> },
+    
+    /// Request the icon of a window for a size in logical pixels at an output scale
+    GetWindowIcon { window_id: u32, size: u32, scale: u32 },
+    
+    /// Window icon response, with the image best suited to the requested size
+    WindowIcon {
+        window_id: u32,
+        icon_name: Option<String>,
+        image: Option<IconImageData>,
+    },
+    
+    /// Request how much of each output's scene is hidden behind opaque content
+    GetOcclusionStats,
+    
+    /// Occlusion statistics response, one entry per output
+    OcclusionStats { outputs: Vec<OutputOcclusionInfo> },
+    
+    /// Request to start or stop tracing Wayland requests and events, of one
+    /// client process or all clients, optionally limited to some interfaces
+    SetProtocolTrace {
+        enabled: bool,
+        client_pid: Option<u32>,
+        interfaces: Vec<String>,
+    },
+    
+    /// Protocol trace state response, with the file traces are written to
+    ProtocolTrace { enabled: bool, path: String },
+    
+    /// Request to start or stop recording input and output timing for replay
+    SetRecording { enabled: bool },
+    
+    /// Recording state response, with the file the recording is written to
+    Recording { enabled: bool, path: String },
+    
+    /// Request the keyboard layouts of the default seat
+    GetKeyboardLayout,
+    
+    /// Request to switch the keyboard layout of the default seat, answered
+    /// with the layouts
+    SwitchKeyboardLayout { layout: KeyboardLayoutSwitch },
+    
+    /// Keyboard layouts response, with the index of the active layout
+    KeyboardLayout { layouts: Vec<String>, active: u32 },
+    
+    /// Request to drag local files from the held pointer button, as UI such
+    /// as the app bar does when one of its items is pressed
+    StartFileDrag { paths: Vec<String> },
+    
+    /// Drag started response
+    DragStarted,
+}
+
+/// Keyboard layout to switch to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KeyboardLayoutSwitch {
+    /// Next layout, wrapping around after the last one
+    Next,
+    /// Previous layout, wrapping around before the first one
+    Previous,
+    /// Layout with the given index
+    Index(u32),
 }
 
 /// Window geometry information
@@ -46,6 +131,78 @@ pub struct WindowGeometry {
     pub height: u32,
 }
 
+/// Window summary for app bars and window switchers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WindowSummary {
+    pub window_id: u32,
+    pub title: String,
+    pub app_id: String,
+    /// Index of the workspace showing the window
+    pub workspace: u32,
+    /// Icon theme name set by the client, if any
+    pub icon_name: Option<String>,
+    /// Logical sizes of the icon images set by the client
+    pub icon_sizes: Vec<u32>,
+    /// Whether the window rang the bell since it last had focus
+    pub urgent: bool,
+    /// Identity of the client owning the window
+    pub client: Option<ClientInfo>,
+}
+
+/// Identity of a connected client, read from its socket credentials
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClientInfo {
+    pub pid: i32,
+    pub uid: u32,
+    pub gid: u32,
+    /// Executable of the process, if it could be resolved
+    pub executable: Option<String>,
+    pub cgroup: Option<String>,
+    /// Connection time in seconds since the Unix epoch
+    pub connected_at: u64,
+    /// Security context of sandboxed clients
+    pub sandbox_engine: Option<String>,
+    pub sandbox_app_id: Option<String>,
+    pub sandbox_instance_id: Option<String>,
+}
+
+/// Icon image provided by a client
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IconImageData {
+    /// Size in logical pixels
+    pub size: u32,
+    pub scale: u32,
+    /// Square ARGB8888 pixels in little-endian byte order, `size * scale` per row
+    pub pixels: Vec<u8>,
+}
+
+/// Clipboard history entry information
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClipboardEntryInfo {
+    pub entry_id: u64,
+    pub mime_types: Vec<String>,
+    /// Combined size of the stored data in bytes
+    pub size: u64,
+    /// Start of the text contents, if the entry has any
+    pub preview: Option<String>,
+    /// Capture time in seconds since the Unix epoch
+    pub timestamp: u64,
+}
+
+/// Occlusion culling statistics of the scene of an output
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutputOcclusionInfo {
+    pub output: String,
+    /// Surfaces with content on the output
+    pub surfaces: u32,
+    /// Surfaces hidden entirely, neither drawn nor sent frame callbacks
+    pub occluded: u32,
+    /// Surfaces drawn only in part
+    pub partially_occluded: u32,
+    pub drawn_pixels: u64,
+    pub culled_pixels: u64,
+}
+
 /// Protocol handler for IPC messages
 pub struct ProtocolHandler {
     // Placeholder for protocol state
@@ -79,6 +236,7 @@ impl ProtocolHandler {
                         width: 800,
                         height: 600,
                     },
+                    client: None,
                 })
             }
             IPCMessage::FocusWindow { window_id: _ } => {
@@ -1,5 +1,9 @@
 use glam::{Mat4, Vec2};
 
+mod region;
+
+pub use region::{IRect, Region};
+
 /// 4K resolution constants
 pub const UHD_WIDTH: u32 = 3840;
 pub const UHD_HEIGHT: u32 = 2160;
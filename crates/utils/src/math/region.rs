@@ -0,0 +1,331 @@
+// Region - Integer pixel regions in a banded representation
+//
+// A region is a set of pixels stored as horizontal bands, like pixman's
+// regions: bands are sorted top to bottom and never overlap, each holds
+// sorted, disjoint spans that cover the band's full height. Vertically
+// adjacent bands with the same spans are merged, so every region has a
+// single representation and equal regions compare equal.
+//
+// Union, intersection and subtraction sweep both regions band by band, so
+// damage, input, opaque regions and exclusive zones can be combined without
+// rasterizing them.
+
+/// Integer rectangle, the building block of regions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct IRect {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl IRect {
+    pub fn new(x: i32, y: i32, width: i32, height: i32) -> Self {
+        Self { x, y, width, height }
+    }
+
+    pub fn from_size(width: i32, height: i32) -> Self {
+        Self::new(0, 0, width, height)
+    }
+
+    /// Rectangle between two corners, the second one excluded
+    pub fn from_corners(x1: i32, y1: i32, x2: i32, y2: i32) -> Self {
+        Self::new(x1, y1, x2 - x1, y2 - y1)
+    }
+
+    pub fn right(&self) -> i32 {
+        self.x + self.width
+    }
+
+    pub fn bottom(&self) -> i32 {
+        self.y + self.height
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width <= 0 || self.height <= 0
+    }
+
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x && x < self.right() && y >= self.y && y < self.bottom()
+    }
+}
+
+/// Horizontal band of a region
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Band {
+    top: i32,
+    bottom: i32,
+    /// Sorted, disjoint and non-touching `(x1, x2)` spans, `x2` excluded
+    spans: Vec<(i32, i32)>,
+}
+
+/// Set operation applied to the spans of two regions
+#[derive(Debug, Clone, Copy)]
+enum Op {
+    Union,
+    Intersection,
+    Subtraction,
+}
+
+impl Op {
+    fn keep(self, in_a: bool, in_b: bool) -> bool {
+        match self {
+            Op::Union => in_a || in_b,
+            Op::Intersection => in_a && in_b,
+            Op::Subtraction => in_a && !in_b,
+        }
+    }
+}
+
+/// Combine the spans of one band of each region
+fn combine_spans(a: &[(i32, i32)], b: &[(i32, i32)], op: Op) -> Vec<(i32, i32)> {
+    let mut edges: Vec<i32> = a.iter().chain(b).flat_map(|&(x1, x2)| [x1, x2]).collect();
+    edges.sort_unstable();
+    edges.dedup();
+
+    let (mut ia, mut ib) = (0, 0);
+    let mut spans: Vec<(i32, i32)> = Vec::new();
+    for pair in edges.windows(2) {
+        let (x1, x2) = (pair[0], pair[1]);
+        while ia < a.len() && a[ia].1 <= x1 {
+            ia += 1;
+        }
+        while ib < b.len() && b[ib].1 <= x1 {
+            ib += 1;
+        }
+        let in_a = ia < a.len() && a[ia].0 <= x1;
+        let in_b = ib < b.len() && b[ib].0 <= x1;
+        if !op.keep(in_a, in_b) {
+            continue;
+        }
+        match spans.last_mut() {
+            Some(last) if last.1 == x1 => last.1 = x2,
+            _ => spans.push((x1, x2)),
+        }
+    }
+    spans
+}
+
+/// Integer pixel region
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Region {
+    bands: Vec<Band>,
+}
+
+impl Region {
+    /// Empty region
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Region covering a rectangle, empty for empty rectangles
+    pub fn from_rect(rect: IRect) -> Self {
+        if rect.is_empty() {
+            return Self::new();
+        }
+        Self {
+            bands: vec![Band {
+                top: rect.y,
+                bottom: rect.bottom(),
+                spans: vec![(rect.x, rect.right())],
+            }],
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bands.is_empty()
+    }
+
+    /// Whether a pixel is part of the region
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        self.bands
+            .iter()
+            .find(|band| y >= band.top && y < band.bottom)
+            .is_some_and(|band| band.spans.iter().any(|&(x1, x2)| x >= x1 && x < x2))
+    }
+
+    /// Whether a rectangle lies entirely within the region
+    pub fn contains_rect(&self, rect: IRect) -> bool {
+        Region::from_rect(rect).subtract(self).is_empty()
+    }
+
+    /// Whether the region shares any pixel with a rectangle
+    pub fn intersects_rect(&self, rect: IRect) -> bool {
+        !self.intersection(&Region::from_rect(rect)).is_empty()
+    }
+
+    /// Bounding box of the region, `None` when empty
+    pub fn extents(&self) -> Option<IRect> {
+        let (first, last) = (self.bands.first()?, self.bands.last()?);
+        let x1 = self.bands.iter().map(|band| band.spans[0].0).min()?;
+        let x2 = self.bands.iter().filter_map(|band| band.spans.last()).map(|span| span.1).max()?;
+        Some(IRect::from_corners(x1, first.top, x2, last.bottom))
+    }
+
+    /// Number of disjoint rectangles the region is made of
+    pub fn rect_count(&self) -> usize {
+        self.bands.iter().map(|band| band.spans.len()).sum()
+    }
+
+    /// Disjoint rectangles of the region, top to bottom and left to right
+    pub fn rects(&self) -> impl Iterator<Item = IRect> + '_ {
+        self.bands.iter().flat_map(|band| {
+            band.spans
+                .iter()
+                .map(move |&(x1, x2)| IRect::from_corners(x1, band.top, x2, band.bottom))
+        })
+    }
+
+    /// Pixels in either region
+    pub fn union(&self, other: &Region) -> Region {
+        self.combine(other, Op::Union)
+    }
+
+    /// Pixels in both regions
+    pub fn intersection(&self, other: &Region) -> Region {
+        self.combine(other, Op::Intersection)
+    }
+
+    /// Pixels of this region that are not in the other
+    pub fn subtract(&self, other: &Region) -> Region {
+        self.combine(other, Op::Subtraction)
+    }
+
+    /// Add a rectangle to the region
+    pub fn add_rect(&mut self, rect: IRect) {
+        *self = self.union(&Region::from_rect(rect));
+    }
+
+    /// Remove a rectangle from the region
+    pub fn subtract_rect(&mut self, rect: IRect) {
+        *self = self.subtract(&Region::from_rect(rect));
+    }
+
+    /// Region moved by an offset
+    pub fn translated(&self, dx: i32, dy: i32) -> Region {
+        let bands = self
+            .bands
+            .iter()
+            .map(|band| Band {
+                top: band.top + dy,
+                bottom: band.bottom + dy,
+                spans: band.spans.iter().map(|&(x1, x2)| (x1 + dx, x2 + dx)).collect(),
+            })
+            .collect();
+        Region { bands }
+    }
+
+    /// Region scaled by a factor, rounding outwards
+    ///
+    /// Every pixel that is partially covered after scaling is included, so a
+    /// scaled damage region always covers what changed.
+    pub fn scaled(&self, factor: f64) -> Region {
+        if factor <= 0.0 {
+            return Region::new();
+        }
+        let scale_down = |value: i32| (value as f64 * factor).floor() as i32;
+        let scale_up = |value: i32| (value as f64 * factor).ceil() as i32;
+        self.rects()
+            .map(|rect| {
+                IRect::from_corners(scale_down(rect.x), scale_down(rect.y), scale_up(rect.right()), scale_up(rect.bottom()))
+            })
+            .collect()
+    }
+
+    /// Sweep both regions band by band, applying `op` to each strip's spans
+    fn combine(&self, other: &Region, op: Op) -> Region {
+        let mut edges: Vec<i32> = self
+            .bands
+            .iter()
+            .chain(&other.bands)
+            .flat_map(|band| [band.top, band.bottom])
+            .collect();
+        edges.sort_unstable();
+        edges.dedup();
+
+        let (mut ia, mut ib) = (0, 0);
+        let mut bands: Vec<Band> = Vec::new();
+        for pair in edges.windows(2) {
+            let (top, bottom) = (pair[0], pair[1]);
+            while ia < self.bands.len() && self.bands[ia].bottom <= top {
+                ia += 1;
+            }
+            while ib < other.bands.len() && other.bands[ib].bottom <= top {
+                ib += 1;
+            }
+            let a = self.bands.get(ia).filter(|band| band.top <= top).map_or(&[][..], |band| &band.spans);
+            let b = other.bands.get(ib).filter(|band| band.top <= top).map_or(&[][..], |band| &band.spans);
+            let spans = combine_spans(a, b, op);
+            if spans.is_empty() {
+                continue;
+            }
+            // Coalesce with the band above when they touch and match
+            match bands.last_mut() {
+                Some(last) if last.bottom == top && last.spans == spans => last.bottom = bottom,
+                _ => bands.push(Band { top, bottom, spans }),
+            }
+        }
+        Region { bands }
+    }
+}
+
+impl From<IRect> for Region {
+    fn from(rect: IRect) -> Self {
+        Region::from_rect(rect)
+    }
+}
+
+impl FromIterator<IRect> for Region {
+    /// Union of rectangles, merged pairwise to keep the sweeps small
+    fn from_iter<T: IntoIterator<Item = IRect>>(iter: T) -> Self {
+        let mut regions: Vec<Region> = iter.into_iter().map(Region::from_rect).collect();
+        while regions.len() > 1 {
+            regions = regions
+                .chunks(2)
+                .map(|pair| match pair {
+                    [a, b] => a.union(b),
+                    [a] => a.clone(),
+                    _ => unreachable!(),
+                })
+                .collect();
+        }
+        regions.pop().unwrap_or_default()
+    }
+}
+
+#[cfg(feature = "smithay")]
+mod smithay_conversions {
+    use super::{IRect, Region};
+    use smithay::utils::{Point, Rectangle, Size};
+
+    impl<Kind> From<Rectangle<i32, Kind>> for IRect {
+        fn from(rect: Rectangle<i32, Kind>) -> Self {
+            IRect::new(rect.loc.x, rect.loc.y, rect.size.w, rect.size.h)
+        }
+    }
+
+    impl<Kind> From<IRect> for Rectangle<i32, Kind> {
+        fn from(rect: IRect) -> Self {
+            Rectangle::new(Point::from((rect.x, rect.y)), Size::from((rect.width, rect.height)))
+        }
+    }
+
+    impl<Kind> From<Rectangle<i32, Kind>> for Region {
+        fn from(rect: Rectangle<i32, Kind>) -> Self {
+            Region::from_rect(rect.into())
+        }
+    }
+
+    impl<Kind> FromIterator<Rectangle<i32, Kind>> for Region {
+        fn from_iter<T: IntoIterator<Item = Rectangle<i32, Kind>>>(iter: T) -> Self {
+            iter.into_iter().map(IRect::from).collect()
+        }
+    }
+
+    impl Region {
+        /// Disjoint rectangles of the region in a smithay coordinate space
+        pub fn to_rectangles<Kind>(&self) -> Vec<Rectangle<i32, Kind>> {
+            self.rects().map(Rectangle::from).collect()
+        }
+    }
+}
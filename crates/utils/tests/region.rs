@@ -0,0 +1,184 @@
+//! Region property tests
+//!
+//! Builds random regions from seeded rectangles and checks every operation
+//! against a pixel set model: the result must cover exactly the expected
+//! pixels, with disjoint rectangles in a single canonical representation.
+
+use compositor_utils::math::{IRect, Region};
+#[cfg(feature = "smithay")]
+use smithay::utils::{Logical, Physical, Rectangle};
+use std::collections::HashSet;
+
+/// Random cases run for each property
+const CASES: u64 = 300;
+
+type Pixels = HashSet<(i32, i32)>;
+
+fn random_rect(rng: &mut fastrand::Rng) -> IRect {
+    // Small coordinates keep the pixel model cheap, empty rectangles included
+    IRect::new(rng.i32(-8..24), rng.i32(-8..24), rng.i32(0..12), rng.i32(0..12))
+}
+
+fn random_rects(rng: &mut fastrand::Rng) -> Vec<IRect> {
+    (0..rng.usize(0..8)).map(|_| random_rect(rng)).collect()
+}
+
+fn rect_pixels(rect: IRect) -> Pixels {
+    (rect.y..rect.bottom())
+        .flat_map(|y| (rect.x..rect.right()).map(move |x| (x, y)))
+        .collect()
+}
+
+fn model(rects: &[IRect]) -> Pixels {
+    rects.iter().flat_map(|&rect| rect_pixels(rect)).collect()
+}
+
+/// Pixels of a region, checking that its rectangles are disjoint and ordered
+fn region_pixels(region: &Region) -> Pixels {
+    let mut pixels = Pixels::new();
+    let mut previous: Option<IRect> = None;
+    for rect in region.rects() {
+        assert!(!rect.is_empty(), "Regions hold no empty rectangles: {region:?}");
+        if let Some(previous) = previous {
+            assert!(
+                (previous.y, previous.x) < (rect.y, rect.x),
+                "Rectangles are sorted top to bottom, left to right: {region:?}"
+            );
+        }
+        for pixel in rect_pixels(rect) {
+            assert!(pixels.insert(pixel), "Rectangles of a region are disjoint: {region:?}");
+        }
+        previous = Some(rect);
+    }
+    pixels
+}
+
+/// Run a property for each seeded pair of random rectangle sets
+fn check(property: impl Fn(&[IRect], &[IRect])) {
+    for seed in 0..CASES {
+        let mut rng = fastrand::Rng::with_seed(seed);
+        let (a, b) = (random_rects(&mut rng), random_rects(&mut rng));
+        property(&a, &b);
+    }
+}
+
+/// Test that regions cover exactly the pixels of their rectangles
+#[test]
+fn region_from_rects_matches_pixels() {
+    check(|a, _| {
+        let region: Region = a.iter().copied().collect();
+        assert_eq!(region_pixels(&region), model(a), "Region of {a:?}");
+        assert_eq!(region.is_empty(), model(a).is_empty());
+        assert_eq!(region.rect_count(), region.rects().count());
+        for &(x, y) in &model(a) {
+            assert!(region.contains(x, y));
+        }
+    });
+}
+
+/// Test that union, intersection and subtraction match set operations
+#[test]
+fn region_set_operations_match_pixels() {
+    check(|a, b| {
+        let (ra, rb): (Region, Region) = (a.iter().copied().collect(), b.iter().copied().collect());
+        let (pa, pb) = (model(a), model(b));
+
+        let union: Pixels = pa.union(&pb).copied().collect();
+        let intersection: Pixels = pa.intersection(&pb).copied().collect();
+        let difference: Pixels = pa.difference(&pb).copied().collect();
+        assert_eq!(region_pixels(&ra.union(&rb)), union, "{a:?} ∪ {b:?}");
+        assert_eq!(region_pixels(&ra.intersection(&rb)), intersection, "{a:?} ∩ {b:?}");
+        assert_eq!(region_pixels(&ra.subtract(&rb)), difference, "{a:?} - {b:?}");
+
+        // Operations built rectangle by rectangle agree with whole regions
+        let mut incremental = ra.clone();
+        b.iter().for_each(|&rect| incremental.subtract_rect(rect));
+        assert_eq!(incremental, ra.subtract(&rb));
+    });
+}
+
+/// Test that equal pixel sets have a single representation
+#[test]
+fn region_representation_is_canonical() {
+    check(|a, b| {
+        let ra: Region = a.iter().copied().collect();
+        let reversed: Region = a.iter().rev().copied().collect();
+        assert_eq!(ra, reversed, "Rectangle order does not change regions");
+
+        let rb: Region = b.iter().copied().collect();
+        let union = ra.union(&rb);
+        assert_eq!(union, rb.union(&ra), "Union is commutative");
+        assert_eq!(ra.intersection(&rb), rb.intersection(&ra), "Intersection is commutative");
+        assert_eq!(union.subtract(&rb).union(&rb), union, "Subtracting and adding back restores the union");
+        assert!(ra.subtract(&ra).is_empty());
+
+        // Extents are the bounding box of the covered pixels
+        if let Some(extents) = ra.extents() {
+            let pixels = model(a);
+            assert_eq!(pixels.iter().map(|p| p.0).min(), Some(extents.x));
+            assert_eq!(pixels.iter().map(|p| p.0).max(), Some(extents.right() - 1));
+            assert_eq!(pixels.iter().map(|p| p.1).min(), Some(extents.y));
+            assert_eq!(pixels.iter().map(|p| p.1).max(), Some(extents.bottom() - 1));
+            assert!(Region::from_rect(extents).contains_rect(extents));
+            assert!(a.iter().all(|&rect| rect.is_empty() || ra.contains_rect(rect)));
+        } else {
+            assert!(model(a).is_empty());
+        }
+    });
+}
+
+/// Test that translation and scaling move and grow the covered pixels
+#[test]
+fn region_translate_and_scale() {
+    check(|a, _| {
+        let region: Region = a.iter().copied().collect();
+        let pixels = model(a);
+
+        let translated: Pixels = pixels.iter().map(|&(x, y)| (x + 5, y - 3)).collect();
+        assert_eq!(region_pixels(&region.translated(5, -3)), translated);
+
+        // Integer factors scale every pixel to a block
+        let doubled: Pixels = pixels
+            .iter()
+            .flat_map(|&(x, y)| rect_pixels(IRect::new(x * 2, y * 2, 2, 2)))
+            .collect();
+        assert_eq!(region_pixels(&region.scaled(2.0)), doubled);
+        assert_eq!(region.scaled(1.0), region);
+
+        // Fractional factors include every partially covered pixel
+        let scaled = region.scaled(1.5);
+        for &(x, y) in &pixels {
+            let x1 = (x as f64 * 1.5).floor() as i32;
+            let y1 = (y as f64 * 1.5).floor() as i32;
+            let x2 = ((x + 1) as f64 * 1.5).ceil() as i32;
+            let y2 = ((y + 1) as f64 * 1.5).ceil() as i32;
+            assert!(scaled.contains_rect(IRect::from_corners(x1, y1, x2, y2)), "{a:?} scaled by 1.5");
+        }
+    });
+}
+
+/// Test conversions from and to smithay rectangles
+#[cfg(feature = "smithay")]
+#[test]
+fn region_smithay_conversions() {
+    let rect: Rectangle<i32, Logical> = Rectangle::new((10, 20).into(), (30, 40).into());
+    assert_eq!(IRect::from(rect), IRect::new(10, 20, 30, 40));
+    assert_eq!(Rectangle::<i32, Logical>::from(IRect::from(rect)), rect);
+
+    // Two overlapping windows split into three disjoint bands
+    let windows: Region = [
+        Rectangle::<i32, Physical>::new((0, 0).into(), (100, 100).into()),
+        Rectangle::new((50, 50).into(), (100, 100).into()),
+    ]
+    .into_iter()
+    .collect();
+    assert_eq!(
+        windows.to_rectangles::<Physical>(),
+        vec![
+            Rectangle::new((0, 0).into(), (100, 50).into()),
+            Rectangle::new((0, 50).into(), (150, 50).into()),
+            Rectangle::new((50, 100).into(), (100, 50).into()),
+        ]
+    );
+    assert!(Region::from(Rectangle::<i32, Physical>::new((0, 0).into(), (0, 10).into())).is_empty());
+}
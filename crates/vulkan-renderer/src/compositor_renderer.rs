@@ -5,8 +5,9 @@
 
 use ash::vk;
 use compositor_utils::prelude::*;
-use crate::{VulkanDevice, VulkanInstance, SurfaceRenderer, SurfacePipeline, SurfaceTexture, SurfacePushConstants};
+use crate::{VulkanDevice, VulkanInstance, SurfaceRenderer, SurfacePipeline, SurfaceTexture, SurfacePushConstants, SurfaceDrawState, ContentType};
 use crate::surface_renderer::{SurfaceBuffer, ShmFormat};
+use crate::damage::{clip_damage, damage_bounds, intersect_rects, BufferAges};
 use std::collections::HashMap;
 use std::sync::Arc;
 
@@ -16,6 +17,8 @@ pub struct CompositorRenderer {
     surface_renderer: Option<SurfaceRenderer>,
     surface_pipeline: Option<SurfacePipeline>,
     render_pass: Option<vk::RenderPass>,
+    /// Render pass keeping previous image contents, for partial redraws
+    load_render_pass: Option<vk::RenderPass>,
     framebuffers: Vec<vk::Framebuffer>,
     command_buffers: Vec<vk::CommandBuffer>,
     command_pool: vk::CommandPool,
@@ -24,12 +27,19 @@ pub struct CompositorRenderer {
     swapchain_extent: vk::Extent2D,
     swapchain_images: Vec<vk::Image>,
     swapchain_image_views: Vec<vk::ImageView>,
+    buffer_ages: BufferAges,
     
     // Per-frame rendering resources
     vertex_buffers: HashMap<u32, vk::Buffer>,
     vertex_buffer_memories: HashMap<u32, vk::DeviceMemory>,
     descriptor_pool: Option<vk::DescriptorPool>,
     descriptor_sets: HashMap<u32, vk::DescriptorSet>,
+    /// Position, crop, scale, alpha and content type of each surface
+    draw_states: HashMap<u32, SurfaceDrawState>,
+    /// Parts of partially occluded surfaces left to draw, nothing for hidden ones
+    visible_regions: HashMap<u32, Vec<vk::Rect2D>>,
+    /// Surfaces back to front, empty until the scene sets a stacking order
+    draw_order: Vec<u32>,
 }
 
 impl CompositorRenderer {
@@ -51,16 +61,21 @@ impl CompositorRenderer {
             surface_renderer: Some(surface_renderer),
             surface_pipeline: None,
             render_pass: None,
+            load_render_pass: None,
             framebuffers: Vec::new(),
             command_buffers: Vec::new(),
             command_pool,
             swapchain_extent: vk::Extent2D { width: 0, height: 0 },
             swapchain_images: Vec::new(),
             swapchain_image_views: Vec::new(),
+            buffer_ages: BufferAges::default(),
             vertex_buffers: HashMap::new(),
             vertex_buffer_memories: HashMap::new(),
             descriptor_pool: None,
             descriptor_sets: HashMap::new(),
+            draw_states: HashMap::new(),
+            visible_regions: HashMap::new(),
+            draw_order: Vec::new(),
         })
     }
     
@@ -78,10 +93,12 @@ impl CompositorRenderer {
         self.swapchain_images = swapchain_images;
         self.swapchain_image_views = swapchain_image_views;
         self.swapchain_extent = swapchain_extent;
+        self.buffer_ages = BufferAges::new(self.swapchain_images.len());
         
-        // Create render pass
-        let render_pass = Self::create_render_pass(&self.device, swapchain_format)?;
+        // Create render passes, clearing for full redraws and loading for partial ones
+        let render_pass = Self::create_render_pass(&self.device, swapchain_format, false)?;
         self.render_pass = Some(render_pass);
+        self.load_render_pass = Some(Self::create_render_pass(&self.device, swapchain_format, true)?);
         
         // Create surface pipeline
         let surface_pipeline = SurfacePipeline::new(
@@ -109,8 +126,52 @@ impl CompositorRenderer {
         &mut self,
         frame_index: usize,
         image_index: u32,
+    ) -> Result<vk::CommandBuffer> {
+        let full = vk::Rect2D {
+            offset: vk::Offset2D { x: 0, y: 0 },
+            extent: self.swapchain_extent,
+        };
+        self.record_frame(frame_index, image_index, &[full], false)
+    }
+    
+    /// Render only the damaged parts of a frame
+    ///
+    /// `damage` is in swapchain pixels and must cover everything changed since
+    /// the image was last rendered, see `buffer_age`. Without damage nothing
+    /// is recorded, no image ages and `None` is returned: the frame needs no
+    /// presenting. Images with undefined contents are otherwise redrawn in full.
+    pub fn render_frame_damaged(
+        &mut self,
+        frame_index: usize,
+        image_index: u32,
+        damage: &[vk::Rect2D],
+    ) -> Result<Option<vk::CommandBuffer>> {
+        let damage = clip_damage(damage, self.swapchain_extent);
+        if damage.is_empty() {
+            return Ok(None);
+        }
+        if self.buffer_age(image_index) == 0 {
+            return self.render_frame(frame_index, image_index).map(Some);
+        }
+        self.record_frame(frame_index, image_index, &damage, true).map(Some)
+    }
+    
+    /// Frames since a swapchain image was last rendered, 0 for undefined contents
+    pub fn buffer_age(&self, image_index: u32) -> usize {
+        self.buffer_ages.age(image_index)
+    }
+    
+    /// Record the draws of a frame clipped to the damaged rectangles
+    fn record_frame(
+        &mut self,
+        frame_index: usize,
+        image_index: u32,
+        damage: &[vk::Rect2D],
+        partial: bool,
     ) -> Result<vk::CommandBuffer> {
         let command_buffer = self.command_buffers[frame_index];
+        let render_area = damage_bounds(damage)
+            .ok_or_else(|| CompositorError::runtime("Frame has no area to render"))?;
         
         // Begin command buffer recording
         let begin_info = vk::CommandBufferBeginInfo {
@@ -123,10 +184,12 @@ impl CompositorRenderer {
         }
         
         // Begin render pass
-        self.begin_render_pass(command_buffer, image_index)?;
+        self.begin_render_pass(command_buffer, image_index, render_area, partial)?;
         
-        // Render all surfaces
-        self.render_surfaces(command_buffer)?;
+        // Render all surfaces, once per damaged rectangle
+        for rect in damage {
+            self.render_surfaces(command_buffer, *rect)?;
+        }
         
         // End render pass and command buffer
         unsafe {
@@ -134,6 +197,7 @@ impl CompositorRenderer {
             self.device.handle().end_command_buffer(command_buffer)?;
         }
         
+        self.buffer_ages.rendered(image_index);
         Ok(command_buffer)
     }
     
@@ -177,6 +241,29 @@ impl CompositorRenderer {
         // Create or update descriptor set for texture sampling
         self.update_surface_descriptor_set(surface_id)?;
         
+        // A texture replaces any single-pixel buffer the surface had
+        if let Some(state) = self.draw_states.get_mut(&surface_id) {
+            state.color = None;
+        }
+        
+        Ok(())
+    }
+    
+    /// Show a single-pixel buffer on a surface
+    ///
+    /// The color is drawn as a solid quad scaled to the surface's destination
+    /// size, so no texture is created or uploaded. `color` has straight alpha.
+    pub fn set_surface_solid_color(&mut self, surface_id: u32, color: [f32; 4]) -> Result<()> {
+        debug!("Setting surface {} to solid color {:?}", surface_id, color);
+        
+        // Drop the texture of a previous buffer
+        self.surface_renderer.as_mut()
+            .ok_or_else(|| CompositorError::runtime("Surface renderer not available"))?
+            .remove_surface_texture(surface_id)?;
+        
+        self.update_surface_vertex_buffer(surface_id, 1, 1)?;
+        self.draw_states.entry(surface_id).or_default().color = Some(color);
+        
         Ok(())
     }
     
@@ -202,10 +289,48 @@ impl CompositorRenderer {
         
         // Remove descriptor set
         self.descriptor_sets.remove(&surface_id);
+        self.draw_states.remove(&surface_id);
+        self.visible_regions.remove(&surface_id);
+        self.draw_order.retain(|&id| id != surface_id);
         
         Ok(())
     }
     
+    /// Set how a surface is drawn in the next frames
+    pub fn set_surface_draw_state(&mut self, surface_id: u32, state: SurfaceDrawState) {
+        self.draw_states.insert(surface_id, state);
+    }
+    
+    /// Limit drawing a surface to the parts not covered by opaque content
+    ///
+    /// `None` draws the whole surface, an empty region skips it entirely.
+    pub fn set_surface_visible_region(&mut self, surface_id: u32, region: Option<Vec<vk::Rect2D>>) {
+        match region {
+            Some(region) => self.visible_regions.insert(surface_id, region),
+            None => self.visible_regions.remove(&surface_id),
+        };
+    }
+    
+    /// Set the stacking order of surfaces, back to front
+    ///
+    /// Surfaces left out of the order are not drawn.
+    pub fn set_draw_order(&mut self, surface_ids: Vec<u32>) {
+        self.draw_order = surface_ids;
+    }
+    
+    /// Draw state of a surface, the defaults until one is set
+    pub fn surface_draw_state(&self, surface_id: u32) -> SurfaceDrawState {
+        self.draw_states.get(&surface_id).copied().unwrap_or_default()
+    }
+    
+    /// Most demanding content type among the drawn surfaces
+    ///
+    /// Lets frame scheduling favour low latency for games and a steady
+    /// cadence for video.
+    pub fn content_type(&self) -> ContentType {
+        self.draw_states.values().map(|state| state.content_type).max().unwrap_or_default()
+    }
+    
     /// Create command pool for rendering operations
     fn create_command_pool(device: &VulkanDevice) -> Result<vk::CommandPool> {
         let pool_info = vk::CommandPoolCreateInfo {
@@ -221,15 +346,23 @@ impl CompositorRenderer {
     }
     
     /// Create render pass for swapchain rendering
-    fn create_render_pass(device: &VulkanDevice, format: vk::Format) -> Result<vk::RenderPass> {
+    ///
+    /// With `load` the previous contents of the image are kept, for redrawing
+    /// only its damaged parts.
+    fn create_render_pass(device: &VulkanDevice, format: vk::Format, load: bool) -> Result<vk::RenderPass> {
+        let (load_op, initial_layout) = if load {
+            (vk::AttachmentLoadOp::LOAD, vk::ImageLayout::PRESENT_SRC_KHR)
+        } else {
+            (vk::AttachmentLoadOp::CLEAR, vk::ImageLayout::UNDEFINED)
+        };
         let color_attachment = vk::AttachmentDescription {
             format,
             samples: vk::SampleCountFlags::TYPE_1,
-            load_op: vk::AttachmentLoadOp::CLEAR,
+            load_op,
             store_op: vk::AttachmentStoreOp::STORE,
             stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
             stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
-            initial_layout: vk::ImageLayout::UNDEFINED,
+            initial_layout,
             final_layout: vk::ImageLayout::PRESENT_SRC_KHR,
             ..Default::default()
         };
@@ -348,7 +481,13 @@ impl CompositorRenderer {
     }
     
     /// Begin render pass
-    fn begin_render_pass(&self, command_buffer: vk::CommandBuffer, image_index: u32) -> Result<()> {
+    fn begin_render_pass(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        image_index: u32,
+        render_area: vk::Rect2D,
+        partial: bool,
+    ) -> Result<()> {
         let clear_values = [vk::ClearValue {
             color: vk::ClearColorValue {
                 float32: [0.0, 0.0, 0.0, 1.0], // Black background
@@ -356,12 +495,9 @@ impl CompositorRenderer {
         }];
         
         let render_pass_info = vk::RenderPassBeginInfo {
-            render_pass: self.render_pass.unwrap(),
+            render_pass: if partial { self.load_render_pass.unwrap() } else { self.render_pass.unwrap() },
             framebuffer: self.framebuffers[image_index as usize],
-            render_area: vk::Rect2D {
-                offset: vk::Offset2D { x: 0, y: 0 },
-                extent: self.swapchain_extent,
-            },
+            render_area,
             clear_value_count: clear_values.len() as u32,
             p_clear_values: clear_values.as_ptr(),
             ..Default::default()
@@ -385,21 +521,16 @@ impl CompositorRenderer {
             max_depth: 1.0,
         };
         
-        let scissor = vk::Rect2D {
-            offset: vk::Offset2D { x: 0, y: 0 },
-            extent: self.swapchain_extent,
-        };
-        
         unsafe {
             self.device.handle().cmd_set_viewport(command_buffer, 0, &[viewport]);
-            self.device.handle().cmd_set_scissor(command_buffer, 0, &[scissor]);
+            self.device.handle().cmd_set_scissor(command_buffer, 0, &[render_area]);
         }
         
         Ok(())
     }
     
-    /// Render all surfaces
-    fn render_surfaces(&self, command_buffer: vk::CommandBuffer) -> Result<()> {
+    /// Render all surfaces within a scissor rectangle
+    fn render_surfaces(&self, command_buffer: vk::CommandBuffer, scissor: vk::Rect2D) -> Result<()> {
         let surface_pipeline = self.surface_pipeline.as_ref()
             .ok_or_else(|| CompositorError::runtime("Surface pipeline not initialized"))?;
         
@@ -412,9 +543,27 @@ impl CompositorRenderer {
             );
         }
         
-        // Render each surface
-        if let Some(ref surface_renderer) = self.surface_renderer {
-            for (surface_id, texture) in surface_renderer.get_all_textures() {
+        // Without a stacking order, textured surfaces are drawn before solid ones
+        let order: Vec<u32> = if self.draw_order.is_empty() {
+            let textured = self.surface_renderer.iter().flat_map(|renderer| renderer.get_all_textures().map(|(id, _)| id));
+            let solid = self.draw_states.iter().filter(|(_, state)| state.color.is_some()).map(|(&id, _)| id);
+            textured.chain(solid).collect()
+        } else {
+            self.draw_order.clone()
+        };
+        
+        for surface_id in order {
+            // Single-pixel buffers are drawn as solid quads without a texture
+            let solid = self.draw_states.get(&surface_id).is_some_and(|state| state.color.is_some());
+            let texture = match solid {
+                true => None,
+                false => match self.surface_renderer.as_ref().and_then(|renderer| renderer.get_surface_texture(surface_id)) {
+                    Some(texture) => Some(texture),
+                    None => continue,
+                },
+            };
+            for clip in self.surface_clips(surface_id, scissor) {
+                self.set_scissor(command_buffer, clip);
                 self.render_surface(command_buffer, surface_pipeline, surface_id, texture)?;
             }
         }
@@ -422,19 +571,36 @@ impl CompositorRenderer {
         Ok(())
     }
     
-    /// Render a single surface
+    /// Scissor rectangles a surface is drawn in, its visible parts within `scissor`
+    fn surface_clips(&self, surface_id: u32, scissor: vk::Rect2D) -> Vec<vk::Rect2D> {
+        match self.visible_regions.get(&surface_id) {
+            Some(region) => region.iter().filter_map(|rect| intersect_rects(*rect, scissor)).collect(),
+            None => vec![scissor],
+        }
+    }
+    
+    fn set_scissor(&self, command_buffer: vk::CommandBuffer, scissor: vk::Rect2D) {
+        unsafe {
+            self.device.handle().cmd_set_scissor(command_buffer, 0, &[scissor]);
+        }
+    }
+    
+    /// Render a single surface, from its texture or as a solid quad without one
     fn render_surface(
         &self,
         command_buffer: vk::CommandBuffer,
         pipeline: &SurfacePipeline,
         surface_id: u32,
-        _texture: &SurfaceTexture,
+        texture: Option<&SurfaceTexture>,
     ) -> Result<()> {
         // Get vertex buffer for this surface
         let vertex_buffer = self.vertex_buffers.get(&surface_id)                .ok_or_else(|| CompositorError::runtime("Missing vertex buffer for surface"))?;
         
         // Get descriptor set for texture
-        let descriptor_set = self.descriptor_sets.get(&surface_id)                .ok_or_else(|| CompositorError::runtime("Missing descriptor set for surface"))?;
+        let descriptor_set = match texture {
+            Some(_) => Some(self.descriptor_sets.get(&surface_id)                .ok_or_else(|| CompositorError::runtime("Missing descriptor set for surface"))?),
+            None => None,
+        };
         
         // Create transform matrix (identity for now - will be enhanced with positioning)
         let transform = [
@@ -444,28 +610,30 @@ impl CompositorRenderer {
             [0.0, 0.0, 0.0, 1.0],
         ];
         
-        let push_constants = SurfacePushConstants {
-            transform,
-            offset: [0.0, 0.0], // TODO: Get from surface position
-            scale: [1.0, 1.0],  // TODO: Get from surface scale
-        };
+        // Solid quads span a single pixel scaled to their destination
+        let (width, height) = texture.map_or((1, 1), |texture| (texture.width, texture.height));
+        let push_constants = self
+            .surface_draw_state(surface_id)
+            .push_constants(transform, width, height);
         
         unsafe {
             // Bind descriptor set
-            self.device.handle().cmd_bind_descriptor_sets(
-                command_buffer,
-                vk::PipelineBindPoint::GRAPHICS,
-                pipeline.pipeline_layout(),
-                0,
-                &[*descriptor_set],
-                &[],
-            );
+            if let Some(descriptor_set) = descriptor_set {
+                self.device.handle().cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    pipeline.pipeline_layout(),
+                    0,
+                    &[*descriptor_set],
+                    &[],
+                );
+            }
             
             // Push constants
             self.device.handle().cmd_push_constants(
                 command_buffer,
                 pipeline.pipeline_layout(),
-                vk::ShaderStageFlags::VERTEX,
+                vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
                 0,
                 &std::mem::transmute::<_, [u8; std::mem::size_of::<SurfacePushConstants>()]>(push_constants),
             );
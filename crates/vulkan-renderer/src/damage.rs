@@ -0,0 +1,85 @@
+// Buffer age tracking for partial redraws
+//
+// Swapchain images keep what was last drawn into them. Counting the frames
+// since each image was last rendered gives its buffer age, the number of
+// frames of damage the compositor must repaint to bring it up to date.
+
+use ash::vk;
+
+/// Frames rendered into each swapchain image
+#[derive(Debug, Clone, Default)]
+pub struct BufferAges {
+    /// Frames rendered so far
+    frame: u64,
+    /// Frame last rendered into each image, `None` for undefined contents
+    image_frames: Vec<Option<u64>>,
+}
+
+impl BufferAges {
+    /// Track the images of a new swapchain, all with undefined contents
+    pub fn new(image_count: usize) -> Self {
+        Self {
+            frame: 0,
+            image_frames: vec![None; image_count],
+        }
+    }
+
+    /// Age of an image for the next frame, 0 when its contents are undefined
+    ///
+    /// An image drawn in the last frame has age 1.
+    pub fn age(&self, image_index: u32) -> usize {
+        match self.image_frames.get(image_index as usize) {
+            Some(Some(frame)) => (self.frame + 1 - frame) as usize,
+            _ => 0,
+        }
+    }
+
+    /// Record a frame rendered into an image
+    pub fn rendered(&mut self, image_index: u32) {
+        self.frame += 1;
+        if let Some(frame) = self.image_frames.get_mut(image_index as usize) {
+            *frame = Some(self.frame);
+        }
+    }
+
+    /// Forget the contents of all images, after a resize or device loss
+    pub fn reset(&mut self) {
+        self.image_frames.iter_mut().for_each(|frame| *frame = None);
+    }
+}
+
+/// Bounding box of damage rectangles, `None` without damage
+pub fn damage_bounds(damage: &[vk::Rect2D]) -> Option<vk::Rect2D> {
+    let mut rects = damage.iter().filter(|rect| rect.extent.width > 0 && rect.extent.height > 0);
+    let first = rects.next()?;
+    let (mut x1, mut y1) = (first.offset.x, first.offset.y);
+    let (mut x2, mut y2) = (x1 + first.extent.width as i32, y1 + first.extent.height as i32);
+    for rect in rects {
+        x1 = x1.min(rect.offset.x);
+        y1 = y1.min(rect.offset.y);
+        x2 = x2.max(rect.offset.x + rect.extent.width as i32);
+        y2 = y2.max(rect.offset.y + rect.extent.height as i32);
+    }
+    Some(vk::Rect2D {
+        offset: vk::Offset2D { x: x1, y: y1 },
+        extent: vk::Extent2D { width: (x2 - x1) as u32, height: (y2 - y1) as u32 },
+    })
+}
+
+/// Overlap of two rectangles, `None` when they do not overlap
+pub fn intersect_rects(a: vk::Rect2D, b: vk::Rect2D) -> Option<vk::Rect2D> {
+    let x1 = a.offset.x.max(b.offset.x);
+    let y1 = a.offset.y.max(b.offset.y);
+    let x2 = (a.offset.x + a.extent.width as i32).min(b.offset.x + b.extent.width as i32);
+    let y2 = (a.offset.y + a.extent.height as i32).min(b.offset.y + b.extent.height as i32);
+    (x2 > x1 && y2 > y1).then(|| vk::Rect2D {
+        offset: vk::Offset2D { x: x1, y: y1 },
+        extent: vk::Extent2D { width: (x2 - x1) as u32, height: (y2 - y1) as u32 },
+    })
+}
+
+/// Damage rectangles clipped to an image, dropping those outside of it
+pub fn clip_damage(damage: &[vk::Rect2D], extent: vk::Extent2D) -> Vec<vk::Rect2D> {
+    let image = vk::Rect2D { offset: vk::Offset2D { x: 0, y: 0 }, extent };
+    damage.iter().filter_map(|rect| intersect_rects(*rect, image)).collect()
+}
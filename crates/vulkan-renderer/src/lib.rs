@@ -20,13 +20,15 @@ pub mod descriptor;
 pub mod surface_renderer;
 pub mod surface_pipeline;
 pub mod compositor_renderer;
+pub mod damage;
 
 pub use instance::VulkanInstance;
 pub use device::VulkanDevice;
 pub use swapchain::Swapchain;
 pub use surface_renderer::{SurfaceRenderer, SurfaceTexture, SurfaceBuffer};
-pub use surface_pipeline::{SurfacePipeline, SurfacePushConstants, SurfaceVertex};
+pub use surface_pipeline::{single_pixel_color, ContentType, SurfaceDrawState, SurfacePipeline, SurfacePushConstants, SurfaceVertex};
 pub use compositor_renderer::CompositorRenderer;
+pub use damage::BufferAges;
 
 /// Main Vulkan renderer context
 pub struct VulkanRenderer {
@@ -89,6 +91,27 @@ impl VulkanRenderer {
         }
     }
     
+    /// Render only the damaged parts of a frame, `None` when nothing changed
+    pub fn render_frame_damaged(
+        &mut self,
+        frame_index: usize,
+        image_index: u32,
+        damage: &[ash::vk::Rect2D],
+    ) -> Result<Option<ash::vk::CommandBuffer>> {
+        if let Some(ref mut compositor_renderer) = self.compositor_renderer {
+            compositor_renderer.render_frame_damaged(frame_index, image_index, damage)
+        } else {
+            Err(CompositorError::runtime("Compositor renderer not initialized"))
+        }
+    }
+    
+    /// Frames since a swapchain image was last rendered, 0 for undefined contents
+    pub fn buffer_age(&self, image_index: u32) -> usize {
+        self.compositor_renderer
+            .as_ref()
+            .map_or(0, |compositor_renderer| compositor_renderer.buffer_age(image_index))
+    }
+    
     /// Update a surface texture with new buffer data
     pub fn update_surface_buffer(
         &mut self, 
@@ -120,6 +143,35 @@ impl VulkanRenderer {
         Ok(())
     }
 
+    /// Show a single-pixel buffer on a surface as a solid-color quad
+    pub fn set_surface_solid_color(&mut self, surface_id: u32, color: [f32; 4]) -> Result<()> {
+        if let Some(ref mut compositor_renderer) = self.compositor_renderer {
+            compositor_renderer.set_surface_solid_color(surface_id, color)?;
+        }
+        Ok(())
+    }
+
+    /// Set the crop, scale, position, alpha and content type a surface is drawn with
+    pub fn set_surface_draw_state(&mut self, surface_id: u32, state: SurfaceDrawState) {
+        if let Some(ref mut compositor_renderer) = self.compositor_renderer {
+            compositor_renderer.set_surface_draw_state(surface_id, state);
+        }
+    }
+
+    /// Limit drawing a surface to its visible parts, `None` draws all of it
+    pub fn set_surface_visible_region(&mut self, surface_id: u32, region: Option<Vec<ash::vk::Rect2D>>) {
+        if let Some(ref mut compositor_renderer) = self.compositor_renderer {
+            compositor_renderer.set_surface_visible_region(surface_id, region);
+        }
+    }
+
+    /// Set the stacking order of surfaces, back to front
+    pub fn set_draw_order(&mut self, surface_ids: Vec<u32>) {
+        if let Some(ref mut compositor_renderer) = self.compositor_renderer {
+            compositor_renderer.set_draw_order(surface_ids);
+        }
+    }
+
     /// Remove a surface texture
     pub fn remove_surface(&mut self, surface_id: u32) -> Result<()> {
         if let Some(ref mut compositor_renderer) = self.compositor_renderer {
@@ -501,6 +553,37 @@ mod tests {
         assert!(!info.device_name.is_empty());
     }
     
+    /// Test buffer age tracking and damage clipping for partial redraws
+    #[test]
+    fn test_buffer_ages() {
+        let mut ages = BufferAges::new(3);
+        assert_eq!(ages.age(0), 0, "Undefined images have no age");
+        
+        // Images rendered in turn are as old as the swapchain is long
+        for image in [0, 1, 2, 0, 1] {
+            ages.rendered(image);
+        }
+        assert_eq!(ages.age(2), 3);
+        assert_eq!(ages.age(0), 2);
+        assert_eq!(ages.age(1), 1);
+        ages.reset();
+        assert_eq!(ages.age(1), 0);
+        
+        let extent = ash::vk::Extent2D { width: 3840, height: 2160 };
+        let rect = |x, y, width, height| ash::vk::Rect2D {
+            offset: ash::vk::Offset2D { x, y },
+            extent: ash::vk::Extent2D { width, height },
+        };
+        let clipped = damage::clip_damage(&[rect(-10, 2150, 20, 20), rect(4000, 0, 10, 10)], extent);
+        assert_eq!(clipped, vec![rect(0, 2150, 10, 10)]);
+        assert_eq!(damage::damage_bounds(&[rect(10, 10, 5, 5), rect(100, 50, 10, 10)]), Some(rect(10, 10, 100, 50)));
+        assert_eq!(damage::damage_bounds(&[]), None);
+        
+        // Occluded surfaces are drawn in the visible parts of each damaged rectangle
+        assert_eq!(damage::intersect_rects(rect(0, 0, 100, 100), rect(50, 80, 100, 100)), Some(rect(50, 80, 50, 20)));
+        assert_eq!(damage::intersect_rects(rect(0, 0, 100, 100), rect(100, 0, 10, 10)), None);
+    }
+    
     /// Test hardware acceleration detection
     #[test]
     fn test_hardware_acceleration() {
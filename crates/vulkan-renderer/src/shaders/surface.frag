@@ -6,9 +6,28 @@ layout(location = 0) out vec4 outColor;
 
 layout(set = 0, binding = 0) uniform sampler2D texSampler;
 
+layout(push_constant) uniform PushConstants {
+    mat4 transform;
+    vec2 offset;
+    vec2 scale;
+    vec2 texOffset;
+    vec2 texScale;
+    float alpha;
+    uint solid;
+    vec4 color;
+} pushConstants;
+
 void main() {
-    // Simple texture sampling - will be enhanced in Phase 2 with AI-generated effects
-    outColor = texture(texSampler, fragTexCoord);
+    if (pushConstants.solid != 0u) {
+        // Single-pixel buffers fill their quad without a texture
+        outColor = pushConstants.color;
+    } else {
+        // Simple texture sampling - will be enhanced in Phase 2 with AI-generated effects
+        outColor = texture(texSampler, fragTexCoord);
+    }
+
+    // Alpha modifier of the surface, blended as straight alpha
+    outColor.a *= pushConstants.alpha;
     
     // Basic alpha handling for client windows
     if (outColor.a < 0.01) {
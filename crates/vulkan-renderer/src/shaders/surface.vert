@@ -9,10 +9,16 @@ layout(push_constant) uniform PushConstants {
     mat4 transform;
     vec2 offset;
     vec2 scale;
+    vec2 texOffset;
+    vec2 texScale;
+    float alpha;
+    uint solid;
+    vec4 color;
 } pushConstants;
 
 void main() {
     vec2 pos = position * pushConstants.scale + pushConstants.offset;
     gl_Position = pushConstants.transform * vec4(pos, 0.0, 1.0);
-    fragTexCoord = texCoord;
+    // Crop to the source rectangle of the surface's viewport
+    fragTexCoord = pushConstants.texOffset + texCoord * pushConstants.texScale;
 }
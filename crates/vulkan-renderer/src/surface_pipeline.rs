@@ -29,6 +29,84 @@ pub struct SurfacePushConstants {
     pub transform: [[f32; 4]; 4],  // MVP matrix
     pub offset: [f32; 2],          // Surface position offset
     pub scale: [f32; 2],           // Surface scale factor
+    pub tex_offset: [f32; 2],      // Source crop origin in texture coordinates
+    pub tex_scale: [f32; 2],       // Source crop size in texture coordinates
+    pub alpha: f32,                // Alpha multiplier
+    pub solid: u32,                // Non-zero to fill with `color` instead of sampling
+    pub _padding: [f32; 2],        // Aligns `color` to 16 bytes as in GLSL
+    pub color: [f32; 4],           // Solid fill color, straight alpha
+}
+
+/// Kind of content a surface shows, as hinted by its client
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ContentType {
+    #[default]
+    None,
+    Photo,
+    Video,
+    Game,
+}
+
+/// Per-draw state of a surface
+///
+/// Positions and sizes are in output pixels, the source rectangle in buffer
+/// pixels. Without a source rectangle the whole buffer is drawn, without a
+/// destination size it is drawn at its buffer size.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SurfaceDrawState {
+    pub position: [f32; 2],
+    /// Source crop as x, y, width and height
+    pub source: Option<[f32; 4]>,
+    pub destination: Option<[f32; 2]>,
+    pub alpha: f32,
+    pub content_type: ContentType,
+    /// Color of a single-pixel buffer, drawn as a solid quad without a texture
+    pub color: Option<[f32; 4]>,
+}
+
+impl Default for SurfaceDrawState {
+    fn default() -> Self {
+        Self {
+            position: [0.0, 0.0],
+            source: None,
+            destination: None,
+            alpha: 1.0,
+            content_type: ContentType::None,
+            color: None,
+        }
+    }
+}
+
+impl SurfaceDrawState {
+    /// Push constants drawing a texture of the given size with this state
+    ///
+    /// Surface quads span the texture size, so the destination size becomes
+    /// a scale factor and the crop a window into the texture coordinates.
+    pub fn push_constants(&self, transform: [[f32; 4]; 4], width: u32, height: u32) -> SurfacePushConstants {
+        let (width, height) = (width.max(1) as f32, height.max(1) as f32);
+        let [x, y, source_width, source_height] = self.source.unwrap_or([0.0, 0.0, width, height]);
+        let [destination_width, destination_height] = self.destination.unwrap_or([source_width, source_height]);
+        SurfacePushConstants {
+            transform,
+            offset: self.position,
+            scale: [destination_width / width, destination_height / height],
+            tex_offset: [x / width, y / height],
+            tex_scale: [source_width / width, source_height / height],
+            alpha: self.alpha.clamp(0.0, 1.0),
+            solid: self.color.is_some() as u32,
+            _padding: [0.0; 2],
+            color: self.color.unwrap_or_default(),
+        }
+    }
+}
+
+/// Straight-alpha color of a premultiplied single-pixel buffer
+pub fn single_pixel_color(premultiplied: [f32; 4]) -> [f32; 4] {
+    let [r, g, b, a] = premultiplied;
+    if a <= 0.0 {
+        return [0.0; 4];
+    }
+    [(r / a).min(1.0), (g / a).min(1.0), (b / a).min(1.0), a]
 }
 
 /// Vertex data for surface quads
@@ -161,7 +239,7 @@ impl SurfacePipeline {
         
         let push_constant_ranges = [
             vk::PushConstantRange {
-                stage_flags: vk::ShaderStageFlags::VERTEX,
+                stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
                 offset: 0,
                 size: std::mem::size_of::<SurfacePushConstants>() as u32,
             },